@@ -1,7 +1,7 @@
 #[cfg(target_os = "android")]
 #[cfg_attr(target_os = "android", ndk_glue::main())]
 pub fn android_main() {
-    let options = doukutsu_rs::LaunchOptions { server_mode: false, editor: false };
+    let options = doukutsu_rs::game::LaunchOptions::default();
 
-    doukutsu_rs::init(options).unwrap();
+    doukutsu_rs::game::init(options).unwrap();
 }
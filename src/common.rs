@@ -22,7 +22,7 @@ lazy_static! {
 }
 
 bitfield! {
-    #[derive(Clone, Copy)]
+    #[derive(Clone, Copy, Serialize, Deserialize)]
     #[repr(C)]
     pub struct Flag(u32);
     impl Debug;
@@ -69,7 +69,7 @@ impl Flag {
 }
 
 bitfield! {
-    #[derive(Clone, Copy)]
+    #[derive(Clone, Copy, Serialize, Deserialize)]
     #[repr(C)]
     pub struct Equipment(u16);
     impl Debug;
@@ -95,7 +95,7 @@ bitfield! {
 }
 
 bitfield! {
-    #[derive(Clone, Copy)]
+    #[derive(Clone, Copy, Serialize, Deserialize)]
     #[repr(C)]
     pub struct Condition(u16);
     impl Debug;
@@ -133,7 +133,7 @@ bitfield! {
 }
 
 bitfield! {
-    #[derive(Clone, Copy)]
+    #[derive(Clone, Copy, Serialize, Deserialize)]
     #[repr(C)]
     pub struct BulletFlag(u8);
     impl Debug;
@@ -152,7 +152,7 @@ bitfield! {
     pub flag_x80, set_flag_x80: 7; // 0x80, nowhere in code?
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum FadeDirection {
     Left = 0,
@@ -185,7 +185,7 @@ impl FadeDirection {
     }
 }
 
-#[derive(Debug, PartialEq, Copy, Clone)]
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum FadeState {
     Visible,
@@ -194,7 +194,7 @@ pub enum FadeState {
     FadeOut(i8, FadeDirection),
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum Direction {
     Left = 0,
@@ -364,6 +364,7 @@ macro_rules! rect_deserialize {
 
 rect_deserialize!(u8);
 rect_deserialize!(u16);
+rect_deserialize!(u32);
 rect_deserialize!(i32);
 rect_deserialize!(isize);
 rect_deserialize!(usize);
@@ -1,3 +1,5 @@
+use serde::{Deserialize, Serialize};
+
 use crate::common::{interpolate_fix9_scale, Rect};
 use crate::entity::GameEntity;
 use crate::framework::context::Context;
@@ -5,7 +7,7 @@ use crate::framework::error::GameResult;
 use crate::game::frame::Frame;
 use crate::game::shared_game_state::SharedGameState;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, Serialize, Deserialize)]
 pub struct NumberPopup {
     pub value: i16,
     pub x: i32,
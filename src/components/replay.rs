@@ -1,26 +1,317 @@
-use std::io::{Cursor, Read};
+use std::hash::Hasher;
+use std::io::{Cursor, Read, Write};
 
 use byteorder::{LE, ReadBytesExt, WriteBytesExt};
 
+use crate::common::VERSION_BANNER;
 use crate::entity::GameEntity;
 use crate::framework::context::Context;
-use crate::framework::error::GameResult;
+use crate::framework::error::{GameError, GameResult};
 use crate::framework::filesystem;
 use crate::framework::keyboard::ScanCode;
 use crate::framework::vfs::OpenOptions;
 use crate::game::frame::Frame;
-use crate::game::shared_game_state::{ReplayKind, ReplayState, SharedGameState};
+use crate::game::shared_game_state::{GameDifficulty, PlayerCount, ReplayKind, ReplayState, SharedGameState};
+use crate::input::player_controller::PlayerController;
 use crate::input::replay_player_controller::{KeyState, ReplayController};
 use crate::game::player::Player;
 use crate::graphics::font::Font;
+use crate::util::hash::Fnv1a;
+
+/// Latest replay format version, files with a higher version are rejected.
+pub const REPLAY_VERSION: u16 = 3;
+
+/// How often (in ticks) a `StateChecksum` is recorded.
+pub const CHECKSUM_INTERVAL: u16 = 50;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ChecksumSubsystem {
+    Players,
+    NPCs,
+    RNG,
+    Flags,
+}
+
+impl ChecksumSubsystem {
+    pub fn name(self) -> &'static str {
+        match self {
+            ChecksumSubsystem::Players => "players",
+            ChecksumSubsystem::NPCs => "NPCs",
+            ChecksumSubsystem::RNG => "game RNG",
+            ChecksumSubsystem::Flags => "flags",
+        }
+    }
+}
+
+/// Truncated hashes of the deterministic parts of the game state, one per subsystem.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct StateChecksum {
+    pub players: u32,
+    pub npcs: u32,
+    pub rng: u32,
+    pub flags: u32,
+}
+
+impl StateChecksum {
+    /// Returns the first subsystem that doesn't match between two checksums.
+    pub fn first_mismatch(&self, other: &StateChecksum) -> Option<ChecksumSubsystem> {
+        if self.players != other.players {
+            Some(ChecksumSubsystem::Players)
+        } else if self.npcs != other.npcs {
+            Some(ChecksumSubsystem::NPCs)
+        } else if self.rng != other.rng {
+            Some(ChecksumSubsystem::RNG)
+        } else if self.flags != other.flags {
+            Some(ChecksumSubsystem::Flags)
+        } else {
+            None
+        }
+    }
+
+    pub fn read_from<R: Read>(data: &mut R) -> GameResult<StateChecksum> {
+        Ok(StateChecksum {
+            players: data.read_u32::<LE>()?,
+            npcs: data.read_u32::<LE>()?,
+            rng: data.read_u32::<LE>()?,
+            flags: data.read_u32::<LE>()?,
+        })
+    }
+
+    pub fn write_to<W: Write>(&self, data: &mut W) -> GameResult {
+        data.write_u32::<LE>(self.players)?;
+        data.write_u32::<LE>(self.npcs)?;
+        data.write_u32::<LE>(self.rng)?;
+        data.write_u32::<LE>(self.flags)?;
+
+        Ok(())
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ReplayDesync {
+    pub tick: usize,
+    pub subsystem: ChecksumSubsystem,
+}
+
+/// Describes the conditions a replay was recorded under.
+///
+/// Version 0 replays only contain the RNG seed, all other fields are left at their defaults and aren't verified.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReplayHeader {
+    pub version: u16,
+    pub rng_seed: u64,
+    pub engine_version: String,
+    pub mod_id: String,
+    pub stage_id: u16,
+    pub difficulty: u8,
+    pub player_count: u8,
+    /// Ticks per second of the `TimingMode` used while recording, 0 for frame synchronized timing.
+    pub tick_rate: u16,
+    /// Hash of the stage table, NPC table and scripts, see `Replay::content_hash`.
+    pub content_hash: u64,
+    /// Number of ticks between stored state checksums, 0 if the replay has none.
+    pub checksum_interval: u16,
+    pub player2_skin: u16,
+}
+
+impl ReplayHeader {
+    pub fn new() -> ReplayHeader {
+        ReplayHeader {
+            version: REPLAY_VERSION,
+            rng_seed: 0,
+            engine_version: String::new(),
+            mod_id: String::new(),
+            stage_id: 0,
+            difficulty: GameDifficulty::Normal as u8,
+            player_count: 1,
+            tick_rate: 0,
+            content_hash: 0,
+            checksum_interval: 0,
+            player2_skin: 0,
+        }
+    }
+
+    /// Builds a header describing currently loaded game data.
+    pub fn current(state: &SharedGameState, stage_id: usize) -> ReplayHeader {
+        let mod_id = match &state.mod_path {
+            Some(mod_path) => state.mod_list.get_id_from_path(mod_path.to_string()).to_owned(),
+            None => String::new(),
+        };
+
+        ReplayHeader {
+            version: REPLAY_VERSION,
+            rng_seed: state.game_rng.dump_state(),
+            engine_version: VERSION_BANNER.clone(),
+            mod_id,
+            stage_id: stage_id as u16,
+            difficulty: state.difficulty as u8,
+            player_count: match state.player_count {
+                PlayerCount::One => 1,
+                PlayerCount::Two => 2,
+            },
+            tick_rate: state.settings.timing_mode.get_tps() as u16,
+            content_hash: state.content_hash,
+            checksum_interval: CHECKSUM_INTERVAL,
+            player2_skin: state.player2_skin,
+        }
+    }
+
+    pub fn is_legacy(&self) -> bool {
+        self.version == 0
+    }
+
+    pub fn difficulty(&self) -> GameDifficulty {
+        GameDifficulty::from_primitive(self.difficulty)
+    }
+
+    pub fn player_count(&self) -> PlayerCount {
+        if self.player_count == 2 {
+            PlayerCount::Two
+        } else {
+            PlayerCount::One
+        }
+    }
+
+    /// Restores the game settings the replay was recorded with.
+    pub fn apply(&self, state: &mut SharedGameState) {
+        state.difficulty = self.difficulty();
+        state.player_count = self.player_count();
+        state.player2_skin = self.player2_skin;
+    }
+
+    /// Checks whether a replay with this header can be played back against `current` game data.
+    pub fn check_compatibility(&self, current: &ReplayHeader) -> GameResult {
+        if self.is_legacy() {
+            log::warn!("Playing back a version 0 replay, game data can't be verified.");
+            return Ok(());
+        }
+
+        if self.mod_id != current.mod_id {
+            return Err(GameError::InvalidValue(format!(
+                "Replay was recorded with mod '{}', but '{}' is active.",
+                self.mod_id, current.mod_id
+            )));
+        }
+
+        if self.stage_id != current.stage_id {
+            return Err(GameError::InvalidValue(format!(
+                "Replay starts on stage {}, but the active mod starts on stage {}.",
+                self.stage_id, current.stage_id
+            )));
+        }
+
+        if self.content_hash != current.content_hash {
+            return Err(GameError::InvalidValue(format!(
+                "Replay was recorded against different game data (hash {:016x}, current {:016x}), the mod was probably updated.",
+                self.content_hash, current.content_hash
+            )));
+        }
+
+        if self.tick_rate != current.tick_rate {
+            log::warn!("Replay was recorded at {} TPS, playing back at {} TPS.", self.tick_rate, current.tick_rate);
+        }
+
+        if self.engine_version != current.engine_version {
+            log::warn!(
+                "Replay was recorded with engine version {}, current version is {}.",
+                self.engine_version,
+                current.engine_version
+            );
+        }
+
+        Ok(())
+    }
+
+    pub fn read_from<R: Read>(mut data: R) -> GameResult<ReplayHeader> {
+        let mut header = ReplayHeader::new();
+        header.version = data.read_u16::<LE>()?;
+
+        if header.version > REPLAY_VERSION {
+            return Err(GameError::ResourceLoadError(format!(
+                "Unsupported replay version {}, latest supported version is {}.",
+                header.version, REPLAY_VERSION
+            )));
+        }
+
+        header.rng_seed = data.read_u64::<LE>()?;
+
+        if header.version >= 1 {
+            header.engine_version = read_string(&mut data)?;
+            header.mod_id = read_string(&mut data)?;
+            header.stage_id = data.read_u16::<LE>()?;
+            header.difficulty = data.read_u8()?;
+            header.player_count = data.read_u8()?;
+            header.tick_rate = data.read_u16::<LE>()?;
+            header.content_hash = data.read_u64::<LE>()?;
+        }
+
+        if header.version >= 2 {
+            header.checksum_interval = data.read_u16::<LE>()?;
+        }
+
+        if header.version >= 3 {
+            header.player2_skin = data.read_u16::<LE>()?;
+        }
+
+        Ok(header)
+    }
+
+    pub fn write_to<W: Write>(&self, mut data: W) -> GameResult {
+        data.write_u16::<LE>(self.version)?;
+        data.write_u64::<LE>(self.rng_seed)?;
+
+        if self.version >= 1 {
+            write_string(&mut data, &self.engine_version)?;
+            write_string(&mut data, &self.mod_id)?;
+            data.write_u16::<LE>(self.stage_id)?;
+            data.write_u8(self.difficulty)?;
+            data.write_u8(self.player_count)?;
+            data.write_u16::<LE>(self.tick_rate)?;
+            data.write_u64::<LE>(self.content_hash)?;
+        }
+
+        if self.version >= 2 {
+            data.write_u16::<LE>(self.checksum_interval)?;
+        }
+
+        if self.version >= 3 {
+            data.write_u16::<LE>(self.player2_skin)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn read_string<R: Read>(data: &mut R) -> GameResult<String> {
+    let len = data.read_u16::<LE>()? as usize;
+    let mut buf = vec![0u8; len];
+    data.read_exact(&mut buf)?;
+
+    Ok(String::from_utf8(buf)?)
+}
+
+fn write_string<W: Write>(data: &mut W, value: &str) -> GameResult {
+    let bytes = value.as_bytes();
+    let len = bytes.len().min(u16::MAX as usize);
+    data.write_u16::<LE>(len as u16)?;
+    data.write_all(&bytes[..len])?;
+
+    Ok(())
+}
 
 #[derive(Clone)]
 pub struct Replay {
-    replay_version: u16,
+    header: ReplayHeader,
     keylist: Vec<u16>,
+    /// Inputs of the second player, only present in co-op replays.
+    keylist2: Vec<u16>,
+    checksums: Vec<StateChecksum>,
+    /// First divergence from the recorded checksums found during playback.
+    pub desync: Option<ReplayDesync>,
     last_input: KeyState,
-    rng_seed: u64,
+    last_input2: KeyState,
     pub controller: ReplayController,
+    pub controller2: ReplayController,
     tick: usize,
     resume_tick: usize,
     is_active: bool,
@@ -29,20 +320,24 @@ pub struct Replay {
 impl Replay {
     pub fn new() -> Replay {
         Replay {
-            replay_version: 0,
+            header: ReplayHeader::new(),
             keylist: Vec::new(),
+            keylist2: Vec::new(),
+            checksums: Vec::new(),
+            desync: None,
             last_input: KeyState(0),
-            rng_seed: 0,
+            last_input2: KeyState(0),
             controller: ReplayController::new(),
+            controller2: ReplayController::new(),
             tick: 0,
             resume_tick: 0,
             is_active: false,
         }
     }
 
-    pub fn initialize_recording(&mut self, state: &mut SharedGameState) {
+    pub fn initialize_recording(&mut self, state: &mut SharedGameState, stage_id: usize) {
         if !self.is_active {
-            self.rng_seed = state.game_rng.dump_state();
+            self.header = ReplayHeader::current(state, stage_id);
             self.is_active = true;
         }
     }
@@ -73,70 +368,236 @@ impl Replay {
         if !self.is_active {
             state.replay_state = ReplayState::Playback(replay_kind);
             self.read_replay(state, ctx, replay_kind)?;
-            state.game_rng.load_state(self.rng_seed);
+            state.game_rng.load_state(self.header.rng_seed);
             self.is_active = true;
         }
         Ok(())
     }
 
+    /// Reads only the header of a stored replay.
+    pub fn load_header(state: &SharedGameState, ctx: &Context, replay_kind: ReplayKind) -> GameResult<ReplayHeader> {
+        let file = filesystem::user_open(ctx, [state.get_rec_filename(), replay_kind.get_suffix()].join(""))?;
+        ReplayHeader::read_from(file)
+    }
+
+    /// Verifies that a stored replay was recorded against currently loaded game data.
+    /// Resources of the mod have to be loaded before calling this.
+    pub fn check_replay_data(
+        state: &SharedGameState,
+        ctx: &Context,
+        replay_kind: ReplayKind,
+    ) -> GameResult<ReplayHeader> {
+        let header = Replay::load_header(state, ctx, replay_kind)?;
+        let current = ReplayHeader::current(state, state.constants.game.new_game_stage as usize);
+        header.check_compatibility(&current)?;
+
+        Ok(header)
+    }
+
+    /// Hashes everything a mod update is likely to touch in a way that breaks replays:
+    /// the stage table, maps, entity lists and scripts of every stage, the NPC table and global scripts.
+    ///
+    /// Only fixed-width little-endian values are fed to the hasher, so the result doesn't depend on the platform.
+    /// It's computed once per resource load and cached in `SharedGameState::content_hash`.
+    pub fn content_hash(state: &SharedGameState, ctx: &Context) -> GameResult<u64> {
+        fn hash_bytes(hasher: &mut Fnv1a, bytes: &[u8]) {
+            hasher.write(&(bytes.len() as u32).to_le_bytes());
+            hasher.write(bytes);
+        }
+
+        fn hash_file(hasher: &mut Fnv1a, ctx: &Context, roots: &Vec<String>, path: String) -> GameResult {
+            if let Ok(mut file) = filesystem::open_find(ctx, roots, path) {
+                let mut data = Vec::new();
+                file.read_to_end(&mut data)?;
+                hash_bytes(hasher, &data);
+            } else {
+                hasher.write(&u32::MAX.to_le_bytes());
+            }
+            Ok(())
+        }
+
+        let mut hasher = Fnv1a::new();
+        let roots = &state.constants.base_paths;
+
+        hasher.write(&(state.stages.len() as u32).to_le_bytes());
+        for stage in state.stages.iter() {
+            hash_bytes(&mut hasher, stage.map.as_bytes());
+            hash_bytes(&mut hasher, stage.tileset.name.as_bytes());
+            hash_bytes(&mut hasher, stage.background.name().as_bytes());
+            hash_bytes(&mut hasher, stage.npc1.name().as_bytes());
+            hash_bytes(&mut hasher, stage.npc2.name().as_bytes());
+            hasher.write(&[stage.background_type as u8, stage.boss_no]);
+
+            for ext in [".pxm", ".pxpack", ".pxe", ".tsc"] {
+                hash_file(&mut hasher, ctx, roots, ["Stage/", &stage.map, ext].join(""))?;
+            }
+        }
+
+        for path in ["npc.tbl", "Head.tsc", "ArmsItem.tsc"] {
+            hash_file(&mut hasher, ctx, roots, path.to_owned())?;
+        }
+
+        Ok(hasher.finish())
+    }
+
     fn write_replay(&mut self, state: &mut SharedGameState, ctx: &mut Context, replay_kind: ReplayKind) -> GameResult {
         if let Ok(mut file) = filesystem::open_options(
             ctx,
             [state.get_rec_filename(), replay_kind.get_suffix()].join(""),
-            OpenOptions::new().write(true).create(true),
+            OpenOptions::new().write(true).create(true).truncate(true),
         ) {
-            file.write_u16::<LE>(0)?; // Space for versioning replay files
-            file.write_u64::<LE>(self.rng_seed)?;
+            self.header.write_to(&mut file)?;
+            file.write_u32::<LE>(self.keylist.len() as u32)?;
             for input in &self.keylist {
                 file.write_u16::<LE>(*input)?;
             }
+            if self.is_coop() {
+                for input in &self.keylist2 {
+                    file.write_u16::<LE>(*input)?;
+                }
+            }
+            for checksum in &self.checksums {
+                checksum.write_to(&mut file)?;
+            }
         }
         Ok(())
     }
 
     fn read_replay(&mut self, state: &mut SharedGameState, ctx: &mut Context, replay_kind: ReplayKind) -> GameResult {
-        if let Ok(mut file) = filesystem::user_open(ctx, [state.get_rec_filename(), replay_kind.get_suffix()].join(""))
-        {
-            self.replay_version = file.read_u16::<LE>()?;
-            self.rng_seed = file.read_u64::<LE>()?;
+        if let Some(verifier) = &state.replay_verifier {
+            return self.read_from(std::fs::File::open(&verifier.path)?);
+        }
+
+        if let Ok(file) = filesystem::user_open(ctx, [state.get_rec_filename(), replay_kind.get_suffix()].join("")) {
+            self.read_from(file)?;
+        }
+        Ok(())
+    }
+
+    fn read_from<R: Read>(&mut self, mut file: R) -> GameResult {
+        self.header = ReplayHeader::read_from(&mut file)?;
+        self.keylist.clear();
+        self.keylist2.clear();
+        self.checksums.clear();
+
+        if self.header.version >= 2 {
+            let count = file.read_u32::<LE>()?;
+            for _ in 0..count {
+                self.keylist.push(file.read_u16::<LE>()?);
+            }
+
+            if self.is_coop() {
+                for _ in 0..count {
+                    self.keylist2.push(file.read_u16::<LE>()?);
+                }
+            }
 
             let mut data = Vec::new();
             file.read_to_end(&mut data)?;
 
-            let count = data.len() / 2;
-            let mut inputs = Vec::new();
+            let count = data.len() / 16;
             let mut f = Cursor::new(data);
 
             for _ in 0..count {
-                inputs.push(f.read_u16::<LE>()?);
+                self.checksums.push(StateChecksum::read_from(&mut f)?);
             }
+        } else {
+            let mut data = Vec::new();
+            file.read_to_end(&mut data)?;
 
-            self.keylist = inputs;
+            let count = data.len() / 2;
+            let mut f = Cursor::new(data);
+
+            for _ in 0..count {
+                self.keylist.push(f.read_u16::<LE>()?);
+            }
         }
+
         Ok(())
     }
+
+    /// Whether `process_checksum` should be called before the next `tick`.
+    pub fn needs_checksum(&self, state: &SharedGameState) -> bool {
+        let interval = self.header.checksum_interval as usize;
+        if interval == 0 {
+            return false;
+        }
+
+        match state.replay_state {
+            ReplayState::Recording => self.keylist.len() % interval == 0,
+            ReplayState::Playback(_) => self.desync.is_none() && self.tick % interval == 0,
+            ReplayState::None => false,
+        }
+    }
+
+    /// Stores the checksum while recording, or compares it against the recorded one during playback.
+    pub fn process_checksum(&mut self, state: &SharedGameState, checksum: StateChecksum) {
+        match state.replay_state {
+            ReplayState::Recording => {
+                self.checksums.push(checksum);
+            }
+            ReplayState::Playback(_) => {
+                let interval = self.header.checksum_interval as usize;
+                if interval == 0 {
+                    return;
+                }
+
+                if let Some(recorded) = self.checksums.get(self.tick / interval) {
+                    if let Some(subsystem) = recorded.first_mismatch(&checksum) {
+                        log::warn!(
+                            "Replay desync detected at tick {} in {}: expected {:?}, got {:?}",
+                            self.tick,
+                            subsystem.name(),
+                            recorded,
+                            checksum
+                        );
+                        self.desync = Some(ReplayDesync { tick: self.tick, subsystem });
+                    }
+                }
+            }
+            ReplayState::None => {}
+        }
+    }
+
+    pub fn tick_count(&self) -> usize {
+        self.tick
+    }
+
+    fn is_coop(&self) -> bool {
+        self.header.player_count() == PlayerCount::Two
+    }
+
+    /// Packs the current state of a player's controller, this mimics the KeyState bitfield.
+    pub fn pack_inputs(controller: &dyn PlayerController) -> u16 {
+        controller.move_left() as u16
+            + ((controller.move_right() as u16) << 1)
+            + ((controller.move_up() as u16) << 2)
+            + ((controller.move_down() as u16) << 3)
+            + ((controller.trigger_map() as u16) << 4)
+            + ((controller.trigger_inventory() as u16) << 5)
+            + (((controller.jump() || controller.trigger_menu_ok()) as u16) << 6)
+            + (((controller.shoot() || controller.trigger_menu_back()) as u16) << 7)
+            + ((controller.next_weapon() as u16) << 8)
+            + ((controller.prev_weapon() as u16) << 9)
+            + ((controller.trigger_menu_ok() as u16) << 11)
+            + ((controller.skip() as u16) << 12)
+            + ((controller.strafe() as u16) << 13)
+    }
 }
 
-impl GameEntity<(&mut Context, &mut Player)> for Replay {
-    fn tick(&mut self, state: &mut SharedGameState, (ctx, player): (&mut Context, &mut Player)) -> GameResult {
+impl GameEntity<(&mut Context, &mut Player, &mut Player)> for Replay {
+    fn tick(
+        &mut self,
+        state: &mut SharedGameState,
+        (ctx, player1, player2): (&mut Context, &mut Player, &mut Player),
+    ) -> GameResult {
         match state.replay_state {
             ReplayState::Recording => {
-                // This mimics the KeyState bitfield
-                let inputs = player.controller.move_left() as u16
-                    + ((player.controller.move_right() as u16) << 1)
-                    + ((player.controller.move_up() as u16) << 2)
-                    + ((player.controller.move_down() as u16) << 3)
-                    + ((player.controller.trigger_map() as u16) << 4)
-                    + ((player.controller.trigger_inventory() as u16) << 5)
-                    + (((player.controller.jump() || player.controller.trigger_menu_ok()) as u16) << 6)
-                    + (((player.controller.shoot() || player.controller.trigger_menu_back()) as u16) << 7)
-                    + ((player.controller.next_weapon() as u16) << 8)
-                    + ((player.controller.prev_weapon() as u16) << 9)
-                    + ((player.controller.trigger_menu_ok() as u16) << 11)
-                    + ((player.controller.skip() as u16) << 12)
-                    + ((player.controller.strafe() as u16) << 13);
-
-                self.keylist.push(inputs);
+                self.keylist.push(Replay::pack_inputs(player1.controller.as_ref()));
+
+                if self.is_coop() {
+                    self.keylist2.push(Replay::pack_inputs(player2.controller.as_ref()));
+                }
             }
             ReplayState::Playback(_) => {
                 let pause = ctx.keyboard_context.is_key_pressed(ScanCode::Escape) && (self.tick - self.resume_tick > 3);
@@ -145,7 +606,18 @@ impl GameEntity<(&mut Context, &mut Player)> for Replay {
 
                 self.controller.state = KeyState(next_input);
                 self.controller.old_state = self.last_input;
-                player.controller = Box::new(self.controller);
+                player1.controller = Box::new(self.controller);
+
+                if self.is_coop() {
+                    let next_input2 =
+                        if pause { self.last_input2.0 } else { *self.keylist2.get(self.tick).unwrap_or(&0) };
+
+                    self.controller2.state = KeyState(next_input2);
+                    self.controller2.old_state = self.last_input2;
+                    player2.controller = Box::new(self.controller2);
+
+                    self.last_input2 = KeyState(next_input2);
+                }
 
                 if !pause {
                     self.last_input = KeyState(next_input);
@@ -156,7 +628,8 @@ impl GameEntity<(&mut Context, &mut Player)> for Replay {
 
                 if self.tick >= self.keylist.len() {
                     state.replay_state = ReplayState::None;
-                    player.controller = state.settings.create_player1_controller();
+                    player1.controller = state.settings.create_player1_controller();
+                    player2.controller = state.settings.create_player2_controller();
                 }
             }
             ReplayState::None => {}
@@ -186,3 +659,49 @@ impl GameEntity<(&mut Context, &mut Player)> for Replay {
         Ok(())
     }
 }
+
+#[test]
+fn test_replay_header() -> GameResult {
+    let mut legacy = Vec::new();
+    legacy.write_u16::<LE>(0)?;
+    legacy.write_u64::<LE>(0x1234)?;
+    legacy.write_u16::<LE>(0x40)?;
+
+    let header = ReplayHeader::read_from(Cursor::new(&legacy))?;
+    assert!(header.is_legacy());
+    assert_eq!(header.rng_seed, 0x1234);
+    assert!(header.check_compatibility(&ReplayHeader::new()).is_ok());
+
+    let mut header = ReplayHeader::new();
+    header.rng_seed = 0xdeadbeef;
+    header.engine_version = "0.100.0".to_owned();
+    header.mod_id = "csmod_01".to_owned();
+    header.stage_id = 13;
+    header.player_count = 2;
+    header.tick_rate = 50;
+    header.content_hash = 0x0123456789abcdef;
+    header.checksum_interval = CHECKSUM_INTERVAL;
+    header.player2_skin = 2;
+
+    let mut data = Vec::new();
+    header.write_to(&mut data)?;
+    assert_eq!(ReplayHeader::read_from(Cursor::new(&data))?, header);
+
+    let mut other = header.clone();
+    assert!(header.check_compatibility(&other).is_ok());
+    other.content_hash = 0;
+    assert!(header.check_compatibility(&other).is_err());
+    other = header.clone();
+    other.mod_id = "csmod_02".to_owned();
+    assert!(header.check_compatibility(&other).is_err());
+
+    data[0] = (REPLAY_VERSION + 1) as u8;
+    assert!(ReplayHeader::read_from(Cursor::new(&data)).is_err());
+
+    let checksum = StateChecksum { players: 1, npcs: 2, rng: 3, flags: 4 };
+    assert_eq!(checksum.first_mismatch(&checksum), None);
+    assert_eq!(checksum.first_mismatch(&StateChecksum { npcs: 5, ..checksum }), Some(ChecksumSubsystem::NPCs));
+    assert_eq!(checksum.first_mismatch(&StateChecksum::default()), Some(ChecksumSubsystem::Players));
+
+    Ok(())
+}
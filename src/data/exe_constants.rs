@@ -0,0 +1,435 @@
+use std::ops::Range;
+
+use byteorder::{ByteOrder, LE};
+use serde_json::{json, Map, Value};
+
+use crate::common::{BulletFlag, Rect};
+use crate::data::exe_parser::ExeParser;
+use crate::engine_constants::{BulletData, EngineConstants, PhysicsConsts};
+use crate::framework::error::{GameError::ParseError, GameResult};
+
+const MUSIC_TABLE_VA: u32 = 0x4981E8;
+const MUSIC_TABLE_LEN: usize = 42;
+const MUSIC_NAME_MAX_LEN: usize = 16;
+
+const BULLET_TABLE_VA: u32 = 0x48F048;
+const BULLET_TABLE_LEN: usize = 46;
+const BULLET_ENTRY_SIZE: usize = 0x2C;
+
+const ARMS_LEVEL_TABLE_VA: u32 = 0x493660;
+const ARMS_LEVEL_TABLE_LEN: usize = 14;
+
+/// `mov dword ptr [ebp + disp8], imm32`
+const MOV_LOCAL_IMM32: u8 = 0xC7;
+const MOV_LOCAL_MODRM: u8 = 0x45;
+const MOV_LOCAL_SIZE: usize = 7;
+const PHYSICS_VALUE_COUNT: usize = 8;
+
+/// Reads the tables and values mods commonly hex-edit in a freeware `Doukutsu.exe` and returns the ones which
+/// differ from the defaults, in the format of `constants.json` files.
+///
+/// Every table is checked for values the original engine couldn't have used, in which case it's assumed the
+/// executable is laid out differently and the table is skipped.
+///
+/// Not covered:
+/// - NPC hit and death sounds, they aren't stored in the executable but in `npc.tbl`, which exe-only mods ship
+///   next to it and which is loaded from there as usual.
+/// - Booster's Lab hacks which relocate or resize tables (eg. extended music or weapon tables). Only the original
+///   locations are read, a table which no longer passes the checks there is skipped and keeps its defaults.
+pub fn extract_constant_overrides(exe: &[u8], parser: &ExeParser) -> GameResult<Value> {
+    let image = ExeImage::new(exe, parser);
+    let defaults = EngineConstants::defaults();
+    let mut overrides = Map::new();
+    let mut player = Map::new();
+    let mut weapon = Map::new();
+
+    match read_music_table(&image) {
+        Some(music_table) if music_table != defaults.music_table => {
+            overrides.insert("music_table".to_owned(), json!(music_table));
+        }
+        Some(_) => {}
+        None => log::warn!("Music table not found in the executable, skipping."),
+    }
+
+    match read_bullet_table(&image) {
+        Some(bullet_table) => {
+            let mut changed = Map::new();
+            for (idx, (bullet, default)) in bullet_table.iter().zip(defaults.weapon.bullet_table.iter()).enumerate() {
+                let bullet = to_value(bullet)?;
+                if bullet != to_value(default)? {
+                    changed.insert(idx.to_string(), bullet);
+                }
+            }
+
+            if !changed.is_empty() {
+                weapon.insert("bullet_table".to_owned(), Value::Object(changed));
+            }
+        }
+        None => log::warn!("Bullet table not found in the executable, skipping."),
+    }
+
+    match read_arms_level_table(&image) {
+        Some(level_table) if level_table != defaults.weapon.level_table => {
+            weapon.insert("level_table".to_owned(), json!(level_table));
+        }
+        Some(_) => {}
+        None => log::warn!("Weapon level table not found in the executable, skipping."),
+    }
+
+    match read_physics(&image) {
+        Some((water_physics, air_physics)) => {
+            let physics = [
+                ("water_physics", water_physics, defaults.player.water_physics),
+                ("air_physics", air_physics, defaults.player.air_physics),
+            ];
+
+            for (name, physics, default) in physics {
+                let changed = diff_objects(to_value(&physics)?, &to_value(&default)?);
+                if !changed.is_empty() {
+                    player.insert(name.to_owned(), Value::Object(changed));
+                }
+            }
+        }
+        None => log::warn!("Player physics not found in the executable, skipping."),
+    }
+
+    if !player.is_empty() {
+        overrides.insert("player".to_owned(), Value::Object(player));
+    }
+
+    if !weapon.is_empty() {
+        overrides.insert("weapon".to_owned(), Value::Object(weapon));
+    }
+
+    Ok(Value::Object(overrides))
+}
+
+fn to_value<T: serde::Serialize>(value: &T) -> GameResult<Value> {
+    serde_json::to_value(value).map_err(|err| ParseError(err.to_string()))
+}
+
+fn diff_objects(value: Value, default: &Value) -> Map<String, Value> {
+    match value {
+        Value::Object(map) => map.into_iter().filter(|(key, value)| default.get(key) != Some(value)).collect(),
+        _ => Map::new(),
+    }
+}
+
+/// File contents of the executable together with the section layout, to find the data pointers and code refer to.
+struct ExeImage<'a> {
+    data: &'a [u8],
+    image_base: u32,
+    /// `(virtual address, raw data size, raw data offset)` of every section.
+    sections: Vec<(u32, u32, u32)>,
+    text: Option<Range<usize>>,
+}
+
+impl<'a> ExeImage<'a> {
+    fn new(data: &'a [u8], parser: &ExeParser) -> ExeImage<'a> {
+        let sections = parser
+            .section_headers
+            .image()
+            .iter()
+            .map(|section| (section.VirtualAddress, section.SizeOfRawData, section.PointerToRawData))
+            .collect();
+        let text = parser
+            .get_named_section_byte_range(".text".to_owned())
+            .ok()
+            .flatten()
+            .map(|range| range.start as usize..range.end as usize);
+
+        ExeImage { data, image_base: parser.image_base, sections, text }
+    }
+
+    /// Translates a virtual address, as found in pointers and code, into an offset in the executable file.
+    fn va_to_file_offset(&self, va: u32) -> Option<usize> {
+        let rva = va.checked_sub(self.image_base)?;
+
+        self.sections.iter().find_map(|&(virtual_address, raw_size, raw_offset)| {
+            let offset = rva.checked_sub(virtual_address)?;
+            if offset < raw_size {
+                Some(raw_offset as usize + offset as usize)
+            } else {
+                None
+            }
+        })
+    }
+
+    fn read_bytes(&self, va: u32, len: usize) -> Option<&'a [u8]> {
+        let offset = self.va_to_file_offset(va)?;
+        self.data.get(offset..offset.checked_add(len)?)
+    }
+
+    fn read_c_str(&self, va: u32) -> Option<String> {
+        let offset = self.va_to_file_offset(va)?;
+        let bytes = self.data.get(offset..)?;
+        let len = bytes.iter().take(MUSIC_NAME_MAX_LEN + 1).position(|&b| b == 0)?;
+        let name = &bytes[..len];
+
+        if name.is_empty() || !name.iter().all(|b| b.is_ascii_alphanumeric() || *b == b'_') {
+            return None;
+        }
+
+        Some(String::from_utf8_lossy(name).to_ascii_lowercase())
+    }
+}
+
+/// The music table is an array of pointers to the names of the songs, the first one is always the silent `XXXX`.
+fn read_music_table(image: &ExeImage) -> Option<Vec<String>> {
+    let pointers = image.read_bytes(MUSIC_TABLE_VA, MUSIC_TABLE_LEN * 4)?;
+    let names: Vec<String> =
+        pointers.chunks_exact(4).map(|ptr| image.read_c_str(LE::read_u32(ptr))).collect::<Option<_>>()?;
+
+    if names[0] != "xxxx" {
+        return None;
+    }
+
+    Some(names)
+}
+
+fn read_bullet_table(image: &ExeImage) -> Option<Vec<BulletData>> {
+    let data = image.read_bytes(BULLET_TABLE_VA, BULLET_TABLE_LEN * BULLET_ENTRY_SIZE)?;
+
+    // the first entry is never used by the game and is all zeros
+    if data[..BULLET_ENTRY_SIZE].iter().any(|&b| b != 0) {
+        return None;
+    }
+
+    data.chunks_exact(BULLET_ENTRY_SIZE).map(parse_bullet).collect()
+}
+
+/// Parses a `BULLET_TABLE` entry, which unlike the packed one in CS+'s `bullet.tbl` pads the integers.
+fn parse_bullet(entry: &[u8]) -> Option<BulletData> {
+    let int = |idx: usize| LE::read_i32(&entry[4 + idx * 4..]);
+    let narrow = |idx: usize, max: i32| if (0..=max).contains(&int(idx)) { Some(int(idx)) } else { None };
+
+    Some(BulletData {
+        damage: entry[0],
+        life: entry[1],
+        lifetime: narrow(0, u16::MAX as i32)? as u16,
+        flags: BulletFlag(narrow(1, u8::MAX as i32)? as u8),
+        enemy_hit_width: narrow(2, u16::MAX as i32)? as u16,
+        enemy_hit_height: narrow(3, u16::MAX as i32)? as u16,
+        block_hit_width: narrow(4, u16::MAX as i32)? as u16,
+        block_hit_height: narrow(5, u16::MAX as i32)? as u16,
+        display_bounds: Rect {
+            left: narrow(6, u8::MAX as i32)? as u8,
+            top: narrow(7, u8::MAX as i32)? as u8,
+            right: narrow(8, u8::MAX as i32)? as u8,
+            bottom: narrow(9, u8::MAX as i32)? as u8,
+        },
+    })
+}
+
+/// Experience needed for each level of every weapon, the first row belongs to the empty weapon slot.
+fn read_arms_level_table(image: &ExeImage) -> Option<[[u16; 3]; ARMS_LEVEL_TABLE_LEN]> {
+    let data = image.read_bytes(ARMS_LEVEL_TABLE_VA, ARMS_LEVEL_TABLE_LEN * 3 * 4)?;
+    let mut table = [[0u16; 3]; ARMS_LEVEL_TABLE_LEN];
+
+    for (value, bytes) in table.iter_mut().flatten().zip(data.chunks_exact(4)) {
+        *value = u16::try_from(LE::read_i32(bytes)).ok()?;
+    }
+
+    if table[0] != [0, 0, 100] {
+        return None;
+    }
+
+    Some(table)
+}
+
+fn read_physics(image: &ExeImage) -> Option<(PhysicsConsts, PhysicsConsts)> {
+    let code = image.data.get(image.text.clone()?)?;
+
+    find_physics(code)
+}
+
+/// Finds the code setting up the movement constants of the player, which assigns them to locals in the order
+/// `max_dash, max_move, gravity_ground, gravity_air, jump, dash_ground, dash_air, resist`, first for water and
+/// then for air. As hex editors only change the immediate values, the code is found by two such runs of
+/// assignments to the same locals.
+fn find_physics(code: &[u8]) -> Option<(PhysicsConsts, PhysicsConsts)> {
+    let run_size = PHYSICS_VALUE_COUNT * MOV_LOCAL_SIZE;
+    let read_run = |start: usize| -> Option<([u8; PHYSICS_VALUE_COUNT], [i32; PHYSICS_VALUE_COUNT])> {
+        let mut locals = [0u8; PHYSICS_VALUE_COUNT];
+        let mut values = [0i32; PHYSICS_VALUE_COUNT];
+
+        for idx in 0..PHYSICS_VALUE_COUNT {
+            let insn = code.get(start + idx * MOV_LOCAL_SIZE..start + (idx + 1) * MOV_LOCAL_SIZE)?;
+            if insn[0] != MOV_LOCAL_IMM32
+                || insn[1] != MOV_LOCAL_MODRM
+                || !(0..=0xffff).contains(&LE::read_i32(&insn[3..]))
+            {
+                return None;
+            }
+
+            locals[idx] = insn[2];
+            values[idx] = LE::read_i32(&insn[3..]);
+        }
+
+        // all of them are distinct locals on the stack
+        let mut sorted = locals;
+        sorted.sort_unstable();
+        if sorted.windows(2).any(|pair| pair[0] == pair[1]) || locals.iter().any(|&disp| disp < 0x80) {
+            return None;
+        }
+
+        Some((locals, values))
+    };
+
+    let to_physics = |values: [i32; PHYSICS_VALUE_COUNT]| PhysicsConsts {
+        max_dash: values[0],
+        max_move: values[1],
+        gravity_ground: values[2],
+        gravity_air: values[3],
+        jump: values[4],
+        dash_ground: values[5],
+        dash_air: values[6],
+        resist: values[7],
+    };
+
+    let mut found = None;
+    for start in 0..code.len() {
+        let (locals, water) = match read_run(start) {
+            Some(run) => run,
+            None => continue,
+        };
+
+        // skips over the jump to the end of the if statement
+        let second = (start + run_size..start + run_size + 16).find_map(&read_run);
+        if let Some((_, air)) = second.filter(|(air_locals, _)| *air_locals == locals) {
+            if found.is_some() {
+                // ambiguous, better not to guess
+                return None;
+            }
+
+            found = Some((to_physics(water), to_physics(air)));
+        }
+    }
+
+    found
+}
+
+#[test]
+fn test_find_physics() {
+    let mut code = vec![0x90, 0xf6, 0x45, 0x08, 0x01];
+    let locals = [0xe0, 0xdc, 0xd8, 0xd4, 0xd0, 0xcc, 0xc8, 0xc4];
+    let water: [i32; 8] = [0x196, 0x2ff, 0x28, 0x10, 0x280, 0x2a, 0x10, 0x19];
+    let air: [i32; 8] = [0x32c, 0x5ff, 0x50, 0x20, 0x700, 0x55, 0x20, 0x33];
+
+    for (idx, values) in [water, air].iter().enumerate() {
+        for (disp, value) in locals.iter().zip(values.iter()) {
+            code.extend_from_slice(&[MOV_LOCAL_IMM32, MOV_LOCAL_MODRM, *disp]);
+            code.extend_from_slice(&value.to_le_bytes());
+        }
+
+        if idx == 0 {
+            code.extend_from_slice(&[0xeb, 0x38]);
+        }
+    }
+    code.extend_from_slice(&[0x8b, 0x45, 0xfc]);
+
+    let (water_physics, air_physics) = find_physics(&code).unwrap();
+    assert_eq!((water_physics.max_dash, water_physics.resist), (0x196, 0x19));
+    assert_eq!((air_physics.gravity_ground, air_physics.jump), (0x50, 0x700));
+}
+
+/// Maps a single section holding all the tables at `0x48F000` to the start of `data`.
+#[cfg(test)]
+fn test_image(data: &[u8]) -> ExeImage<'_> {
+    ExeImage { data, image_base: 0x400000, sections: vec![(0x8F000, data.len() as u32, 0)], text: None }
+}
+
+#[cfg(test)]
+fn test_offset(va: u32) -> usize {
+    (va - 0x48F000) as usize
+}
+
+#[test]
+fn test_va_to_file_offset() {
+    let data = vec![0u8; 0x300];
+    let sections = vec![(0x1000, 0x200, 0x400), (0x3000, 0x100, 0x200)];
+    let image = ExeImage { data: &data, image_base: 0x400000, sections, text: None };
+
+    assert_eq!(image.va_to_file_offset(0x401000), Some(0x400));
+    assert_eq!(image.va_to_file_offset(0x4011ff), Some(0x5ff));
+    assert_eq!(image.va_to_file_offset(0x403080), Some(0x280));
+    // uninitialized data at the end of a section has no bytes in the file
+    assert_eq!(image.va_to_file_offset(0x401200), None);
+    assert_eq!(image.va_to_file_offset(0x3fffff), None);
+    assert_eq!(image.read_bytes(0x4030f0, 0x10).map(|bytes| bytes.len()), Some(0x10));
+    assert_eq!(image.read_bytes(0x4030f0, 0x20), None);
+}
+
+#[test]
+fn test_read_music_table() {
+    let mut data = vec![0u8; 0xA000];
+    let mut name_va = 0x498400u32;
+    for idx in 0..MUSIC_TABLE_LEN {
+        let name = if idx == 0 { "XXXX".to_owned() } else { format!("Song_{}", idx) };
+        let ptr = test_offset(MUSIC_TABLE_VA) + idx * 4;
+        data[ptr..ptr + 4].copy_from_slice(&name_va.to_le_bytes());
+        let offset = test_offset(name_va);
+        data[offset..offset + name.len()].copy_from_slice(name.as_bytes());
+        name_va += 0x10;
+    }
+
+    let music_table = read_music_table(&test_image(&data)).unwrap();
+    assert_eq!(music_table.len(), MUSIC_TABLE_LEN);
+    assert_eq!(music_table[0], "xxxx");
+    assert_eq!(music_table[41], "song_41");
+
+    // the first song has to be the silent one
+    let offset = test_offset(0x498400);
+    data[offset..offset + 4].copy_from_slice(b"ABCD");
+    assert!(read_music_table(&test_image(&data)).is_none());
+    data[offset..offset + 4].copy_from_slice(b"XXXX");
+
+    // pointers outside of the image
+    let ptr = test_offset(MUSIC_TABLE_VA) + 4;
+    data[ptr..ptr + 4].copy_from_slice(&0x10u32.to_le_bytes());
+    assert!(read_music_table(&test_image(&data)).is_none());
+}
+
+#[test]
+fn test_read_bullet_table() {
+    let mut data = vec![0u8; 0xA000];
+    let entry = test_offset(BULLET_TABLE_VA) + BULLET_ENTRY_SIZE;
+    data[entry] = 4;
+    data[entry + 1] = 1;
+    for (idx, value) in [20i32, 0x24, 4, 4, 2, 2, 16, 16, 16, 16].iter().enumerate() {
+        data[entry + 4 + idx * 4..entry + 8 + idx * 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    let bullet_table = read_bullet_table(&test_image(&data)).unwrap();
+    assert_eq!(bullet_table.len(), BULLET_TABLE_LEN);
+    let bullet = &bullet_table[1];
+    assert_eq!((bullet.damage, bullet.life, bullet.lifetime), (4, 1, 20));
+    assert!(bullet.flags.no_collision_checks() && bullet.flags.check_block_hit());
+    assert_eq!((bullet.enemy_hit_width, bullet.block_hit_height, bullet.display_bounds.left), (4, 2, 16));
+
+    // flags are a single byte in the engine
+    data[entry + 8..entry + 12].copy_from_slice(&0x100i32.to_le_bytes());
+    assert!(read_bullet_table(&test_image(&data)).is_none());
+    data[entry + 8..entry + 12].copy_from_slice(&0x24i32.to_le_bytes());
+
+    data[test_offset(BULLET_TABLE_VA)] = 1;
+    assert!(read_bullet_table(&test_image(&data)).is_none());
+}
+
+#[test]
+fn test_read_arms_level_table() {
+    let mut data = vec![0u8; 0xA000];
+    let table = test_offset(ARMS_LEVEL_TABLE_VA);
+    for (idx, value) in [0i32, 0, 100, 10, 20, 30].iter().enumerate() {
+        data[table + idx * 4..table + idx * 4 + 4].copy_from_slice(&value.to_le_bytes());
+    }
+
+    let level_table = read_arms_level_table(&test_image(&data)).unwrap();
+    assert_eq!(level_table[0], [0, 0, 100]);
+    assert_eq!(level_table[1], [10, 20, 30]);
+
+    data[table + 12..table + 16].copy_from_slice(&(-1i32).to_le_bytes());
+    assert!(read_arms_level_table(&test_image(&data)).is_none());
+    data[table + 12..table + 16].copy_from_slice(&70000i32.to_le_bytes());
+    assert!(read_arms_level_table(&test_image(&data)).is_none());
+}
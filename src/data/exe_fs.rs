@@ -0,0 +1,197 @@
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::io::{Cursor, ErrorKind, SeekFrom};
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
+use std::{fmt, io};
+
+use crate::framework::error::GameError::FilesystemError;
+use crate::framework::error::GameResult;
+use crate::framework::vfs::{OpenOptions, VFile, VMetadata, VFS};
+
+#[derive(Debug)]
+pub struct ExeFile(Cursor<Arc<[u8]>>);
+
+impl io::Read for ExeFile {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl io::Seek for ExeFile {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.0.seek(pos)
+    }
+}
+
+impl io::Write for ExeFile {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(ErrorKind::PermissionDenied, "Executable file system is read-only."))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Err(io::Error::new(ErrorKind::PermissionDenied, "Executable file system is read-only."))
+    }
+}
+
+struct ExeMetadata {
+    is_dir: bool,
+    size: u64,
+}
+
+impl VMetadata for ExeMetadata {
+    fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+
+    fn is_file(&self) -> bool {
+        !self.is_dir
+    }
+
+    fn len(&self) -> u64 {
+        self.size
+    }
+}
+
+/// Read-only file system serving the resources embedded in a freeware `Doukutsu.exe`, see `VanillaExtractor`.
+///
+/// Lookups are case-insensitive, like on Windows which the original game was made for.
+pub struct ExeFS {
+    /// Files keyed by their lowercase path without the leading slash, with the path in original case.
+    files: HashMap<String, (String, Arc<[u8]>)>,
+}
+
+impl ExeFS {
+    pub fn new(files: Vec<(String, Vec<u8>)>) -> ExeFS {
+        ExeFS {
+            files: files.into_iter().map(|(path, data)| (path.to_ascii_lowercase(), (path, data.into()))).collect(),
+        }
+    }
+
+    fn normalize(path: &Path) -> GameResult<String> {
+        let mut components = path.components();
+        if components.next() != Some(Component::RootDir) {
+            return Err(FilesystemError("Path must be absolute.".to_string()));
+        }
+
+        let mut parts = Vec::new();
+        for component in components {
+            match component {
+                Component::Normal(name) => parts.push(name.to_string_lossy().to_ascii_lowercase()),
+                Component::CurDir => {}
+                _ => return Err(FilesystemError(format!("Invalid path: {:?}", path))),
+            }
+        }
+
+        Ok(parts.join("/"))
+    }
+
+    fn is_dir(&self, key: &str) -> bool {
+        key.is_empty() || self.files.keys().any(|path| path.starts_with(key) && path[key.len()..].starts_with('/'))
+    }
+}
+
+impl Debug for ExeFS {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        write!(f, "<ExeFS>")
+    }
+}
+
+impl VFS for ExeFS {
+    fn open_options(&self, path: &Path, open_options: OpenOptions) -> GameResult<Box<dyn VFile>> {
+        if open_options.write || open_options.create || open_options.append || open_options.truncate {
+            let msg = format!("Cannot alter file {:?} in root {:?}, filesystem read-only", path, self);
+            return Err(FilesystemError(msg));
+        }
+
+        match self.files.get(&ExeFS::normalize(path)?) {
+            Some((_, data)) => Ok(Box::new(ExeFile(Cursor::new(data.clone())))),
+            None => Err(FilesystemError("File not found.".to_string())),
+        }
+    }
+
+    fn mkdir(&self, _path: &Path) -> GameResult<()> {
+        Err(FilesystemError("Tried to make directory {} but FS is read-only".to_string()))
+    }
+
+    fn rm(&self, _path: &Path) -> GameResult<()> {
+        Err(FilesystemError("Tried to remove file {} but FS is read-only".to_string()))
+    }
+
+    fn rmrf(&self, _path: &Path) -> GameResult<()> {
+        Err(FilesystemError("Tried to remove file/dir {} but FS is read-only".to_string()))
+    }
+
+    fn exists(&self, path: &Path) -> bool {
+        match ExeFS::normalize(path) {
+            Ok(key) => self.files.contains_key(&key) || self.is_dir(&key),
+            Err(_) => false,
+        }
+    }
+
+    fn metadata(&self, path: &Path) -> GameResult<Box<dyn VMetadata>> {
+        let key = ExeFS::normalize(path)?;
+
+        match self.files.get(&key) {
+            Some((_, data)) => Ok(Box::new(ExeMetadata { is_dir: false, size: data.len() as u64 })),
+            None if self.is_dir(&key) => Ok(Box::new(ExeMetadata { is_dir: true, size: 0 })),
+            None => Err(FilesystemError("File not found.".to_string())),
+        }
+    }
+
+    fn read_dir(&self, path: &Path) -> GameResult<Box<dyn Iterator<Item = GameResult<PathBuf>>>> {
+        let key = ExeFS::normalize(path)?;
+        if !self.is_dir(&key) {
+            return Err(FilesystemError(format!("Expected a directory: {:?}", path)));
+        }
+
+        let prefix_len = if key.is_empty() { 0 } else { key.len() + 1 };
+        let mut entries: Vec<String> = Vec::new();
+        for (lowercase, (path, _)) in self.files.iter() {
+            if prefix_len > 0 && !(lowercase.starts_with(&key) && lowercase[key.len()..].starts_with('/')) {
+                continue;
+            }
+
+            let name = path[prefix_len..].split('/').next().unwrap_or_default();
+            if !entries.iter().any(|entry| entry.eq_ignore_ascii_case(name)) {
+                entries.push(name.to_owned());
+            }
+        }
+
+        let path = path.to_path_buf();
+        Ok(Box::new(entries.into_iter().map(move |name| Ok(path.join(name)))))
+    }
+
+    fn to_path_buf(&self) -> Option<PathBuf> {
+        None
+    }
+}
+
+#[test]
+fn test_exe_fs() {
+    use std::io::Read;
+
+    let fs = ExeFS::new(vec![
+        ("Org/ACCESS.org".to_owned(), b"Org-02".to_vec()),
+        ("Org/WANPAKU.org".to_owned(), Vec::new()),
+        ("PIXEL.pbm".to_owned(), b"BM".to_vec()),
+    ]);
+
+    let mut data = Vec::new();
+    fs.open(Path::new("/org/access.org")).unwrap().read_to_end(&mut data).unwrap();
+    assert_eq!(data, b"Org-02");
+
+    assert!(fs.exists(Path::new("/ORG")));
+    assert!(fs.metadata(Path::new("/Org/")).unwrap().is_dir());
+    assert!(!fs.exists(Path::new("/Or")));
+    assert!(fs.create(Path::new("/Org/ACCESS.org")).is_err());
+
+    let mut entries: Vec<PathBuf> = fs.read_dir(Path::new("/")).unwrap().map(Result::unwrap).collect();
+    entries.sort();
+    assert_eq!(entries, vec![PathBuf::from("/Org"), PathBuf::from("/PIXEL.pbm")]);
+
+    let mut entries: Vec<PathBuf> = fs.read_dir(Path::new("/org")).unwrap().map(Result::unwrap).collect();
+    entries.sort();
+    assert_eq!(entries, vec![PathBuf::from("/org/ACCESS.org"), PathBuf::from("/org/WANPAKU.org")]);
+    assert!(fs.exists(&entries[0]));
+}
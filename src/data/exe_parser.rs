@@ -35,6 +35,7 @@ impl ExeResourceDirectory {
 pub struct ExeParser<'a> {
     pub resources: Resources<'a>,
     pub section_headers: Box<&'a SectionHeaders>,
+    pub image_base: u32,
 }
 
 impl<'a> ExeParser<'a> {
@@ -50,8 +51,9 @@ impl<'a> ExeParser<'a> {
                 }
 
                 let section_headers = pe.section_headers();
+                let image_base = pe.optional_header().ImageBase;
 
-                Ok(Self { resources: resources.unwrap(), section_headers: Box::new(section_headers) })
+                Ok(Self { resources: resources.unwrap(), section_headers: Box::new(section_headers), image_base })
             }
             Err(_) => Err(ParseError("Failed to parse PE file".to_string())),
         };
@@ -1,3 +1,5 @@
 pub mod builtin_fs;
+pub mod exe_constants;
+pub mod exe_fs;
 pub mod exe_parser;
 pub mod vanilla;
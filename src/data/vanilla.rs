@@ -1,12 +1,9 @@
-use std::{
-    env,
-    io::{Read, Write},
-    ops::Range,
-    path::PathBuf,
-};
+use std::{env, io::Read, ops::Range};
 
-use byteorder::{LE, WriteBytesExt};
+use byteorder::{WriteBytesExt, LE};
 
+use crate::data::exe_constants::extract_constant_overrides;
+use crate::data::exe_fs::ExeFS;
 use crate::data::exe_parser::ExeParser;
 use crate::framework::{
     context::Context,
@@ -16,7 +13,6 @@ use crate::framework::{
 
 pub struct VanillaExtractor {
     exe_buffer: Vec<u8>,
-    data_base_dir: String,
 }
 
 const VANILLA_STAGE_COUNT: u32 = 95;
@@ -25,7 +21,7 @@ const VANILLA_STAGE_OFFSET: u32 = 0x937B0;
 const VANILLA_STAGE_TABLE_SIZE: u32 = VANILLA_STAGE_COUNT * VANILLA_STAGE_ENTRY_SIZE;
 
 impl VanillaExtractor {
-    pub fn from(ctx: &mut Context, exe_name: String, data_base_dir: String) -> Option<Self> {
+    pub fn from(ctx: &mut Context, exe_name: String) -> Option<Self> {
         let mut vanilla_exe_path = env::current_exe().unwrap();
         vanilla_exe_path.pop();
         vanilla_exe_path.push(exe_name);
@@ -34,10 +30,10 @@ impl VanillaExtractor {
             return None;
         }
 
-        log::info!("Found vanilla game executable, attempting to extract resources.");
+        log::info!("Found vanilla game executable, attempting to load resources.");
 
-        if filesystem::exists(ctx, format!("{}/stage.sect", data_base_dir.clone())) {
-            log::info!("Vanilla resources are already extracted, not proceeding.");
+        if filesystem::exists(ctx, "/stage.sect") {
+            log::info!("Vanilla resources are already present in the data directory, not proceeding.");
             return None;
         }
 
@@ -54,38 +50,29 @@ impl VanillaExtractor {
             return None;
         }
 
-        Some(Self { exe_buffer, data_base_dir })
+        Some(Self { exe_buffer })
     }
 
-    pub fn extract_data(&self) -> GameResult {
+    /// Creates a read-only file system serving the resources of the executable, so nothing has to be written to
+    /// the data directory.
+    pub fn into_vfs(self) -> GameResult<ExeFS> {
         let parser = ExeParser::from(&self.exe_buffer);
         if parser.is_err() {
             return Err(ParseError("Failed to create vanilla parser.".to_string()));
         }
 
         let parser = parser.unwrap();
+        let mut files = Vec::new();
 
-        self.extract_organya(&parser)?;
-        self.extract_bitmaps(&parser)?;
-        self.extract_stage_table(&parser)?;
-
-        Ok(())
-    }
-
-    fn deep_create_dir_if_not_exists(&self, path: PathBuf) -> GameResult {
-        if path.is_dir() {
-            return Ok(());
-        }
-
-        let result = std::fs::create_dir_all(path);
-        if result.is_err() {
-            return Err(ParseError(format!("Failed to create directory structure: {}", result.unwrap_err())));
-        }
+        self.extract_organya(&parser, &mut files)?;
+        self.extract_bitmaps(&parser, &mut files)?;
+        self.extract_stage_table(&parser, &mut files)?;
+        self.extract_constants(&parser, &mut files)?;
 
-        Ok(())
+        Ok(ExeFS::new(files))
     }
 
-    fn extract_organya(&self, parser: &ExeParser) -> GameResult {
+    fn extract_organya(&self, parser: &ExeParser, files: &mut Vec<(String, Vec<u8>)>) -> GameResult {
         let orgs = parser.get_resource_dir("ORG".to_string());
 
         if orgs.is_err() {
@@ -93,36 +80,14 @@ impl VanillaExtractor {
         }
 
         for org in orgs.unwrap().data_files {
-            let mut org_path = env::current_exe().unwrap();
-            org_path.pop();
-            org_path.push(self.data_base_dir.clone());
-            org_path.push("Org/");
-
-            if self.deep_create_dir_if_not_exists(org_path.clone()).is_err() {
-                return Err(ParseError("Failed to create directory structure.".to_string()));
-            }
-
-            org_path.push(format!("{}.org", org.name));
-
-            let mut org_file = match std::fs::File::create(org_path) {
-                Ok(file) => file,
-                Err(_) => {
-                    return Err(ParseError("Failed to create organya file.".to_string()));
-                }
-            };
-
-            let result = org_file.write_all(&org.bytes);
-            if result.is_err() {
-                return Err(ParseError("Failed to write organya file.".to_string()));
-            }
-
-            log::info!("Extracted organya file: {}", org.name);
+            log::info!("Loaded organya file: {}", org.name);
+            files.push((format!("Org/{}.org", org.name), org.bytes));
         }
 
         Ok(())
     }
 
-    fn extract_bitmaps(&self, parser: &ExeParser) -> GameResult {
+    fn extract_bitmaps(&self, parser: &ExeParser, files: &mut Vec<(String, Vec<u8>)>) -> GameResult {
         let bitmaps = parser.get_bitmap_dir();
 
         if bitmaps.is_err() {
@@ -130,41 +95,23 @@ impl VanillaExtractor {
         }
 
         for bitmap in bitmaps.unwrap().data_files {
-            let mut data_path = env::current_exe().unwrap();
-            data_path.pop();
-            data_path.push(self.data_base_dir.clone());
-
-            if self.deep_create_dir_if_not_exists(data_path.clone()).is_err() {
-                return Err(ParseError("Failed to create data directory structure.".to_string()));
-            }
+            let mut data = Vec::with_capacity(bitmap.bytes.len() + 0xE);
 
-            data_path.push(format!("{}.pbm", bitmap.name));
+            data.write_u8(0x42)?; // B
+            data.write_u8(0x4D)?; // M
+            data.write_u32::<LE>(bitmap.bytes.len() as u32 + 0xE)?; // Size of BMP file
+            data.write_u32::<LE>(0)?; // unused null bytes
+            data.write_u32::<LE>(0x76)?; // Bitmap data offset (hardcoded for now, might wanna get the actual offset)
+            data.extend_from_slice(&bitmap.bytes);
 
-            let file = std::fs::File::create(data_path);
-            if file.is_err() {
-                return Err(ParseError("Failed to create bitmap file.".to_string()));
-            }
-
-            let mut file = file.unwrap();
-
-            file.write_u8(0x42)?; // B
-            file.write_u8(0x4D)?; // M
-            file.write_u32::<LE>(bitmap.bytes.len() as u32 + 0xE)?; // Size of BMP file
-            file.write_u32::<LE>(0)?; // unused null bytes
-            file.write_u32::<LE>(0x76)?; // Bitmap data offset (hardcoded for now, might wanna get the actual offset)
-
-            let result = file.write_all(&bitmap.bytes);
-            if result.is_err() {
-                return Err(ParseError("Failed to write bitmap file.".to_string()));
-            }
-
-            log::info!("Extracted bitmap file: {}", bitmap.name);
+            log::info!("Loaded bitmap file: {}", bitmap.name);
+            files.push((format!("{}.pbm", bitmap.name), data));
         }
 
         Ok(())
     }
 
-    fn extract_stage_table(&self, parser: &ExeParser) -> GameResult {
+    fn extract_stage_table(&self, parser: &ExeParser, files: &mut Vec<(String, Vec<u8>)>) -> GameResult {
         let range = parser.get_named_section_byte_range(".csmap".to_string());
         if range.is_err() {
             return Err(ParseError("Failed to retrieve stage table from executable.".to_string()));
@@ -175,32 +122,30 @@ impl VanillaExtractor {
             None => Range { start: VANILLA_STAGE_OFFSET, end: VANILLA_STAGE_OFFSET + VANILLA_STAGE_TABLE_SIZE },
         };
 
-        let start = range.start as usize;
-        let end = range.end as usize;
+        let byte_slice = match self.exe_buffer.get(range.start as usize..range.end as usize) {
+            Some(byte_slice) => byte_slice,
+            None => return Err(ParseError("Stage table is out of the executable's bounds.".to_string())),
+        };
 
-        let byte_slice = &self.exe_buffer[start..end];
+        files.push(("stage.sect".to_string(), byte_slice.to_vec()));
 
-        let mut stage_tbl_path = env::current_exe().unwrap();
-        stage_tbl_path.pop();
-        stage_tbl_path.push(self.data_base_dir.clone());
+        Ok(())
+    }
 
-        if self.deep_create_dir_if_not_exists(stage_tbl_path.clone()).is_err() {
-            return Err(ParseError("Failed to create data directory structure.".to_string()));
+    /// Provides values hex-edited into the executable as `constants.json`, which is applied like the ones from mods.
+    fn extract_constants(&self, parser: &ExeParser, files: &mut Vec<(String, Vec<u8>)>) -> GameResult {
+        let overrides = extract_constant_overrides(&self.exe_buffer, parser)?;
+        if overrides.as_object().is_some_and(|overrides| overrides.is_empty()) {
+            return Ok(());
         }
 
-        stage_tbl_path.push("stage.sect");
-
-        let mut stage_tbl_file = match std::fs::File::create(stage_tbl_path) {
-            Ok(file) => file,
-            Err(_) => {
-                return Err(ParseError("Failed to create stage table file.".to_string()));
-            }
+        let data = match serde_json::to_vec_pretty(&overrides) {
+            Ok(data) => data,
+            Err(_) => return Err(ParseError("Failed to serialize constants file.".to_string())),
         };
 
-        let result = stage_tbl_file.write_all(byte_slice);
-        if result.is_err() {
-            return Err(ParseError("Failed to write to stage table file.".to_string()));
-        }
+        log::info!("Loaded modified constants from the executable.");
+        files.push(("constants.json".to_string(), data));
 
         Ok(())
     }
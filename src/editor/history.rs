@@ -0,0 +1,248 @@
+use crate::game::map::NPCData;
+use crate::game::stage::Stage;
+
+/// Maximum number of undoable actions kept per editor instance.
+const MAX_HISTORY: usize = 256;
+
+#[derive(Clone, Copy)]
+pub struct TileChange {
+    pub index: usize,
+    pub old: u8,
+    pub new: u8,
+}
+
+/// A reversible edit of a stage.
+pub enum EditorCommand {
+    /// Foreground tile changes made by the brush, fill or rectangle tools.
+    Tiles(Vec<TileChange>),
+    /// Map size change, stores the whole tile data since resizing shifts every row.
+    Resize { old_size: (u16, u16), old_tiles: Vec<u8>, new_size: (u16, u16), new_tiles: Vec<u8> },
+    /// Any change to the entity list, stored as before and after copies.
+    Entities { old: Vec<NPCData>, new: Vec<NPCData> },
+}
+
+impl EditorCommand {
+    fn apply(&self, stage: &mut Stage, npcs: &mut Vec<NPCData>) {
+        match self {
+            EditorCommand::Tiles(changes) => {
+                for change in changes.iter() {
+                    if let Some(tile) = stage.map.tiles.get_mut(change.index) {
+                        *tile = change.new;
+                    }
+                }
+            }
+            EditorCommand::Resize { new_size, new_tiles, .. } => {
+                stage.map.width = new_size.0;
+                stage.map.height = new_size.1;
+                stage.map.tiles = new_tiles.clone();
+            }
+            EditorCommand::Entities { new, .. } => {
+                *npcs = new.clone();
+            }
+        }
+    }
+
+    fn revert(&self, stage: &mut Stage, npcs: &mut Vec<NPCData>) {
+        match self {
+            EditorCommand::Tiles(changes) => {
+                for change in changes.iter().rev() {
+                    if let Some(tile) = stage.map.tiles.get_mut(change.index) {
+                        *tile = change.old;
+                    }
+                }
+            }
+            EditorCommand::Resize { old_size, old_tiles, .. } => {
+                stage.map.width = old_size.0;
+                stage.map.height = old_size.1;
+                stage.map.tiles = old_tiles.clone();
+            }
+            EditorCommand::Entities { old, .. } => {
+                *npcs = old.clone();
+            }
+        }
+    }
+
+    /// Extends this command with a following one of the same kind, returns false if they can't be merged.
+    fn merge(&mut self, other: &EditorCommand) -> bool {
+        match (self, other) {
+            (EditorCommand::Tiles(changes), EditorCommand::Tiles(other_changes)) => {
+                for change in other_changes.iter() {
+                    // keep the original value of tiles painted over multiple times
+                    match changes.iter_mut().find(|c| c.index == change.index) {
+                        Some(existing) => existing.new = change.new,
+                        None => changes.push(*change),
+                    }
+                }
+
+                true
+            }
+            (EditorCommand::Entities { new, .. }, EditorCommand::Entities { new: other_new, .. }) => {
+                *new = other_new.clone();
+
+                true
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Undo/redo stack of an editor instance.
+pub struct History {
+    undo_stack: Vec<EditorCommand>,
+    redo_stack: Vec<EditorCommand>,
+    /// Whether the last command is still being extended, eg. by an ongoing brush stroke.
+    open: bool,
+}
+
+impl History {
+    pub fn new() -> History {
+        History { undo_stack: Vec::new(), redo_stack: Vec::new(), open: false }
+    }
+
+    /// Records an already applied command. While the history is open it's merged into the last command if both are
+    /// of the same kind, older commands are never extended.
+    pub fn push(&mut self, command: EditorCommand) {
+        self.redo_stack.clear();
+
+        if self.open {
+            if let Some(last) = self.undo_stack.last_mut() {
+                if last.merge(&command) {
+                    return;
+                }
+            }
+        }
+
+        if self.undo_stack.len() >= MAX_HISTORY {
+            self.undo_stack.remove(0);
+        }
+
+        self.undo_stack.push(command);
+        self.open = true;
+    }
+
+    /// Ends the current action, the next command will be undoable separately.
+    pub fn close(&mut self) {
+        self.open = false;
+    }
+
+    pub fn can_undo(&self) -> bool {
+        !self.undo_stack.is_empty()
+    }
+
+    pub fn can_redo(&self) -> bool {
+        !self.redo_stack.is_empty()
+    }
+
+    pub fn undo(&mut self, stage: &mut Stage, npcs: &mut Vec<NPCData>) -> bool {
+        self.open = false;
+
+        if let Some(command) = self.undo_stack.pop() {
+            command.revert(stage, npcs);
+            self.redo_stack.push(command);
+            return true;
+        }
+
+        false
+    }
+
+    pub fn redo(&mut self, stage: &mut Stage, npcs: &mut Vec<NPCData>) -> bool {
+        self.open = false;
+
+        if let Some(command) = self.redo_stack.pop() {
+            command.apply(stage, npcs);
+            self.undo_stack.push(command);
+            return true;
+        }
+
+        false
+    }
+}
+
+#[cfg(test)]
+fn test_stage(tiles: Vec<u8>) -> Stage {
+    use crate::common::Color;
+    use crate::game::map::Map;
+    use crate::game::shared_game_state::TileSize;
+    use crate::game::stage::{Background, BackgroundType, NpcType, StageData, Tileset};
+
+    Stage {
+        map: Map { width: tiles.len() as u16, height: 1, tiles, attrib: [0; 0x100], tile_size: TileSize::Tile16x16 },
+        data: StageData {
+            name: String::new(),
+            name_jp: String::new(),
+            map: String::new(),
+            boss_no: 0,
+            tileset: Tileset { name: "0".to_string() },
+            pxpack_data: None,
+            background: Background::new("0"),
+            background_type: BackgroundType::Black,
+            background_type_id: BackgroundType::Black as u8,
+            background_color: Color { r: 0.0, g: 0.0, b: 0.0, a: 0.0 },
+            npc1: NpcType::new("0"),
+            npc2: NpcType::new("0"),
+        },
+    }
+}
+
+#[cfg(test)]
+fn test_paint(stage: &mut Stage, history: &mut History, index: usize, new: u8) {
+    let old = stage.map.tiles[index];
+    stage.map.tiles[index] = new;
+    history.push(EditorCommand::Tiles(vec![TileChange { index, old, new }]));
+}
+
+#[test]
+fn test_undo_redo() {
+    let mut stage = test_stage(vec![0; 4]);
+    let mut npcs = Vec::new();
+    let mut history = History::new();
+
+    // a single stroke painting over the same tile twice
+    test_paint(&mut stage, &mut history, 0, 1);
+    test_paint(&mut stage, &mut history, 1, 2);
+    test_paint(&mut stage, &mut history, 0, 3);
+    history.close();
+    test_paint(&mut stage, &mut history, 2, 4);
+    history.close();
+    assert_eq!(stage.map.tiles, [3, 2, 4, 0]);
+
+    assert!(history.undo(&mut stage, &mut npcs));
+    assert_eq!(stage.map.tiles, [3, 2, 0, 0]);
+    assert!(history.undo(&mut stage, &mut npcs));
+    assert_eq!(stage.map.tiles, [0, 0, 0, 0]);
+    assert!(!history.undo(&mut stage, &mut npcs));
+
+    assert!(history.redo(&mut stage, &mut npcs));
+    assert_eq!(stage.map.tiles, [3, 2, 0, 0]);
+
+    // a new command drops the undone ones
+    test_paint(&mut stage, &mut history, 3, 5);
+    assert!(!history.can_redo());
+    assert!(history.undo(&mut stage, &mut npcs));
+    assert!(history.undo(&mut stage, &mut npcs));
+    assert_eq!(stage.map.tiles, [0, 0, 0, 0]);
+}
+
+#[test]
+fn test_merge_into_top_only() {
+    let mut stage = test_stage(vec![0; 4]);
+    let mut npcs = Vec::new();
+    let mut history = History::new();
+
+    let npc = NPCData { id: 0, x: 1, y: 2, flag_num: 0, event_num: 0, npc_type: 3, flags: 0, layer: 0 };
+
+    test_paint(&mut stage, &mut history, 0, 1);
+    npcs.push(npc.clone());
+    history.push(EditorCommand::Entities { old: Vec::new(), new: npcs.clone() });
+    test_paint(&mut stage, &mut history, 1, 2);
+    history.close();
+
+    // the last stroke didn't get merged into the first one, past the entity change
+    assert!(history.undo(&mut stage, &mut npcs));
+    assert_eq!((stage.map.tiles.as_slice(), npcs.as_slice()), (&[1, 0, 0, 0][..], &[npc][..]));
+    assert!(history.undo(&mut stage, &mut npcs));
+    assert_eq!((stage.map.tiles.as_slice(), npcs.len()), (&[1, 0, 0, 0][..], 0));
+    assert!(history.undo(&mut stage, &mut npcs));
+    assert_eq!(stage.map.tiles, [0, 0, 0, 0]);
+    assert!(!history.can_undo());
+}
@@ -1,15 +1,23 @@
 use std::cell::RefCell;
+use std::io::Read;
 use std::ops::Deref;
 use std::rc::Rc;
 
-use imgui::{Image, MouseButton, Window, WindowFlags};
+use imgui::{Image, Key, MouseButton, Window, WindowFlags};
 
 use crate::{Context, GameResult, graphics, I_MAG, SharedGameState};
 use crate::common::{Color, Rect};
 use crate::components::background::Background;
 use crate::components::tilemap::{TileLayer, Tilemap};
+use crate::editor::history::{EditorCommand, History, TileChange};
+use crate::framework::error::GameError;
+use crate::framework::filesystem;
 use crate::game::frame::Frame;
-use crate::game::stage::{Stage, StageTexturePaths};
+use crate::game::map::NPCData;
+use crate::game::stage::{Stage, StageData, StageTexturePaths};
+
+pub mod history;
+pub mod stage_table;
 
 #[derive(Copy, Clone, Eq, PartialEq)]
 pub enum CurrentTool {
@@ -17,11 +25,37 @@ pub enum CurrentTool {
     Brush,
     Fill,
     Rectangle,
+    Entity,
 }
 
+/// PXE entity flags, matching [crate::game::npc::NPCFlag].
+static ENTITY_FLAGS: [(u16, &str); 16] = [
+    (0x0001, "Solid (soft)"),
+    (0x0002, "Ignore tile 44"),
+    (0x0004, "Invulnerable"),
+    (0x0008, "Ignore solidity"),
+    (0x0010, "Bouncy"),
+    (0x0020, "Shootable"),
+    (0x0040, "Solid (hard)"),
+    (0x0080, "Rear and top don't hurt"),
+    (0x0100, "Event when touched"),
+    (0x0200, "Event when killed"),
+    (0x0400, "Flag 0x400"),
+    (0x0800, "Appear when flag set"),
+    (0x1000, "Spawn facing right"),
+    (0x2000, "Interactable"),
+    (0x4000, "Hide when flag set"),
+    (0x8000, "Show damage"),
+];
+
+const FLAG_SPAWN_FACING_RIGHT: u16 = 0x1000;
+
 pub struct EditorInstance {
     pub stage: Stage,
     pub stage_id: usize,
+    pub npcs: Vec<NPCData>,
+    /// PXE format version the entity list was loaded from, kept so the file is saved back in the same format.
+    pub pxe_version: u8,
     pub frame: Frame,
     pub background: Background,
     pub stage_textures: Rc<RefCell<StageTexturePaths>>,
@@ -30,10 +64,29 @@ pub struct EditorInstance {
     pub current_tile: u8,
     pub mouse_pos: (f32, f32),
     pub want_capture_mouse: bool,
+    /// First sprite rect of every NPC type, see [crate::engine_constants::npcs::NPCConsts::preview_rects].
+    pub npc_preview_rects: Rc<Vec<Option<Rect<u16>>>>,
+    pub selected_entity: Option<usize>,
+    /// Last tile clicked with the entity tool, new entities are placed there.
+    pub entity_cursor: (i16, i16),
+    pub new_entity_type: u16,
+    pub history: History,
+    /// Entity list from before the ongoing entity edit, taken once the edit changes something.
+    entity_edit: Option<Vec<NPCData>>,
+    dragging_entity: bool,
+    /// Tile where the rectangle tool drag started.
+    rect_start: Option<(i32, i32)>,
+    resize_window: Option<(i32, i32)>,
 }
 
 impl EditorInstance {
-    pub fn new(stage_id: usize, stage: Stage) -> EditorInstance {
+    pub fn new(
+        stage_id: usize,
+        stage: Stage,
+        pxe_version: u8,
+        npcs: Vec<NPCData>,
+        npc_preview_rects: Rc<Vec<Option<Rect<u16>>>>,
+    ) -> EditorInstance {
         let stage_textures = {
             let mut textures = StageTexturePaths::new();
             textures.update(&stage);
@@ -46,6 +99,8 @@ impl EditorInstance {
         EditorInstance {
             stage,
             stage_id,
+            npcs,
+            pxe_version,
             frame,
             background: Background::new(),
             stage_textures,
@@ -54,6 +109,15 @@ impl EditorInstance {
             current_tile: 0,
             mouse_pos: (0.0, 0.0),
             want_capture_mouse: true,
+            npc_preview_rects,
+            selected_entity: None,
+            entity_cursor: (0, 0),
+            new_entity_type: 1,
+            history: History::new(),
+            entity_edit: None,
+            dragging_entity: false,
+            rect_start: None,
+            resize_window: None,
         }
     }
 
@@ -65,6 +129,14 @@ impl EditorInstance {
 
         let mut drag = false;
 
+        // ends brush strokes, entity drags and inspector edits
+        if !ui.is_mouse_down(MouseButton::Left) && !ui.is_any_item_active() {
+            self.finish_entity_edit();
+            self.history.close();
+        }
+
+        self.resize_map_window(ui);
+
         match tool {
             CurrentTool::Move => {
                 if ui.io().want_capture_mouse {
@@ -83,29 +155,54 @@ impl EditorInstance {
                 drag |= ui.is_mouse_down(MouseButton::Right);
 
                 if !drag && ui.is_mouse_down(MouseButton::Left) {
-                    let tile_size = self.stage.map.tile_size.as_int();
-                    let halft = tile_size / 2;
-                    let stage_mouse_x = (self.frame.x / 0x200) + halft + (self.mouse_pos.0 / self.zoom) as i32;
-                    let stage_mouse_y = (self.frame.y / 0x200) + halft + (self.mouse_pos.1 / self.zoom) as i32;
-                    let tile_x = stage_mouse_x / tile_size;
-                    let tile_y = stage_mouse_y / tile_size;
-
-                    if tile_x >= 0
-                        && tile_y >= 0
-                        && tile_x < self.stage.map.width as i32
-                        && tile_y < self.stage.map.height as i32
-                    {
-                        self.stage.change_tile(tile_x as usize, tile_y as usize, self.current_tile);
-                    }
+                    let tile = self.mouse_tile();
+                    self.set_tiles(&[tile], self.current_tile);
                 }
             }
             CurrentTool::Fill => {
                 self.palette_window(state, ctx, ui);
+
+                if ui.io().want_capture_mouse {
+                    return;
+                }
+
                 drag |= ui.is_mouse_down(MouseButton::Right);
+
+                if !drag && ui.is_mouse_clicked(MouseButton::Left) {
+                    let (tile_x, tile_y) = self.mouse_tile();
+                    let area = self.fill_area(tile_x, tile_y);
+                    self.set_tiles(&area, self.current_tile);
+                    self.history.close();
+                }
             }
             CurrentTool::Rectangle => {
                 self.palette_window(state, ctx, ui);
+
+                if !ui.is_mouse_down(MouseButton::Left) {
+                    if let Some(start) = self.rect_start.take() {
+                        let area = self.rect_area(start, self.mouse_tile());
+                        self.set_tiles(&area, self.current_tile);
+                        self.history.close();
+                    }
+                }
+
+                if ui.io().want_capture_mouse {
+                    return;
+                }
+
                 drag |= ui.is_mouse_down(MouseButton::Right);
+
+                if !drag && ui.is_mouse_clicked(MouseButton::Left) {
+                    self.rect_start = Some(self.mouse_tile());
+                }
+            }
+            CurrentTool::Entity => {
+                self.entity_inspector_window(ui);
+
+                if !ui.io().want_capture_mouse {
+                    drag |= ui.is_mouse_down(MouseButton::Right);
+                    self.process_entity_tool(ui);
+                }
             }
         }
 
@@ -117,6 +214,362 @@ impl EditorInstance {
         }
     }
 
+    /// Returns the tile under the mouse cursor, which may be outside of the map.
+    fn mouse_tile(&self) -> (i32, i32) {
+        let tile_size = self.stage.map.tile_size.as_int();
+        let halft = tile_size / 2;
+        let stage_mouse_x = (self.frame.x / 0x200) + halft + (self.mouse_pos.0 / self.zoom) as i32;
+        let stage_mouse_y = (self.frame.y / 0x200) + halft + (self.mouse_pos.1 / self.zoom) as i32;
+
+        (stage_mouse_x.div_euclid(tile_size), stage_mouse_y.div_euclid(tile_size))
+    }
+
+    /// Paints foreground tiles and records the change in the history, positions outside of the map are skipped.
+    fn set_tiles(&mut self, positions: &[(i32, i32)], tile: u8) {
+        let width = self.stage.map.width as i32;
+        let height = self.stage.map.height as i32;
+        let mut changes = Vec::new();
+
+        for &(x, y) in positions {
+            if x < 0 || y < 0 || x >= width || y >= height {
+                continue;
+            }
+
+            let old = self.stage.tile_at(x as usize, y as usize);
+            if self.stage.change_tile(x as usize, y as usize, tile) {
+                changes.push(TileChange { index: (y * width + x) as usize, old, new: tile });
+            }
+        }
+
+        if !changes.is_empty() {
+            self.history.push(EditorCommand::Tiles(changes));
+        }
+    }
+
+    /// Returns the area of same tiles connected to given position, used by the fill tool.
+    fn fill_area(&self, x: i32, y: i32) -> Vec<(i32, i32)> {
+        let width = self.stage.map.width as i32;
+        let height = self.stage.map.height as i32;
+
+        if x < 0 || y < 0 || x >= width || y >= height {
+            return Vec::new();
+        }
+
+        let target = self.stage.tile_at(x as usize, y as usize);
+        let mut visited = vec![false; (width * height) as usize];
+        let mut queue = vec![(x, y)];
+        let mut area = Vec::new();
+
+        while let Some((x, y)) = queue.pop() {
+            if x < 0 || y < 0 || x >= width || y >= height {
+                continue;
+            }
+
+            let index = (y * width + x) as usize;
+            if visited[index] || self.stage.tile_at(x as usize, y as usize) != target {
+                continue;
+            }
+
+            visited[index] = true;
+            area.push((x, y));
+            queue.extend_from_slice(&[(x - 1, y), (x + 1, y), (x, y - 1), (x, y + 1)]);
+        }
+
+        area
+    }
+
+    fn rect_area(&self, (x1, y1): (i32, i32), (x2, y2): (i32, i32)) -> Vec<(i32, i32)> {
+        let mut area = Vec::new();
+
+        for y in y1.min(y2)..=y1.max(y2) {
+            for x in x1.min(x2)..=x1.max(x2) {
+                area.push((x, y));
+            }
+        }
+
+        area
+    }
+
+    /// Changes the map size, keeping the tiles in the top left corner.
+    pub fn resize_map(&mut self, width: u16, height: u16) {
+        let map = &self.stage.map;
+        if map.tiles.len() != map.width as usize * map.height as usize {
+            log::warn!("Resizing multi-layer maps is not supported.");
+            return;
+        }
+
+        let mut new_tiles = vec![0u8; width as usize * height as usize];
+        for y in 0..height.min(map.height) as usize {
+            for x in 0..width.min(map.width) as usize {
+                new_tiles[y * width as usize + x] = map.tiles[y * map.width as usize + x];
+            }
+        }
+
+        let command = EditorCommand::Resize {
+            old_size: (map.width, map.height),
+            old_tiles: map.tiles.clone(),
+            new_size: (width, height),
+            new_tiles: new_tiles.clone(),
+        };
+
+        self.stage.map.width = width;
+        self.stage.map.height = height;
+        self.stage.map.tiles = new_tiles;
+
+        self.history.close();
+        self.history.push(command);
+        self.history.close();
+    }
+
+    pub fn show_resize_window(&mut self) {
+        self.resize_window = Some((self.stage.map.width as i32, self.stage.map.height as i32));
+    }
+
+    fn resize_map_window(&mut self, ui: &imgui::Ui) {
+        let (mut width, mut height) = match self.resize_window {
+            Some(size) => size,
+            None => return,
+        };
+        let mut apply = false;
+        let mut close = false;
+
+        Window::new("Resize map").resizable(false).collapsible(false).build(ui, || {
+            ui.input_int("Width", &mut width).build();
+            ui.input_int("Height", &mut height).build();
+
+            apply = ui.button("Apply");
+            ui.same_line();
+            close = ui.button("Cancel");
+        });
+
+        width = width.clamp(1, u16::MAX as i32);
+        height = height.clamp(1, u16::MAX as i32);
+        self.resize_window = Some((width, height));
+
+        if apply {
+            self.resize_map(width as u16, height as u16);
+        }
+
+        if apply || close {
+            self.resize_window = None;
+        }
+    }
+
+    /// Keeps a copy of the entity list before it's changed by an entity edit, if it's the first change of the edit.
+    fn begin_entity_edit(&mut self) {
+        if self.entity_edit.is_none() {
+            self.entity_edit = Some(self.npcs.clone());
+        }
+    }
+
+    /// Records the changes of the ongoing entity edit as a single command.
+    fn finish_entity_edit(&mut self) {
+        if let Some(old) = self.entity_edit.take() {
+            if old != self.npcs {
+                self.history.push(EditorCommand::Entities { old, new: self.npcs.clone() });
+            }
+        }
+    }
+
+    pub fn undo(&mut self) {
+        self.finish_entity_edit();
+        if self.history.undo(&mut self.stage, &mut self.npcs) {
+            self.after_history_change();
+        }
+    }
+
+    pub fn redo(&mut self) {
+        self.finish_entity_edit();
+        if self.history.redo(&mut self.stage, &mut self.npcs) {
+            self.after_history_change();
+        }
+    }
+
+    fn after_history_change(&mut self) {
+        self.dragging_entity = false;
+        self.rect_start = None;
+
+        if self.selected_entity.map_or(false, |idx| idx >= self.npcs.len()) {
+            self.selected_entity = None;
+        }
+    }
+
+    fn process_entity_tool(&mut self, ui: &imgui::Ui) {
+        let (tile_x, tile_y) = self.mouse_tile();
+
+        if ui.is_mouse_clicked(MouseButton::Left) {
+            self.entity_cursor = (tile_x as i16, tile_y as i16);
+            // topmost entity is drawn last
+            self.selected_entity = self.npcs.iter().rposition(|npc| npc.x as i32 == tile_x && npc.y as i32 == tile_y);
+            self.dragging_entity = self.selected_entity.is_some();
+        }
+
+        if !ui.is_mouse_down(MouseButton::Left) {
+            self.dragging_entity = false;
+        }
+
+        if self.dragging_entity {
+            let (x, y) = (tile_x as i16, tile_y as i16);
+            let moved = |npc: &NPCData| (npc.x, npc.y) != (x, y);
+            if let Some(idx) = self.selected_entity.filter(|&idx| self.npcs.get(idx).map_or(false, moved)) {
+                self.begin_entity_edit();
+                self.npcs[idx].x = x;
+                self.npcs[idx].y = y;
+            }
+        }
+
+        if ui.is_key_pressed(Key::Delete) {
+            self.delete_selected_entity();
+        }
+    }
+
+    fn add_entity(&mut self) {
+        let (x, y) = self.entity_cursor;
+        let id = 170 + self.npcs.len() as u16;
+
+        let npc_type = self.new_entity_type;
+
+        self.begin_entity_edit();
+        self.npcs.push(NPCData { id, x, y, flag_num: 0, event_num: 0, npc_type, flags: 0, layer: 0 });
+        self.selected_entity = Some(self.npcs.len() - 1);
+    }
+
+    fn delete_selected_entity(&mut self) {
+        if let Some(idx) = self.selected_entity.take() {
+            if idx < self.npcs.len() {
+                self.begin_entity_edit();
+                self.npcs.remove(idx);
+            }
+
+            // keep the IDs in sync with what the loader assigns
+            for (i, npc) in self.npcs.iter_mut().enumerate() {
+                npc.id = 170 + i as u16;
+            }
+        }
+    }
+
+    fn entity_inspector_window(&mut self, ui: &imgui::Ui) {
+        Window::new("Entity")
+            .size([260.0, 460.0], imgui::Condition::FirstUseEver)
+            .position([ui.io().display_size[0], 60.0], imgui::Condition::FirstUseEver)
+            .position_pivot([1.0, 0.0])
+            .build(ui, || {
+                ui.text(format!("Entities: {}", self.npcs.len()));
+
+                let mut new_type = self.new_entity_type as i32;
+                if ui.input_int("New type", &mut new_type).build() {
+                    self.new_entity_type = new_type.clamp(0, u16::MAX as i32) as u16;
+                }
+
+                if ui.button("Add") {
+                    self.add_entity();
+                }
+
+                ui.same_line();
+                ui.disabled(self.selected_entity.is_none(), || {
+                    if ui.button("Delete") {
+                        self.delete_selected_entity();
+                    }
+                });
+
+                ui.separator();
+
+                // edited on a copy, so the entity list is only snapshotted once something changes
+                let selected = self.selected_entity.and_then(|idx| Some((idx, self.npcs.get(idx)?.clone())));
+                let (idx, mut npc) = match selected {
+                    Some(selected) => selected,
+                    None => {
+                        ui.text("No entity selected.");
+                        return;
+                    }
+                };
+
+                ui.text(format!("Entity #{}", npc.id));
+
+                let mut fields = [
+                    ("X", npc.x as i32),
+                    ("Y", npc.y as i32),
+                    ("NPC type", npc.npc_type as i32),
+                    ("Event", npc.event_num as i32),
+                    ("Flag", npc.flag_num as i32),
+                ];
+
+                for (label, value) in fields.iter_mut() {
+                    ui.input_int(label, value).build();
+                }
+
+                npc.x = fields[0].1.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+                npc.y = fields[1].1.clamp(i16::MIN as i32, i16::MAX as i32) as i16;
+                npc.npc_type = fields[2].1.clamp(0, u16::MAX as i32) as u16;
+                npc.event_num = fields[3].1.clamp(0, u16::MAX as i32) as u16;
+                npc.flag_num = fields[4].1.clamp(0, u16::MAX as i32) as u16;
+
+                let mut direction = if npc.flags & FLAG_SPAWN_FACING_RIGHT != 0 { 1 } else { 0 };
+                if ui.combo_simple_string("Direction", &mut direction, &["Left", "Right"]) {
+                    npc.flags = (npc.flags & !FLAG_SPAWN_FACING_RIGHT)
+                        | if direction == 1 { FLAG_SPAWN_FACING_RIGHT } else { 0 };
+                }
+
+                ui.text("Flags");
+                for (bit, name) in ENTITY_FLAGS.iter() {
+                    let mut set = npc.flags & bit != 0;
+                    if ui.checkbox(name, &mut set) {
+                        npc.flags = if set { npc.flags | bit } else { npc.flags & !bit };
+                    }
+                }
+
+                if npc != self.npcs[idx] {
+                    self.begin_entity_edit();
+                    self.npcs[idx] = npc;
+                }
+            });
+    }
+
+    /// Writes the map, tile attributes and entities of this stage into the active mod directory, see
+    /// [SharedGameState::get_resource_save_root].
+    pub fn save(&self, state: &SharedGameState, ctx: &Context) -> GameResult {
+        let map_name = &self.stage.data.map;
+
+        if filesystem::exists_find(ctx, &state.constants.base_paths, ["Stage/", map_name, ".pxpack"].join("")) {
+            return Err(GameError::InvalidValue(format!("Cannot save {}: PxPack maps are not supported.", map_name)));
+        }
+
+        let root = state.get_resource_save_root(ctx, &["Stage/", map_name, ".pxm"].join(""));
+
+        let pxm_file = filesystem::create(ctx, [root.as_str(), "Stage/", map_name, ".pxm"].join(""))?;
+        self.stage.map.write_pxm(pxm_file)?;
+
+        let tileset_name = &self.stage.data.tileset.name;
+        let pxa_file = filesystem::create(ctx, [root.as_str(), "Stage/", tileset_name, ".pxa"].join(""))?;
+        self.stage.map.write_pxa(pxa_file)?;
+
+        let pxe_file = filesystem::create(ctx, [root.as_str(), "Stage/", map_name, ".pxe"].join(""))?;
+        NPCData::write_to(&self.npcs, self.pxe_version, pxe_file)?;
+
+        log::info!("Saved stage {} to {} in the editor output directory.", map_name, root);
+
+        Ok(())
+    }
+
+    /// Replaces the stage table entry of the edited stage, reloading the tile attributes if the tileset changed.
+    pub fn set_stage_data(&mut self, state: &SharedGameState, ctx: &mut Context, data: StageData) -> GameResult {
+        if data.tileset != self.stage.data.tileset {
+            let roots = &state.constants.base_paths;
+            let mut attrib_file = filesystem::open_find(ctx, roots, ["Stage/", &data.tileset.name, ".pxa"].join(""))?;
+
+            let mut attrib = [0u8; 0x100];
+            if attrib_file.read_exact(&mut attrib).is_err() {
+                log::warn!("Map attribute data is shorter than 256 bytes!");
+            }
+            self.stage.map.attrib = attrib;
+        }
+
+        self.stage.data = data;
+        self.stage_textures.borrow_mut().update(&self.stage);
+
+        Ok(())
+    }
+
     fn tile_cursor(&self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
         if self.want_capture_mouse {
             return Ok(());
@@ -124,16 +577,15 @@ impl EditorInstance {
 
         let tile_size = self.stage.map.tile_size.as_int();
         let halft = tile_size / 2;
-        let stage_mouse_x = (self.frame.x / 0x200) + halft + (self.mouse_pos.0 / self.zoom) as i32;
-        let stage_mouse_y = (self.frame.y / 0x200) + halft + (self.mouse_pos.1 / self.zoom) as i32;
-        let tile_x = stage_mouse_x / tile_size;
-        let tile_y = stage_mouse_y / tile_size;
+        let (tile_x, tile_y) = self.mouse_tile();
         let frame_x = self.frame.x as f32 / 512.0;
         let frame_y = self.frame.y as f32 / 512.0;
 
-        if tile_x < 0 || tile_y < 0 || tile_x >= self.stage.map.width as i32 || tile_y >= self.stage.map.height as i32 {
-            return Ok(());
-        }
+        // preview the whole area while dragging with the rectangle tool
+        let area = match self.rect_start {
+            Some(start) => self.rect_area(start, (tile_x, tile_y)),
+            None => vec![(tile_x, tile_y)],
+        };
 
         let name = &self.stage_textures.deref().borrow().tileset_fg;
 
@@ -146,12 +598,18 @@ impl EditorInstance {
                 tile_size16,
             );
 
-            batch.add_rect_tinted(
-                (tile_x * tile_size - halft) as f32 - frame_x,
-                (tile_y * tile_size - halft) as f32 - frame_y,
-                (255, 255, 255, 192),
-                &rect,
-            );
+            for (x, y) in area {
+                if x < 0 || y < 0 || x >= self.stage.map.width as i32 || y >= self.stage.map.height as i32 {
+                    continue;
+                }
+
+                batch.add_rect_tinted(
+                    (x * tile_size - halft) as f32 - frame_x,
+                    (y * tile_size - halft) as f32 - frame_y,
+                    (255, 255, 255, 192),
+                    &rect,
+                );
+            }
 
             batch.draw(ctx)?;
         }
@@ -219,10 +677,11 @@ impl EditorInstance {
         self.tilemap.draw(state, ctx, &self.frame, TileLayer::Foreground, &*paths, &self.stage)?;
         self.tilemap.draw(state, ctx, &self.frame, TileLayer::Snack, &*paths, &self.stage)?;
 
+        self.draw_entities(state, ctx, tool)?;
         self.draw_black_bars(state, ctx)?;
 
         match tool {
-            CurrentTool::Move => (),
+            CurrentTool::Move | CurrentTool::Entity => (),
             CurrentTool::Brush | CurrentTool::Fill | CurrentTool::Rectangle => {
                 self.tile_cursor(state, ctx)?;
             }
@@ -233,6 +692,58 @@ impl EditorInstance {
         Ok(())
     }
 
+    fn draw_entities(&self, state: &mut SharedGameState, ctx: &mut Context, tool: CurrentTool) -> GameResult {
+        state.npc_table.stage_textures = self.stage_textures.clone();
+
+        let tile_size = self.stage.map.tile_size.as_int();
+        let halft = tile_size / 2;
+        let (frame_x, frame_y) = self.frame.xy_interpolated(state.frame_time);
+
+        for npc in self.npcs.iter() {
+            let rect = self.npc_preview_rects.get(npc.npc_type as usize).copied().flatten();
+            let spritesheet_id = state.npc_table.get_entry(npc.npc_type).map(|entry| entry.spritesheet_id as u16);
+
+            if let (Some(rect), Some(spritesheet_id)) = (rect, spritesheet_id) {
+                let bounds = state.npc_table.get_display_bounds(npc.npc_type);
+                let facing_right = npc.flags & FLAG_SPAWN_FACING_RIGHT != 0;
+                let off_x = if facing_right { bounds.right } else { bounds.left } as i32 / 0x200;
+                let off_y = bounds.top as i32 / 0x200;
+
+                let texture_ref = state.npc_table.get_texture_ref(spritesheet_id);
+                let batch = state.texture_set.get_or_load_batch(ctx, &state.constants, &*texture_ref)?;
+
+                // like tiles, entities are centered on the tile origin, offset by their display bounds
+                batch.add_rect(
+                    (npc.x as i32 * tile_size - off_x) as f32 - frame_x,
+                    (npc.y as i32 * tile_size - off_y) as f32 - frame_y,
+                    &rect,
+                );
+                batch.draw(ctx)?;
+            }
+        }
+
+        if tool != CurrentTool::Entity {
+            return Ok(());
+        }
+
+        // outline every entity, so ones without a sprite can be found too
+        for (idx, npc) in self.npcs.iter().enumerate() {
+            let color = if self.selected_entity == Some(idx) {
+                Color::from_rgba(255, 255, 0, 255)
+            } else {
+                Color::from_rgba(0, 255, 128, 160)
+            };
+
+            let x = ((npc.x as i32 * tile_size - halft) as f32 - frame_x) * state.scale;
+            let y = ((npc.y as i32 * tile_size - halft) as f32 - frame_y) * state.scale;
+            let size = tile_size as f32 * state.scale;
+            let rect = Rect::new(x as isize, y as isize, (x + size) as isize, (y + size) as isize);
+            graphics::draw_outline_rect(ctx, rect, 1, color)?;
+        }
+
+        Ok(())
+    }
+
     fn draw_black_bars(&self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
         let color = Color::from_rgba(0, 0, 0, 128);
         let (x, y) = self.frame.xy_interpolated(state.frame_time);
@@ -0,0 +1,83 @@
+use std::io::Write;
+
+use crate::framework::context::Context;
+use crate::framework::error::GameResult;
+use crate::framework::filesystem;
+use crate::game::map::{Map, NPCData};
+use crate::game::scripting::tsc::encryption::encrypt_tsc;
+use crate::game::shared_game_state::{SharedGameState, TileSize};
+use crate::game::stage::StageData;
+
+/// Size of maps created for new stages, a single screen at the original resolution.
+const NEW_MAP_SIZE: (u16, u16) = (21, 16);
+
+/// Script of new stages, so transporting into them with `<TRA` doesn't leave the screen faded out.
+const NEW_STAGE_SCRIPT: &str = concat!(
+    "#0090\r\n<MNA<FAI0000<END\r\n",
+    "#0091\r\n<MNA<FAI0001<END\r\n",
+    "#0092\r\n<MNA<FAI0002<END\r\n",
+    "#0093\r\n<MNA<FAI0003<END\r\n",
+    "#0094\r\n<MNA<FAI0004<END\r\n",
+);
+
+/// Writes the stage table back in the format it was loaded from.
+pub fn save_stage_table(state: &SharedGameState, ctx: &Context) -> GameResult {
+    let format = state.stage_table_format;
+    let path = format.path().trim_start_matches('/');
+    let root = state.get_resource_save_root(ctx, path);
+
+    // serialize first, so a stage that doesn't fit in the format doesn't leave a truncated table behind
+    let mut data = Vec::new();
+    StageData::write_stage_table(&state.stages, format, state.constants.is_switch, &mut data)?;
+
+    let mut file = filesystem::create(ctx, [root.as_str(), path].join(""))?;
+    file.write_all(&data)?;
+
+    log::info!("Saved {:?} stage table to {}", format, root);
+
+    Ok(())
+}
+
+/// Creates an empty map, entity list and script for a new stage. Files which already exist are left untouched.
+pub fn create_stage_files(state: &SharedGameState, ctx: &Context, stage: &StageData) -> GameResult {
+    let roots = &state.constants.base_paths;
+    let map_path = ["Stage/", &stage.map, ".pxm"].join("");
+    let attrib_path = ["Stage/", &stage.tileset.name, ".pxa"].join("");
+    let npc_path = ["Stage/", &stage.map, ".pxe"].join("");
+    let script_path = ["Stage/", &stage.map, ".tsc"].join("");
+    let root = state.get_resource_save_root(ctx, &map_path);
+
+    let (width, height) = NEW_MAP_SIZE;
+    let map = Map {
+        width,
+        height,
+        tiles: vec![0; width as usize * height as usize],
+        attrib: [0; 0x100],
+        tile_size: TileSize::Tile16x16,
+    };
+
+    if !filesystem::exists_find(ctx, roots, &map_path) {
+        map.write_pxm(filesystem::create(ctx, [root.as_str(), &map_path].join(""))?)?;
+    }
+
+    if !filesystem::exists_find(ctx, roots, &attrib_path) {
+        map.write_pxa(filesystem::create(ctx, [root.as_str(), &attrib_path].join(""))?)?;
+    }
+
+    if !filesystem::exists_find(ctx, roots, &npc_path) {
+        NPCData::write_to(&[], 0, filesystem::create(ctx, [root.as_str(), &npc_path].join(""))?)?;
+    }
+
+    if !filesystem::exists_find(ctx, roots, &script_path) {
+        let mut script = NEW_STAGE_SCRIPT.as_bytes().to_vec();
+        if state.constants.textscript.encrypted {
+            encrypt_tsc(&mut script);
+        }
+
+        filesystem::create(ctx, [root.as_str(), &script_path].join(""))?.write_all(&script)?;
+    }
+
+    log::info!("Created files for stage {} in {}", stage.map, root);
+
+    Ok(())
+}
@@ -8,11 +8,13 @@ use xmltree::Element;
 use crate::case_insensitive_hashmap;
 use crate::common::{BulletFlag, Color, Rect};
 use crate::engine_constants::npcs::NPCConsts;
+use crate::engine_constants::overrides::ConstantOverrides;
 use crate::framework::context::Context;
 use crate::framework::error::GameResult;
 use crate::framework::filesystem;
 use crate::framework::gamepad::{Axis, Button};
 use crate::game::player::ControlMode;
+use crate::game::scripting::tsc::opcodes::CustomOpCode;
 use crate::game::scripting::tsc::text_script::TextScriptEncoding;
 use crate::game::settings::Settings;
 use crate::game::shared_game_state::{FontData, Season};
@@ -21,8 +23,9 @@ use crate::sound::pixtone::{Channel, Envelope, PixToneParameters, Waveform};
 use crate::sound::SoundManager;
 
 mod npcs;
+mod overrides;
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
 pub struct PhysicsConsts {
     pub max_dash: i32,
     pub max_move: i32,
@@ -34,7 +37,7 @@ pub struct PhysicsConsts {
     pub jump: i32,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
 pub struct BoosterConsts {
     pub fuel: u32,
     pub b2_0_up: i32,
@@ -44,7 +47,7 @@ pub struct BoosterConsts {
     pub b2_0_right: i32,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
 pub struct PlayerConsts {
     pub life: u16,
     pub max_life: u16,
@@ -56,7 +59,7 @@ pub struct PlayerConsts {
     pub frames_bubble: [Rect<u16>; 2],
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
 pub struct GameConsts {
     pub intro_stage: u16,
     pub intro_event: u16,
@@ -67,7 +70,7 @@ pub struct GameConsts {
     pub tile_offset_x: i32,
 }
 
-#[derive(Debug)]
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct CaretConsts {
     pub offsets: [(i32, i32); 18],
     pub bubble_left_rects: Vec<Rect<u16>>,
@@ -125,7 +128,7 @@ impl Clone for CaretConsts {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
 pub struct BulletData {
     pub damage: u8,
     pub life: u8,
@@ -138,7 +141,7 @@ pub struct BulletData {
     pub display_bounds: Rect<u8>,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
 pub struct BulletRects {
     pub b001_snake_l1: [Rect<u16>; 8],
     pub b002_003_snake_l2_3: [Rect<u16>; 3],
@@ -175,7 +178,7 @@ pub struct BulletRects {
     pub b042_spur_trail_l3: [Rect<u16>; 6],
 }
 
-#[derive(Debug)]
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct WeaponConsts {
     pub bullet_table: Vec<BulletData>,
     pub bullet_rects: BulletRects,
@@ -192,7 +195,7 @@ impl Clone for WeaponConsts {
     }
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
 pub struct WorldConsts {
     pub snack_rect: Rect<u16>,
     pub water_push_rect: Rect<u16>,
@@ -212,7 +215,7 @@ pub struct ExtraSoundtrack {
     pub available: bool,
 }
 
-#[derive(Debug, Copy, Clone)]
+#[derive(Debug, Copy, Clone, serde::Serialize, serde::Deserialize)]
 pub struct TextScriptConsts {
     pub encoding: TextScriptEncoding,
     pub encrypted: bool,
@@ -245,7 +248,7 @@ pub struct TextScriptConsts {
     pub fade_ticks: i8,
 }
 
-#[derive(Debug)]
+#[derive(Debug, serde::Serialize, serde::Deserialize)]
 pub struct TitleConsts {
     pub intro_text: String,
     pub logo_rect: Rect<u16>,
@@ -342,6 +345,10 @@ pub struct EngineConstants {
     pub missile_flags: Vec<u16>,
     pub locales: Vec<Locale>,
     pub gamepad: GamepadConsts,
+    /// TSC commands registered by Lua scripts.
+    pub custom_opcodes: Vec<CustomOpCode>,
+    /// Values from before `constants.json` files were applied.
+    constant_defaults: Option<Box<ConstantOverrides>>,
 }
 
 impl Clone for EngineConstants {
@@ -374,6 +381,8 @@ impl Clone for EngineConstants {
             missile_flags: self.missile_flags.clone(),
             locales: self.locales.clone(),
             gamepad: self.gamepad.clone(),
+            custom_opcodes: self.custom_opcodes.clone(),
+            constant_defaults: self.constant_defaults.clone(),
         }
     }
 }
@@ -1695,6 +1704,8 @@ impl EngineConstants {
                     (Axis::TriggerRight, GamepadConsts::rects(Rect::new(32, 80, 64, 96))),
                 ]),
             },
+            custom_opcodes: Vec::new(),
+            constant_defaults: None,
         }
     }
 
@@ -1782,7 +1793,8 @@ impl EngineConstants {
         self.title.logo_splash_rect = Rect { left: 224, top: 0, right: 320, bottom: 48 };
     }
 
-    pub fn rebuild_path_list(&mut self, mod_path: Option<String>, season: Season, settings: &Settings) {
+    /// Rebuilds `base_paths` with the directories of the active mods, given with the lowest priority first.
+    pub fn rebuild_path_list(&mut self, mod_paths: &[String], season: Season, settings: &Settings) {
         self.base_paths.clear();
         self.base_paths.push("/builtin/builtin_data/".to_owned());
         self.base_paths.push("/".to_owned());
@@ -1809,13 +1821,14 @@ impl EngineConstants {
             }
         }
 
-        if let Some(mut mod_path) = mod_path {
+        for mod_path in mod_paths.iter() {
             self.base_paths.insert(0, mod_path.clone());
             if settings.original_textures {
-                mod_path.push_str("ogph/");
-                self.base_paths.insert(0, mod_path);
+                self.base_paths.insert(0, format!("{}ogph/", mod_path));
             }
+        }
 
+        if !mod_paths.is_empty() {
             // Nicalis left a landmine of a file in the original graphics for the nemesis challenge
             // It has 17 colors defined for a 4-bit color depth bitmap
             if self.is_cs_plus && !self.is_switch {
@@ -1899,8 +1912,6 @@ impl EngineConstants {
         Ok(())
     }
 
-    pub fn apply_constant_json_files(&mut self) {}
-
     pub fn load_texture_size_hints(&mut self, ctx: &mut Context) -> GameResult {
         if let Ok(file) = filesystem::open_find(ctx, &self.base_paths, "texture_sizes.json") {
             match serde_json::from_reader::<_, TextureSizeTable>(file) {
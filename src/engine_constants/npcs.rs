@@ -1168,6 +1168,49 @@ pub struct NPCConsts {
     pub b09_ballos: SafeNPCRect<14>,
 }
 
+impl NPCConsts {
+    /// Returns the first non-empty sprite rect of each NPC type, indexed by the type.
+    /// Used by the editor to preview entities without running their AI.
+    pub fn preview_rects(&self) -> Vec<Option<Rect<u16>>> {
+        fn first_rect(value: &serde_json::Value) -> Option<Rect<u16>> {
+            match value {
+                serde_json::Value::Array(array) if array.len() == 4 && array.iter().all(|v| v.is_u64()) => {
+                    let rect: Rect<u16> = serde_json::from_value(value.clone()).ok()?;
+                    if rect.width() > 0 && rect.height() > 0 {
+                        Some(rect)
+                    } else {
+                        None
+                    }
+                }
+                serde_json::Value::Array(array) => array.iter().find_map(first_rect),
+                serde_json::Value::Object(fields) => fields.values().find_map(first_rect),
+                _ => None,
+            }
+        }
+
+        let mut rects = Vec::new();
+
+        if let Ok(serde_json::Value::Object(fields)) = serde_json::to_value(self) {
+            for (name, value) in fields.iter() {
+                // fields are named after the NPC type they belong to, eg. n001_experience
+                let npc_type = name.strip_prefix('n').and_then(|n| n.get(..3)).and_then(|n| n.parse::<usize>().ok());
+
+                if let Some(npc_type) = npc_type {
+                    if rects.len() <= npc_type {
+                        rects.resize(npc_type + 1, None);
+                    }
+
+                    if rects[npc_type].is_none() {
+                        rects[npc_type] = first_rect(value);
+                    }
+                }
+            }
+        }
+
+        rects
+    }
+}
+
 fn default_n001_experience() -> SafeNPCRect<6> {
     SafeNPCRect([
         Rect { left: 0, top: 16, right: 16, bottom: 32 },
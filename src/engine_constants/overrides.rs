@@ -0,0 +1,310 @@
+use std::io::Read;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::common::Color;
+use crate::engine_constants::npcs::NPCConsts;
+use crate::engine_constants::{
+    BoosterConsts, CaretConsts, EngineConstants, GameConsts, PlayerConsts, TextScriptConsts, TitleConsts, WeaponConsts,
+    WorldConsts,
+};
+use crate::framework::context::Context;
+use crate::framework::error::{GameError, GameResult};
+use crate::framework::filesystem;
+
+/// Parts of `EngineConstants` which can be overridden by `constants.json` files.
+///
+/// The file mirrors this structure, but only has to contain the values it changes, eg.
+/// `{ "player": { "air_physics": { "jump": 1200 } } }`. Arrays are replaced as a whole, single entries can be
+/// changed by using an object with indices as keys instead, eg. `{ "weapon": { "bullet_table": { "4": { ... } } } }`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConstantOverrides {
+    game: GameConsts,
+    player: PlayerConsts,
+    booster: BoosterConsts,
+    caret: CaretConsts,
+    world: WorldConsts,
+    npc: NPCConsts,
+    weapon: WeaponConsts,
+    textscript: TextScriptConsts,
+    title: TitleConsts,
+    inventory_dim_color: Color,
+    font_space_offset: f32,
+    music_table: Vec<String>,
+    organya_paths: Vec<String>,
+    credit_illustration_paths: Vec<String>,
+    missile_flags: Vec<u16>,
+}
+
+impl ConstantOverrides {
+    pub fn capture(constants: &EngineConstants) -> ConstantOverrides {
+        ConstantOverrides {
+            game: constants.game,
+            player: constants.player,
+            booster: constants.booster,
+            caret: constants.caret.clone(),
+            world: constants.world,
+            npc: constants.npc,
+            weapon: constants.weapon.clone(),
+            textscript: constants.textscript,
+            title: constants.title.clone(),
+            inventory_dim_color: constants.inventory_dim_color,
+            font_space_offset: constants.font_space_offset,
+            music_table: constants.music_table.clone(),
+            organya_paths: constants.organya_paths.clone(),
+            credit_illustration_paths: constants.credit_illustration_paths.clone(),
+            missile_flags: constants.missile_flags.clone(),
+        }
+    }
+
+    pub fn apply(self, constants: &mut EngineConstants) {
+        constants.game = self.game;
+        constants.player = self.player;
+        constants.booster = self.booster;
+        constants.caret = self.caret;
+        constants.world = self.world;
+        constants.npc = self.npc;
+        constants.weapon = self.weapon;
+        constants.textscript = self.textscript;
+        constants.title = self.title;
+        constants.inventory_dim_color = self.inventory_dim_color;
+        constants.font_space_offset = self.font_space_offset;
+        constants.music_table = self.music_table;
+        constants.organya_paths = self.organya_paths;
+        constants.credit_illustration_paths = self.credit_illustration_paths;
+        constants.missile_flags = self.missile_flags;
+    }
+
+    fn to_value(&self) -> GameResult<Value> {
+        serde_json::to_value(self).map_err(|err| GameError::ParseError(err.to_string()))
+    }
+}
+
+impl EngineConstants {
+    /// Undoes the changes made by `constants.json` files, so a different set can be applied for another mod.
+    pub fn reset_constant_overrides(&mut self) {
+        match self.constant_defaults.clone() {
+            Some(defaults) => defaults.apply(self),
+            None => self.constant_defaults = Some(Box::new(ConstantOverrides::capture(self))),
+        }
+    }
+
+    /// Deep-merges every `constants.json` found in `base_paths` into the constants, files in paths with
+    /// higher priority are applied last.
+    pub fn apply_constant_json_files(&mut self, ctx: &mut Context) -> GameResult {
+        let mut merged = ConstantOverrides::capture(self).to_value()?;
+        let mut applied = false;
+
+        for base_path in self.base_paths.iter().rev() {
+            let path = format!("{}constants.json", base_path);
+            if !filesystem::exists(ctx, &path) {
+                continue;
+            }
+
+            let mut data = String::new();
+            filesystem::open(ctx, &path)?.read_to_string(&mut data)?;
+
+            let overlay: Value = serde_json::from_str(&data)
+                .map_err(|err| GameError::ResourceLoadError(format!("Failed to parse {}: {}", path, err)))?;
+
+            let before = merged.clone();
+            let mut errors = Vec::new();
+            merge_value(&mut merged, overlay.clone(), "", &mut errors);
+            if errors.is_empty() && serde_json::from_value::<ConstantOverrides>(merged.clone()).is_err() {
+                check_ranges(&before, &overlay, &mut Vec::new(), &mut errors);
+            }
+            if !errors.is_empty() {
+                return Err(GameError::ResourceLoadError(format!("Invalid {}: {}", path, errors.join("; "))));
+            }
+
+            log::info!("Loaded constant overrides from {}.", path);
+            applied = true;
+        }
+
+        if applied {
+            let overrides: ConstantOverrides = serde_json::from_value(merged).map_err(|err| {
+                GameError::ResourceLoadError(format!("Invalid value in constants.json files: {}", err))
+            })?;
+            overrides.apply(self);
+        }
+
+        Ok(())
+    }
+}
+
+fn join_path(path: &str, key: &str) -> String {
+    if path.is_empty() {
+        key.to_owned()
+    } else {
+        format!("{}.{}", path, key)
+    }
+}
+
+fn type_name(value: &Value) -> &'static str {
+    match value {
+        Value::Null => "null",
+        Value::Bool(_) => "a boolean",
+        Value::Number(number) if number.is_f64() => "a number",
+        Value::Number(_) => "an integer",
+        Value::String(_) => "a string",
+        Value::Array(_) => "an array",
+        Value::Object(_) => "an object",
+    }
+}
+
+/// Checks that `value` has the same type as `template`, and for objects that it doesn't contain unknown keys.
+fn check_value(template: &Value, value: &Value, path: &str, errors: &mut Vec<String>) {
+    match (template, value) {
+        (Value::Object(template), Value::Object(value)) => {
+            for (key, value) in value.iter() {
+                match template.get(key) {
+                    Some(template) => check_value(template, value, &join_path(path, key), errors),
+                    None => errors.push(format!("unknown key `{}`", join_path(path, key))),
+                }
+            }
+        }
+        (Value::Array(template), Value::Array(value)) => {
+            if let Some(template) = template.first() {
+                for (idx, value) in value.iter().enumerate() {
+                    check_value(template, value, &join_path(path, &idx.to_string()), errors);
+                }
+            }
+        }
+        (Value::Number(template), Value::Number(value)) => {
+            // floats can be set to integers, but not the other way around
+            if !template.is_f64() && value.is_f64() {
+                errors.push(format!("`{}` must be an integer, found {}", path, value));
+            }
+        }
+        (Value::Bool(_), Value::Bool(_)) | (Value::String(_), Value::String(_)) => {}
+        (template, value) => {
+            errors.push(format!("`{}` must be {}, found {}", path, type_name(template), type_name(value)));
+        }
+    }
+}
+
+/// Merges `overlay` into `base`, reporting unknown keys and mismatched types instead of applying them.
+fn merge_value(base: &mut Value, overlay: Value, path: &str, errors: &mut Vec<String>) {
+    match (base, overlay) {
+        (Value::Object(base), Value::Object(overlay)) => {
+            for (key, overlay) in overlay.into_iter() {
+                let key_path = join_path(path, &key);
+                match base.get_mut(&key) {
+                    Some(base) => merge_value(base, overlay, &key_path, errors),
+                    None => errors.push(format!("unknown key `{}`", key_path)),
+                }
+            }
+        }
+        (Value::Array(base), Value::Object(overlay)) => {
+            for (key, overlay) in overlay.into_iter() {
+                let key_path = join_path(path, &key);
+                match key.parse::<usize>().ok().and_then(|idx| base.get_mut(idx)) {
+                    Some(base) => merge_value(base, overlay, &key_path, errors),
+                    None => errors.push(format!("`{}` is not an index of `{}` ({} entries)", key, path, base.len())),
+                }
+            }
+        }
+        (base, overlay) => {
+            let errors_before = errors.len();
+            check_value(base, &overlay, path, errors);
+            if errors.len() > errors_before {
+                return;
+            }
+
+            // keep floats floats, so later files still accept fractional values
+            *base = match overlay.as_f64() {
+                Some(number) if base.is_f64() => Value::from(number),
+                _ => overlay,
+            };
+        }
+    }
+}
+
+/// Reports the values of `overlay` which can't be stored in their fields, eg. negative or too large numbers.
+///
+/// JSON numbers don't carry the width of the field they came from, so every value is tried on its own
+/// by merging it into `root` and deserializing the result.
+fn check_ranges(root: &Value, overlay: &Value, keys: &mut Vec<String>, errors: &mut Vec<String>) {
+    if let Value::Object(overlay) = overlay {
+        for (key, value) in overlay.iter() {
+            keys.push(key.clone());
+            check_ranges(root, value, keys, errors);
+            keys.pop();
+        }
+        return;
+    }
+
+    let single = keys.iter().rev().fold(overlay.clone(), |value, key| {
+        let mut object = serde_json::Map::new();
+        object.insert(key.clone(), value);
+        Value::Object(object)
+    });
+
+    let mut candidate = root.clone();
+    merge_value(&mut candidate, single, "", &mut Vec::new());
+    if let Err(err) = serde_json::from_value::<ConstantOverrides>(candidate) {
+        errors.push(format!("`{}` has an invalid value: {}", keys.join("."), err));
+    }
+}
+
+#[test]
+fn test_merge_constants() {
+    let mut base = serde_json::json!({
+        "player": { "life": 3, "air_physics": { "jump": 1280 } },
+        "weapon": { "bullet_table": [{ "damage": 0 }, { "damage": 1 }] },
+        "music_table": ["xxxx", "wanpaku"],
+        "font_space_offset": 0.0,
+    });
+
+    let mut errors = Vec::new();
+    let overlay = serde_json::json!({
+        "player": { "air_physics": { "jump": 1500 } },
+        "weapon": { "bullet_table": { "1": { "damage": 4 } } },
+        "music_table": ["xxxx"],
+        "font_space_offset": 2,
+    });
+    merge_value(&mut base, overlay, "", &mut errors);
+    assert!(errors.is_empty(), "{:?}", errors);
+    assert_eq!(base["player"]["life"], 3);
+    assert_eq!(base["player"]["air_physics"]["jump"], 1500);
+    assert_eq!(base["weapon"]["bullet_table"][1]["damage"], 4);
+    assert_eq!(base["music_table"], serde_json::json!(["xxxx"]));
+
+    let overlay = serde_json::json!({
+        "player": { "lives": 3, "air_physics": { "jump": "high" } },
+        "weapon": { "bullet_table": { "2": { "damage": 1 } } },
+        "music_table": [1],
+        "font_space_offset": 1.5,
+    });
+    merge_value(&mut base, overlay, "", &mut errors);
+    errors.sort();
+    assert_eq!(
+        errors,
+        vec![
+            "`2` is not an index of `weapon.bullet_table` (2 entries)",
+            "`music_table.0` must be a string, found an integer",
+            "`player.air_physics.jump` must be an integer, found a string",
+            "unknown key `player.lives`",
+        ]
+    );
+    assert_eq!(base["player"]["air_physics"]["jump"], 1500);
+}
+
+#[test]
+fn test_check_ranges() {
+    let root = ConstantOverrides::capture(&EngineConstants::defaults()).to_value().unwrap();
+
+    let mut errors = Vec::new();
+    let overlay = serde_json::json!({
+        "player": { "life": -1, "max_life": 70000, "air_physics": { "jump": 1500 } },
+        "game": { "tile_offset_x": -16 },
+        "missile_flags": [1, 256],
+    });
+    check_ranges(&root, &overlay, &mut Vec::new(), &mut errors);
+    errors.sort();
+
+    assert_eq!(errors.len(), 2, "{:?}", errors);
+    assert!(errors[0].starts_with("`player.life` has an invalid value"), "{}", errors[0]);
+    assert!(errors[1].starts_with("`player.max_life` has an invalid value"), "{}", errors[1]);
+}
@@ -51,7 +51,15 @@ impl BackendEventLoop for NullEventLoop {
                 game.loops = 0;
                 state_ref.frame_time = 0.0;
             }
-            std::thread::sleep(std::time::Duration::from_millis(10));
+
+            if let Some(mut verifier) = state_ref.replay_verifier.take() {
+                if let Some(scene) = &game.scene {
+                    verifier.tick(state_ref, scene.as_ref());
+                }
+                state_ref.replay_verifier = Some(verifier);
+            } else {
+                std::thread::sleep(std::time::Duration::from_millis(10));
+            }
 
             game.draw(ctx).unwrap();
         }
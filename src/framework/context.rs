@@ -5,9 +5,12 @@ use crate::framework::gamepad::GamepadContext;
 use crate::framework::graphics::VSyncMode;
 use crate::framework::keyboard::KeyboardContext;
 use crate::game::Game;
+use crate::sound::AudioOutput;
 
 pub struct Context {
     pub headless: bool,
+    /// Overrides where the sound manager sends its audio, headless mode doesn't initialize audio otherwise.
+    pub audio_output: Option<AudioOutput>,
     pub size_hint: (u16, u16),
     pub(crate) filesystem: Filesystem,
     pub(crate) renderer: Option<Box<dyn BackendRenderer>>,
@@ -23,6 +26,7 @@ impl Context {
     pub fn new() -> Context {
         Context {
             headless: false,
+            audio_output: None,
             size_hint: (640, 480),
             filesystem: Filesystem::new(),
             renderer: None,
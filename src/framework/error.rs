@@ -99,6 +99,14 @@ impl From<serde_json::Error> for GameError {
     }
 }
 
+#[cfg(feature = "netplay")]
+impl From<serde_cbor::Error> for GameError {
+    fn from(e: serde_cbor::Error) -> Self {
+        let errstr = format!("CBOR error: {:?}", e);
+        GameError::ParseError(errstr)
+    }
+}
+
 #[cfg(target_os = "android")]
 impl From<jni::errors::Error> for GameError {
     fn from(e: jni::errors::Error) -> GameError {
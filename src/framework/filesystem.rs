@@ -83,6 +83,17 @@ impl Filesystem {
         self.vfs.open(path.as_ref()).map(|f| File::VfsFile(f))
     }
 
+    /// Creates a new file in the resource directory and opens it
+    /// to be written to, truncating it if it already exists.
+    /// Missing parent directories are created.
+    /// Only works if a writeable resource directory is mounted.
+    pub(crate) fn create<P: AsRef<path::Path>>(&self, path: P) -> GameResult<File> {
+        if let Some(parent) = path.as_ref().parent() {
+            let _ = self.vfs.mkdir(parent);
+        }
+        self.vfs.create(path.as_ref()).map(|f| File::VfsFile(f))
+    }
+
     /// Opens the given `path` from user directory and returns the resulting `File`
     /// in read-only mode.
     pub(crate) fn user_open<P: AsRef<path::Path>>(&self, path: P) -> GameResult<File> {
@@ -218,6 +229,11 @@ impl Filesystem {
         self.vfs.push_back(vfs);
     }
 
+    /// Mounts a VFS in front of all resource directories, so its files take precedence over theirs.
+    pub fn mount_vfs_front(&mut self, vfs: Box<dyn vfs::VFS>) {
+        self.vfs.push_front(vfs);
+    }
+
     pub fn mount_user_vfs(&mut self, vfs: Box<dyn vfs::VFS>) {
         self.user_vfs.push_back(vfs);
     }
@@ -246,6 +262,12 @@ pub fn open_find<P: AsRef<path::Path>>(ctx: &Context, roots: &Vec<String>, path:
     Err(GameError::ResourceNotFound("File not found".to_owned(), errors))
 }
 
+/// Creates a new file in the resource directory and opens it
+/// to be written to, truncating it if it already exists.
+pub fn create<P: AsRef<path::Path>>(ctx: &Context, path: P) -> GameResult<File> {
+    ctx.filesystem.create(path)
+}
+
 /// Opens the given path in the user directory and returns the resulting `File`
 /// in read-only mode.
 pub fn user_open<P: AsRef<path::Path>>(ctx: &Context, path: P) -> GameResult<File> {
@@ -379,6 +401,11 @@ pub fn mount_vfs(ctx: &mut Context, vfs: Box<dyn vfs::VFS>) {
     ctx.filesystem.mount_vfs(vfs)
 }
 
+/// Adds a VFS in front of the list of resource search locations.
+pub fn mount_vfs_front(ctx: &mut Context, vfs: Box<dyn vfs::VFS>) {
+    ctx.filesystem.mount_vfs_front(vfs)
+}
+
 /// Adds a VFS to the list of user data search locations.
 pub fn mount_user_vfs(ctx: &mut Context, vfs: Box<dyn vfs::VFS>) {
     ctx.filesystem.mount_user_vfs(vfs)
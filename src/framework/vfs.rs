@@ -9,12 +9,13 @@
 //! as a trait object, and its path abstraction is not the most
 //! convenient.
 
-use std::collections::VecDeque;
+use std::collections::{HashMap, VecDeque};
 use std::ffi::OsStr;
 use std::fmt::{self, Debug};
 use std::fs;
-use std::io::{Read, Seek, Write};
+use std::io::{self, Cursor, ErrorKind, Read, Seek, SeekFrom, Write};
 use std::path::{self, Component, Path, PathBuf};
+use std::sync::Mutex;
 
 use crate::framework::error::{GameError, GameResult};
 
@@ -529,6 +530,276 @@ impl VFS for OverlayFS {
     }
 }
 
+/// Source of the data of a `ZipFS`, a file on disk or an in-memory buffer.
+pub trait ZipSource: Read + Seek + Send {}
+
+impl<T> ZipSource for T where T: Read + Seek + Send {}
+
+/// A file opened from a `ZipFS`, decompressed into memory as entries can't be seeked.
+#[derive(Debug)]
+pub struct ZipFileData(Cursor<Vec<u8>>);
+
+impl Read for ZipFileData {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        self.0.read(buf)
+    }
+}
+
+impl Seek for ZipFileData {
+    fn seek(&mut self, pos: SeekFrom) -> io::Result<u64> {
+        self.0.seek(pos)
+    }
+}
+
+impl Write for ZipFileData {
+    fn write(&mut self, _buf: &[u8]) -> io::Result<usize> {
+        Err(io::Error::new(ErrorKind::PermissionDenied, "Zip file system is read-only."))
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Err(io::Error::new(ErrorKind::PermissionDenied, "Zip file system is read-only."))
+    }
+}
+
+#[derive(Debug, Clone)]
+/// Zip FS metadata
+pub struct ZipMetadata {
+    is_dir: bool,
+    size: u64,
+}
+
+impl VMetadata for ZipMetadata {
+    fn is_dir(&self) -> bool {
+        self.is_dir
+    }
+    fn is_file(&self) -> bool {
+        !self.is_dir
+    }
+    fn len(&self) -> u64 {
+        self.size
+    }
+}
+
+/// A read-only VFS over the contents of a zip archive, so mods can be distributed as a single `.zip` or `.pk3`
+/// file.
+///
+/// The contents are placed under a mount point, eg. `/mods/foo/`, and if every entry of the archive is inside
+/// of the same directory that directory is skipped, as that's what most tools create when compressing a folder.
+/// It's only skipped if it's named like the archive or contains mod files, so a zip with just `Stage/` is kept as is.
+/// Lookups are case-insensitive, like the case insensitive path emulation of `PhysicalFS`.
+pub struct ZipFS {
+    source: Option<PathBuf>,
+    archive: Mutex<zip::ZipArchive<Box<dyn ZipSource>>>,
+    /// Archive entry indices keyed by their lowercase path, without the leading slash.
+    files: HashMap<String, (usize, u64)>,
+    /// Entries of every directory keyed by their lowercase path, without the leading slash.
+    dirs: HashMap<String, Vec<String>>,
+}
+
+impl ZipFS {
+    /// Opens the zip archive at `path` on the disk, with its contents placed under `mount_point`.
+    pub fn new(path: &Path, mount_point: &str) -> GameResult<ZipFS> {
+        let file = fs::File::open(path)?;
+        let stem = path.file_stem().and_then(OsStr::to_str);
+        let mut zip_fs = ZipFS::load(Box::new(io::BufReader::new(file)), mount_point, stem)?;
+        zip_fs.source = Some(path.to_path_buf());
+
+        Ok(zip_fs)
+    }
+
+    /// Reads the zip archive from `reader`, with its contents placed under `mount_point`.
+    pub fn from_reader(reader: Box<dyn ZipSource>, mount_point: &str) -> GameResult<ZipFS> {
+        ZipFS::load(reader, mount_point, None)
+    }
+
+    fn load(reader: Box<dyn ZipSource>, mount_point: &str, stem: Option<&str>) -> GameResult<ZipFS> {
+        let mut archive = zip::ZipArchive::new(reader).map_err(ZipFS::zip_error)?;
+
+        let mut entries = Vec::new();
+        for idx in 0..archive.len() {
+            let file = archive.by_index_raw(idx).map_err(ZipFS::zip_error)?;
+            let name = file.name();
+
+            let parts: Vec<&str> = name.split(['/', '\\']).filter(|part| !part.is_empty() && *part != ".").collect();
+            if parts.is_empty() || parts.contains(&"..") {
+                log::warn!("Skipping invalid path in zip archive: {}", name);
+                continue;
+            }
+
+            let parts: Vec<String> = parts.iter().map(|part| part.to_string()).collect();
+            entries.push((idx, parts, file.is_dir(), file.size()));
+        }
+
+        // skip the folder the archive was created from
+        let common_dir = entries.first().map(|(_, parts, _, _)| parts[0].clone());
+        if let Some(common_dir) = common_dir {
+            let is_common =
+                entries.iter().all(|(_, parts, is_dir, _)| parts[0] == common_dir && (parts.len() > 1 || *is_dir));
+            let is_named = stem.map_or(false, |stem| stem.eq_ignore_ascii_case(&common_dir));
+            let has_markers = entries.iter().any(|(_, parts, _, _)| {
+                parts.get(1).map_or(false, |part| {
+                    ["mod.json", "Stage", "data"].iter().any(|marker| marker.eq_ignore_ascii_case(part))
+                })
+            });
+
+            if is_common && (is_named || has_markers) {
+                for (_, parts, _, _) in entries.iter_mut() {
+                    parts.remove(0);
+                }
+            }
+        }
+
+        let mut zip_fs =
+            ZipFS { source: None, archive: Mutex::new(archive), files: HashMap::new(), dirs: HashMap::new() };
+        zip_fs.dirs.insert(String::new(), Vec::new());
+
+        let mount_parts: Vec<String> =
+            mount_point.split('/').filter(|part| !part.is_empty()).map(|part| part.to_string()).collect();
+        zip_fs.insert_path(&mount_parts, true);
+
+        for (idx, parts, is_dir, size) in entries {
+            if parts.is_empty() {
+                continue;
+            }
+
+            let mut path = mount_parts.clone();
+            path.extend(parts);
+            let key = zip_fs.insert_path(&path, is_dir);
+
+            if !is_dir {
+                zip_fs.files.insert(key, (idx, size));
+            }
+        }
+
+        Ok(zip_fs)
+    }
+
+    fn zip_error(err: zip::result::ZipError) -> GameError {
+        match err {
+            zip::result::ZipError::Io(err) => GameError::from(err),
+            err => GameError::FilesystemError(format!("Invalid zip archive: {}", err)),
+        }
+    }
+
+    /// Registers the path in its parent directories, creating them if needed, and returns its key.
+    fn insert_path(&mut self, parts: &[String], is_dir: bool) -> String {
+        let mut key = String::new();
+
+        for part in parts {
+            let siblings = self.dirs.entry(key.clone()).or_default();
+            if !siblings.iter().any(|name| name.to_lowercase() == part.to_lowercase()) {
+                siblings.push(part.clone());
+            }
+
+            if !key.is_empty() {
+                key.push('/');
+            }
+            key.push_str(&part.to_lowercase());
+        }
+
+        if is_dir {
+            self.dirs.entry(key.clone()).or_default();
+        }
+
+        key
+    }
+
+    fn normalize(path: &Path) -> GameResult<String> {
+        match sanitize_path(path) {
+            Some(path) => {
+                let parts: Vec<String> =
+                    path.components().map(|c| c.as_os_str().to_string_lossy().to_lowercase()).collect();
+                Ok(parts.join("/"))
+            }
+            None => Err(GameError::FilesystemError(format!("Invalid path: {:?}", path))),
+        }
+    }
+}
+
+impl Debug for ZipFS {
+    fn fmt(&self, f: &mut fmt::Formatter) -> Result<(), fmt::Error> {
+        match &self.source {
+            Some(source) => write!(f, "<ZipFS {:?}>", source),
+            None => write!(f, "<ZipFS>"),
+        }
+    }
+}
+
+impl VFS for ZipFS {
+    /// Open the file at this path with the given options
+    fn open_options(&self, path: &Path, open_options: OpenOptions) -> GameResult<Box<dyn VFile>> {
+        if open_options.write || open_options.create || open_options.append || open_options.truncate {
+            let msg = format!("Cannot alter file {:?} in root {:?}, filesystem read-only", path, self);
+            return Err(GameError::FilesystemError(msg));
+        }
+
+        let (idx, size) = match self.files.get(&ZipFS::normalize(path)?) {
+            Some(entry) => *entry,
+            None => return Err(GameError::FilesystemError("File not found.".to_string())),
+        };
+
+        let mut archive = self.archive.lock().unwrap();
+        let mut file = archive.by_index(idx).map_err(ZipFS::zip_error)?;
+        // the size comes from the archive header, don't trust it for anything but a bounded hint
+        let mut data = Vec::with_capacity(size.min(0x100000) as usize);
+        file.read_to_end(&mut data)?;
+
+        Ok(Box::new(ZipFileData(Cursor::new(data))))
+    }
+
+    /// Create a directory at the location by this path
+    fn mkdir(&self, _path: &Path) -> GameResult {
+        Err(GameError::FilesystemError("Tried to make directory {} but FS is read-only".to_string()))
+    }
+
+    /// Remove a file
+    fn rm(&self, _path: &Path) -> GameResult {
+        Err(GameError::FilesystemError("Tried to remove file {} but FS is read-only".to_string()))
+    }
+
+    /// Remove a file or directory and all its contents
+    fn rmrf(&self, _path: &Path) -> GameResult {
+        Err(GameError::FilesystemError("Tried to remove file/dir {} but FS is read-only".to_string()))
+    }
+
+    /// Check if the file exists
+    fn exists(&self, path: &Path) -> bool {
+        match ZipFS::normalize(path) {
+            Ok(key) => self.files.contains_key(&key) || self.dirs.contains_key(&key),
+            Err(_) => false,
+        }
+    }
+
+    /// Get the file's metadata
+    fn metadata(&self, path: &Path) -> GameResult<Box<dyn VMetadata>> {
+        let key = ZipFS::normalize(path)?;
+
+        if let Some((_, size)) = self.files.get(&key) {
+            Ok(Box::new(ZipMetadata { is_dir: false, size: *size }))
+        } else if self.dirs.contains_key(&key) {
+            Ok(Box::new(ZipMetadata { is_dir: true, size: 0 }))
+        } else {
+            Err(GameError::FilesystemError(format!("File not found: {:?}", path)))
+        }
+    }
+
+    /// Retrieve the path entries in this path
+    fn read_dir(&self, path: &Path) -> GameResult<Box<dyn Iterator<Item=GameResult<PathBuf>>>> {
+        // same as PhysicalFS, the paths are relative to the root of the VFS
+        let entries = match self.dirs.get(&ZipFS::normalize(path)?) {
+            Some(entries) => entries.iter().map(|name| Ok(path.join(name))).collect::<Vec<_>>(),
+            None => return Err(GameError::FilesystemError(format!("Expected a directory: {:?}", path))),
+        };
+
+        Ok(Box::new(entries.into_iter()))
+    }
+
+    /// Retrieve the actual location of the VFS root, if available.
+    fn to_path_buf(&self) -> Option<PathBuf> {
+        self.source.clone()
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use std::io::{self, BufRead};
@@ -672,5 +943,58 @@ mod tests {
         assert!(!fs.exists(testdir));
     }
 
-    // BUGGO: TODO: Make sure all functions are tested for OverlayFS and ZipFS!!
+    #[test]
+    fn headless_test_zip() {
+        let mut buf = Cursor::new(Vec::new());
+        {
+            let mut zip = zip::ZipWriter::new(&mut buf);
+            let options = zip::write::FileOptions::default();
+            zip.add_directory("MyMod/", options).unwrap();
+            zip.start_file("MyMod/mod.txt", options).unwrap();
+            zip.write_all(b"My Mod").unwrap();
+            zip.start_file("MyMod/Stage/Cave.pxm", options).unwrap();
+            zip.write_all(b"PXM").unwrap();
+            zip.finish().unwrap();
+        }
+        buf.set_position(0);
+
+        let fs = ZipFS::from_reader(Box::new(buf), "/mods/mymod/").unwrap();
+        let mut data = String::new();
+        fs.open(Path::new("/mods/mymod/MOD.TXT")).unwrap().read_to_string(&mut data).unwrap();
+        assert_eq!(data, "My Mod");
+
+        assert!(fs.exists(Path::new("/mods")));
+        assert!(fs.exists(Path::new("/mods/mymod/stage/cave.pxm")));
+        assert!(!fs.exists(Path::new("/mods/mymod/MyMod")));
+        assert!(fs.metadata(Path::new("/mods/mymod/Stage")).unwrap().is_dir());
+        assert_eq!(fs.metadata(Path::new("/mods/MyMod/Stage/Cave.pxm")).unwrap().len(), 3);
+        assert!(fs.create(Path::new("/mods/mymod/mod.txt")).is_err());
+        assert!(fs.mkdir(Path::new("/mods/mymod/Npc")).is_err());
+
+        let mut entries: Vec<PathBuf> = fs.read_dir(Path::new("/mods/mymod")).unwrap().map(Result::unwrap).collect();
+        entries.sort();
+        assert_eq!(entries, vec![PathBuf::from("/mods/mymod/Stage"), PathBuf::from("/mods/mymod/mod.txt")]);
+    }
+
+    #[test]
+    fn headless_test_zip_single_folder() {
+        let mut buf = Cursor::new(Vec::new());
+        {
+            let mut zip = zip::ZipWriter::new(&mut buf);
+            let options = zip::write::FileOptions::default();
+            zip.start_file("Stage/Cave.pxm", options).unwrap();
+            zip.write_all(b"PXM").unwrap();
+            zip.start_file("Stage/Cave.pxe", options).unwrap();
+            zip.write_all(b"PXE").unwrap();
+            zip.finish().unwrap();
+        }
+        buf.set_position(0);
+
+        let fs = ZipFS::from_reader(Box::new(buf), "/mods/mymod/").unwrap();
+        assert!(fs.metadata(Path::new("/mods/mymod/Stage")).unwrap().is_dir());
+        assert!(fs.exists(Path::new("/mods/mymod/Stage/Cave.pxm")));
+        assert!(!fs.exists(Path::new("/mods/mymod/Cave.pxm")));
+    }
+
+    // BUGGO: TODO: Make sure all functions are tested for OverlayFS!!
 }
@@ -1,9 +1,11 @@
+use serde::{Deserialize, Serialize};
+
 use crate::common::{fix9_scale, interpolate_fix9_scale};
 use crate::game::shared_game_state::SharedGameState;
 use crate::game::stage::Stage;
 use crate::util::rng::RNG;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum UpdateTarget {
     Player,
@@ -11,6 +13,7 @@ pub enum UpdateTarget {
     Boss(u16),
 }
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Frame {
     pub x: i32,
     pub y: i32,
@@ -3,7 +3,7 @@ use std::io;
 use std::io::{BufRead, BufReader, Cursor, Read};
 use std::sync::Arc;
 
-use byteorder::{LE, ReadBytesExt};
+use byteorder::{LE, ReadBytesExt, WriteBytesExt};
 
 use crate::common::{Color, Rect};
 use crate::framework::context::Context;
@@ -67,6 +67,33 @@ impl Map {
         Ok(Map { width, height, tiles, attrib, tile_size: TileSize::Tile16x16 })
     }
 
+    /// Writes the tile data in PXM format, the inverse of [Map::load_pxm].
+    pub fn write_pxm<W: io::Write>(&self, mut map_data: W) -> GameResult {
+        if self.tiles.len() != self.width as usize * self.height as usize {
+            return Err(GameError::InvalidValue(format!(
+                "Tile data doesn't match the map size: {} tiles for {}x{}",
+                self.tiles.len(),
+                self.width,
+                self.height
+            )));
+        }
+
+        map_data.write_all(b"PXM")?;
+        map_data.write_u8(SUPPORTED_PXM_VERSIONS[0])?;
+        map_data.write_u16::<LE>(self.width)?;
+        map_data.write_u16::<LE>(self.height)?;
+        map_data.write_all(&self.tiles)?;
+
+        Ok(())
+    }
+
+    /// Writes the tile attribute table in PXA format.
+    pub fn write_pxa<W: io::Write>(&self, mut attrib_data: W) -> GameResult {
+        attrib_data.write_all(&self.attrib)?;
+
+        Ok(())
+    }
+
     pub fn load_pxpack<R: io::Read>(
         mut map_data: R,
         roots: &Vec<String>,
@@ -474,7 +501,7 @@ impl Map {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct NPCData {
     pub id: u16,
     pub x: i16,
@@ -487,7 +514,12 @@ pub struct NPCData {
 }
 
 impl NPCData {
-    pub fn load_from<R: io::Read>(mut data: R) -> GameResult<Vec<NPCData>> {
+    pub fn load_from<R: io::Read>(data: R) -> GameResult<Vec<NPCData>> {
+        Ok(NPCData::load_versioned_from(data)?.1)
+    }
+
+    /// Loads the entity list along with the PXE format version, which is needed to write the file back as is.
+    pub fn load_versioned_from<R: io::Read>(mut data: R) -> GameResult<(u8, Vec<NPCData>)> {
         let mut magic = [0; 3];
 
         data.read_exact(&mut magic)?;
@@ -518,7 +550,33 @@ impl NPCData {
             npcs.push(NPCData { id: 170 + i as u16, x, y, flag_num, event_num, npc_type, flags, layer })
         }
 
-        Ok(npcs)
+        Ok((version, npcs))
+    }
+
+    /// Writes the entity list in PXE format. The layer field is only stored in version 0x10.
+    pub fn write_to<W: io::Write>(npcs: &[NPCData], version: u8, mut data: W) -> GameResult {
+        if !SUPPORTED_PXE_VERSIONS.contains(&version) {
+            return Err(GameError::InvalidValue(format!("Unsupported PXE version: {:#x}", version)));
+        }
+
+        data.write_all(b"PXE")?;
+        data.write_u8(version)?;
+        data.write_u32::<LE>(npcs.len() as u32)?;
+
+        for npc in npcs {
+            data.write_i16::<LE>(npc.x)?;
+            data.write_i16::<LE>(npc.y)?;
+            data.write_u16::<LE>(npc.flag_num)?;
+            data.write_u16::<LE>(npc.event_num)?;
+            data.write_u16::<LE>(npc.npc_type)?;
+            data.write_u16::<LE>(npc.flags)?;
+
+            if version == 0x10 {
+                data.write_u8(npc.layer)?;
+            }
+        }
+
+        Ok(())
     }
 }
 
@@ -625,3 +683,36 @@ impl WaterParams {
         self.entries.get(&tile).unwrap_or(&DEFAULT_ENTRY)
     }
 }
+
+#[test]
+fn test_map_round_trip() -> GameResult {
+    let mut pxm = b"PXM\x10\x03\x00\x02\x00".to_vec();
+    pxm.extend_from_slice(&[1, 2, 3, 4, 5, 6]);
+    let pxa: Vec<u8> = (0..=255).collect();
+
+    let map = Map::load_pxm(Cursor::new(&pxm), Cursor::new(&pxa))?;
+    let mut out_pxm = Vec::new();
+    let mut out_pxa = Vec::new();
+    map.write_pxm(&mut out_pxm)?;
+    map.write_pxa(&mut out_pxa)?;
+    assert_eq!(pxm, out_pxm);
+    assert_eq!(pxa, out_pxa);
+
+    for version in SUPPORTED_PXE_VERSIONS {
+        let npcs = vec![
+            NPCData { id: 170, x: 5, y: -3, flag_num: 100, event_num: 200, npc_type: 46, flags: 0x1000, layer: 0 },
+            NPCData { id: 171, x: 12, y: 7, flag_num: 0, event_num: 0, npc_type: 211, flags: 0x0820, layer: 1 },
+        ];
+
+        let mut pxe = Vec::new();
+        NPCData::write_to(&npcs, version, &mut pxe)?;
+        let (loaded_version, loaded) = NPCData::load_versioned_from(Cursor::new(&pxe))?;
+        assert_eq!(loaded_version, version);
+
+        let mut out_pxe = Vec::new();
+        NPCData::write_to(&loaded, loaded_version, &mut out_pxe)?;
+        assert_eq!(pxe, out_pxe);
+    }
+
+    Ok(())
+}
@@ -1,5 +1,5 @@
 use std::cell::UnsafeCell;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 use std::sync::Mutex;
 use std::time::{Duration, Instant};
 
@@ -9,16 +9,20 @@ use scripting::tsc::text_script::ScriptMode;
 
 use crate::data::builtin_fs::BuiltinFS;
 use crate::framework::context::Context;
-use crate::framework::error::GameResult;
-use crate::framework::filesystem::{mount_user_vfs, mount_vfs};
+use crate::framework::error::{GameError, GameResult};
+use crate::framework::filesystem::{mount_user_vfs, mount_vfs, mount_vfs_front};
 use crate::framework::graphics;
 use crate::framework::graphics::VSyncMode;
 use crate::framework::ui::UI;
-use crate::framework::vfs::PhysicalFS;
+use crate::framework::vfs::{PhysicalFS, ZipFS};
+use crate::game::replay_verifier::ReplayVerifier;
 use crate::game::shared_game_state::{Fps, SharedGameState, TimingMode};
 use crate::graphics::texture_set::{G_MAG, I_MAG};
 use crate::scene::loading_scene::LoadingScene;
 use crate::scene::Scene;
+use crate::sound::render::render_to_file;
+pub use crate::sound::render::{RenderOptions, RenderSource};
+pub use crate::sound::AudioOutput;
 
 pub mod caret;
 pub mod frame;
@@ -28,15 +32,43 @@ pub mod npc;
 pub mod physics;
 pub mod player;
 pub mod profile;
+pub mod replay_verifier;
 pub mod scripting;
 pub mod settings;
 pub mod shared_game_state;
+pub mod snapshot;
 pub mod stage;
 pub mod weapon;
 
 pub struct LaunchOptions {
     pub server_mode: bool,
     pub editor: bool,
+    /// Replay file to play back headlessly, see `ReplayVerifier`.
+    pub verify_replay: Option<PathBuf>,
+    pub max_replay_ticks: usize,
+    /// Netplay server to join, see `NetClient`.
+    pub connect: Option<String>,
+    /// Address the netplay server listens on in server mode.
+    pub bind_address: Option<String>,
+    /// Song or sound effect to render to a WAV file instead of starting the game.
+    pub render_audio: Option<RenderOptions>,
+    /// Replaces the audio device, see `AudioOutput`.
+    pub audio_output: Option<AudioOutput>,
+}
+
+impl Default for LaunchOptions {
+    fn default() -> Self {
+        LaunchOptions {
+            server_mode: false,
+            editor: false,
+            verify_replay: None,
+            max_replay_ticks: replay_verifier::DEFAULT_MAX_TICKS,
+            connect: None,
+            bind_address: None,
+            render_audio: None,
+            audio_output: None,
+        }
+    }
 }
 
 lazy_static! {
@@ -214,6 +246,38 @@ impl Game {
     }
 }
 
+/// Mounts every `.zip` and `.pk3` archive in the `mods` directory, so `mods/foo.zip` is accessible the same way as
+/// an extracted `mods/foo/` directory.
+fn mount_mod_archives(ctx: &mut Context, data_dir: &Path) {
+    let mods_dir = data_dir.join("mods");
+    let mut archives: Vec<PathBuf> = match std::fs::read_dir(&mods_dir) {
+        Ok(entries) => entries.filter_map(|entry| entry.ok().map(|entry| entry.path())).collect(),
+        Err(_) => return,
+    };
+    archives.sort();
+
+    for path in archives {
+        let extension = path.extension().unwrap_or_default().to_string_lossy().to_lowercase();
+        if !path.is_file() || (extension != "zip" && extension != "pk3") {
+            continue;
+        }
+
+        let name = path.file_stem().unwrap_or_default().to_string_lossy().to_string();
+        if mods_dir.join(&name).is_dir() {
+            log::warn!("Both mods/{}/ and {:?} exist, ignoring the archive.", name, path);
+            continue;
+        }
+
+        match ZipFS::new(&path, &format!("/mods/{}/", name)) {
+            Ok(zip_fs) => {
+                log::info!("Mounted mod archive {:?}.", path);
+                mount_vfs(ctx, Box::new(zip_fs));
+            }
+            Err(err) => log::error!("Failed to open mod archive {:?}: {}", path, err),
+        }
+    }
+}
+
 pub fn init(options: LaunchOptions) -> GameResult {
     let _ = simple_logger::SimpleLogger::new()
         .without_timestamps()
@@ -221,6 +285,10 @@ pub fn init(options: LaunchOptions) -> GameResult {
         .with_level(log::Level::Info.to_level_filter())
         .init();
 
+    if let Some(render) = &options.render_audio {
+        return render_to_file(render);
+    }
+
     #[cfg(not(target_os = "android"))]
         let resource_dir = if let Ok(data_dir) = std::env::var("CAVESTORY_DATA_DIR") {
         PathBuf::from(data_dir)
@@ -265,15 +333,28 @@ pub fn init(options: LaunchOptions) -> GameResult {
     log::info!("Resource directory: {:?}", resource_dir);
     log::info!("Initializing engine...");
 
+    #[cfg(feature = "netplay")]
+    if options.server_mode {
+        log::info!("Running in netplay server mode...");
+        let default_address = format!("0.0.0.0:{}", crate::netplay::protocol::DEFAULT_PORT);
+        return crate::netplay::server::run(options.bind_address.as_deref().unwrap_or(&default_address));
+    }
+
+    #[cfg(not(feature = "netplay"))]
+    if options.connect.is_some() || options.bind_address.is_some() {
+        return Err(GameError::InvalidValue("This build doesn't support netplay.".to_owned()));
+    }
+
     let mut context = Context::new();
     #[cfg(not(target_os = "android"))]
         mount_vfs(&mut context, Box::new(PhysicalFS::new(&resource_dir, true)));
+    #[cfg(not(target_os = "android"))]
+        mount_mod_archives(&mut context, &resource_dir);
 
     #[cfg(not(target_os = "android"))]
         let project_dirs = match directories::ProjectDirs::from("", "", "doukutsu-rs") {
         Some(dirs) => dirs,
         None => {
-            use crate::framework::error::GameError;
             return Err(GameError::FilesystemError(String::from("No valid home directory path could be retrieved.")));
         }
     };
@@ -292,20 +373,30 @@ pub fn init(options: LaunchOptions) -> GameResult {
             log::info!("Android data directories: data_path={:?} user_path={:?}", &data_path, &user_path);
 
             mount_vfs(&mut context, Box::new(PhysicalFS::new(&data_path, true)));
+            mount_mod_archives(&mut context, &data_path);
             mount_user_vfs(&mut context, Box::new(PhysicalFS::new(&user_path, false)));
         }
 
     #[cfg(not(target_os = "android"))]
         {
-            if crate::framework::filesystem::open(&context, "/.drs_localstorage").is_ok() {
+            let user_dir = if crate::framework::filesystem::open(&context, "/.drs_localstorage").is_ok() {
                 let mut user_dir = resource_dir.clone();
                 user_dir.push("_drs_profile");
 
                 let _ = std::fs::create_dir_all(&user_dir);
-                mount_user_vfs(&mut context, Box::new(PhysicalFS::new(&user_dir, false)));
+                user_dir
             } else {
-                mount_user_vfs(&mut context, Box::new(PhysicalFS::new(project_dirs.data_local_dir(), false)));
+                project_dirs.data_local_dir().to_path_buf()
+            };
+            mount_user_vfs(&mut context, Box::new(PhysicalFS::new(&user_dir, false)));
+
+            // the game data stays read-only, files saved by the editor are written to a separate directory shadowing
+            // it, also outside of the editor so the edited stages can be played
+            let editor_dir = user_dir.join("editor");
+            if options.editor {
+                log::info!("Editor output directory: {:?}", editor_dir);
             }
+            mount_vfs_front(&mut context, Box::new(PhysicalFS::new(&editor_dir, false)));
         }
 
     mount_vfs(&mut context, Box::new(BuiltinFS::new()));
@@ -315,15 +406,38 @@ pub fn init(options: LaunchOptions) -> GameResult {
         context.headless = true;
     }
 
+    if options.verify_replay.is_some() {
+        log::info!("Running in replay verification mode...");
+        context.headless = true;
+    }
+
+    context.audio_output = options.audio_output;
+
     let game = UnsafeCell::new(Game::new(&mut context)?);
     let state_ref = unsafe { &mut *((&mut *game.get()).state.get()) };
+    if let Some(path) = options.verify_replay {
+        state_ref.replay_verifier = Some(ReplayVerifier::new(path, options.max_replay_ticks));
+    }
     #[cfg(feature = "scripting-lua")]
         {
             state_ref.lua.update_refs(unsafe { (&*game.get()).state.get() }, &mut context as *mut Context);
         }
 
-    state_ref.next_scene = Some(Box::new(LoadingScene::new()));
+    let mut loading_scene = LoadingScene::new();
+    #[cfg(feature = "netplay")]
+    {
+        loading_scene.netplay_address = options.connect;
+    }
+    state_ref.next_scene = Some(Box::new(loading_scene));
     context.run(unsafe { &mut *game.get() })?;
 
+    if let Some(verifier) = &state_ref.replay_verifier {
+        verifier.report();
+
+        if !verifier.succeeded() {
+            return Err(GameError::InvalidValue("Replay verification failed.".to_owned()));
+        }
+    }
+
     Ok(())
 }
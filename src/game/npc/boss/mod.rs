@@ -1,6 +1,8 @@
 use std::mem::{MaybeUninit, transmute};
 use std::ops::Deref;
 
+use serde::{Deserialize, Serialize};
+
 use crate::common::{Direction, interpolate_fix9_scale};
 use crate::components::flash::Flash;
 use crate::entity::GameEntity;
@@ -24,6 +26,7 @@ pub mod omega;
 pub mod sisters;
 pub mod undead_core;
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct BossNPC {
     pub boss_type: u16,
     pub parts: [NPC; 20],
@@ -5,6 +5,7 @@ use std::ops::Deref;
 use std::rc::Rc;
 
 use byteorder::{LE, ReadBytesExt};
+use serde::{Deserialize, Serialize};
 
 use crate::bitfield;
 use crate::common::{Condition, interpolate_fix9_scale, Rect};
@@ -31,7 +32,7 @@ pub mod list;
 pub mod utils;
 
 bitfield! {
-    #[derive(Clone, Copy)]
+    #[derive(Clone, Copy, Serialize, Deserialize)]
     pub struct NPCFlag(u16);
     impl Debug;
     /// Represented by 0x01
@@ -68,7 +69,7 @@ bitfield! {
     pub show_damage, set_show_damage: 15;
 }
 
-#[derive(Debug, Copy, Clone, Eq, PartialOrd, PartialEq)]
+#[derive(Debug, Copy, Clone, Eq, PartialOrd, PartialEq, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum NPCLayer {
     Background = 0,
@@ -77,7 +78,7 @@ pub enum NPCLayer {
 }
 
 /// Represents an NPC object.
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 #[repr(C)]
 pub struct NPC {
     pub id: u16,
@@ -261,7 +261,7 @@ impl NPCList {
 
             if smoke {
                 if let Some(table_entry) = state.npc_table.get_entry(npc.npc_type) {
-                    state.sound_manager.play_sfx(table_entry.death_sound);
+                    state.sound_manager.play_sfx_at(table_entry.death_sound, npc.x, npc.y);
                 }
 
                 match npc.size {
@@ -284,7 +284,7 @@ impl NPCList {
     pub fn kill_npc(&self, id: usize, vanish: bool, can_drop_missile: bool, state: &mut SharedGameState) {
         if let Some(npc) = self.get_npc(id) {
             if let Some(table_entry) = state.npc_table.get_entry(npc.npc_type) {
-                state.sound_manager.play_sfx(table_entry.death_sound);
+                state.sound_manager.play_sfx_at(table_entry.death_sound, npc.x, npc.y);
             }
 
             match npc.size {
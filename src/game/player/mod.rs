@@ -2,6 +2,7 @@ use std::clone::Clone;
 
 use num_derive::FromPrimitive;
 use num_traits::clamp;
+use serde::{Deserialize, Serialize};
 
 use crate::common::{Condition, Direction, Equipment, Flag, interpolate_fix9_scale, Rect};
 use crate::components::number_popup::NumberPopup;
@@ -22,14 +23,14 @@ use crate::util::rng::RNG;
 mod player_hit;
 pub mod skin;
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, FromPrimitive, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum ControlMode {
     Normal = 0,
     IronHead,
 }
 
-#[derive(PartialEq, Eq, Copy, Clone)]
+#[derive(PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
 pub enum TargetPlayer {
     Player1,
     Player2,
@@ -42,7 +43,7 @@ impl TargetPlayer {
     }
 }
 
-#[derive(PartialEq, Eq, Copy, Clone)]
+#[derive(PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
 enum BoosterSwitch {
     None,
     Up,
@@ -74,6 +75,50 @@ impl DogStack {
     }
 }
 
+/// Simulation state of a [Player], without the skin and controller which are recreated on load.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct PlayerSnapshot {
+    x: i32,
+    y: i32,
+    vel_x: i32,
+    vel_y: i32,
+    target_x: i32,
+    target_y: i32,
+    camera_target_x: i32,
+    camera_target_y: i32,
+    prev_x: i32,
+    prev_y: i32,
+    life: u16,
+    max_life: u16,
+    cond: Condition,
+    flags: Flag,
+    equip: Equipment,
+    direction: Direction,
+    control_mode: ControlMode,
+    question: bool,
+    booster_fuel: u32,
+    up: bool,
+    down: bool,
+    shock_counter: u8,
+    xp_counter: u8,
+    current_weapon: u8,
+    stars: u8,
+    damage: u16,
+    air_counter: u16,
+    air: u16,
+    strafe_up: bool,
+    weapon_offset_y: i8,
+    splash: bool,
+    tick: u8,
+    booster_switch: BoosterSwitch,
+    damage_counter: u16,
+    damage_taken: i16,
+    anim_num: u16,
+    anim_counter: u16,
+    has_dog: bool,
+    teleport_counter: u16,
+}
+
 #[derive(Clone)]
 pub struct Player {
     pub x: i32,
@@ -189,6 +234,92 @@ impl Player {
         }
     }
 
+    pub fn snapshot(&self) -> PlayerSnapshot {
+        PlayerSnapshot {
+            x: self.x,
+            y: self.y,
+            vel_x: self.vel_x,
+            vel_y: self.vel_y,
+            target_x: self.target_x,
+            target_y: self.target_y,
+            camera_target_x: self.camera_target_x,
+            camera_target_y: self.camera_target_y,
+            prev_x: self.prev_x,
+            prev_y: self.prev_y,
+            life: self.life,
+            max_life: self.max_life,
+            cond: self.cond,
+            flags: self.flags,
+            equip: self.equip,
+            direction: self.direction,
+            control_mode: self.control_mode,
+            question: self.question,
+            booster_fuel: self.booster_fuel,
+            up: self.up,
+            down: self.down,
+            shock_counter: self.shock_counter,
+            xp_counter: self.xp_counter,
+            current_weapon: self.current_weapon,
+            stars: self.stars,
+            damage: self.damage,
+            air_counter: self.air_counter,
+            air: self.air,
+            strafe_up: self.strafe_up,
+            weapon_offset_y: self.weapon_offset_y,
+            splash: self.splash,
+            tick: self.tick,
+            booster_switch: self.booster_switch,
+            damage_counter: self.damage_counter,
+            damage_taken: self.damage_taken,
+            anim_num: self.anim_num,
+            anim_counter: self.anim_counter,
+            has_dog: self.has_dog,
+            teleport_counter: self.teleport_counter,
+        }
+    }
+
+    pub fn restore(&mut self, snapshot: &PlayerSnapshot) {
+        self.x = snapshot.x;
+        self.y = snapshot.y;
+        self.vel_x = snapshot.vel_x;
+        self.vel_y = snapshot.vel_y;
+        self.target_x = snapshot.target_x;
+        self.target_y = snapshot.target_y;
+        self.camera_target_x = snapshot.camera_target_x;
+        self.camera_target_y = snapshot.camera_target_y;
+        self.prev_x = snapshot.prev_x;
+        self.prev_y = snapshot.prev_y;
+        self.life = snapshot.life;
+        self.max_life = snapshot.max_life;
+        self.cond = snapshot.cond;
+        self.flags = snapshot.flags;
+        self.equip = snapshot.equip;
+        self.direction = snapshot.direction;
+        self.control_mode = snapshot.control_mode;
+        self.question = snapshot.question;
+        self.booster_fuel = snapshot.booster_fuel;
+        self.up = snapshot.up;
+        self.down = snapshot.down;
+        self.shock_counter = snapshot.shock_counter;
+        self.xp_counter = snapshot.xp_counter;
+        self.current_weapon = snapshot.current_weapon;
+        self.stars = snapshot.stars;
+        self.damage = snapshot.damage;
+        self.air_counter = snapshot.air_counter;
+        self.air = snapshot.air;
+        self.strafe_up = snapshot.strafe_up;
+        self.weapon_offset_y = snapshot.weapon_offset_y;
+        self.splash = snapshot.splash;
+        self.tick = snapshot.tick;
+        self.booster_switch = snapshot.booster_switch;
+        self.damage_counter = snapshot.damage_counter;
+        self.damage_taken = snapshot.damage_taken;
+        self.anim_num = snapshot.anim_num;
+        self.anim_counter = snapshot.anim_counter;
+        self.has_dog = snapshot.has_dog;
+        self.teleport_counter = snapshot.teleport_counter;
+    }
+
     fn tick_normal(&mut self, state: &mut SharedGameState, npc_list: &NPCList) -> GameResult {
         if !state.control_flags.interactions_disabled() && state.control_flags.control_enabled() {
             if self.equip.has_air_tank() {
@@ -0,0 +1,151 @@
+use std::fs::File;
+use std::path::PathBuf;
+
+use crate::components::replay::{ReplayDesync, ReplayHeader};
+use crate::framework::context::Context;
+use crate::framework::error::{GameError, GameResult};
+use crate::game::shared_game_state::{ReplayKind, ReplayState, SharedGameState, TimingMode};
+use crate::scene::game_scene::GameScene;
+use crate::scene::Scene;
+
+/// Upper bound of simulated ticks, a bit more than the Nikumaru counter limit.
+pub const DEFAULT_MAX_TICKS: usize = 360000;
+
+/// Plays back a replay file headlessly, as fast as possible, and reports the outcome.
+///
+/// Used by the `--verify-replay` command line mode to regression test engine changes against recorded runs.
+pub struct ReplayVerifier {
+    pub path: PathBuf,
+    pub max_ticks: usize,
+    /// Number of ticks simulated since the playback started.
+    pub ticks: usize,
+    /// Set when the run reached the end of the challenge (`<STC`) during playback.
+    pub finished: bool,
+    /// Nikumaru counter value at the end of the run.
+    pub time: usize,
+    pub state_hash: Option<u64>,
+    pub desync: Option<ReplayDesync>,
+    pub error: Option<String>,
+    started: bool,
+}
+
+impl ReplayVerifier {
+    pub fn new(path: PathBuf, max_ticks: usize) -> ReplayVerifier {
+        ReplayVerifier {
+            path,
+            max_ticks,
+            ticks: 0,
+            finished: false,
+            time: 0,
+            state_hash: None,
+            desync: None,
+            error: None,
+            started: false,
+        }
+    }
+
+    /// Activates the mod the replay was recorded with and starts a new game in playback mode.
+    pub fn start(state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
+        let path = match &state.replay_verifier {
+            Some(verifier) => verifier.path.clone(),
+            None => return Ok(()),
+        };
+
+        let header = ReplayHeader::read_from(File::open(&path)?)?;
+
+        let mod_info = if header.is_legacy() {
+            // Version 0 replays don't store the mod, but they're named after it.
+            let file_name = path.file_name().map(|n| n.to_string_lossy().to_string()).unwrap_or_default();
+            let mod_name = file_name
+                .strip_suffix(&ReplayKind::Last.get_suffix())
+                .or_else(|| file_name.strip_suffix(&ReplayKind::Best.get_suffix()))
+                .unwrap_or(&file_name)
+                .to_owned();
+
+            state.mod_list.mods.iter().find(|m| m.name == mod_name)
+        } else {
+            state.mod_list.mods.iter().find(|m| m.id == header.mod_id)
+        };
+
+        let mod_path = match mod_info {
+            Some(mod_info) => mod_info.path.clone(),
+            None => {
+                return Err(GameError::ResourceLoadError(format!(
+                    "Cannot find the mod replay {:?} was recorded with.",
+                    path
+                )));
+            }
+        };
+
+        state.mod_path = Some(mod_path);
+        state.reload_resources(ctx)?;
+
+        let current = ReplayHeader::current(state, state.constants.game.new_game_stage as usize);
+        header.check_compatibility(&current)?;
+
+        // Ticks the game exactly once per update, regardless of wall clock.
+        state.settings.timing_mode = TimingMode::FrameSynchronized;
+        header.apply(state);
+        state.replay_state = ReplayState::Playback(ReplayKind::Best);
+        state.start_new_game(ctx)
+    }
+
+    /// Called after every game update, stops the game once the playback is over.
+    pub fn tick(&mut self, state: &mut SharedGameState, scene: &dyn Scene) {
+        let game_scene = match scene.downcast_ref::<GameScene>() {
+            Ok(game_scene) => game_scene,
+            Err(_) => {
+                if self.started {
+                    self.fail(state, "Game scene was left during playback.".to_owned());
+                }
+                return;
+            }
+        };
+
+        if let ReplayState::Playback(_) = state.replay_state {
+            self.started = true;
+            self.ticks += 1;
+
+            if self.ticks < self.max_ticks {
+                return;
+            }
+
+            log::warn!("Replay didn't end after {} ticks, stopping.", self.max_ticks);
+        } else if !self.started {
+            return;
+        }
+
+        self.time = game_scene.nikumaru.tick;
+        self.desync = game_scene.replay.desync;
+        self.state_hash = Some(game_scene.state_hash(state));
+        state.shutdown();
+    }
+
+    pub fn fail(&mut self, state: &mut SharedGameState, error: String) {
+        log::error!("Replay verification failed: {}", error);
+        self.error = Some(error);
+        state.shutdown();
+    }
+
+    pub fn succeeded(&self) -> bool {
+        self.finished && self.desync.is_none() && self.error.is_none()
+    }
+
+    pub fn report(&self) {
+        println!("replay: {}", self.path.display());
+        println!("finished: {}", self.finished);
+        println!("ticks: {}", self.ticks);
+        println!("time: {}", self.time);
+        match self.state_hash {
+            Some(hash) => println!("state_hash: {:016x}", hash),
+            None => println!("state_hash: none"),
+        }
+        match self.desync {
+            Some(desync) => println!("desync: tick {} ({})", desync.tick, desync.subsystem.name()),
+            None => println!("desync: none"),
+        }
+        if let Some(error) = &self.error {
+            println!("error: {}", error);
+        }
+    }
+}
@@ -73,6 +73,7 @@ end
 
 __doukutsu_rs_runtime_dont_touch._registered = {
     tick = {},
+    draw = {},
 }
 
 __doukutsu_rs_runtime_dont_touch._handlers = setmetatable({
@@ -85,6 +86,15 @@ __doukutsu_rs_runtime_dont_touch._handlers = setmetatable({
             pcall(h, scene)
         end
     end,
+    draw = function(scene)
+        for _, h in pairs(__doukutsu_rs_runtime_dont_touch._registered.draw) do
+            local status, err = pcall(h, scene)
+
+            if not status then
+                print("error in draw handler:" .. err)
+            end
+        end
+    end,
 }, {
     __index = function(self, event)
         error("Unknown event: " .. event)
@@ -111,6 +121,43 @@ __doukutsu_rs_runtime_dont_touch._tryNPCHook = function(npc_id, npc_type)
     return false
 end
 
+__doukutsu_rs_runtime_dont_touch._registeredTSCOpcodes = {}
+__doukutsu_rs_runtime_dont_touch._tscCoroutine = nil
+
+-- returns the number of ticks to wait before resuming the handler, or nil if it has finished
+__doukutsu_rs_runtime_dont_touch._stepTSCOpcode = function(co, ...)
+    local status, ticks = coroutine.resume(co, ...)
+    if not status then
+        print("error in TSC opcode handler:" .. ticks)
+    end
+
+    if not status or coroutine.status(co) == "dead" then
+        __doukutsu_rs_runtime_dont_touch._tscCoroutine = nil
+        return nil
+    end
+
+    __doukutsu_rs_runtime_dont_touch._tscCoroutine = co
+    return tonumber(ticks) or 0
+end
+
+__doukutsu_rs_runtime_dont_touch._runTSCOpcode = function(name, ...)
+    local handler = __doukutsu_rs_runtime_dont_touch._registeredTSCOpcodes[name]
+    if handler == nil then
+        return nil
+    end
+
+    return __doukutsu_rs_runtime_dont_touch._stepTSCOpcode(coroutine.create(handler), ...)
+end
+
+__doukutsu_rs_runtime_dont_touch._resumeTSCOpcode = function()
+    local co = __doukutsu_rs_runtime_dont_touch._tscCoroutine
+    if co == nil then
+        return nil
+    end
+
+    return __doukutsu_rs_runtime_dont_touch._stepTSCOpcode(co)
+end
+
 __doukutsu_rs_runtime_dont_touch._initializeScript = function(script)
     -- for compatibility with Lua 5.2+, copy-pasted from Lua mailing list
     -- http://lua-users.org/lists/lua-l/2010-06/msg00313.html
@@ -389,6 +436,29 @@ function doukutsu.playSong(id)
     __doukutsu_rs:playSong(id)
 end
 
+function doukutsu.drawSprite(sheet, rect, x, y, world)
+    assert(type(sheet) == "string", "sheet name must be a string.")
+    assert(type(rect) == "table", "rect must be a table.")
+
+    __doukutsu_rs:drawSprite(sheet, rect[1], rect[2], rect[3], rect[4], x, y, world == true)
+end
+
+function doukutsu.drawRect(x, y, width, height, color, world)
+    assert(type(color) == "table", "color must be a table.")
+
+    __doukutsu_rs:drawRect(x, y, width, height, color[1], color[2], color[3], color[4] or 255, world == true)
+end
+
+function doukutsu.drawText(text, x, y, color, world)
+    color = color or { 255, 255, 255, 255 }
+
+    __doukutsu_rs:drawText(tostring(text), x, y, color[1], color[2], color[3], color[4] or 255, world == true)
+end
+
+function doukutsu.drawNumber(value, x, y, alignRight, world)
+    __doukutsu_rs:drawNumber(value, x, y, alignRight == true, world == true)
+end
+
 function doukutsu.players()
     return { __doukutsu_rs_runtime_dont_touch._playerRef0, __doukutsu_rs_runtime_dont_touch._playerRef1 }
 end
@@ -408,6 +478,15 @@ function doukutsu.setNPCHandler(npc_type, handler)
     __doukutsu_rs_runtime_dont_touch._registeredNPCHooks[npc_type] = handler
 end
 
+function doukutsu.registerTSCOpcode(name, operands, handler)
+    assert(type(name) == "string", "opcode name must be a string.")
+    assert(type(operands) == "number", "operand count must be an integer.")
+    assert(type(handler) == "function", "opcode handler must be a function.")
+
+    __doukutsu_rs:registerTSCOpcode(name, operands)
+    __doukutsu_rs_runtime_dont_touch._registeredTSCOpcodes[name] = handler
+end
+
 function doukutsu.on(event, handler)
     assert(type(event) == "string", "event type must be a string.")
     assert(type(handler) == "function", "event handler must be a function.")
@@ -258,6 +258,40 @@ declare namespace doukutsu {
      */
     function setSkipFlag(id: number, value: boolean): void;
 
+    /**
+     * Draws a part of a sprite sheet. Can only be used inside of draw event handlers.
+     * @param sheet name of the sheet, eg. "MyChar" or "Npc/NpcSym"
+     * @param rect source rectangle as [left, top, right, bottom]
+     * @param x destination position in X axis
+     * @param y destination position in Y axis
+     * @param world if true, the position is relative to the stage instead of the screen
+     */
+    function drawSprite(sheet: string, rect: [number, number, number, number], x: number, y: number,
+                        world?: boolean): void;
+
+    /**
+     * Draws a filled rectangle. Can only be used inside of draw event handlers.
+     * @param color color as [r, g, b, a] (0-255), alpha defaults to 255
+     * @param world if true, the position is relative to the stage instead of the screen
+     */
+    function drawRect(x: number, y: number, width: number, height: number, color: [number, number, number, number?],
+                      world?: boolean): void;
+
+    /**
+     * Draws a text using the game font. Can only be used inside of draw event handlers.
+     * @param color color as [r, g, b, a] (0-255), defaults to white
+     * @param world if true, the position is relative to the stage instead of the screen
+     */
+    function drawText(text: string, x: number, y: number, color?: [number, number, number, number?],
+                      world?: boolean): void;
+
+    /**
+     * Draws a number using the digits from TextBox sheet. Can only be used inside of draw event handlers.
+     * @param alignRight if true, the number ends at the given position instead of starting there
+     * @param world if true, the position is relative to the stage instead of the screen
+     */
+    function drawNumber(value: number, x: number, y: number, alignRight?: boolean, world?: boolean): void;
+
     /**
      * Returns a list of players currently in game.
      */
@@ -290,6 +324,17 @@ declare namespace doukutsu {
      */
     function setNPCHandler(npcType: number, handler: (this: void, npc: NPC) => void | null): void;
 
+    /**
+     * Registers a custom TSC command, eg. `<XYZ0001:0002`. Scripts are loaded before the TSC files are compiled,
+     * so the command can be used in any of them. The handler is called with the operands of the command and runs
+     * as a coroutine, calling `coroutine.yield(ticks)` pauses the script for given number of ticks.
+     * @param name three letter name of the command, can't be one of built-in commands
+     * @param operands number of operands, from 0 to 4
+     * @param handler command handler procedure
+     */
+    function registerTSCOpcode(name: string, operands: number,
+                               handler: (this: void, ...operands: number[]) => void): void;
+
     /**
      * Registers an event handler called after all scripts are loaded.
      * @param event event name
@@ -304,5 +349,13 @@ declare namespace doukutsu {
      */
     function on(event: "tick", handler: EventHandler<DoukutsuStage>): EventHandler<DoukutsuStage>;
 
+    /**
+     * Registers an event handler called each frame after the stage and HUD are drawn. Drawing functions can only
+     * be used inside of it.
+     * @param event event name
+     * @param handler event handler procedure
+     */
+    function on(event: "draw", handler: EventHandler<DoukutsuStage>): EventHandler<DoukutsuStage>;
+
     function on<T>(event: string, handler: EventHandler<T>): EventHandler<T>;
 }
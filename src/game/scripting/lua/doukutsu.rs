@@ -1,12 +1,16 @@
 use std::io::Read;
+use std::str::FromStr;
 
 use lua_ffi::{c_int, LuaObject, State};
 use lua_ffi::c_str;
 use lua_ffi::ffi::luaL_Reg;
 use lua_ffi::lua_method;
 
-use crate::common::{Direction, Rect};
+use crate::common::{Color, Direction, Rect};
+use crate::components::draw_common::{draw_number, Alignment};
 use crate::framework::filesystem;
+use crate::framework::graphics;
+use crate::game::scripting::tsc::opcodes::{CustomOpCode, TSCOpCode};
 use crate::game::scripting::lua::{check_status, DRS_RUNTIME_GLOBAL, LuaScriptingState};
 use crate::scene::game_scene::LightingMode;
 use crate::util::rng::RNG;
@@ -464,6 +468,188 @@ impl Doukutsu {
         1
     }
 
+    /// Returns the offset subtracted from the coordinates passed to drawing functions.
+    unsafe fn draw_offset(&self, world: bool) -> (f32, f32) {
+        if !world || (*self.ptr).game_scene.is_null() {
+            return (0.0, 0.0);
+        }
+
+        let game_scene = &*(*self.ptr).game_scene;
+        let game_state = &*(*self.ptr).state_ptr;
+
+        game_scene.frame.xy_interpolated(game_state.frame_time)
+    }
+
+    unsafe fn check_drawing(&self, state: &mut State) -> bool {
+        if !(*self.ptr).drawing {
+            state.error("Drawing functions can only be used inside of draw event handlers.");
+            return false;
+        }
+
+        true
+    }
+
+    unsafe fn lua_draw_sprite(&self, state: &mut State) -> c_int {
+        if !self.check_drawing(state) {
+            return 0;
+        }
+
+        let sheet = state.to_str(2).map(|s| s.to_owned());
+        let rect = (state.to_int(3), state.to_int(4), state.to_int(5), state.to_int(6));
+        let pos = (state.to_float(7), state.to_float(8));
+        let world = state.to_bool(9).unwrap_or(false);
+
+        if let (Some(sheet), (Some(l), Some(t), Some(r), Some(b)), (Some(x), Some(y))) = (sheet, rect, pos) {
+            let game_state = &mut (*(*self.ptr).state_ptr);
+            let ctx = &mut (*(*self.ptr).ctx_ptr);
+            let (off_x, off_y) = self.draw_offset(world);
+
+            let rect = Rect { left: l as u16, top: t as u16, right: r as u16, bottom: b as u16 };
+            let result = match game_state.texture_set.get_or_load_batch(ctx, &game_state.constants, &sheet) {
+                Ok(batch) => {
+                    batch.add_rect(x - off_x, y - off_y, &rect);
+                    batch.draw(ctx)
+                }
+                Err(err) => Err(err),
+            };
+
+            if let Err(err) = result {
+                state.error(&err.to_string());
+            }
+        } else {
+            state.error("Invalid parameters supplied.");
+        }
+
+        0
+    }
+
+    unsafe fn lua_draw_rect(&self, state: &mut State) -> c_int {
+        if !self.check_drawing(state) {
+            return 0;
+        }
+
+        let rect = (state.to_float(2), state.to_float(3), state.to_float(4), state.to_float(5));
+        let color = (state.to_int(6), state.to_int(7), state.to_int(8), state.to_int(9).unwrap_or(255));
+        let world = state.to_bool(10).unwrap_or(false);
+
+        if let ((Some(x), Some(y), Some(w), Some(h)), (Some(r), Some(g), Some(b), a)) = (rect, color) {
+            let game_state = &mut (*(*self.ptr).state_ptr);
+            let ctx = &mut (*(*self.ptr).ctx_ptr);
+            let (off_x, off_y) = self.draw_offset(world);
+
+            let scale = game_state.scale;
+            let rect = Rect::new_size(
+                ((x - off_x) * scale) as isize,
+                ((y - off_y) * scale) as isize,
+                (w * scale) as isize,
+                (h * scale) as isize,
+            );
+            let color = Color::from_rgba(r as u8, g as u8, b as u8, a as u8);
+
+            if let Err(err) = graphics::draw_rect(ctx, rect, color) {
+                state.error(&err.to_string());
+            }
+        } else {
+            state.error("Invalid parameters supplied.");
+        }
+
+        0
+    }
+
+    unsafe fn lua_draw_text(&self, state: &mut State) -> c_int {
+        if !self.check_drawing(state) {
+            return 0;
+        }
+
+        let text = state.to_str(2).map(|s| s.to_owned());
+        let pos = (state.to_float(3), state.to_float(4));
+        let color = (
+            state.to_int(5).unwrap_or(255),
+            state.to_int(6).unwrap_or(255),
+            state.to_int(7).unwrap_or(255),
+            state.to_int(8).unwrap_or(255),
+        );
+        let world = state.to_bool(9).unwrap_or(false);
+
+        if let (Some(text), (Some(x), Some(y))) = (text, pos) {
+            let game_state = &mut (*(*self.ptr).state_ptr);
+            let ctx = &mut (*(*self.ptr).ctx_ptr);
+            let (off_x, off_y) = self.draw_offset(world);
+
+            let result = game_state
+                .font
+                .builder()
+                .position(x - off_x, y - off_y)
+                .color((color.0 as u8, color.1 as u8, color.2 as u8, color.3 as u8))
+                .shadow(true)
+                .draw(&text, ctx, &game_state.constants, &mut game_state.texture_set);
+
+            if let Err(err) = result {
+                state.error(&err.to_string());
+            }
+        } else {
+            state.error("Invalid parameters supplied.");
+        }
+
+        0
+    }
+
+    unsafe fn lua_draw_number(&self, state: &mut State) -> c_int {
+        if !self.check_drawing(state) {
+            return 0;
+        }
+
+        let value = state.to_int(2);
+        let pos = (state.to_float(3), state.to_float(4));
+        let align = if state.to_bool(5).unwrap_or(false) { Alignment::Right } else { Alignment::Left };
+        let world = state.to_bool(6).unwrap_or(false);
+
+        if let (Some(value), (Some(x), Some(y))) = (value, pos) {
+            let game_state = &mut (*(*self.ptr).state_ptr);
+            let ctx = &mut (*(*self.ptr).ctx_ptr);
+            let (off_x, off_y) = self.draw_offset(world);
+
+            if let Err(err) = draw_number(x - off_x, y - off_y, value.max(0) as usize, align, game_state, ctx) {
+                state.error(&err.to_string());
+            }
+        } else {
+            state.error("Invalid parameters supplied.");
+        }
+
+        0
+    }
+
+    unsafe fn lua_register_tsc_opcode(&self, state: &mut State) -> c_int {
+        let name = state.to_str(2).map(|s| s.to_owned());
+        let operands = state.to_int(3);
+
+        if let (Some(name), Some(operands)) = (name, operands) {
+            if name.chars().count() != 3 {
+                state.error("TSC opcode name must be 3 characters long.");
+                return 0;
+            }
+
+            if TSCOpCode::from_str(&name).is_ok() {
+                state.error(&format!("<{} is a built-in TSC opcode.", name));
+                return 0;
+            }
+
+            if !(0..=4).contains(&operands) {
+                state.error("TSC opcodes can have up to 4 operands.");
+                return 0;
+            }
+
+            let game_state = &mut (*(*self.ptr).state_ptr);
+            let custom_opcodes = &mut game_state.constants.custom_opcodes;
+            custom_opcodes.retain(|op| op.name != name);
+            custom_opcodes.push(CustomOpCode { name, operands: operands as u8 });
+        } else {
+            state.error("Invalid parameters supplied.");
+        }
+
+        0
+    }
+
     unsafe fn lua_load_script(&mut self, state: &mut State) -> c_int {
         let lua_state = &mut (*self.ptr);
 
@@ -530,6 +716,11 @@ impl LuaObject for Doukutsu {
             lua_method!("npcCommand", Doukutsu, Doukutsu::lua_npc_command),
             lua_method!("stageCommand", Doukutsu, Doukutsu::lua_stage_command),
             lua_method!("loadScript", Doukutsu, Doukutsu::lua_load_script),
+            lua_method!("drawSprite", Doukutsu, Doukutsu::lua_draw_sprite),
+            lua_method!("drawRect", Doukutsu, Doukutsu::lua_draw_rect),
+            lua_method!("drawText", Doukutsu, Doukutsu::lua_draw_text),
+            lua_method!("drawNumber", Doukutsu, Doukutsu::lua_draw_number),
+            lua_method!("registerTSCOpcode", Doukutsu, Doukutsu::lua_register_tsc_opcode),
         ]
     }
 }
@@ -558,4 +749,50 @@ impl LuaScriptingState {
 
         result
     }
+
+    /// Runs the handler of a custom TSC opcode. Returns the number of ticks to wait for before calling
+    /// [LuaScriptingState::resume_tsc_opcode] if the handler has yielded.
+    pub fn run_tsc_opcode(&mut self, name: &str, operands: &[i32]) -> Option<u16> {
+        let mut result = None;
+
+        if let Some(state) = &mut self.state {
+            state.get_global(DRS_RUNTIME_GLOBAL);
+            state.get_field(-1, "_runTSCOpcode");
+
+            state.push(name);
+            for &operand in operands {
+                state.push(operand);
+            }
+
+            if let Err((_, err)) = state.pcall(operands.len() as c_int + 1, 1, 0) {
+                log::error!("tsc opcode error: {}", err);
+            } else if let Some(ticks) = state.to_float(-1) {
+                result = Some(ticks.clamp(0.0, u16::MAX as f32) as u16);
+            }
+
+            state.pop(2);
+        }
+
+        result
+    }
+
+    /// Resumes the handler of a custom TSC opcode which has yielded, see [LuaScriptingState::run_tsc_opcode].
+    pub fn resume_tsc_opcode(&mut self) -> Option<u16> {
+        let mut result = None;
+
+        if let Some(state) = &mut self.state {
+            state.get_global(DRS_RUNTIME_GLOBAL);
+            state.get_field(-1, "_resumeTSCOpcode");
+
+            if let Err((_, err)) = state.pcall(0, 1, 0) {
+                log::error!("tsc opcode error: {}", err);
+            } else if let Some(ticks) = state.to_float(-1) {
+                result = Some(ticks.clamp(0.0, u16::MAX as f32) as u16);
+            }
+
+            state.pop(2);
+        }
+
+        result
+    }
 }
@@ -23,6 +23,8 @@ pub struct LuaScriptingState {
     state_ptr: *mut SharedGameState,
     ctx_ptr: *mut Context,
     game_scene: *mut GameScene,
+    mod_scripts: Vec<String>,
+    drawing: bool,
 }
 
 pub(crate) static DRS_API_GLOBAL: &str = "__doukutsu_rs";
@@ -62,7 +64,19 @@ fn print(state: &mut State) -> c_int {
 
 impl LuaScriptingState {
     pub fn new() -> LuaScriptingState {
-        LuaScriptingState { state: None, state_ptr: null_mut(), ctx_ptr: null_mut(), game_scene: null_mut() }
+        LuaScriptingState {
+            state: None,
+            state_ptr: null_mut(),
+            ctx_ptr: null_mut(),
+            game_scene: null_mut(),
+            mod_scripts: Vec::new(),
+            drawing: false,
+        }
+    }
+
+    /// Sets the entry points of the active mods, loaded by the next `reload_scripts` call.
+    pub fn set_mod_scripts(&mut self, mod_scripts: Vec<String>) {
+        self.mod_scripts = mod_scripts;
     }
 
     pub fn update_refs(&mut self, state: *mut SharedGameState, ctx: *mut Context) {
@@ -107,6 +121,11 @@ impl LuaScriptingState {
     }
 
     pub fn reload_scripts(&mut self, ctx: &mut Context) -> GameResult {
+        if !self.state_ptr.is_null() {
+            // the scripts register them again while loading
+            unsafe { (*self.state_ptr).constants.custom_opcodes.clear() };
+        }
+
         let mut state = State::new();
         state.open_libs();
 
@@ -161,6 +180,19 @@ impl LuaScriptingState {
             }
         }
 
+        for path in self.mod_scripts.iter() {
+            match filesystem::open(ctx, path) {
+                Ok(script) => {
+                    if !LuaScriptingState::load_script(&mut state, path, script) {
+                        log::warn!("Error loading mod script {}.", path);
+                    }
+                }
+                Err(err) => {
+                    log::warn!("Error opening script {:?}: {}", path, err);
+                }
+            }
+        }
+
         self.state = Some(state);
 
         Ok(())
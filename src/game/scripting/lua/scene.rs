@@ -56,4 +56,23 @@ impl LuaScriptingState {
             state.pop(2);
         }
     }
+
+    pub fn scene_draw(&mut self) {
+        if let Some(state) = &mut self.state {
+            let val = LuaGameScene::new(self.game_scene);
+
+            state.get_global(DRS_RUNTIME_GLOBAL);
+            state.get_field(-1, "_handlers");
+            state.get_field(-1, "draw");
+
+            self.drawing = true;
+            state.push(val);
+            if let Err((_, err)) = state.pcall(1, 0, 0) {
+                log::error!("scene_draw error: {}", err);
+            }
+            self.drawing = false;
+
+            state.pop(2);
+        }
+    }
 }
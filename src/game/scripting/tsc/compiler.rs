@@ -1,3 +1,4 @@
+use std::cell::Cell;
 use std::collections::HashMap;
 use std::iter::Peekable;
 use std::str::FromStr;
@@ -5,27 +6,63 @@ use std::str::FromStr;
 use itertools::Itertools;
 
 use crate::framework::error::GameError::ParseError;
-use crate::framework::error::GameResult;
+use crate::framework::error::{GameError, GameResult};
 use crate::game::scripting::tsc::bytecode_utils::{put_string, put_varint};
 use crate::game::scripting::tsc::credit_script::CreditScript;
-use crate::game::scripting::tsc::opcodes::{CreditOpCode, TSCOpCode};
+use crate::game::scripting::tsc::opcodes::{CreditOpCode, CustomOpCode, TSCOpCode};
 use crate::game::scripting::tsc::parse_utils::{expect_char, read_number, skip_until};
 use crate::game::scripting::tsc::text_script::{TextScript, TextScriptEncoding};
 
 impl TextScript {
     /// Compiles a decrypted text script data into internal bytecode.
-    pub fn compile(data: &[u8], strict: bool, encoding: TextScriptEncoding) -> GameResult<TextScript> {
+    /// `custom_opcodes` are the commands registered by scripts in addition to the built-in ones.
+    pub fn compile(
+        data: &[u8],
+        strict: bool,
+        encoding: TextScriptEncoding,
+        custom_opcodes: &[CustomOpCode],
+    ) -> GameResult<TextScript> {
+        TextScript::compile_with_line(data, strict, encoding, custom_opcodes).map_err(|(line, err)| match err {
+            ParseError(msg) => ParseError(format!("Line {}: {}", line, msg)),
+            err => err,
+        })
+    }
+
+    /// Same as [TextScript::compile], but returns the 1-based line number the error occurred at separately.
+    pub fn compile_with_line(
+        data: &[u8],
+        strict: bool,
+        encoding: TextScriptEncoding,
+        custom_opcodes: &[CustomOpCode],
+    ) -> Result<TextScript, (usize, GameError)> {
+        let position = Cell::new(0usize);
+        let mut iter = data.iter().copied().inspect(|_| position.set(position.get() + 1)).peekable();
+
+        TextScript::compile_events(&mut iter, strict, encoding, custom_opcodes).map_err(|err| {
+            // the peeked character is already consumed from the underlying iterator
+            let consumed = position.get().saturating_sub(1).min(data.len());
+            let line = data[..consumed].iter().filter(|&&c| c == b'\n').count() + 1;
+
+            (line, err)
+        })
+    }
+
+    fn compile_events<I: Iterator<Item=u8>>(
+        iter: &mut Peekable<I>,
+        strict: bool,
+        encoding: TextScriptEncoding,
+        custom_opcodes: &[CustomOpCode],
+    ) -> GameResult<TextScript> {
         let mut event_map = HashMap::new();
-        let mut iter = data.iter().copied().peekable();
         let mut last_event = 0;
 
         while let Some(&chr) = iter.peek() {
             match chr {
                 b'#' => {
                     iter.next();
-                    let event_num = read_number(&mut iter)? as u16;
+                    let event_num = read_number(iter)? as u16;
                     if iter.peek().is_some() {
-                        skip_until(b'\n', &mut iter)?;
+                        skip_until(b'\n', iter)?;
                         iter.next();
                     }
                     last_event = event_num;
@@ -35,7 +72,7 @@ impl TextScript {
                             return Err(ParseError(format!("Event {} has been defined twice.", event_num)));
                         }
 
-                        match skip_until(b'#', &mut iter).ok() {
+                        match skip_until(b'#', iter).ok() {
                             Some(_) => {
                                 continue;
                             }
@@ -45,7 +82,7 @@ impl TextScript {
                         }
                     }
 
-                    let bytecode = TextScript::compile_event(&mut iter, strict, encoding)?;
+                    let bytecode = TextScript::compile_event(iter, strict, encoding, custom_opcodes)?;
                     log::info!("Successfully compiled event #{} ({} bytes generated).", event_num, bytecode.len());
                     event_map.insert(event_num, bytecode);
                 }
@@ -71,6 +108,7 @@ impl TextScript {
         iter: &mut Peekable<I>,
         strict: bool,
         encoding: TextScriptEncoding,
+        custom_opcodes: &[CustomOpCode],
     ) -> GameResult<Vec<u8>> {
         let mut bytecode = Vec::new();
         let mut char_buf = Vec::with_capacity(16);
@@ -104,7 +142,7 @@ impl TextScript {
 
                     let code = String::from_utf8_lossy(&n);
 
-                    TextScript::compile_code(&code, strict, iter, &mut bytecode)?;
+                    TextScript::compile_code(&code, strict, iter, &mut bytecode, custom_opcodes)?;
                 }
                 b'\r' => {
                     iter.next();
@@ -136,8 +174,19 @@ impl TextScript {
         strict: bool,
         iter: &mut Peekable<I>,
         out: &mut Vec<u8>,
+        custom_opcodes: &[CustomOpCode],
     ) -> GameResult {
-        let instr = TSCOpCode::from_str(code).map_err(|_| ParseError(format!("Unknown opcode: {}", code)))?;
+        let instr = match TSCOpCode::from_str(code) {
+            Ok(instr) => instr,
+            Err(_) => {
+                let custom = custom_opcodes
+                    .iter()
+                    .find(|op| op.name == code)
+                    .ok_or_else(|| ParseError(format!("Unknown opcode: {}", code)))?;
+
+                return TextScript::compile_custom_code(custom, strict, iter, out);
+            }
+        };
 
         match instr {
             // Zero operand codes
@@ -241,7 +290,8 @@ impl TextScript {
             | TSCOpCode::SMP
             | TSCOpCode::PSp
             | TSCOpCode::IpN
-            | TSCOpCode::FFm => {
+            | TSCOpCode::FFm
+            | TSCOpCode::XMU => {
                 let operand_a = read_number(iter)?;
                 if strict {
                     expect_char(b':', iter)?;
@@ -303,13 +353,48 @@ impl TextScript {
                 put_varint(operand_c as i32, out);
                 put_varint(operand_d as i32, out);
             }
-            TSCOpCode::_NOP | TSCOpCode::_UNI | TSCOpCode::_STR | TSCOpCode::_END => {
+            TSCOpCode::_NOP | TSCOpCode::_UNI | TSCOpCode::_STR | TSCOpCode::_END | TSCOpCode::_CUS => {
                 unreachable!()
             }
         }
 
         Ok(())
     }
+
+    /// Custom opcodes are stored as `(name_len: varint, name: [varint; name_len], operand_count: varint,
+    /// operands: [varint; operand_count])`, so the VM doesn't need to know about them to skip them.
+    fn compile_custom_code<I: Iterator<Item=u8>>(
+        opcode: &CustomOpCode,
+        strict: bool,
+        iter: &mut Peekable<I>,
+        out: &mut Vec<u8>,
+    ) -> GameResult {
+        let mut operands = Vec::with_capacity(opcode.operands as usize);
+
+        for i in 0..opcode.operands {
+            if i > 0 {
+                if strict {
+                    expect_char(b':', iter)?;
+                } else {
+                    iter.next().ok_or_else(|| ParseError("Script unexpectedly ended.".to_owned()))?;
+                }
+            }
+
+            operands.push(read_number(iter)?);
+        }
+
+        put_varint(TSCOpCode::_CUS as i32, out);
+        put_varint(opcode.name.chars().count() as i32, out);
+        for chr in opcode.name.chars() {
+            put_varint(chr as i32, out);
+        }
+        put_varint(operands.len() as i32, out);
+        for operand in operands {
+            put_varint(operand, out);
+        }
+
+        Ok(())
+    }
 }
 
 impl CreditScript {
@@ -409,3 +494,34 @@ impl CreditScript {
         Ok(CreditScript { labels, bytecode })
     }
 }
+
+#[test]
+fn test_compile_error_line() {
+    let script = b"#0090\r\n<MNA<FAI0000<END\r\n#0091\r\n<MNA<XYZ0000<END\r\n";
+
+    match TextScript::compile_with_line(script, false, TextScriptEncoding::UTF8, &[]) {
+        Err((line, ParseError(msg))) => {
+            assert_eq!(line, 4);
+            assert!(msg.contains("XYZ"));
+        }
+        _ => panic!("expected a parse error"),
+    }
+
+    assert!(TextScript::compile(b"#0090\r\n<MNA<END\r\n", false, TextScriptEncoding::UTF8, &[]).is_ok());
+}
+
+#[test]
+fn test_compile_custom_opcode() {
+    let script = b"#0090\r\n<XYZ0001:0002<END\r\n";
+    let custom_opcodes = [CustomOpCode { name: "XYZ".to_owned(), operands: 2 }];
+
+    assert!(TextScript::compile(script, true, TextScriptEncoding::UTF8, &[]).is_err());
+
+    let script = TextScript::compile(script, true, TextScriptEncoding::UTF8, &custom_opcodes).unwrap();
+    let mut expected = Vec::new();
+    for val in [TSCOpCode::_CUS as i32, 3, 'X' as i32, 'Y' as i32, 'Z' as i32, 2, 1, 2, TSCOpCode::END as i32] {
+        put_varint(val, &mut expected);
+    }
+
+    assert_eq!(script.event_map[&90], expected);
+}
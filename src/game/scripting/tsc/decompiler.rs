@@ -121,7 +121,8 @@ impl TextScript {
                         | TSCOpCode::SMP
                         | TSCOpCode::PSp
                         | TSCOpCode::IpN
-                        | TSCOpCode::FFm => {
+                        | TSCOpCode::FFm
+                        | TSCOpCode::XMU => {
                             let par_a = read_cur_varint(&mut cursor)?;
                             let par_b = read_cur_varint(&mut cursor)?;
 
@@ -170,6 +171,21 @@ impl TextScript {
                             }
                             result.push_str("\")\n");
                         }
+                        TSCOpCode::_CUS => {
+                            let name_len = read_cur_varint(&mut cursor)?;
+                            let mut name = String::new();
+                            for _ in 0..name_len {
+                                name.push(std::char::from_u32(read_cur_varint(&mut cursor)? as u32).unwrap_or('?'));
+                            }
+
+                            let operand_count = read_cur_varint(&mut cursor)?;
+                            let mut operands = Vec::new();
+                            for _ in 0..operand_count {
+                                operands.push(read_cur_varint(&mut cursor)?.to_string());
+                            }
+
+                            writeln!(&mut result, "%custom({}, [{}])", name, operands.join(", ")).unwrap();
+                        }
                         TSCOpCode::_NOP => result.push_str("%no_op()\n"),
                         TSCOpCode::_UNI => result.push_str("%unimplemented()\n"),
                         TSCOpCode::_END => result.push_str("%end_marker()\n"),
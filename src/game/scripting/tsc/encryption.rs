@@ -11,3 +11,33 @@ pub fn decrypt_tsc(buf: &mut [u8]) {
         *byte = byte.wrapping_add(key);
     }
 }
+
+/// Inverse of [decrypt_tsc], the middle byte of the plain text is used as the key.
+pub fn encrypt_tsc(buf: &mut [u8]) {
+    let half = buf.len() / 2;
+    let key = match buf.get(half) {
+        Some(0) => 0x07,
+        Some(&n) => n,
+        None => return,
+    };
+
+    for (idx, byte) in buf.iter_mut().enumerate() {
+        if idx == half {
+            continue;
+        }
+
+        *byte = byte.wrapping_add(key);
+    }
+}
+
+#[test]
+fn test_tsc_encryption_round_trip() {
+    let plain = b"#0090\r\n<MNA<FAI0000<END\r\n".to_vec();
+    let mut buf = plain.clone();
+
+    encrypt_tsc(&mut buf);
+    assert_ne!(buf, plain);
+
+    decrypt_tsc(&mut buf);
+    assert_eq!(buf, plain);
+}
@@ -265,6 +265,21 @@ pub enum TSCOpCode {
     /// <FRE related to player 2?
     FR2,
     // ---- Custom opcodes, for use by modders ----
+    /// internal: command registered by a script, see [CustomOpCode]
+    _CUS,
+
+    // ---- doukutsu-rs specific opcodes ----
+    /// <XMUxxxx:yyyy, Crossfades BGM to xxxx over yyyy game ticks, at the rate of the current timing mode
+    XMU,
+}
+
+/// TSC command registered by a Lua script, compiled into [TSCOpCode::_CUS].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomOpCode {
+    /// Three letter name of the command, eg. `XYZ` for `<XYZ`.
+    pub name: String,
+    /// Number of `xxxx` operands, up to 4.
+    pub operands: u8,
 }
 
 #[derive(FromPrimitive, PartialEq, Copy, Clone)]
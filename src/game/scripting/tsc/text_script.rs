@@ -9,6 +9,7 @@ use std::ops::Not;
 use std::rc::Rc;
 
 use num_traits::{clamp, FromPrimitive};
+use serde::{Deserialize, Serialize};
 
 use crate::bitfield;
 use crate::common::{Direction, FadeDirection, FadeState, Rect};
@@ -43,14 +44,14 @@ bitfield! {
     pub cutscene_skip, set_cutscene_skip: 7;
 }
 
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum TextScriptEncoding {
     UTF8 = 0,
     ShiftJIS,
 }
 
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum TextScriptLine {
     Line1 = 0,
@@ -58,14 +59,14 @@ pub enum TextScriptLine {
     Line3,
 }
 
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum ConfirmSelection {
     Yes,
     No,
 }
 
-#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
 #[repr(u8)]
 pub enum ScriptMode {
     Map,
@@ -86,7 +87,7 @@ impl Not for ConfirmSelection {
     }
 }
 
-#[derive(Debug, PartialEq, Copy, Clone)]
+#[derive(Debug, PartialEq, Copy, Clone, Serialize, Deserialize)]
 pub enum TextScriptExecutionState {
     Ended,
     Running(u16, u32),
@@ -102,6 +103,8 @@ pub enum TextScriptExecutionState {
     SaveProfile(u16, u32),
     LoadProfile,
     Reset,
+    /// Waits until the handler of a custom opcode can be resumed.
+    WaitScript(u16, u32, u16),
 }
 
 #[derive(PartialEq, Copy, Clone)]
@@ -467,6 +470,26 @@ impl TextScriptVM {
                         break;
                     }
                 }
+                TextScriptExecutionState::WaitScript(event, ip, ticks) => {
+                    if ticks > 0 {
+                        state.textscript_vm.state = TextScriptExecutionState::WaitScript(event, ip, ticks - 1);
+                        break;
+                    }
+
+                    #[allow(unused_mut, unused_assignments)]
+                    let mut wait_ticks = None;
+                    #[cfg(feature = "scripting-lua")]
+                    {
+                        wait_ticks = state.lua.resume_tsc_opcode();
+                    }
+
+                    if let Some(ticks) = wait_ticks {
+                        state.textscript_vm.state = TextScriptExecutionState::WaitScript(event, ip, ticks);
+                        break;
+                    }
+
+                    state.textscript_vm.state = TextScriptExecutionState::Running(event, ip);
+                }
                 TextScriptExecutionState::WaitConfirmation(event, ip, no_event, wait, selection) => {
                     if wait > 0 {
                         state.textscript_vm.state =
@@ -1289,6 +1312,24 @@ impl TextScriptVM {
 
                 exec_state = TextScriptExecutionState::Running(event, cursor.position() as u32);
             }
+            TSCOpCode::XMU => {
+                let song_id = read_cur_varint(&mut cursor)? as usize;
+                let ticks = read_cur_varint(&mut cursor)? as usize;
+                // frame synchronized timing has no fixed tick rate, assume the usual 60Hz display
+                let tps = match state.settings.timing_mode.get_tps() {
+                    0 => 60,
+                    tps => tps,
+                };
+                state.sound_manager.crossfade_song(
+                    song_id,
+                    ticks as f32 / tps as f32,
+                    &state.constants,
+                    &state.settings,
+                    ctx,
+                )?;
+
+                exec_state = TextScriptExecutionState::Running(event, cursor.position() as u32);
+            }
             TSCOpCode::FMU => {
                 state.sound_manager.play_song(0, &state.constants, &state.settings, ctx)?;
 
@@ -1771,10 +1812,15 @@ impl TextScriptVM {
                 );
             }
             TSCOpCode::STC => {
-                let new_record = game_scene.nikumaru.save_counter(state, ctx)?;
+                if let Some(verifier) = state.replay_verifier.as_mut() {
+                    // don't touch the records of the user when verifying someone else's replay
+                    verifier.finished = true;
+                } else {
+                    let new_record = game_scene.nikumaru.save_counter(state, ctx)?;
 
-                if state.replay_state == ReplayState::Recording {
-                    game_scene.replay.stop_recording(state, ctx, new_record)?;
+                    if state.replay_state == ReplayState::Recording {
+                        game_scene.replay.stop_recording(state, ctx, new_record)?;
+                    }
                 }
 
                 exec_state = TextScriptExecutionState::Running(event, cursor.position() as u32);
@@ -1800,6 +1846,35 @@ impl TextScriptVM {
 
                 exec_state = TextScriptExecutionState::Running(event, cursor.position() as u32);
             }
+            TSCOpCode::_CUS => {
+                let name_len = read_cur_varint(&mut cursor)?;
+                let mut name = String::new();
+                for _ in 0..name_len {
+                    name.push(std::char::from_u32(read_cur_varint(&mut cursor)? as u32).unwrap_or('?'));
+                }
+
+                let operand_count = read_cur_varint(&mut cursor)?;
+                let mut operands = Vec::new();
+                for _ in 0..operand_count {
+                    operands.push(read_cur_varint(&mut cursor)?);
+                }
+
+                #[allow(unused_mut, unused_assignments)]
+                let mut wait_ticks = None;
+                #[cfg(feature = "scripting-lua")]
+                {
+                    wait_ticks = state.lua.run_tsc_opcode(&name, &operands);
+                }
+                #[cfg(not(feature = "scripting-lua"))]
+                {
+                    log::warn!("Ignoring custom opcode <{}{:?}, Lua scripting is disabled.", name, operands);
+                }
+
+                exec_state = match wait_ticks {
+                    Some(ticks) => TextScriptExecutionState::WaitScript(event, cursor.position() as u32, ticks),
+                    None => TextScriptExecutionState::Running(event, cursor.position() as u32),
+                };
+            }
         }
 
         Ok(exec_state)
@@ -1836,7 +1911,7 @@ impl TextScript {
             decrypt_tsc(&mut buf);
         }
 
-        TextScript::compile(&buf, false, constants.textscript.encoding)
+        TextScript::compile(&buf, false, constants.textscript.encoding, &constants.custom_opcodes)
     }
 
     pub fn get_event_ids(&self) -> Vec<u16> {
@@ -11,7 +11,7 @@ use crate::input::gamepad_player_controller::GamepadController;
 use crate::input::keyboard_player_controller::KeyboardController;
 use crate::input::player_controller::PlayerController;
 use crate::input::touch_player_controller::TouchPlayerController;
-use crate::sound::InterpolationMode;
+use crate::sound::{FadeCurve, InterpolationMode};
 
 #[derive(serde::Serialize, serde::Deserialize)]
 pub struct Settings {
@@ -39,6 +39,14 @@ pub struct Settings {
     pub pause_on_focus_loss: bool,
     #[serde(default = "default_interpolation")]
     pub organya_interpolation: InterpolationMode,
+    /// Length of the crossfade between songs in seconds, 0.0 switches them instantly.
+    #[serde(default)]
+    pub crossfade_time: f32,
+    #[serde(default = "default_crossfade_curve")]
+    pub crossfade_curve: FadeCurve,
+    /// Pans and attenuates sound effects of NPCs and bullets by their position on screen.
+    #[serde(default)]
+    pub positional_sfx: bool,
     #[serde(default = "default_controller_type")]
     pub player1_controller_type: ControllerType,
     #[serde(default = "default_controller_type")]
@@ -81,6 +89,9 @@ pub struct Settings {
     pub more_rust: bool,
     #[serde(default = "default_cutscene_skip_mode")]
     pub cutscene_skip_mode: CutsceneSkipMode,
+    /// IDs of the content packs layered on top of the selected mod, in the order they were enabled.
+    #[serde(default)]
+    pub enabled_mods: Vec<String>,
 }
 
 fn default_true() -> bool {
@@ -107,6 +118,11 @@ fn default_interpolation() -> InterpolationMode {
     InterpolationMode::Linear
 }
 
+#[inline(always)]
+fn default_crossfade_curve() -> FadeCurve {
+    FadeCurve::EqualPower
+}
+
 #[inline(always)]
 fn default_speed() -> f64 {
     1.0
@@ -396,6 +412,9 @@ impl Default for Settings {
             timing_mode: default_timing(),
             pause_on_focus_loss: default_pause_on_focus_loss(),
             organya_interpolation: InterpolationMode::Linear,
+            crossfade_time: 0.0,
+            crossfade_curve: default_crossfade_curve(),
+            positional_sfx: false,
             player1_controller_type: default_controller_type(),
             player2_controller_type: default_controller_type(),
             player1_key_map: p1_default_keymap(),
@@ -419,6 +438,7 @@ impl Default for Settings {
             noclip: false,
             more_rust: false,
             cutscene_skip_mode: CutsceneSkipMode::Hold,
+            enabled_mods: Vec::new(),
         }
     }
 }
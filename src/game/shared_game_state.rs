@@ -4,6 +4,7 @@ use chrono::{Datelike, Local};
 
 use crate::common::{ControlFlags, Direction, FadeState};
 use crate::components::draw_common::{draw_number, Alignment};
+use crate::components::replay::Replay;
 use crate::data::vanilla::VanillaExtractor;
 use crate::engine_constants::EngineConstants;
 use crate::framework::backend::BackendTexture;
@@ -15,18 +16,22 @@ use crate::framework::{filesystem, graphics};
 use crate::game::caret::{Caret, CaretType};
 use crate::game::npc::NPCTable;
 use crate::game::profile::GameProfile;
+use crate::game::replay_verifier::ReplayVerifier;
 #[cfg(feature = "scripting-lua")]
 use crate::game::scripting::lua::LuaScriptingState;
 use crate::game::scripting::tsc::credit_script::{CreditScript, CreditScriptVM};
 use crate::game::scripting::tsc::text_script::{ScriptMode, TextScript, TextScriptExecutionState, TextScriptVM};
 use crate::game::settings::Settings;
-use crate::game::stage::StageData;
+use crate::game::snapshot::GameSnapshot;
+use crate::game::stage::{StageData, StageTableFormat};
 use crate::graphics::bmfont::BMFont;
 use crate::graphics::texture_set::TextureSet;
 use crate::i18n::Locale;
 use crate::input::touch_controls::TouchControls;
 use crate::mod_list::ModList;
 use crate::mod_requirements::ModRequirements;
+#[cfg(feature = "netplay")]
+use crate::netplay::NetplaySession;
 use crate::scene::game_scene::GameScene;
 use crate::scene::title_scene::TitleScene;
 use crate::scene::Scene;
@@ -277,6 +282,8 @@ pub struct SharedGameState {
     pub npc_curly_counter: u16,
     pub water_level: i32,
     pub stages: Vec<StageData>,
+    /// Format of the loaded stage table, the editor saves it back in the same one.
+    pub stage_table_format: StageTableFormat,
     pub frame_time: f64,
     pub debugger: bool,
     pub command_line: bool,
@@ -298,11 +305,18 @@ pub struct SharedGameState {
     pub sound_manager: SoundManager,
     pub settings: Settings,
     pub save_slot: usize,
+    /// Quick-save slot used by quick-save and quick-load, below `QUICK_SAVE_SLOTS`.
+    pub quick_save_slot: usize,
     pub difficulty: GameDifficulty,
     pub player_count: PlayerCount,
     pub player_count_modified_in_game: bool,
     pub player2_skin: u16,
     pub replay_state: ReplayState,
+    pub replay_verifier: Option<ReplayVerifier>,
+    /// Hash of the loaded game data, see `Replay::content_hash`.
+    pub content_hash: u64,
+    #[cfg(feature = "netplay")]
+    pub netplay: Option<NetplaySession>,
     pub mod_requirements: ModRequirements,
     pub loc: Locale,
     pub tutorial_counter: u16,
@@ -322,17 +336,10 @@ impl SharedGameState {
             None => "Doukutsu.exe",
         };
 
-        let vanilla_ext_outdir = match option_env!("VANILLA_EXT_OUTDIR") {
-            Some(outdir) => outdir,
-            None => "data",
-        };
-
-        let vanilla_extractor =
-            VanillaExtractor::from(ctx, vanilla_ext_exe.to_string(), vanilla_ext_outdir.to_string());
-        if vanilla_extractor.is_some() {
-            let result = vanilla_extractor.unwrap().extract_data();
-            if result.is_err() {
-                log::error!("Failed to extract vanilla data: {}", result.unwrap_err());
+        if let Some(vanilla_extractor) = VanillaExtractor::from(ctx, vanilla_ext_exe.to_string()) {
+            match vanilla_extractor.into_vfs() {
+                Ok(exe_fs) => filesystem::mount_vfs(ctx, Box::new(exe_fs)),
+                Err(err) => log::error!("Failed to load vanilla data: {}", err),
             }
         }
 
@@ -372,7 +379,7 @@ impl SharedGameState {
         }
 
         let season = Season::current();
-        constants.rebuild_path_list(None, season, &settings);
+        constants.rebuild_path_list(&[], season, &settings);
 
         constants.load_locales(ctx)?;
 
@@ -383,7 +390,7 @@ impl SharedGameState {
             BMFont::load(&vec!["/".to_owned()], "builtin/builtin_font.fnt", ctx, 1.0)
         })?;
 
-        let mod_list = ModList::load(ctx, &constants.string_table)?;
+        let mod_list = ModList::load(ctx, &constants)?;
 
         for i in 0..0xffu8 {
             let path = format!("pxt/fx{:02x}.pxt", i);
@@ -401,6 +408,7 @@ impl SharedGameState {
 
         sound_manager.set_song_volume(settings.bgm_volume);
         sound_manager.set_sfx_volume(settings.sfx_volume);
+        sound_manager.set_positional_sfx(settings.positional_sfx);
 
         let current_time = Local::now();
         let more_rust = (current_time.month() == 7 && current_time.day() == 7) || settings.more_rust;
@@ -430,6 +438,7 @@ impl SharedGameState {
             npc_curly_counter: 0,
             water_level: 0,
             stages: Vec::with_capacity(96),
+            stage_table_format: StageTableFormat::Freeware,
             frame_time: 0.0,
             debugger: false,
             command_line: false,
@@ -451,11 +460,16 @@ impl SharedGameState {
             sound_manager,
             settings,
             save_slot: 1,
+            quick_save_slot: 0,
             difficulty: GameDifficulty::Normal,
             player_count: PlayerCount::One,
             player_count_modified_in_game: false,
             player2_skin: 0,
             replay_state: ReplayState::None,
+            replay_verifier: None,
+            content_hash: 0,
+            #[cfg(feature = "netplay")]
+            netplay: None,
             mod_requirements,
             loc: locale,
             tutorial_counter: 0,
@@ -464,8 +478,35 @@ impl SharedGameState {
         })
     }
 
+    /// Returns the paths of the selected mod and the enabled content packs, along with their dependencies, lowest
+    /// priority first.
+    pub fn active_mod_paths(&self) -> Vec<String> {
+        let stack = self.mod_list.resolve_stack(self.mod_path.as_deref(), &self.settings.enabled_mods);
+        let mut mod_paths: Vec<String> = stack.iter().map(|mod_info| mod_info.path.clone()).collect();
+
+        // mods not in the list can still be loaded directly, eg. by replays
+        if let Some(mod_path) = &self.mod_path {
+            if !mod_paths.contains(mod_path) {
+                mod_paths.insert(0, mod_path.clone());
+            }
+        }
+
+        mod_paths
+    }
+
     pub fn reload_resources(&mut self, ctx: &mut Context) -> GameResult {
-        self.constants.rebuild_path_list(self.mod_path.clone(), self.season, &self.settings);
+        self.constants.rebuild_path_list(&self.active_mod_paths(), self.season, &self.settings);
+        self.constants.reset_constant_overrides();
+        #[cfg(feature = "scripting-lua")]
+        {
+            let entry_points = self
+                .mod_list
+                .resolve_stack(self.mod_path.as_deref(), &self.settings.enabled_mods)
+                .iter()
+                .filter_map(|mod_info| mod_info.manifest.as_ref()?.entry_point_path(&mod_info.path))
+                .collect();
+            self.lua.set_mod_scripts(entry_points);
+        }
         if !self.constants.is_demo {
             //TODO find a more elegant way to handle this
             self.constants.special_treatment_for_csplus_mods(self.mod_path.as_ref());
@@ -473,13 +514,20 @@ impl SharedGameState {
         self.constants.load_csplus_tables(ctx)?;
         self.constants.load_animated_faces(ctx)?;
         self.constants.load_texture_size_hints(ctx)?;
-        let stages = StageData::load_stage_table(ctx, &self.constants.base_paths, self.constants.is_switch)?;
+        self.constants.apply_constant_json_files(ctx)?;
+        let (stages, stage_table_format) =
+            StageData::load_stage_table(ctx, &self.constants.base_paths, self.constants.is_switch)?;
         self.stages = stages;
+        self.stage_table_format = stage_table_format;
 
         let npc_tbl = filesystem::open_find(ctx, &self.constants.base_paths, "npc.tbl")?;
         let npc_table = NPCTable::load_from(npc_tbl)?;
         self.npc_table = npc_table;
 
+        // scripts can register custom TSC opcodes, so they have to be loaded first
+        #[cfg(feature = "scripting-lua")]
+        self.lua.reload_scripts(ctx)?;
+
         let head_tsc = filesystem::open_find(ctx, &self.constants.base_paths, "Head.tsc")?;
         let head_script = TextScript::load_from(head_tsc, &self.constants)?;
         self.textscript_vm.set_global_script(head_script);
@@ -488,6 +536,8 @@ impl SharedGameState {
         let arms_item_script = TextScript::load_from(arms_item_tsc, &self.constants)?;
         self.textscript_vm.set_inventory_script(arms_item_script);
 
+        self.content_hash = Replay::content_hash(self, ctx)?;
+
         let stage_select_tsc = filesystem::open_find(ctx, &self.constants.base_paths, "StageSelect.tsc")?;
         let stage_select_script = TextScript::load_from(stage_select_tsc, &self.constants)?;
         self.textscript_vm.set_stage_select_script(stage_select_script);
@@ -509,7 +559,7 @@ impl SharedGameState {
     }
 
     pub fn reload_graphics(&mut self) {
-        self.constants.rebuild_path_list(self.mod_path.clone(), self.season, &self.settings);
+        self.constants.rebuild_path_list(&self.active_mod_paths(), self.season, &self.settings);
         self.texture_set.unload_all();
     }
 
@@ -628,6 +678,60 @@ impl SharedGameState {
         self.start_new_game(ctx)
     }
 
+    /// Returns whether the snapshot was saved.
+    pub fn quick_save(&mut self, game_scene: &mut GameScene, ctx: &mut Context) -> GameResult<bool> {
+        if self.replay_state != ReplayState::None || self.textscript_vm.mode != ScriptMode::Map {
+            log::info!("Quick-save is not available right now.");
+            return Ok(false);
+        }
+
+        if let Some(snapshot_path) = self.get_snapshot_filename(self.save_slot, self.quick_save_slot) {
+            let snapshot = GameSnapshot::capture(self, game_scene)?;
+            snapshot.write_to(filesystem::user_create(ctx, snapshot_path)?)?;
+
+            Ok(true)
+        } else {
+            log::info!("Mod has saves disabled.");
+
+            Ok(false)
+        }
+    }
+
+    pub fn quick_load(&mut self, ctx: &mut Context) -> GameResult {
+        if self.replay_state != ReplayState::None {
+            log::info!("Quick-load is not available right now.");
+            return Ok(());
+        }
+
+        if let Some(snapshot_path) = self.get_snapshot_filename(self.save_slot, self.quick_save_slot) {
+            if let Ok(data) = filesystem::user_open(ctx, snapshot_path) {
+                match GameSnapshot::read_from(data).and_then(|snapshot| Ok((snapshot.profile()?, snapshot))) {
+                    Ok((profile, snapshot)) => {
+                        self.reset();
+                        let mut next_scene = GameScene::new(self, ctx, snapshot.stage_id)?;
+
+                        profile.apply(self, &mut next_scene, ctx);
+                        next_scene.pending_snapshot = Some(Box::new(snapshot));
+
+                        #[cfg(feature = "scripting-lua")]
+                        self.lua.reload_scripts(ctx)?;
+
+                        self.next_scene = Some(Box::new(next_scene));
+                    }
+                    Err(e) => {
+                        log::warn!("Failed to load quick-save: {}", e);
+                    }
+                }
+            } else {
+                log::warn!("No quick-save found.");
+            }
+        } else {
+            log::info!("Mod has saves disabled.");
+        }
+
+        Ok(())
+    }
+
     pub fn reset(&mut self) {
         self.control_flags.0 = 0;
         self.game_flags = BitVec::with_size(8000);
@@ -747,6 +851,12 @@ impl SharedGameState {
 
     pub fn get_save_filename(&mut self, slot: usize) -> Option<String> {
         if let Some(mod_path) = &self.mod_path {
+            // mods with a manifest don't have save slot numbers assigned, so their IDs are used instead
+            let manifest = self.mod_list.get_info_from_path(mod_path).and_then(|mod_info| mod_info.manifest.as_ref());
+            if let Some(manifest) = manifest {
+                return if manifest.saves { Some(format!("/Mod_{}_Profile{}.dat", manifest.id, slot)) } else { None };
+            }
+
             let save_slot = self.mod_list.get_save_from_path(mod_path.to_string());
             if save_slot < 0 {
                 return None;
@@ -762,6 +872,31 @@ impl SharedGameState {
         }
     }
 
+    pub fn get_snapshot_filename(&mut self, slot: usize, quick_save_slot: usize) -> Option<String> {
+        let suffix = format!(".quicksave{}.json", quick_save_slot + 1);
+        self.get_save_filename(slot).map(|path| path.replace(".dat", &suffix))
+    }
+
+    /// Returns the data directory edited resource files should be written to.
+    ///
+    /// With a mod loaded it's always the mod directory, otherwise the directory `path` (relative, without the leading
+    /// slash) was loaded from. The game data itself is read-only, the files end up in the `editor` directory in the
+    /// user directory, which is mounted over the game data both in and outside of the editor, so they shadow the
+    /// originals. Edits of a mod are kept under `editor/mods/<mod>/`, copy its contents into the mod directory to
+    /// distribute them.
+    pub fn get_resource_save_root(&self, ctx: &Context, path: &str) -> String {
+        match &self.mod_path {
+            Some(mod_path) => mod_path.clone(),
+            None => self
+                .constants
+                .base_paths
+                .iter()
+                .find(|root| filesystem::exists(ctx, [root.as_str(), path].join("")))
+                .cloned()
+                .unwrap_or_else(|| "/".to_owned()),
+        }
+    }
+
     pub fn get_rec_filename(&self) -> String {
         if let Some(mod_path) = &self.mod_path {
             let name = self.mod_list.get_name_from_path(mod_path.to_string());
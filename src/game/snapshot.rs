@@ -0,0 +1,299 @@
+use std::io;
+use std::io::Cursor;
+
+use serde::{Deserialize, Serialize};
+
+use crate::common::{get_timestamp, ControlFlags, FadeState};
+use crate::framework::context::Context;
+use crate::framework::error::GameError::ResourceLoadError;
+use crate::framework::error::GameResult;
+use crate::game::frame::Frame;
+use crate::game::inventory::Inventory;
+use crate::game::npc::boss::BossNPC;
+use crate::game::npc::list::NPCList;
+use crate::game::npc::NPC;
+use crate::game::player::{PlayerSnapshot, TargetPlayer};
+use crate::game::profile::GameProfile;
+use crate::game::scripting::tsc::text_script::{TextScriptExecutionState, TextScriptLine, TextScriptVM};
+use crate::game::shared_game_state::SharedGameState;
+use crate::game::weapon::bullet::Bullet;
+use crate::scene::game_scene::GameScene;
+use crate::sound::SongSnapshot;
+use crate::util::bitvec::BitVec;
+
+pub const SNAPSHOT_VERSION: u32 = 2;
+
+/// Number of quick-save slots kept for every save slot.
+pub const QUICK_SAVE_SLOTS: usize = 4;
+
+/// State of the map script at the moment of taking a snapshot, including the text box contents.
+#[derive(Serialize, Deserialize)]
+pub struct TextScriptSnapshot {
+    pub state: TextScriptExecutionState,
+    pub stack: Vec<TextScriptExecutionState>,
+    pub flags: u16,
+    pub executor_player: TargetPlayer,
+    pub numbers: [u16; 4],
+    pub face: u16,
+    pub item: u16,
+    pub current_line: TextScriptLine,
+    pub line_1: Vec<char>,
+    pub line_2: Vec<char>,
+    pub line_3: Vec<char>,
+}
+
+impl TextScriptSnapshot {
+    pub fn capture(vm: &TextScriptVM) -> TextScriptSnapshot {
+        TextScriptSnapshot {
+            state: vm.state,
+            stack: vm.stack.clone(),
+            flags: vm.flags.0,
+            executor_player: vm.executor_player,
+            numbers: vm.numbers,
+            face: vm.face,
+            item: vm.item,
+            current_line: vm.current_line,
+            line_1: vm.line_1.clone(),
+            line_2: vm.line_2.clone(),
+            line_3: vm.line_3.clone(),
+        }
+    }
+
+    pub fn restore(&self, vm: &mut TextScriptVM) {
+        vm.state = self.state;
+        vm.stack = self.stack.clone();
+        vm.flags.0 = self.flags;
+        vm.executor_player = self.executor_player;
+        vm.numbers = self.numbers;
+        vm.face = self.face;
+        vm.item = self.item;
+        vm.current_line = self.current_line;
+        vm.line_1 = self.line_1.clone();
+        vm.line_2 = self.line_2.clone();
+        vm.line_3 = self.line_3.clone();
+    }
+}
+
+fn restore_npcs(npc_list: &NPCList, npcs: &[NPC]) -> GameResult {
+    npc_list.clear();
+    for npc in npcs {
+        npc_list.spawn_at_slot(npc.id, npc.clone())?;
+
+        // spawning reseeds the NPC, put the saved RNG state back
+        if let Some(slot) = npc_list.get_npc(npc.id as usize) {
+            slot.rng = npc.rng.clone();
+        }
+    }
+
+    Ok(())
+}
+
+/// Save-anywhere snapshot of a running game.
+///
+/// Persistent progress (inventory, flags, teleporters) is stored as an embedded regular profile, everything else
+/// is the live state of the stage, which is restored on top of a freshly loaded [GameScene] once it's initialized.
+/// Songs continue from where they were, including the one saved for `<RMU`.
+/// Bullets, carets and other purely visual effects are not preserved.
+#[derive(Serialize, Deserialize)]
+pub struct GameSnapshot {
+    pub version: u32,
+    pub timestamp: u64,
+    pub stage_id: usize,
+    pub profile: Vec<u8>,
+    pub player1: PlayerSnapshot,
+    pub player2: PlayerSnapshot,
+    pub npcs: Vec<NPC>,
+    pub boss: BossNPC,
+    pub tiles: Vec<u8>,
+    pub frame: Frame,
+    pub textscript: TextScriptSnapshot,
+    pub control_flags: ControlFlags,
+    pub fade_state: FadeState,
+    pub game_rng: u64,
+    pub quake_counter: u16,
+    pub super_quake_counter: u16,
+    pub water_level: i32,
+    pub nikumaru: usize,
+    pub tick: u32,
+    pub song: SongSnapshot,
+}
+
+impl GameSnapshot {
+    pub fn capture(state: &mut SharedGameState, game_scene: &mut GameScene) -> GameResult<GameSnapshot> {
+        let mut profile = Vec::new();
+        GameProfile::dump(state, game_scene).write_save(&mut profile)?;
+
+        Ok(GameSnapshot {
+            version: SNAPSHOT_VERSION,
+            timestamp: get_timestamp(),
+            stage_id: game_scene.stage_id,
+            profile,
+            player1: game_scene.player1.snapshot(),
+            player2: game_scene.player2.snapshot(),
+            npcs: game_scene.npc_list.iter_alive().map(|npc| npc.clone()).collect(),
+            boss: game_scene.boss.clone(),
+            tiles: game_scene.stage.map.tiles.clone(),
+            frame: game_scene.frame.clone(),
+            textscript: TextScriptSnapshot::capture(&state.textscript_vm),
+            control_flags: state.control_flags,
+            fade_state: state.fade_state,
+            game_rng: state.game_rng.dump_state(),
+            quake_counter: state.quake_counter,
+            super_quake_counter: state.super_quake_counter,
+            water_level: state.water_level,
+            nikumaru: game_scene.nikumaru.tick,
+            tick: game_scene.tick,
+            song: state.sound_manager.song_snapshot(),
+        })
+    }
+
+    pub fn profile(&self) -> GameResult<GameProfile> {
+        GameProfile::load_from_save(Cursor::new(&self.profile))
+    }
+
+    /// Restores the live stage state, must be called after the scene has been initialized.
+    pub fn restore(&self, state: &mut SharedGameState, game_scene: &mut GameScene, ctx: &mut Context) -> GameResult {
+        game_scene.player1.restore(&self.player1);
+        game_scene.player2.restore(&self.player2);
+
+        restore_npcs(&game_scene.npc_list, &self.npcs)?;
+        game_scene.boss = self.boss.clone();
+
+        if self.tiles.len() == game_scene.stage.map.tiles.len() {
+            game_scene.stage.map.tiles.copy_from_slice(&self.tiles);
+        } else {
+            log::warn!("Snapshot tile data doesn't match the stage, map changes were not restored.");
+        }
+
+        game_scene.frame = self.frame.clone();
+        game_scene.nikumaru.tick = self.nikumaru;
+        game_scene.tick = self.tick;
+
+        self.textscript.restore(&mut state.textscript_vm);
+        state.control_flags = self.control_flags;
+        state.fade_state = self.fade_state;
+        state.game_rng.load_state(self.game_rng);
+        state.quake_counter = self.quake_counter;
+        state.super_quake_counter = self.super_quake_counter;
+        state.water_level = self.water_level;
+        state.sound_manager.restore_song_snapshot(&self.song, &state.constants, &state.settings, ctx)?;
+
+        Ok(())
+    }
+
+    pub fn write_to<W: io::Write>(&self, data: W) -> GameResult {
+        serde_json::to_writer(data, self)?;
+
+        Ok(())
+    }
+
+    pub fn read_from<R: io::Read>(data: R) -> GameResult<GameSnapshot> {
+        let snapshot = serde_json::from_reader::<_, GameSnapshot>(data)?;
+
+        if snapshot.version != SNAPSHOT_VERSION {
+            return Err(ResourceLoadError(format!("Unsupported snapshot version: {}", snapshot.version)));
+        }
+
+        Ok(snapshot)
+    }
+}
+
+/// In-memory copy of the simulation state of a stage, used to roll back mispredicted frames in online co-op.
+///
+/// Unlike [GameSnapshot] it also covers inventories, flags and bullets, but it can only be restored into the same
+/// [GameScene] it was captured from. Carets and other purely visual effects are not preserved. Only the id of the
+/// current song is restored, the song which is playing is left alone.
+pub struct RollbackSnapshot {
+    player1: PlayerSnapshot,
+    player2: PlayerSnapshot,
+    inventory_player1: Inventory,
+    inventory_player2: Inventory,
+    npcs: Vec<NPC>,
+    boss: BossNPC,
+    bullets: Vec<Bullet>,
+    bullet_seed: u64,
+    tiles: Vec<u8>,
+    frame: Frame,
+    textscript: TextScriptSnapshot,
+    game_flags: BitVec,
+    map_flags: BitVec,
+    teleporter_slots: Vec<(u16, u16)>,
+    control_flags: ControlFlags,
+    fade_state: FadeState,
+    game_rng: u64,
+    quake_counter: u16,
+    super_quake_counter: u16,
+    water_level: i32,
+    npc_super_pos: (i32, i32),
+    npc_curly_target: (i32, i32),
+    npc_curly_counter: u16,
+    nikumaru: usize,
+    tick: u32,
+    song: usize,
+}
+
+impl RollbackSnapshot {
+    pub fn capture(state: &SharedGameState, game_scene: &GameScene) -> RollbackSnapshot {
+        RollbackSnapshot {
+            player1: game_scene.player1.snapshot(),
+            player2: game_scene.player2.snapshot(),
+            inventory_player1: game_scene.inventory_player1.clone(),
+            inventory_player2: game_scene.inventory_player2.clone(),
+            npcs: game_scene.npc_list.iter_alive().map(|npc| npc.clone()).collect(),
+            boss: game_scene.boss.clone(),
+            bullets: game_scene.bullet_manager.bullets.clone(),
+            bullet_seed: game_scene.bullet_manager.seeder.dump_state(),
+            tiles: game_scene.stage.map.tiles.clone(),
+            frame: game_scene.frame.clone(),
+            textscript: TextScriptSnapshot::capture(&state.textscript_vm),
+            game_flags: state.game_flags.clone(),
+            map_flags: state.map_flags.clone(),
+            teleporter_slots: state.teleporter_slots.clone(),
+            control_flags: state.control_flags,
+            fade_state: state.fade_state,
+            game_rng: state.game_rng.dump_state(),
+            quake_counter: state.quake_counter,
+            super_quake_counter: state.super_quake_counter,
+            water_level: state.water_level,
+            npc_super_pos: state.npc_super_pos,
+            npc_curly_target: state.npc_curly_target,
+            npc_curly_counter: state.npc_curly_counter,
+            nikumaru: game_scene.nikumaru.tick,
+            tick: game_scene.tick,
+            song: state.sound_manager.current_song(),
+        }
+    }
+
+    pub fn restore(&self, state: &mut SharedGameState, game_scene: &mut GameScene) -> GameResult {
+        game_scene.player1.restore(&self.player1);
+        game_scene.player2.restore(&self.player2);
+        game_scene.inventory_player1 = self.inventory_player1.clone();
+        game_scene.inventory_player2 = self.inventory_player2.clone();
+        restore_npcs(&game_scene.npc_list, &self.npcs)?;
+        game_scene.boss = self.boss.clone();
+        game_scene.bullet_manager.bullets = self.bullets.clone();
+        game_scene.bullet_manager.new_bullets.clear();
+        game_scene.bullet_manager.seeder.load_state(self.bullet_seed);
+        game_scene.stage.map.tiles.clone_from(&self.tiles);
+        game_scene.frame = self.frame.clone();
+        game_scene.nikumaru.tick = self.nikumaru;
+        game_scene.tick = self.tick;
+
+        self.textscript.restore(&mut state.textscript_vm);
+        state.game_flags = self.game_flags.clone();
+        state.map_flags = self.map_flags.clone();
+        state.teleporter_slots = self.teleporter_slots.clone();
+        state.control_flags = self.control_flags;
+        state.fade_state = self.fade_state;
+        state.game_rng.load_state(self.game_rng);
+        state.quake_counter = self.quake_counter;
+        state.super_quake_counter = self.super_quake_counter;
+        state.water_level = self.water_level;
+        state.npc_super_pos = self.npc_super_pos;
+        state.npc_curly_target = self.npc_curly_target;
+        state.npc_curly_counter = self.npc_curly_counter;
+        state.sound_manager.set_current_song(self.song);
+
+        Ok(())
+    }
+}
@@ -1,8 +1,8 @@
-use std::io::{Cursor, Read};
+use std::io::{Cursor, Read, Write};
 use std::str::from_utf8;
 
 use byteorder::LE;
-use byteorder::ReadBytesExt;
+use byteorder::{ReadBytesExt, WriteBytesExt};
 use log::info;
 
 use crate::common::Color;
@@ -12,8 +12,8 @@ use crate::framework::error::{GameError, GameResult};
 use crate::framework::error::GameError::ResourceLoadError;
 use crate::framework::filesystem;
 use crate::game::map::{Map, NPCData};
-use crate::game::scripting::tsc::text_script::TextScript;
-use crate::util::encoding::read_cur_shift_jis;
+use crate::game::scripting::tsc::text_script::{TextScript, TextScriptEncoding};
+use crate::util::encoding::{encode_shift_jis, read_cur_shift_jis};
 
 #[derive(Debug, PartialEq, Eq, Hash)]
 pub struct NpcType {
@@ -31,6 +31,10 @@ impl NpcType {
         Self { name: name.to_owned() }
     }
 
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
     pub fn filename(&self) -> String {
         ["Npc", &self.name].join("")
     }
@@ -205,6 +209,8 @@ pub struct StageData {
     pub pxpack_data: Option<PxPackStageData>,
     pub background: Background,
     pub background_type: BackgroundType,
+    /// Background type id as stored in the stage table, may be one the engine doesn't know.
+    pub background_type_id: u8,
     pub background_color: Color,
     pub npc1: NpcType,
     pub npc2: NpcType,
@@ -221,6 +227,7 @@ impl Clone for StageData {
             pxpack_data: self.pxpack_data.clone(),
             background: self.background.clone(),
             background_type: self.background_type,
+            background_type_id: self.background_type_id,
             background_color: self.background_color,
             npc1: self.npc1.clone(),
             npc2: self.npc2.clone(),
@@ -283,12 +290,68 @@ fn from_csplus_stagetbl(s: &[u8], is_switch: bool) -> String {
     }
 }
 
+/// Writes a zero padded, fixed size string field of a stage table.
+fn write_field<W: Write>(data: &mut W, value: &str, size: usize, encoding: TextScriptEncoding) -> GameResult {
+    let mut buf = match encoding {
+        TextScriptEncoding::ShiftJIS => encode_shift_jis(value).ok_or_else(|| {
+            GameError::InvalidValue(format!("'{}' contains characters that can't be encoded in Shift-JIS.", value))
+        })?,
+        TextScriptEncoding::UTF8 => value.as_bytes().to_vec(),
+    };
+
+    // keep space for the terminator, the original engine reads them as C strings
+    if buf.len() >= size {
+        return Err(GameError::InvalidValue(format!(
+            "'{}' is too long for the stage table, it must be shorter than {} bytes.",
+            value, size
+        )));
+    }
+
+    buf.resize(size, 0);
+    data.write_all(&buf)?;
+
+    Ok(())
+}
+
+/// NXEngine stores tilesets, backgrounds and NPC sheets as indices into fixed lists.
+fn nxengine_index(list: &[&str], name: &str, field: &str) -> GameResult<u8> {
+    list.iter().position(|&n| n == name).map(|idx| idx as u8).ok_or_else(|| {
+        GameError::InvalidValue(format!("{} '{}' is not available in NXEngine stage tables.", field, name))
+    })
+}
+
+/// Binary layout of a stage table, each supported game version stores it in a different file.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum StageTableFormat {
+    /// Cave Story+ `stage.tbl`.
+    CavestoryPlus,
+    /// Cave Story freeware executable dump, `stage.sect`.
+    Freeware,
+    /// Moustache Rider `mrmap.bin`.
+    MoustacheRider,
+    /// NXEngine `stage.dat`.
+    NXEngine,
+}
+
+impl StageTableFormat {
+    pub fn path(self) -> &'static str {
+        match self {
+            StageTableFormat::CavestoryPlus => "/stage.tbl",
+            StageTableFormat::Freeware => "/stage.sect",
+            StageTableFormat::MoustacheRider => "/mrmap.bin",
+            StageTableFormat::NXEngine => "/stage.dat",
+        }
+    }
+}
+
 impl StageData {
-    pub fn load_stage_table(ctx: &mut Context, roots: &Vec<String>, is_switch: bool) -> GameResult<Vec<Self>> {
-        let stage_tbl_path = "/stage.tbl";
-        let stage_sect_path = "/stage.sect";
-        let mrmap_bin_path = "/mrmap.bin";
-        let stage_dat_path = "/stage.dat";
+    /// Loads the stage table, returning it along with the format it was stored in.
+    pub fn load_stage_table(
+        ctx: &mut Context,
+        roots: &Vec<String>,
+        is_switch: bool,
+    ) -> GameResult<(Vec<Self>, StageTableFormat)> {
+        let stage_tbl_path = StageTableFormat::CavestoryPlus.path();
 
         if filesystem::exists_find(ctx, roots, stage_tbl_path) {
             // Cave Story+ stage table.
@@ -299,55 +362,10 @@ impl StageData {
                 if let Ok(mut file) = filesystem::open(ctx, [path, stage_tbl_path].join("")) {
                     info!("Loading Cave Story+ stage table from {}", &path);
 
-                    let mut new_stages = Vec::new();
-
                     let mut data = Vec::new();
                     file.read_to_end(&mut data)?;
 
-                    let count = data.len() / 0xe5;
-                    let mut f = Cursor::new(data);
-                    for _ in 0..count {
-                        let mut ts_buf = vec![0u8; 0x20];
-                        let mut map_buf = vec![0u8; 0x20];
-                        let mut back_buf = vec![0u8; 0x20];
-                        let mut npc1_buf = vec![0u8; 0x20];
-                        let mut npc2_buf = vec![0u8; 0x20];
-                        let mut name_jap_buf = vec![0u8; 0x20];
-                        let mut name_buf = vec![0u8; 0x20];
-
-                        f.read_exact(&mut ts_buf)?;
-                        f.read_exact(&mut map_buf)?;
-                        let bg_type = f.read_u32::<LE>()? as u8;
-                        f.read_exact(&mut back_buf)?;
-                        f.read_exact(&mut npc1_buf)?;
-                        f.read_exact(&mut npc2_buf)?;
-                        let boss_no = f.read_u8()?;
-                        f.read_exact(&mut name_jap_buf)?;
-                        f.read_exact(&mut name_buf)?;
-
-                        let tileset = from_csplus_stagetbl(&ts_buf[0..zero_index(&ts_buf)], is_switch);
-                        let map = from_csplus_stagetbl(&map_buf[0..zero_index(&map_buf)], is_switch);
-                        let background = from_csplus_stagetbl(&back_buf[0..zero_index(&back_buf)], is_switch);
-                        let npc1 = from_csplus_stagetbl(&npc1_buf[0..zero_index(&npc1_buf)], is_switch);
-                        let npc2 = from_csplus_stagetbl(&npc2_buf[0..zero_index(&npc2_buf)], is_switch);
-                        let name = from_csplus_stagetbl(&name_buf[0..zero_index(&name_buf)], is_switch);
-                        let name_jp = from_csplus_stagetbl(&name_jap_buf[0..zero_index(&name_jap_buf)], is_switch);
-
-                        let stage = StageData {
-                            name: name.clone(),
-                            name_jp: name_jp.clone(),
-                            map: map.clone(),
-                            boss_no,
-                            tileset: Tileset::new(&tileset),
-                            pxpack_data: None,
-                            background: Background::new(&background),
-                            background_type: BackgroundType::from(bg_type),
-                            background_color: Color::from_rgb(0, 0, 32),
-                            npc1: NpcType::new(&npc1),
-                            npc2: NpcType::new(&npc2),
-                        };
-                        new_stages.push(stage);
-                    }
+                    let new_stages = Self::read_stage_table(StageTableFormat::CavestoryPlus, &data, is_switch)?;
 
                     if new_stages.len() >= stages.len() {
                         stages = new_stages;
@@ -357,183 +375,312 @@ impl StageData {
                 }
             }
 
-            return Ok(stages);
-        } else if let Ok(mut file) = filesystem::open_find(ctx, roots, stage_sect_path) {
-            // Cave Story freeware executable dump.
-            let mut stages = Vec::new();
-
-            info!("Loading Cave Story freeware exe dump stage table from {}", &stage_sect_path);
-
-            let mut data = Vec::new();
-            file.read_to_end(&mut data)?;
-
-            let count = data.len() / 0xc8;
-            let mut f = Cursor::new(data);
-            for _ in 0..count {
-                let mut ts_buf = vec![0u8; 0x20];
-                let mut map_buf = vec![0u8; 0x20];
-                let mut back_buf = vec![0u8; 0x20];
-                let mut npc1_buf = vec![0u8; 0x20];
-                let mut npc2_buf = vec![0u8; 0x20];
-                let mut name_buf = vec![0u8; 0x20];
-
-                f.read_exact(&mut ts_buf)?;
-                f.read_exact(&mut map_buf)?;
-                let bg_type = f.read_u32::<LE>()? as u8;
-                f.read_exact(&mut back_buf)?;
-                f.read_exact(&mut npc1_buf)?;
-                f.read_exact(&mut npc2_buf)?;
-                let boss_no = f.read_u8()?;
-                f.read_exact(&mut name_buf)?;
-                // alignment
-                {
-                    let mut lol = [0u8; 3];
-                    let _ = f.read(&mut lol)?;
-                }
+            return Ok((stages, StageTableFormat::CavestoryPlus));
+        }
 
-                let tileset = from_shift_jis(&ts_buf[0..zero_index(&ts_buf)]);
-                let map = from_shift_jis(&map_buf[0..zero_index(&map_buf)]);
-                let background = from_shift_jis(&back_buf[0..zero_index(&back_buf)]);
-                let npc1 = from_shift_jis(&npc1_buf[0..zero_index(&npc1_buf)]);
-                let npc2 = from_shift_jis(&npc2_buf[0..zero_index(&npc2_buf)]);
-                let name = from_shift_jis(&name_buf[0..zero_index(&name_buf)]);
-
-                let stage = StageData {
-                    name: name.clone(),
-                    name_jp: name.clone(),
-                    map: map.clone(),
-                    boss_no,
-                    tileset: Tileset::new(&tileset),
-                    pxpack_data: None,
-                    background: Background::new(&background),
-                    background_type: BackgroundType::from(bg_type),
-                    background_color: Color::from_rgb(0, 0, 32),
-                    npc1: NpcType::new(&npc1),
-                    npc2: NpcType::new(&npc2),
-                };
-                stages.push(stage);
-            }
+        for format in [StageTableFormat::Freeware, StageTableFormat::MoustacheRider, StageTableFormat::NXEngine] {
+            if let Ok(mut file) = filesystem::open_find(ctx, roots, format.path()) {
+                info!("Loading {:?} stage table from {}", format, format.path());
 
-            return Ok(stages);
-        } else if let Ok(mut file) = filesystem::open_find(ctx, roots, mrmap_bin_path) {
-            // Moustache Rider stage table
-            let mut stages = Vec::new();
+                let mut data = Vec::new();
+                file.read_to_end(&mut data)?;
 
-            info!("Loading Moustache Rider stage table from {}", &mrmap_bin_path);
+                return Ok((Self::read_stage_table(format, &data, is_switch)?, format));
+            }
+        }
 
-            let mut data = Vec::new();
+        Err(ResourceLoadError("No stage table found.".to_string()))
+    }
 
-            let count = file.read_u32::<LE>()?;
-            file.read_to_end(&mut data)?;
+    /// Parses a single stage table file.
+    pub fn read_stage_table(format: StageTableFormat, data: &[u8], is_switch: bool) -> GameResult<Vec<Self>> {
+        let mut stages = Vec::new();
+        let mut f = Cursor::new(data);
+
+        match format {
+            StageTableFormat::CavestoryPlus => {
+                let count = data.len() / 0xe5;
+                for _ in 0..count {
+                    let mut ts_buf = vec![0u8; 0x20];
+                    let mut map_buf = vec![0u8; 0x20];
+                    let mut back_buf = vec![0u8; 0x20];
+                    let mut npc1_buf = vec![0u8; 0x20];
+                    let mut npc2_buf = vec![0u8; 0x20];
+                    let mut name_jap_buf = vec![0u8; 0x20];
+                    let mut name_buf = vec![0u8; 0x20];
+
+                    f.read_exact(&mut ts_buf)?;
+                    f.read_exact(&mut map_buf)?;
+                    let bg_type = f.read_u32::<LE>()? as u8;
+                    f.read_exact(&mut back_buf)?;
+                    f.read_exact(&mut npc1_buf)?;
+                    f.read_exact(&mut npc2_buf)?;
+                    let boss_no = f.read_u8()?;
+                    f.read_exact(&mut name_jap_buf)?;
+                    f.read_exact(&mut name_buf)?;
+
+                    let tileset = from_csplus_stagetbl(&ts_buf[0..zero_index(&ts_buf)], is_switch);
+                    let map = from_csplus_stagetbl(&map_buf[0..zero_index(&map_buf)], is_switch);
+                    let background = from_csplus_stagetbl(&back_buf[0..zero_index(&back_buf)], is_switch);
+                    let npc1 = from_csplus_stagetbl(&npc1_buf[0..zero_index(&npc1_buf)], is_switch);
+                    let npc2 = from_csplus_stagetbl(&npc2_buf[0..zero_index(&npc2_buf)], is_switch);
+                    let name = from_csplus_stagetbl(&name_buf[0..zero_index(&name_buf)], is_switch);
+                    let name_jp = from_csplus_stagetbl(&name_jap_buf[0..zero_index(&name_jap_buf)], is_switch);
+
+                    let stage = StageData {
+                        name: name.clone(),
+                        name_jp: name_jp.clone(),
+                        map: map.clone(),
+                        boss_no,
+                        tileset: Tileset::new(&tileset),
+                        pxpack_data: None,
+                        background: Background::new(&background),
+                        background_type: BackgroundType::from(bg_type),
+                        background_type_id: bg_type,
+                        background_color: Color::from_rgb(0, 0, 32),
+                        npc1: NpcType::new(&npc1),
+                        npc2: NpcType::new(&npc2),
+                    };
+                    stages.push(stage);
+                }
+            }
+            StageTableFormat::Freeware => {
+                let count = data.len() / 0xc8;
+                for _ in 0..count {
+                    let mut ts_buf = vec![0u8; 0x20];
+                    let mut map_buf = vec![0u8; 0x20];
+                    let mut back_buf = vec![0u8; 0x20];
+                    let mut npc1_buf = vec![0u8; 0x20];
+                    let mut npc2_buf = vec![0u8; 0x20];
+                    let mut name_buf = vec![0u8; 0x20];
+
+                    f.read_exact(&mut ts_buf)?;
+                    f.read_exact(&mut map_buf)?;
+                    let bg_type = f.read_u32::<LE>()? as u8;
+                    f.read_exact(&mut back_buf)?;
+                    f.read_exact(&mut npc1_buf)?;
+                    f.read_exact(&mut npc2_buf)?;
+                    let boss_no = f.read_u8()?;
+                    f.read_exact(&mut name_buf)?;
+                    // alignment
+                    {
+                        let mut lol = [0u8; 3];
+                        let _ = f.read(&mut lol)?;
+                    }
 
-            if data.len() < count as usize * 0x74 {
-                return Err(ResourceLoadError(
-                    "Specified stage table size is bigger than actual number of entries.".to_string(),
-                ));
+                    let tileset = from_shift_jis(&ts_buf[0..zero_index(&ts_buf)]);
+                    let map = from_shift_jis(&map_buf[0..zero_index(&map_buf)]);
+                    let background = from_shift_jis(&back_buf[0..zero_index(&back_buf)]);
+                    let npc1 = from_shift_jis(&npc1_buf[0..zero_index(&npc1_buf)]);
+                    let npc2 = from_shift_jis(&npc2_buf[0..zero_index(&npc2_buf)]);
+                    let name = from_shift_jis(&name_buf[0..zero_index(&name_buf)]);
+
+                    let stage = StageData {
+                        name: name.clone(),
+                        name_jp: name.clone(),
+                        map: map.clone(),
+                        boss_no,
+                        tileset: Tileset::new(&tileset),
+                        pxpack_data: None,
+                        background: Background::new(&background),
+                        background_type: BackgroundType::from(bg_type),
+                        background_type_id: bg_type,
+                        background_color: Color::from_rgb(0, 0, 32),
+                        npc1: NpcType::new(&npc1),
+                        npc2: NpcType::new(&npc2),
+                    };
+                    stages.push(stage);
+                }
             }
+            StageTableFormat::MoustacheRider => {
+                let count = f.read_u32::<LE>()? as usize;
+
+                if data.len() - 4 < count * 0x74 {
+                    return Err(ResourceLoadError(
+                        "Specified stage table size is bigger than actual number of entries.".to_string(),
+                    ));
+                }
 
-            let mut f = Cursor::new(data);
-            for _ in 0..count {
-                let mut ts_buf = vec![0u8; 0x10];
-                let mut map_buf = vec![0u8; 0x10];
-                let mut back_buf = vec![0u8; 0x10];
-                let mut npc1_buf = vec![0u8; 0x10];
-                let mut npc2_buf = vec![0u8; 0x10];
-                let mut name_buf = vec![0u8; 0x22];
-
-                f.read_exact(&mut ts_buf)?;
-                f.read_exact(&mut map_buf)?;
-                let bg_type = f.read_u8()?;
-                f.read_exact(&mut back_buf)?;
-                f.read_exact(&mut npc1_buf)?;
-                f.read_exact(&mut npc2_buf)?;
-                let boss_no = f.read_u8()?;
-                f.read_exact(&mut name_buf)?;
-
-                let tileset = from_shift_jis(&ts_buf[0..zero_index(&ts_buf)]);
-                let map = from_shift_jis(&map_buf[0..zero_index(&map_buf)]);
-                let background = from_shift_jis(&back_buf[0..zero_index(&back_buf)]);
-                let npc1 = from_shift_jis(&npc1_buf[0..zero_index(&npc1_buf)]);
-                let npc2 = from_shift_jis(&npc2_buf[0..zero_index(&npc2_buf)]);
-                let name = from_shift_jis(&name_buf[0..zero_index(&name_buf)]);
-
-                let stage = StageData {
-                    name: name.clone(),
-                    name_jp: name.clone(),
-                    map: map.clone(),
-                    boss_no,
-                    tileset: Tileset::new(&tileset),
-                    pxpack_data: None,
-                    background: Background::new(&background),
-                    background_type: BackgroundType::from(bg_type),
-                    background_color: Color::from_rgb(0, 0, 32),
-                    npc1: NpcType::new(&npc1),
-                    npc2: NpcType::new(&npc2),
-                };
-                stages.push(stage);
+                for _ in 0..count {
+                    let mut ts_buf = vec![0u8; 0x10];
+                    let mut map_buf = vec![0u8; 0x10];
+                    let mut back_buf = vec![0u8; 0x10];
+                    let mut npc1_buf = vec![0u8; 0x10];
+                    let mut npc2_buf = vec![0u8; 0x10];
+                    let mut name_buf = vec![0u8; 0x22];
+
+                    f.read_exact(&mut ts_buf)?;
+                    f.read_exact(&mut map_buf)?;
+                    let bg_type = f.read_u8()?;
+                    f.read_exact(&mut back_buf)?;
+                    f.read_exact(&mut npc1_buf)?;
+                    f.read_exact(&mut npc2_buf)?;
+                    let boss_no = f.read_u8()?;
+                    f.read_exact(&mut name_buf)?;
+
+                    let tileset = from_shift_jis(&ts_buf[0..zero_index(&ts_buf)]);
+                    let map = from_shift_jis(&map_buf[0..zero_index(&map_buf)]);
+                    let background = from_shift_jis(&back_buf[0..zero_index(&back_buf)]);
+                    let npc1 = from_shift_jis(&npc1_buf[0..zero_index(&npc1_buf)]);
+                    let npc2 = from_shift_jis(&npc2_buf[0..zero_index(&npc2_buf)]);
+                    let name = from_shift_jis(&name_buf[0..zero_index(&name_buf)]);
+
+                    let stage = StageData {
+                        name: name.clone(),
+                        name_jp: name.clone(),
+                        map: map.clone(),
+                        boss_no,
+                        tileset: Tileset::new(&tileset),
+                        pxpack_data: None,
+                        background: Background::new(&background),
+                        background_type: BackgroundType::from(bg_type),
+                        background_type_id: bg_type,
+                        background_color: Color::from_rgb(0, 0, 32),
+                        npc1: NpcType::new(&npc1),
+                        npc2: NpcType::new(&npc2),
+                    };
+                    stages.push(stage);
+                }
             }
+            StageTableFormat::NXEngine => {
+                let count = f.read_u8()? as usize;
 
-            return Ok(stages);
-        } else if let Ok(mut file) = filesystem::open_find(ctx, roots, stage_dat_path) {
-            let mut stages = Vec::new();
+                if data.len() - 1 < count * 0x49 {
+                    return Err(ResourceLoadError(
+                        "Specified stage table size is bigger than actual number of entries.".to_string(),
+                    ));
+                }
 
-            info!("Loading NXEngine stage table from {}", &stage_dat_path);
+                for _ in 0..count {
+                    let mut map_buf = vec![0u8; 0x20];
+                    let mut name_buf = vec![0u8; 0x23];
+
+                    f.read_exact(&mut map_buf)?;
+                    f.read_exact(&mut name_buf)?;
+
+                    let tileset_id = f.read_u8()? as usize;
+                    let bg_id = f.read_u8()? as usize;
+                    let bg_type = f.read_u8()?;
+                    let boss_no = f.read_u8()?;
+                    let npc1 = f.read_u8()? as usize;
+                    let npc2 = f.read_u8()? as usize;
+
+                    let map = from_utf8(&map_buf)
+                        .map_err(|_| ResourceLoadError("UTF-8 error in map field".to_string()))?
+                        .trim_matches('\0')
+                        .to_owned();
+                    let name = from_utf8(&name_buf)
+                        .map_err(|_| ResourceLoadError("UTF-8 error in name field".to_string()))?
+                        .trim_matches('\0')
+                        .to_owned();
+
+                    let stage = StageData {
+                        name: name.clone(),
+                        name_jp: name.clone(),
+                        map: map.clone(),
+                        boss_no,
+                        tileset: Tileset::new(NXENGINE_TILESETS.get(tileset_id).unwrap_or(&"0")),
+                        pxpack_data: None,
+                        background: Background::new(NXENGINE_BACKDROPS.get(bg_id).unwrap_or(&"0")),
+                        background_type: BackgroundType::from(bg_type),
+                        background_type_id: bg_type,
+                        background_color: Color::from_rgb(0, 0, 32),
+                        npc1: NpcType::new(NXENGINE_NPCS.get(npc1).unwrap_or(&"0")),
+                        npc2: NpcType::new(NXENGINE_NPCS.get(npc2).unwrap_or(&"0")),
+                    };
+                    stages.push(stage);
+                }
+            }
+        }
 
-            let mut data = Vec::new();
+        Ok(stages)
+    }
 
-            let count = file.read_u8()? as usize;
-            file.read_to_end(&mut data)?;
+    /// Returns the background type id to write to the stage table, unknown ids read from it are kept unless the
+    /// background type was changed.
+    pub fn stored_background_type(&self) -> u8 {
+        if BackgroundType::from(self.background_type_id) == self.background_type {
+            self.background_type_id
+        } else {
+            self.background_type as u8
+        }
+    }
 
-            if data.len() < count * 0x49 {
-                return Err(ResourceLoadError(
-                    "Specified stage table size is bigger than actual number of entries.".to_string(),
-                ));
+    /// Writes the stage table in given format, fails if any of the fields doesn't fit in it.
+    pub fn write_stage_table<W: Write>(
+        stages: &[StageData],
+        format: StageTableFormat,
+        is_switch: bool,
+        mut data: W,
+    ) -> GameResult {
+        match format {
+            StageTableFormat::CavestoryPlus => {
+                let encoding = if is_switch { TextScriptEncoding::UTF8 } else { TextScriptEncoding::ShiftJIS };
+
+                for stage in stages {
+                    write_field(&mut data, &stage.tileset.name, 0x20, encoding)?;
+                    write_field(&mut data, &stage.map, 0x20, encoding)?;
+                    data.write_u32::<LE>(stage.stored_background_type() as u32)?;
+                    write_field(&mut data, stage.background.name(), 0x20, encoding)?;
+                    write_field(&mut data, stage.npc1.name(), 0x20, encoding)?;
+                    write_field(&mut data, stage.npc2.name(), 0x20, encoding)?;
+                    data.write_u8(stage.boss_no)?;
+                    write_field(&mut data, &stage.name_jp, 0x20, encoding)?;
+                    write_field(&mut data, &stage.name, 0x20, encoding)?;
+                }
             }
-
-            let mut f = Cursor::new(data);
-            for _ in 0..count {
-                let mut map_buf = vec![0u8; 0x20];
-                let mut name_buf = vec![0u8; 0x23];
-
-                f.read_exact(&mut map_buf)?;
-                f.read_exact(&mut name_buf)?;
-
-                let tileset_id = f.read_u8()? as usize;
-                let bg_id = f.read_u8()? as usize;
-                let bg_type = f.read_u8()?;
-                let boss_no = f.read_u8()?;
-                let npc1 = f.read_u8()? as usize;
-                let npc2 = f.read_u8()? as usize;
-
-                let map = from_utf8(&map_buf)
-                    .map_err(|_| ResourceLoadError("UTF-8 error in map field".to_string()))?
-                    .trim_matches('\0')
-                    .to_owned();
-                let name = from_utf8(&name_buf)
-                    .map_err(|_| ResourceLoadError("UTF-8 error in name field".to_string()))?
-                    .trim_matches('\0')
-                    .to_owned();
-
-                let stage = StageData {
-                    name: name.clone(),
-                    name_jp: name.clone(),
-                    map: map.clone(),
-                    boss_no,
-                    tileset: Tileset::new(NXENGINE_TILESETS.get(tileset_id).unwrap_or(&"0")),
-                    pxpack_data: None,
-                    background: Background::new(NXENGINE_BACKDROPS.get(bg_id).unwrap_or(&"0")),
-                    background_type: BackgroundType::from(bg_type),
-                    background_color: Color::from_rgb(0, 0, 32),
-                    npc1: NpcType::new(NXENGINE_NPCS.get(npc1).unwrap_or(&"0")),
-                    npc2: NpcType::new(NXENGINE_NPCS.get(npc2).unwrap_or(&"0")),
-                };
-                stages.push(stage);
+            StageTableFormat::Freeware => {
+                for stage in stages {
+                    write_field(&mut data, &stage.tileset.name, 0x20, TextScriptEncoding::ShiftJIS)?;
+                    write_field(&mut data, &stage.map, 0x20, TextScriptEncoding::ShiftJIS)?;
+                    data.write_u32::<LE>(stage.stored_background_type() as u32)?;
+                    write_field(&mut data, stage.background.name(), 0x20, TextScriptEncoding::ShiftJIS)?;
+                    write_field(&mut data, stage.npc1.name(), 0x20, TextScriptEncoding::ShiftJIS)?;
+                    write_field(&mut data, stage.npc2.name(), 0x20, TextScriptEncoding::ShiftJIS)?;
+                    data.write_u8(stage.boss_no)?;
+                    write_field(&mut data, &stage.name, 0x20, TextScriptEncoding::ShiftJIS)?;
+                    // alignment
+                    data.write_all(&[0u8; 3])?;
+                }
             }
+            StageTableFormat::MoustacheRider => {
+                data.write_u32::<LE>(stages.len() as u32)?;
+
+                for stage in stages {
+                    write_field(&mut data, &stage.tileset.name, 0x10, TextScriptEncoding::ShiftJIS)?;
+                    write_field(&mut data, &stage.map, 0x10, TextScriptEncoding::ShiftJIS)?;
+                    data.write_u8(stage.stored_background_type())?;
+                    write_field(&mut data, stage.background.name(), 0x10, TextScriptEncoding::ShiftJIS)?;
+                    write_field(&mut data, stage.npc1.name(), 0x10, TextScriptEncoding::ShiftJIS)?;
+                    write_field(&mut data, stage.npc2.name(), 0x10, TextScriptEncoding::ShiftJIS)?;
+                    data.write_u8(stage.boss_no)?;
+                    write_field(&mut data, &stage.name, 0x22, TextScriptEncoding::ShiftJIS)?;
+                }
+            }
+            StageTableFormat::NXEngine => {
+                if stages.len() > u8::MAX as usize {
+                    return Err(GameError::InvalidValue(format!(
+                        "NXEngine stage table can hold at most {} stages.",
+                        u8::MAX
+                    )));
+                }
 
-            return Ok(stages);
+                data.write_u8(stages.len() as u8)?;
+
+                for stage in stages {
+                    write_field(&mut data, &stage.map, 0x20, TextScriptEncoding::UTF8)?;
+                    write_field(&mut data, &stage.name, 0x23, TextScriptEncoding::UTF8)?;
+                    data.write_u8(nxengine_index(&NXENGINE_TILESETS, &stage.tileset.name, "Tileset")?)?;
+                    data.write_u8(nxengine_index(&NXENGINE_BACKDROPS, stage.background.name(), "Background")?)?;
+                    data.write_u8(stage.stored_background_type())?;
+                    data.write_u8(stage.boss_no)?;
+                    data.write_u8(nxengine_index(&NXENGINE_NPCS, stage.npc1.name(), "NPC sheet")?)?;
+                    data.write_u8(nxengine_index(&NXENGINE_NPCS, stage.npc2.name(), "NPC sheet")?)?;
+                }
+            }
         }
 
-        Err(ResourceLoadError("No stage table found.".to_string()))
+        Ok(())
     }
 }
 
@@ -659,3 +806,54 @@ impl StageTexturePaths {
         self.npc2 = ["Npc/", &stage.data.npc2.filename()].join("");
     }
 }
+
+#[test]
+fn test_stage_table_round_trip() {
+    let stage = |name: &str, map: &str| StageData {
+        name: name.to_owned(),
+        name_jp: name.to_owned(),
+        map: map.to_owned(),
+        boss_no: 3,
+        tileset: Tileset::new("Cave"),
+        pxpack_data: None,
+        background: Background::new("bkBlue"),
+        background_type: BackgroundType::TiledParallax,
+        background_type_id: BackgroundType::TiledParallax as u8,
+        background_color: Color::from_rgb(0, 0, 32),
+        npc1: NpcType::new("Weed"),
+        npc2: NpcType::new("Frog"),
+    };
+    // unknown background types are loaded as Black but written back as they were
+    let mut unknown_background = stage("Mimiga Village", "Mimi");
+    unknown_background.background_type = BackgroundType::Black;
+    unknown_background.background_type_id = 12;
+    let stages = vec![stage("First Cave", "Cave"), unknown_background];
+
+    for format in [
+        StageTableFormat::CavestoryPlus,
+        StageTableFormat::Freeware,
+        StageTableFormat::MoustacheRider,
+        StageTableFormat::NXEngine,
+    ] {
+        let mut data = Vec::new();
+        StageData::write_stage_table(&stages, format, false, &mut data).unwrap();
+
+        let loaded = StageData::read_stage_table(format, &data, false).unwrap();
+        assert_eq!(loaded.len(), stages.len());
+
+        for (a, b) in stages.iter().zip(loaded.iter()) {
+            assert_eq!(a.name, b.name);
+            assert_eq!(a.map, b.map);
+            assert_eq!(a.boss_no, b.boss_no);
+            assert_eq!(a.tileset, b.tileset);
+            assert_eq!(a.background, b.background);
+            assert_eq!(a.background_type, b.background_type);
+            assert_eq!(a.background_type_id, b.background_type_id);
+            assert_eq!(a.npc1, b.npc1);
+            assert_eq!(a.npc2, b.npc2);
+        }
+    }
+
+    let too_long = vec![stage("A stage name that doesn't fit in the table", "Cave")];
+    assert!(StageData::write_stage_table(&too_long, StageTableFormat::Freeware, false, Vec::<u8>::new()).is_err());
+}
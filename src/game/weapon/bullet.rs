@@ -54,6 +54,7 @@ impl BulletManager {
                 let bullet = unsafe { self.bullets.get_unchecked_mut(i) };
                 i += 1;
 
+                state.sound_manager.set_sfx_origin(Some((bullet.x, bullet.y)));
                 bullet.tick(state, players, npc_list, &mut self.new_bullets);
             }
 
@@ -63,6 +64,7 @@ impl BulletManager {
 
             self.bullets.append(&mut self.new_bullets);
         }
+        state.sound_manager.set_sfx_origin(None);
 
         self.bullets.retain(|b| !b.is_dead());
     }
@@ -74,9 +76,11 @@ impl BulletManager {
                 let bullet = unsafe { self.bullets.get_unchecked_mut(i) };
                 i += 1;
 
+                state.sound_manager.set_sfx_origin(Some((bullet.x, bullet.y)));
                 bullet.tick_map_collisions(state, npc_list, stage);
             }
         }
+        state.sound_manager.set_sfx_origin(None);
     }
 
     pub fn count_bullets(&self, btype: u16, player_id: TargetPlayer) -> usize {
@@ -0,0 +1,200 @@
+use std::collections::VecDeque;
+
+use crate::game::player::Player;
+use crate::input::replay_player_controller::{KeyState, ReplayController};
+
+/// Keeps track of the inputs both players' characters were simulated with when the inputs of the remote player
+/// arrive late, like in online co-op.
+///
+/// Frames for which only the local input is known are simulated with the remote input predicted from the last
+/// confirmed one. Once the confirmed inputs arrive and don't match the prediction, a rollback to the first
+/// mispredicted frame is requested, the caller is expected to restore the state from before that frame and
+/// simulate the frames again. Inputs are packed the same way as in replays, see `Replay::pack_inputs`.
+pub struct InputSync {
+    local_slot: usize,
+    max_prediction: u32,
+    /// First frame which may still be rolled back to, the queues below start at this frame.
+    base: u32,
+    /// Inputs the frames were simulated with.
+    simulated: VecDeque<[u16; 2]>,
+    /// Inputs of both players received from the other side.
+    confirmed: VecDeque<[u16; 2]>,
+    /// Inputs of the local player.
+    local: VecDeque<u16>,
+    /// Inputs of the frame before `base`.
+    last_confirmed: [u16; 2],
+    rollback: Option<u32>,
+}
+
+impl InputSync {
+    /// `local_slot` is 0 if the local player controls player 1, `max_prediction` limits how many frames can be
+    /// simulated ahead of the confirmed ones.
+    pub fn new(local_slot: usize, max_prediction: u32) -> InputSync {
+        InputSync {
+            local_slot: local_slot.min(1),
+            max_prediction,
+            base: 0,
+            simulated: VecDeque::new(),
+            confirmed: VecDeque::new(),
+            local: VecDeque::new(),
+            last_confirmed: [0; 2],
+            rollback: None,
+        }
+    }
+
+    /// The next frame to be simulated.
+    pub fn frame(&self) -> u32 {
+        self.base + self.simulated.len() as u32
+    }
+
+    /// Number of frames with confirmed inputs.
+    pub fn confirmed_frames(&self) -> u32 {
+        self.base + self.confirmed.len() as u32
+    }
+
+    /// Number of frames with known local inputs.
+    pub fn local_frames(&self) -> u32 {
+        self.base + self.local.len() as u32
+    }
+
+    /// Frames before this one were simulated with confirmed inputs and can't be rolled back anymore.
+    pub fn verified_frames(&self) -> u32 {
+        self.base
+    }
+
+    pub fn is_predicted(&self, frame: u32) -> bool {
+        frame >= self.confirmed_frames()
+    }
+
+    /// Adds the local input for frame `local_frames()`.
+    pub fn add_local_input(&mut self, keys: u16) {
+        self.local.push_back(keys);
+    }
+
+    /// Adds inputs of both players for frame `confirmed_frames()`.
+    pub fn confirm(&mut self, inputs: [u16; 2]) {
+        let frame = self.confirmed_frames();
+        self.confirmed.push_back(inputs);
+
+        if let Some(simulated) = self.simulated.get((frame - self.base) as usize) {
+            if *simulated != inputs {
+                self.rollback = Some(self.rollback.map_or(frame, |rollback| rollback.min(frame)));
+            }
+        }
+
+        self.trim();
+    }
+
+    /// Returns the first mispredicted frame, `frame()` is reset to it.
+    pub fn take_rollback(&mut self) -> Option<u32> {
+        let frame = self.rollback.take()?;
+        self.rewind(frame);
+
+        Some(frame)
+    }
+
+    /// Forgets about simulating frames from `frame` on, it has to be one that can still be rolled back to.
+    pub fn rewind(&mut self, frame: u32) {
+        let frame = frame.max(self.base);
+        self.simulated.truncate((frame - self.base) as usize);
+    }
+
+    /// Inputs the frame before `frame()` was simulated with.
+    pub fn previous_inputs(&self) -> [u16; 2] {
+        self.simulated.back().copied().unwrap_or(self.last_confirmed)
+    }
+
+    /// Returns the inputs to simulate `frame()` with and advances to the next frame.
+    ///
+    /// Returns `None` if the frame has to wait for inputs, either because the local input isn't known yet,
+    /// the prediction window is exhausted or `allow_prediction` is false and the frame isn't confirmed.
+    pub fn next_frame(&mut self, allow_prediction: bool) -> Option<[u16; 2]> {
+        let frame = self.frame();
+        let idx = (frame - self.base) as usize;
+
+        let inputs = match self.confirmed.get(idx) {
+            Some(inputs) => *inputs,
+            None => {
+                if !allow_prediction || frame - self.confirmed_frames() >= self.max_prediction {
+                    return None;
+                }
+
+                let mut inputs = self.confirmed.back().copied().unwrap_or(self.last_confirmed);
+                inputs[self.local_slot] = *self.local.get(idx)?;
+                inputs
+            }
+        };
+
+        self.simulated.push_back(inputs);
+        self.trim();
+
+        Some(inputs)
+    }
+
+    fn trim(&mut self) {
+        while !self.confirmed.is_empty() && !self.simulated.is_empty() {
+            if self.rollback.is_some_and(|rollback| rollback <= self.base) {
+                break;
+            }
+
+            self.last_confirmed = self.confirmed.pop_front().unwrap_or_default();
+            self.simulated.pop_front();
+            self.local.pop_front();
+            self.base += 1;
+        }
+    }
+}
+
+/// Feeds the inputs of a frame into both players' controllers, like replay playback does.
+pub fn feed_controllers(players: [&mut Player; 2], inputs: [u16; 2], previous: [u16; 2]) {
+    for (idx, player) in players.into_iter().enumerate() {
+        let mut controller = ReplayController::new();
+        controller.state = KeyState(inputs[idx]);
+        controller.old_state = KeyState(previous[idx]);
+        player.controller = Box::new(controller);
+    }
+}
+
+#[test]
+fn test_input_sync_rollback() {
+    let mut sync = InputSync::new(0, 4);
+
+    for keys in 1..=6 {
+        sync.add_local_input(keys);
+    }
+
+    // nothing from the remote player yet, it's predicted as idle
+    assert_eq!(sync.next_frame(true), Some([1, 0]));
+    assert_eq!(sync.next_frame(true), Some([2, 0]));
+    assert!(sync.is_predicted(1));
+
+    // prediction was right
+    sync.confirm([1, 0]);
+    assert_eq!(sync.take_rollback(), None);
+    assert_eq!(sync.verified_frames(), 1);
+
+    // the remote player started holding a key on frame 1
+    sync.confirm([2, 8]);
+    assert_eq!(sync.take_rollback(), Some(1));
+    assert_eq!(sync.frame(), 1);
+    assert_eq!(sync.previous_inputs(), [1, 0]);
+    assert_eq!(sync.next_frame(false), Some([2, 8]));
+    assert_eq!(sync.next_frame(false), None);
+    assert_eq!(sync.next_frame(true), Some([3, 8]));
+    assert_eq!(sync.verified_frames(), 2);
+
+    // the prediction window is exhausted
+    assert_eq!(sync.next_frame(true), Some([4, 8]));
+    assert_eq!(sync.next_frame(true), Some([5, 8]));
+    assert_eq!(sync.next_frame(true), Some([6, 8]));
+    assert_eq!(sync.next_frame(true), None);
+
+    // only the mispredicted frame and the ones after it are simulated again
+    sync.confirm([3, 0]);
+    sync.confirm([4, 8]);
+    assert_eq!(sync.take_rollback(), Some(2));
+    assert_eq!(sync.next_frame(true), Some([3, 0]));
+    assert_eq!(sync.next_frame(true), Some([4, 8]));
+    assert_eq!(sync.next_frame(true), Some([5, 8]));
+    assert_eq!(sync.verified_frames(), 4);
+}
@@ -2,6 +2,7 @@ pub mod combined_menu_controller;
 pub mod combined_player_controller;
 pub mod dummy_player_controller;
 pub mod gamepad_player_controller;
+pub mod input_sync;
 pub mod keyboard_player_controller;
 pub mod player_controller;
 pub mod replay_player_controller;
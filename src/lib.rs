@@ -20,7 +20,10 @@ mod live_debugger;
 mod macros;
 mod menu;
 mod mod_list;
+mod mod_manifest;
 mod mod_requirements;
+#[cfg(feature = "netplay")]
+mod netplay;
 mod scene;
 mod sound;
 mod util;
@@ -246,7 +246,9 @@ impl CommandLineCommand {
             }
             CommandLineCommand::TSC(script) => {
                 log::info!("Executing TSC script: {}", format!("#9999\n{}", script));
-                match TextScript::compile(format!("#9999\n{}", script).as_bytes(), true, TextScriptEncoding::UTF8) {
+                let data = format!("#9999\n{}", script);
+                let custom_opcodes = &state.constants.custom_opcodes;
+                match TextScript::compile(data.as_bytes(), true, TextScriptEncoding::UTF8, custom_opcodes) {
                     Ok(text_script) => {
                         state.textscript_vm.set_debug_script(text_script);
                         state.textscript_vm.set_mode(ScriptMode::Debug);
@@ -3,13 +3,15 @@ use itertools::Itertools;
 
 use crate::framework::context::Context;
 use crate::framework::error::GameResult;
-use crate::game::shared_game_state::SharedGameState;
+use crate::game::shared_game_state::{ReplayState, SharedGameState};
 use crate::scene::game_scene::GameScene;
 use crate::game::scripting::tsc::text_script::TextScriptExecutionState;
 
 use self::command_line::CommandLineParser;
+use self::script_editor::ScriptEditor;
 
 pub mod command_line;
+pub mod script_editor;
 
 #[derive(Debug, PartialEq, Eq, Copy, Clone)]
 #[repr(u8)]
@@ -27,6 +29,7 @@ pub struct LiveDebugger {
     npc_inspector_visible: bool,
     hotkey_list_visible: bool,
     command_line_parser: CommandLineParser,
+    script_editor: ScriptEditor,
     last_stage_id: usize,
     stages: Vec<ImString>,
     selected_stage: i32,
@@ -46,6 +49,7 @@ impl LiveDebugger {
             npc_inspector_visible: false,
             hotkey_list_visible: false,
             command_line_parser: CommandLineParser::new(),
+            script_editor: ScriptEditor::new(),
             last_stage_id: usize::MAX,
             stages: Vec::new(),
             selected_stage: -1,
@@ -159,6 +163,15 @@ impl LiveDebugger {
                     game_scene.player1.booster_fuel
                 ));
 
+                if let Some(desync) = game_scene.replay.desync {
+                    ui.text_colored(
+                        [1.0, 0.0, 0.0, 1.0],
+                        format!("Replay desync at tick {} ({})", desync.tick, desync.subsystem.name()),
+                    );
+                } else if let ReplayState::Playback(_) = state.replay_state {
+                    ui.text(format!("Replay tick {}, no desync detected", game_scene.replay.tick_count()));
+                }
+
                 ui.text(format!("Game speed ({:.1} TPS):", state.current_tps()));
                 let mut speed = state.settings.speed;
                 Slider::new("", 0.1, 3.0).build(ui, &mut speed);
@@ -181,6 +194,14 @@ impl LiveDebugger {
                     self.events_visible = !self.events_visible;
                 }
 
+                ui.same_line();
+                if ui.button("Edit Script") {
+                    let path = ["Stage/", &game_scene.stage.data.map, ".tsc"].join("");
+                    if let Err(err) = self.script_editor.open(state, ctx, &path) {
+                        self.error = Some(ImString::new(err.to_string()));
+                    }
+                }
+
                 ui.same_line();
                 if ui.button("Flags") {
                     self.flags_visible = !self.flags_visible;
@@ -519,6 +540,8 @@ impl LiveDebugger {
             self.text_windows.remove(remove as usize);
         }
 
+        self.script_editor.action(state, ctx, ui);
+
         if self.error.is_some() {
             Window::new("Error!")
                 .resizable(false)
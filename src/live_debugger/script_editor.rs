@@ -0,0 +1,308 @@
+use std::io::{Cursor, Read, Write};
+use std::str::FromStr;
+
+use imgui::{Condition, TabItem, Window};
+
+use crate::framework::context::Context;
+use crate::framework::error::GameError::ParseError;
+use crate::framework::error::{GameError, GameResult};
+use crate::framework::filesystem;
+use crate::game::scripting::tsc::encryption::{decrypt_tsc, encrypt_tsc};
+use crate::game::scripting::tsc::opcodes::TSCOpCode;
+use crate::game::scripting::tsc::text_script::{TextScript, TextScriptEncoding};
+use crate::game::shared_game_state::SharedGameState;
+use crate::util::encoding::{encode_shift_jis, read_cur_shift_jis};
+
+const COLOR_TEXT: [f32; 4] = [0.9, 0.9, 0.9, 1.0];
+const COLOR_EVENT: [f32; 4] = [1.0, 0.8, 0.3, 1.0];
+const COLOR_OPCODE: [f32; 4] = [0.4, 0.7, 1.0, 1.0];
+const COLOR_ARGUMENT: [f32; 4] = [0.6, 1.0, 0.6, 1.0];
+const COLOR_ERROR: [f32; 4] = [1.0, 0.3, 0.3, 1.0];
+const COLOR_LINE_NUMBER: [f32; 4] = [0.5, 0.5, 0.5, 1.0];
+
+#[derive(Debug, PartialEq, Eq, Copy, Clone)]
+enum TokenKind {
+    Text,
+    Event,
+    Opcode,
+    UnknownOpcode,
+    Argument,
+}
+
+/// Splits a line of TSC source into highlighted parts.
+fn tokenize_line(line: &str) -> Vec<(TokenKind, &str)> {
+    let mut tokens = Vec::new();
+
+    if line.starts_with('#') {
+        tokens.push((TokenKind::Event, line));
+        return tokens;
+    }
+
+    let mut rest = line;
+    while !rest.is_empty() {
+        let text_end = rest.find('<').unwrap_or(rest.len());
+        if text_end > 0 {
+            tokens.push((TokenKind::Text, &rest[..text_end]));
+            rest = &rest[text_end..];
+            continue;
+        }
+
+        // `<` followed by 3 characters
+        let code_end = rest.char_indices().nth(4).map_or(rest.len(), |(idx, _)| idx);
+        let code = &rest[..code_end];
+        let kind = match TSCOpCode::from_str(&code[1..]) {
+            Ok(_) if code.len() == 4 => TokenKind::Opcode,
+            _ => TokenKind::UnknownOpcode,
+        };
+        tokens.push((kind, code));
+        rest = &rest[code_end..];
+
+        let args_end = rest.find(|c: char| !c.is_ascii_digit() && c != ':').unwrap_or(rest.len());
+        if kind == TokenKind::Opcode && args_end > 0 {
+            tokens.push((TokenKind::Argument, &rest[..args_end]));
+            rest = &rest[args_end..];
+        }
+    }
+
+    tokens
+}
+
+fn decode(data: &[u8], encoding: TextScriptEncoding) -> String {
+    match encoding {
+        TextScriptEncoding::UTF8 => String::from_utf8_lossy(data).into_owned(),
+        TextScriptEncoding::ShiftJIS => {
+            let mut cursor = Cursor::new(data);
+            let mut result = String::with_capacity(data.len());
+            let mut bytes = data.len() as u32;
+
+            while bytes > 0 {
+                let (consumed, chr) = read_cur_shift_jis(&mut cursor, bytes);
+                result.push(chr);
+                bytes -= consumed;
+            }
+
+            result
+        }
+    }
+}
+
+fn encode(text: &str, encoding: TextScriptEncoding) -> GameResult<Vec<u8>> {
+    match encoding {
+        TextScriptEncoding::UTF8 => Ok(text.as_bytes().to_vec()),
+        TextScriptEncoding::ShiftJIS => encode_shift_jis(text)
+            .ok_or_else(|| GameError::InvalidValue("Script contains characters that can't be encoded.".to_owned())),
+    }
+}
+
+/// Text script editing window, checks the script while typing and swaps it into the running game when applied.
+pub struct ScriptEditor {
+    pub visible: bool,
+    /// Path of the edited script, relative to the data directory.
+    path: String,
+    source: String,
+    /// Compiler error and the line it occurred at, 0 if it's not related to a specific line.
+    error: Option<(usize, String)>,
+    status: String,
+}
+
+impl ScriptEditor {
+    pub fn new() -> ScriptEditor {
+        ScriptEditor { visible: false, path: String::new(), source: String::new(), error: None, status: String::new() }
+    }
+
+    /// Opens a stage script, eg. `Stage/Cave.tsc`.
+    pub fn open(&mut self, state: &SharedGameState, ctx: &mut Context, path: &str) -> GameResult {
+        let mut file = filesystem::open_find(ctx, &state.constants.base_paths, path)?;
+        let mut data = Vec::new();
+        file.read_to_end(&mut data)?;
+
+        if state.constants.textscript.encrypted {
+            decrypt_tsc(&mut data);
+        }
+
+        self.path = path.to_owned();
+        self.source = decode(&data, state.constants.textscript.encoding).replace("\r\n", "\n");
+        self.status = format!("Opened {}", path);
+        self.visible = true;
+        self.check(state);
+
+        Ok(())
+    }
+
+    /// Returns the script in the on-disk form, without encryption.
+    fn script_data(&self, state: &SharedGameState) -> GameResult<Vec<u8>> {
+        encode(&self.source.replace('\n', "\r\n"), state.constants.textscript.encoding)
+    }
+
+    fn compile(&mut self, state: &SharedGameState) -> Option<TextScript> {
+        let data = match self.script_data(state) {
+            Ok(data) => data,
+            Err(err) => {
+                self.error = Some((0, err.to_string()));
+                return None;
+            }
+        };
+
+        match TextScript::compile_with_line(
+            &data,
+            false,
+            state.constants.textscript.encoding,
+            &state.constants.custom_opcodes,
+        ) {
+            Ok(script) => {
+                self.error = None;
+                Some(script)
+            }
+            Err((line, ParseError(msg))) => {
+                self.error = Some((line, msg));
+                None
+            }
+            Err((line, err)) => {
+                self.error = Some((line, err.to_string()));
+                None
+            }
+        }
+    }
+
+    fn check(&mut self, state: &SharedGameState) {
+        let _ = self.compile(state);
+    }
+
+    /// Replaces the stage script of the text script VM, running events are stopped.
+    fn apply(&mut self, state: &mut SharedGameState) -> bool {
+        match self.compile(state) {
+            Some(script) => {
+                state.textscript_vm.set_scene_script(script);
+                self.status = "Script reloaded.".to_owned();
+                true
+            }
+            None => {
+                self.status = "Script has errors, not reloaded.".to_owned();
+                false
+            }
+        }
+    }
+
+    /// Writes the script to disk, the running game keeps using the last applied one.
+    fn save(&mut self, state: &SharedGameState, ctx: &mut Context) -> GameResult {
+        let mut data = self.script_data(state)?;
+        if state.constants.textscript.encrypted {
+            encrypt_tsc(&mut data);
+        }
+
+        let root = state.get_resource_save_root(ctx, &self.path);
+        let mut file = filesystem::create(ctx, [root.as_str(), &self.path].join(""))?;
+        file.write_all(&data)?;
+
+        self.status = format!("Saved to {}{}", root, self.path);
+
+        Ok(())
+    }
+
+    pub fn action(&mut self, state: &mut SharedGameState, ctx: &mut Context, ui: &imgui::Ui) {
+        if !self.visible {
+            return;
+        }
+
+        let mut visible = self.visible;
+        let mut apply = false;
+        let mut save = false;
+        let mut revert = false;
+
+        Window::new(format!("Script: {}###ScriptEditor", self.path))
+            .size([560.0, 440.0], Condition::FirstUseEver)
+            .opened(&mut visible)
+            .build(ui, || {
+                apply = ui.button("Apply");
+                ui.same_line();
+                save = ui.button("Save");
+                ui.same_line();
+                revert = ui.button("Revert");
+                ui.same_line();
+                ui.text(&self.status);
+
+                match &self.error {
+                    Some((0, msg)) => ui.text_colored(COLOR_ERROR, msg),
+                    Some((line, msg)) => ui.text_colored(COLOR_ERROR, format!("Line {}: {}", line, msg)),
+                    None => ui.text_colored(COLOR_ARGUMENT, "No errors."),
+                }
+
+                if let Some(tab_bar) = ui.tab_bar("ScriptTabs") {
+                    if let Some(tab) = TabItem::new("Source").begin(ui) {
+                        if ui.input_text_multiline("##source", &mut self.source, [-1.0, -1.0]).build() {
+                            self.check(state);
+                        }
+                        tab.end();
+                    }
+
+                    if let Some(tab) = TabItem::new("Highlighted").begin(ui) {
+                        self.highlighted_view(ui);
+                        tab.end();
+                    }
+
+                    tab_bar.end();
+                }
+            });
+
+        self.visible = visible;
+
+        if apply {
+            self.apply(state);
+        }
+
+        if save {
+            if let Err(err) = self.save(state, ctx) {
+                self.status = format!("Save failed: {}", err);
+            }
+        }
+
+        if revert {
+            let path = self.path.clone();
+            if let Err(err) = self.open(state, ctx, &path) {
+                self.status = format!("Reload failed: {}", err);
+            }
+        }
+    }
+
+    fn highlighted_view(&self, ui: &imgui::Ui) {
+        let error_line = self.error.as_ref().map(|(line, _)| *line);
+        let line_count = self.source.lines().count();
+        let number_width = line_count.to_string().len();
+
+        for (idx, line) in self.source.lines().enumerate() {
+            let line_number = idx + 1;
+            let number_color = if error_line == Some(line_number) { COLOR_ERROR } else { COLOR_LINE_NUMBER };
+            ui.text_colored(number_color, format!("{:>width$} ", line_number, width = number_width));
+
+            for (kind, token) in tokenize_line(line) {
+                let color = match kind {
+                    TokenKind::Text => COLOR_TEXT,
+                    TokenKind::Event => COLOR_EVENT,
+                    TokenKind::Opcode => COLOR_OPCODE,
+                    TokenKind::UnknownOpcode => COLOR_ERROR,
+                    TokenKind::Argument => COLOR_ARGUMENT,
+                };
+
+                ui.same_line_with_spacing(0.0, 0.0);
+                ui.text_colored(color, token);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_tokenize_line() {
+    let tokens = tokenize_line("<MSGHello<TRA0001:0090:0010:0010<XYZ");
+
+    assert_eq!(
+        tokens,
+        vec![
+            (TokenKind::Opcode, "<MSG"),
+            (TokenKind::Text, "Hello"),
+            (TokenKind::Opcode, "<TRA"),
+            (TokenKind::Argument, "0001:0090:0010:0010"),
+            (TokenKind::UnknownOpcode, "<XYZ"),
+        ]
+    );
+    assert_eq!(tokenize_line("#0200"), vec![(TokenKind::Event, "#0200")]);
+}
@@ -1,12 +1,24 @@
 #![cfg_attr(not(debug_assertions), windows_subsystem = "windows")]
 
+use std::path::PathBuf;
 use std::process::exit;
 
+use doukutsu_rs::game::{AudioOutput, RenderOptions, RenderSource};
+
 fn main() {
-    let args = std::env::args();
-    let mut options = doukutsu_rs::game::LaunchOptions { server_mode: false, editor: false };
+    let mut args = std::env::args().skip(1);
+    let mut options = doukutsu_rs::game::LaunchOptions::default();
+
+    let mut render_source = None;
+    let mut render_output = None;
+    let mut render_interpolation = None;
+    let mut render_loops = None;
+    let mut render_fade_out = None;
+    let mut render_sample_rate = None;
+    let mut audio_output = None;
+    let mut audio_speed = None;
 
-    for arg in args {
+    while let Some(arg) = args.next() {
         if arg == "--server-mode" {
             options.server_mode = true;
         }
@@ -14,6 +26,180 @@ fn main() {
         if arg == "--editor" {
             options.editor = true;
         }
+
+        if arg == "--verify-replay" {
+            match args.next() {
+                Some(path) => options.verify_replay = Some(PathBuf::from(path)),
+                None => {
+                    eprintln!("--verify-replay requires a path to the replay file.");
+                    exit(1);
+                }
+            }
+        }
+
+        if arg == "--connect" || arg == "--bind" {
+            match args.next() {
+                Some(address) if arg == "--connect" => options.connect = Some(address),
+                Some(address) => options.bind_address = Some(address),
+                None => {
+                    eprintln!("{} requires an address.", arg);
+                    exit(1);
+                }
+            }
+        }
+
+        if arg == "--render-org" {
+            match args.next() {
+                Some(path) => render_source = Some(RenderSource::Organya(PathBuf::from(path))),
+                None => {
+                    eprintln!("--render-org requires a path to the .org file.");
+                    exit(1);
+                }
+            }
+        }
+
+        if arg == "--render-sfx" {
+            match args.next().and_then(|id| id.parse().ok()) {
+                Some(id) => render_source = Some(RenderSource::PixTone(id)),
+                None => {
+                    eprintln!("--render-sfx requires a sound effect ID (0-159).");
+                    exit(1);
+                }
+            }
+        }
+
+        if arg == "--output" {
+            match args.next() {
+                Some(path) => render_output = Some(PathBuf::from(path)),
+                None => {
+                    eprintln!("--output requires a path to the WAV file.");
+                    exit(1);
+                }
+            }
+        }
+
+        if arg == "--interpolation" {
+            match args.next().and_then(|mode| mode.parse().ok()) {
+                Some(mode) => render_interpolation = Some(mode),
+                None => {
+                    eprintln!("--interpolation requires one of: nearest, linear, cosine, cubic, polyphase.");
+                    exit(1);
+                }
+            }
+        }
+
+        if arg == "--loops" {
+            match args.next().and_then(|loops| loops.parse().ok()) {
+                Some(loops) => render_loops = Some(loops),
+                None => {
+                    eprintln!("--loops requires a number of loops.");
+                    exit(1);
+                }
+            }
+        }
+
+        if arg == "--fade-out" {
+            match args.next().and_then(|seconds| seconds.parse().ok()) {
+                Some(seconds) if seconds >= 0.0 => render_fade_out = Some(seconds),
+                _ => {
+                    eprintln!("--fade-out requires a length in seconds.");
+                    exit(1);
+                }
+            }
+        }
+
+        if arg == "--sample-rate" {
+            match args.next().and_then(|rate| rate.parse().ok()) {
+                Some(rate) if rate > 0 => render_sample_rate = Some(rate),
+                _ => {
+                    eprintln!("--sample-rate requires a sample rate in Hz.");
+                    exit(1);
+                }
+            }
+        }
+
+        if arg == "--audio-output" {
+            match args.next() {
+                Some(output) => audio_output = Some(output),
+                None => {
+                    eprintln!("--audio-output requires device, null or a path to a WAV file.");
+                    exit(1);
+                }
+            }
+        }
+
+        if arg == "--audio-speed" {
+            match args.next().and_then(|speed| speed.parse().ok()) {
+                Some(speed) if speed >= 0.0 => audio_speed = Some(speed),
+                _ => {
+                    eprintln!("--audio-speed requires a speed multiplier, 0 runs the mixer as fast as possible.");
+                    exit(1);
+                }
+            }
+        }
+
+        if arg == "--max-ticks" {
+            match args.next().and_then(|ticks| ticks.parse().ok()) {
+                Some(ticks) => options.max_replay_ticks = ticks,
+                None => {
+                    eprintln!("--max-ticks requires a number of ticks.");
+                    exit(1);
+                }
+            }
+        }
+    }
+
+    if let Some(source) = render_source {
+        let output = match render_output {
+            Some(output) => output,
+            None => {
+                eprintln!("--render-org and --render-sfx require an --output path.");
+                exit(1);
+            }
+        };
+
+        let mut render = RenderOptions::new(source, output);
+        if let Some(interpolation) = render_interpolation {
+            render.interpolation = interpolation;
+        }
+        if let Some(loops) = render_loops {
+            render.loops = loops;
+        }
+        if let Some(fade_out) = render_fade_out {
+            render.fade_out = fade_out;
+        }
+        if let Some(sample_rate) = render_sample_rate {
+            render.sample_rate = sample_rate;
+        }
+
+        options.render_audio = Some(render);
+    } else if render_output.is_some() {
+        eprintln!("--output requires --render-org or --render-sfx.");
+        exit(1);
+    }
+
+    let speed = audio_speed.unwrap_or(1.0);
+    options.audio_output = match audio_output.as_deref() {
+        Some("device") => Some(AudioOutput::Device),
+        Some("null") => Some(AudioOutput::Null { speed }),
+        Some(path) => Some(AudioOutput::File { path: PathBuf::from(path), speed }),
+        None if audio_speed.is_some() => {
+            eprintln!("--audio-speed requires --audio-output null or a WAV file.");
+            exit(1);
+        }
+        None => None,
+    };
+
+    if options.render_audio.is_some()
+        && (options.server_mode || options.editor || options.verify_replay.is_some() || options.connect.is_some())
+    {
+        eprintln!("Audio rendering can't be combined with --server-mode, --editor, --verify-replay or --connect.");
+        exit(1);
+    }
+
+    if options.connect.is_some() && (options.server_mode || options.editor || options.verify_replay.is_some()) {
+        eprintln!("--connect can't be combined with --server-mode, --editor or --verify-replay.");
+        exit(1);
     }
 
     if options.server_mode && options.editor {
@@ -21,6 +207,11 @@ fn main() {
         exit(1);
     }
 
+    if options.verify_replay.is_some() && options.editor {
+        eprintln!("Cannot verify replays in editor mode.");
+        exit(1);
+    }
+
     let result = doukutsu_rs::game::init(options);
 
     #[cfg(target_os = "windows")]
@@ -2,7 +2,8 @@ use crate::framework::context::Context;
 use crate::framework::error::GameResult;
 use crate::framework::graphics;
 use crate::framework::keyboard::ScanCode;
-use crate::game::shared_game_state::{MenuCharacter, PlayerCount, SharedGameState};
+use crate::game::shared_game_state::{MenuCharacter, PlayerCount, ReplayState, SharedGameState};
+use crate::game::snapshot::QUICK_SAVE_SLOTS;
 use crate::input::combined_menu_controller::CombinedMenuController;
 use crate::menu::{Menu, MenuSelectionResult};
 use crate::menu::MenuEntry;
@@ -25,6 +26,9 @@ enum CurrentMenu {
 enum PauseMenuEntry {
     Resume,
     Retry,
+    QuickSaveSlot,
+    QuickSave,
+    QuickLoad,
     AddPlayer2,
     DropPlayer2,
     Settings,
@@ -61,6 +65,7 @@ pub struct PauseMenu {
     confirm_menu: Menu<ConfirmMenuEntry>,
     tick: u32,
     should_update_coop_menu: bool,
+    quick_save_requested: bool,
 }
 
 impl PauseMenu {
@@ -77,6 +82,7 @@ impl PauseMenu {
             confirm_menu: Menu::new(0, 0, 75, 0),
             tick: 0,
             should_update_coop_menu: false,
+            quick_save_requested: false,
         }
     }
 
@@ -86,6 +92,14 @@ impl PauseMenu {
 
         self.pause_menu.push_entry(PauseMenuEntry::Resume, MenuEntry::Active(state.loc.t("menus.pause_menu.resume").to_owned()));
         self.pause_menu.push_entry(PauseMenuEntry::Retry, MenuEntry::Active(state.loc.t("menus.pause_menu.retry").to_owned()));
+        if state.replay_state == ReplayState::None && state.get_snapshot_filename(state.save_slot, 0).is_some() {
+            let label = state.loc.t("menus.pause_menu.quick_save_slot").to_owned();
+            let slots = (1..=QUICK_SAVE_SLOTS).map(|slot| slot.to_string()).collect();
+            self.pause_menu
+                .push_entry(PauseMenuEntry::QuickSaveSlot, MenuEntry::Options(label, state.quick_save_slot, slots));
+            self.pause_menu.push_entry(PauseMenuEntry::QuickSave, MenuEntry::Active(state.loc.t("menus.pause_menu.quick_save").to_owned()));
+            self.pause_menu.push_entry(PauseMenuEntry::QuickLoad, MenuEntry::Active(state.loc.t("menus.pause_menu.quick_load").to_owned()));
+        }
         self.pause_menu.push_entry(PauseMenuEntry::AddPlayer2, MenuEntry::Hidden);
         self.pause_menu.push_entry(PauseMenuEntry::DropPlayer2, MenuEntry::Hidden);
         self.pause_menu.push_entry(PauseMenuEntry::Settings, MenuEntry::Active(state.loc.t("menus.pause_menu.options").to_owned()));
@@ -159,6 +173,11 @@ impl PauseMenu {
         self.is_paused
     }
 
+    /// Returns whether the player picked Quick Save, the snapshot itself is taken by the game scene.
+    pub fn take_quick_save_request(&mut self) -> bool {
+        std::mem::take(&mut self.quick_save_requested)
+    }
+
     pub fn tick(&mut self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
         self.update_sizes(state);
 
@@ -191,6 +210,28 @@ impl PauseMenu {
                     state.sound_manager.play_song(0, &state.constants, &state.settings, ctx)?;
                     state.load_or_start_game(ctx)?;
                 }
+                MenuSelectionResult::Selected(PauseMenuEntry::QuickSaveSlot, toggle)
+                | MenuSelectionResult::Right(PauseMenuEntry::QuickSaveSlot, toggle, _) => {
+                    if let MenuEntry::Options(_, value, _) = toggle {
+                        state.quick_save_slot = (state.quick_save_slot + 1) % QUICK_SAVE_SLOTS;
+                        *value = state.quick_save_slot;
+                    }
+                }
+                MenuSelectionResult::Left(PauseMenuEntry::QuickSaveSlot, toggle, _) => {
+                    if let MenuEntry::Options(_, value, _) = toggle {
+                        state.quick_save_slot = (state.quick_save_slot + QUICK_SAVE_SLOTS - 1) % QUICK_SAVE_SLOTS;
+                        *value = state.quick_save_slot;
+                    }
+                }
+                MenuSelectionResult::Selected(PauseMenuEntry::QuickSave, _) => {
+                    self.quick_save_requested = true;
+                    self.tick = 0;
+                    self.is_paused = false;
+                }
+                MenuSelectionResult::Selected(PauseMenuEntry::QuickLoad, _) => {
+                    state.stop_noise();
+                    state.quick_load(ctx)?;
+                }
                 MenuSelectionResult::Selected(PauseMenuEntry::AddPlayer2, _) => {
                     if !state.constants.is_cs_plus {
                         state.player_count = PlayerCount::Two;
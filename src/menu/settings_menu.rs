@@ -10,10 +10,13 @@ use crate::input::combined_menu_controller::CombinedMenuController;
 use crate::menu::MenuEntry;
 use crate::menu::{Menu, MenuSelectionResult};
 use crate::scene::title_scene::TitleScene;
-use crate::sound::InterpolationMode;
+use crate::sound::{FadeCurve, InterpolationMode};
 
 use super::controls_menu::ControlsMenu;
 
+/// Crossfade lengths selectable in the sound menu, in seconds.
+const CROSSFADE_TIMES: [f32; 4] = [0.0, 0.5, 1.0, 2.0];
+
 #[derive(PartialEq, Eq, Copy, Clone)]
 #[repr(u8)]
 #[allow(unused)]
@@ -26,6 +29,7 @@ enum CurrentMenu {
     LanguageMenu,
     BehaviorMenu,
     LinksMenu,
+    ContentPacksMenu,
 }
 
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
@@ -34,6 +38,7 @@ enum MainMenuEntry {
     Sound,
     Controls,
     Language,
+    ContentPacks,
     Behavior,
     Links,
     Back,
@@ -71,6 +76,9 @@ enum SoundMenuEntry {
     MusicVolume,
     EffectsVolume,
     BGMInterpolation,
+    Crossfade,
+    CrossfadeCurve,
+    PositionalSfx,
     Soundtrack,
     Back,
 }
@@ -106,6 +114,19 @@ impl Default for LanguageMenuEntry {
     }
 }
 
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum ContentPacksMenuEntry {
+    Title,
+    ContentPack(usize),
+    Back,
+}
+
+impl Default for ContentPacksMenuEntry {
+    fn default() -> Self {
+        ContentPacksMenuEntry::Back
+    }
+}
+
 #[derive(Debug, Clone, Copy, Eq, PartialEq)]
 enum BehaviorMenuEntry {
     GameTiming,
@@ -140,6 +161,7 @@ pub struct SettingsMenu {
     sound: Menu<SoundMenuEntry>,
     soundtrack: Menu<SoundtrackMenuEntry>,
     language: Menu<LanguageMenuEntry>,
+    content_packs: Menu<ContentPacksMenuEntry>,
     behavior: Menu<BehaviorMenuEntry>,
     links: Menu<LinksMenuEntry>,
     controls_menu: ControlsMenu,
@@ -161,6 +183,7 @@ impl SettingsMenu {
         let sound = Menu::new(0, 0, 260, 0);
         let soundtrack = Menu::new(0, 0, 260, 0);
         let language = Menu::new(0, 0, 120, 0);
+        let content_packs = Menu::new(0, 0, 220, 0);
         let behavior = Menu::new(0, 0, 220, 0);
         let links = Menu::new(0, 0, 220, 0);
 
@@ -173,6 +196,7 @@ impl SettingsMenu {
             sound,
             soundtrack,
             language,
+            content_packs,
             behavior,
             links,
             controls_menu,
@@ -322,6 +346,41 @@ impl SettingsMenu {
             );
         }
 
+        self.content_packs.push_entry(
+            ContentPacksMenuEntry::Title,
+            MenuEntry::Disabled(state.loc.t("menus.options_menu.content_packs").to_owned()),
+        );
+
+        for (idx, mod_info) in state.mod_list.mods.iter().enumerate() {
+            if !mod_info.is_content_pack() {
+                continue;
+            }
+
+            if mod_info.valid {
+                let enabled = state.settings.enabled_mods.contains(&mod_info.id);
+                self.content_packs.push_entry(
+                    ContentPacksMenuEntry::ContentPack(idx),
+                    MenuEntry::Toggle(mod_info.name.clone(), enabled),
+                );
+            } else {
+                self.content_packs.push_entry(
+                    ContentPacksMenuEntry::ContentPack(idx),
+                    MenuEntry::Disabled(format!("{} ({})", mod_info.name, mod_info.description)),
+                );
+            }
+        }
+
+        self.content_packs
+            .push_entry(ContentPacksMenuEntry::Back, MenuEntry::Active(state.loc.t("common.back").to_owned()));
+
+        // the resources are reloaded when a pack is toggled, which isn't possible in-game
+        if self.on_title && state.mod_list.mods.iter().any(|mod_info| mod_info.is_content_pack()) {
+            self.main.push_entry(
+                MainMenuEntry::ContentPacks,
+                MenuEntry::Active(state.loc.t("menus.options_menu.content_packs").to_owned()),
+            );
+        }
+
         self.main
             .push_entry(MainMenuEntry::Behavior, MenuEntry::Active(state.loc.t("menus.options_menu.behavior").to_owned()));
 
@@ -373,6 +432,45 @@ impl SettingsMenu {
                 ],
             ),
         );
+        self.sound.push_entry(
+            SoundMenuEntry::Crossfade,
+            MenuEntry::Options(
+                state.loc.t("menus.options_menu.sound_menu.crossfade.entry").to_owned(),
+                CROSSFADE_TIMES.iter().rposition(|&time| time <= state.settings.crossfade_time).unwrap_or(0),
+                CROSSFADE_TIMES
+                    .iter()
+                    .map(|&time| {
+                        if time == 0.0 {
+                            state.loc.t("menus.options_menu.sound_menu.crossfade.off").to_owned()
+                        } else {
+                            state.tt(
+                                "menus.options_menu.sound_menu.crossfade.seconds",
+                                &[("seconds", &time.to_string())],
+                            )
+                        }
+                    })
+                    .collect(),
+            ),
+        );
+        self.sound.push_entry(
+            SoundMenuEntry::CrossfadeCurve,
+            MenuEntry::Options(
+                state.loc.t("menus.options_menu.sound_menu.crossfade_curve.entry").to_owned(),
+                state.settings.crossfade_curve as usize,
+                vec![
+                    state.loc.t("menus.options_menu.sound_menu.crossfade_curve.linear").to_owned(),
+                    state.loc.t("menus.options_menu.sound_menu.crossfade_curve.equal_power").to_owned(),
+                    state.loc.t("menus.options_menu.sound_menu.crossfade_curve.s_curve").to_owned(),
+                ],
+            ),
+        );
+        self.sound.push_entry(
+            SoundMenuEntry::PositionalSfx,
+            MenuEntry::Toggle(
+                state.loc.t("menus.options_menu.sound_menu.positional_sfx").to_owned(),
+                state.settings.positional_sfx,
+            ),
+        );
         self.sound.push_entry(
             SoundMenuEntry::Soundtrack,
             MenuEntry::Active(
@@ -484,6 +582,11 @@ impl SettingsMenu {
         self.language.x = ((state.canvas_size.0 - self.language.width as f32) / 2.0).floor() as isize;
         self.language.y = ((state.canvas_size.1 - self.language.height as f32) / 2.0).floor() as isize;
 
+        self.content_packs.update_width(state);
+        self.content_packs.update_height();
+        self.content_packs.x = ((state.canvas_size.0 - self.content_packs.width as f32) / 2.0).floor() as isize;
+        self.content_packs.y = ((state.canvas_size.1 - self.content_packs.height as f32) / 2.0).floor() as isize;
+
         self.behavior.update_width(state);
         self.behavior.update_height();
         self.behavior.x = ((state.canvas_size.0 - self.behavior.width as f32) / 2.0).floor() as isize;
@@ -518,6 +621,9 @@ impl SettingsMenu {
                 MenuSelectionResult::Selected(MainMenuEntry::Language, _) => {
                     self.current = CurrentMenu::LanguageMenu;
                 }
+                MenuSelectionResult::Selected(MainMenuEntry::ContentPacks, _) => {
+                    self.current = CurrentMenu::ContentPacksMenu;
+                }
                 MenuSelectionResult::Selected(MainMenuEntry::Behavior, _) => {
                     self.current = CurrentMenu::BehaviorMenu;
                 }
@@ -721,6 +827,57 @@ impl SettingsMenu {
                         let _ = state.settings.save(ctx);
                     }
                 }
+                MenuSelectionResult::Selected(SoundMenuEntry::Crossfade, toggle)
+                | MenuSelectionResult::Right(SoundMenuEntry::Crossfade, toggle, _) => {
+                    if let MenuEntry::Options(_, value, _) = toggle {
+                        *value = (*value + 1) % CROSSFADE_TIMES.len();
+                        state.settings.crossfade_time = CROSSFADE_TIMES[*value];
+                        let _ = state.settings.save(ctx);
+                    }
+                }
+                MenuSelectionResult::Left(SoundMenuEntry::Crossfade, toggle, _) => {
+                    if let MenuEntry::Options(_, value, _) = toggle {
+                        *value = (*value + CROSSFADE_TIMES.len() - 1) % CROSSFADE_TIMES.len();
+                        state.settings.crossfade_time = CROSSFADE_TIMES[*value];
+                        let _ = state.settings.save(ctx);
+                    }
+                }
+                MenuSelectionResult::Selected(SoundMenuEntry::CrossfadeCurve, toggle)
+                | MenuSelectionResult::Right(SoundMenuEntry::CrossfadeCurve, toggle, _) => {
+                    if let MenuEntry::Options(_, value, _) = toggle {
+                        let (new_curve, new_value) = match *value {
+                            0 => (FadeCurve::EqualPower, 1),
+                            1 => (FadeCurve::SCurve, 2),
+                            _ => (FadeCurve::Linear, 0),
+                        };
+
+                        *value = new_value;
+                        state.settings.crossfade_curve = new_curve;
+                        let _ = state.settings.save(ctx);
+                    }
+                }
+                MenuSelectionResult::Left(SoundMenuEntry::CrossfadeCurve, toggle, _) => {
+                    if let MenuEntry::Options(_, value, _) = toggle {
+                        let (new_curve, new_value) = match *value {
+                            0 => (FadeCurve::SCurve, 2),
+                            1 => (FadeCurve::Linear, 0),
+                            _ => (FadeCurve::EqualPower, 1),
+                        };
+
+                        *value = new_value;
+                        state.settings.crossfade_curve = new_curve;
+                        let _ = state.settings.save(ctx);
+                    }
+                }
+                MenuSelectionResult::Selected(SoundMenuEntry::PositionalSfx, toggle) => {
+                    if let MenuEntry::Toggle(_, value) = toggle {
+                        state.settings.positional_sfx = !state.settings.positional_sfx;
+                        state.sound_manager.set_positional_sfx(state.settings.positional_sfx);
+                        let _ = state.settings.save(ctx);
+
+                        *value = state.settings.positional_sfx;
+                    }
+                }
                 MenuSelectionResult::Selected(SoundMenuEntry::Soundtrack, _) => {
                     let mut active_soundtrack = SoundtrackMenuEntry::Soundtrack(0);
 
@@ -778,6 +935,35 @@ impl SettingsMenu {
                 }
                 _ => {}
             },
+            CurrentMenu::ContentPacksMenu => match self.content_packs.tick(controller, state) {
+                MenuSelectionResult::Selected(ContentPacksMenuEntry::ContentPack(idx), toggle) => {
+                    if let (MenuEntry::Toggle(_, value), Some(mod_info)) = (toggle, state.mod_list.mods.get(idx)) {
+                        let id = mod_info.id.clone();
+                        let previous_mods = state.settings.enabled_mods.clone();
+                        if state.settings.enabled_mods.contains(&id) {
+                            state.settings.enabled_mods.retain(|enabled| *enabled != id);
+                        } else {
+                            state.settings.enabled_mods.push(id.clone());
+                        }
+
+                        if let Err(err) = state.reload_resources(ctx) {
+                            log::error!("Failed to load resources with content pack {} toggled: {}", id, err);
+
+                            // go back to the set of packs that was working before
+                            state.settings.enabled_mods = previous_mods;
+                            state.reload_resources(ctx)?;
+                        } else {
+                            let _ = state.settings.save(ctx);
+                        }
+
+                        *value = state.settings.enabled_mods.contains(&id);
+                    }
+                }
+                MenuSelectionResult::Selected(ContentPacksMenuEntry::Back, _) | MenuSelectionResult::Canceled => {
+                    self.current = CurrentMenu::MainMenu;
+                }
+                _ => (),
+            },
             CurrentMenu::SoundtrackMenu => match self.soundtrack.tick(controller, state) {
                 MenuSelectionResult::Selected(SoundtrackMenuEntry::Soundtrack(_), entry) => {
                     if let MenuEntry::Active(name) = entry {
@@ -866,6 +1052,7 @@ impl SettingsMenu {
             CurrentMenu::SoundtrackMenu => self.soundtrack.draw(state, ctx)?,
             CurrentMenu::ControlsMenu => self.controls_menu.draw(state, ctx)?,
             CurrentMenu::LanguageMenu => self.language.draw(state, ctx)?,
+            CurrentMenu::ContentPacksMenu => self.content_packs.draw(state, ctx)?,
             CurrentMenu::BehaviorMenu => self.behavior.draw(state, ctx)?,
             CurrentMenu::LinksMenu => self.links.draw(state, ctx)?,
         }
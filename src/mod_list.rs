@@ -1,11 +1,12 @@
-use std::collections::HashMap;
 use std::io::{BufRead, BufReader};
 use std::iter::Peekable;
 use std::str::Chars;
 
+use crate::engine_constants::EngineConstants;
 use crate::framework::context::Context;
 use crate::framework::error::GameResult;
 use crate::framework::filesystem;
+use crate::mod_manifest::{DataEdition, ModManifest};
 use crate::mod_requirements::ModRequirements;
 
 #[derive(Debug)]
@@ -18,9 +19,19 @@ pub struct ModInfo {
     pub name: String,
     pub description: String,
     pub valid: bool,
+    /// Set for mods with a `mod.json`, see `ModManifest`.
+    pub manifest: Option<ModManifest>,
 }
 
 impl ModInfo {
+    pub fn is_content_pack(&self) -> bool {
+        self.manifest.as_ref().is_some_and(|manifest| manifest.content_pack)
+    }
+
+    fn conflicts_with(&self, other: &ModInfo) -> bool {
+        self.manifest.as_ref().is_some_and(|manifest| manifest.conflicts.contains(&other.id))
+    }
+
     pub fn satisfies_requirement(&self, mod_requirements: &ModRequirements) -> bool {
         match self.requirement {
             Requirement::Unlocked => true,
@@ -51,7 +62,8 @@ pub struct ModList {
 }
 
 impl ModList {
-    pub fn load(ctx: &mut Context, string_table: &HashMap<String, String>) -> GameResult<ModList> {
+    pub fn load(ctx: &mut Context, constants: &EngineConstants) -> GameResult<ModList> {
+        let string_table = &constants.string_table;
         let mut mods = Vec::new();
 
         if let Ok(file) = filesystem::open(ctx, "/mods.txt") {
@@ -173,15 +185,162 @@ impl ModList {
                     description = "mod.txt not found".to_string();
                 }
 
-                mods.push(ModInfo { id, requirement, priority, save_slot, path, name, description, valid })
+                let manifest = match ModManifest::load(ctx, &ModList::dir_path(&path)) {
+                    Ok(manifest) => manifest,
+                    Err(err) => {
+                        log::warn!("Failed to load the manifest of {}: {}", path, err);
+                        None
+                    }
+                };
+
+                mods.push(ModInfo { id, requirement, priority, save_slot, path, name, description, valid, manifest })
             }
         }
 
+        ModList::discover_mods(ctx, &mut mods);
+        ModList::check_manifests(&mut mods, DataEdition::current(constants));
+
         mods.sort_by(|a, b| a.priority.cmp(&b.priority));
 
         Ok(ModList { mods })
     }
 
+    fn dir_path(path: &str) -> String {
+        if path.ends_with('/') {
+            path.to_owned()
+        } else {
+            format!("{}/", path)
+        }
+    }
+
+    /// Adds the mods in `/mods/` which have a `mod.json`, but aren't listed in `mods.txt`.
+    fn discover_mods(ctx: &Context, mods: &mut Vec<ModInfo>) {
+        let mut dirs: Vec<String> = match filesystem::read_dir(ctx, "/mods/") {
+            Ok(entries) => entries
+                .filter(|entry| filesystem::is_dir(ctx, entry))
+                .map(|entry| ModList::dir_path(&entry.to_string_lossy()))
+                .collect(),
+            Err(_) => return,
+        };
+        // the same directory can be present in multiple VFS roots
+        dirs.sort();
+        dirs.dedup_by(|a, b| a.eq_ignore_ascii_case(b));
+
+        for path in dirs {
+            if mods.iter().any(|mod_info| ModList::dir_path(&mod_info.path).eq_ignore_ascii_case(&path)) {
+                continue;
+            }
+
+            let manifest = match ModManifest::load(ctx, &path) {
+                Ok(Some(manifest)) => manifest,
+                Ok(None) => continue,
+                Err(err) => {
+                    log::warn!("Failed to load the manifest of {}: {}", path, err);
+                    continue;
+                }
+            };
+
+            log::info!("Found mod {} {} by {} in {}.", manifest.name, manifest.version, manifest.author, path);
+
+            mods.push(ModInfo {
+                id: manifest.id.clone(),
+                requirement: Requirement::Unlocked,
+                priority: 1000,
+                save_slot: if manifest.saves { 0 } else { -1 },
+                path,
+                name: manifest.name.clone(),
+                description: manifest.description.clone(),
+                valid: true,
+                manifest: Some(manifest),
+            });
+        }
+    }
+
+    /// Disables the mods made for a different edition of the game or missing their dependencies.
+    fn check_manifests(mods: &mut [ModInfo], edition: DataEdition) {
+        let ids: Vec<String> = mods.iter().map(|mod_info| mod_info.id.clone()).collect();
+
+        for mod_info in mods.iter_mut() {
+            let manifest = match &mod_info.manifest {
+                Some(manifest) => manifest,
+                None => continue,
+            };
+
+            if let Some(required) = manifest.edition.filter(|&required| required != edition) {
+                mod_info.valid = false;
+                mod_info.description = format!("Requires the {} data files", required.name());
+            } else if let Some(missing) = manifest.dependencies.iter().find(|id| !ids.contains(id)) {
+                mod_info.valid = false;
+                mod_info.description = format!("Requires the {} mod", missing);
+            }
+        }
+    }
+
+    /// Returns the mods to load when the `mod_path` one is selected, lowest priority first.
+    ///
+    /// The enabled content packs are layered on top of the selected mod, in the order they were enabled, and every
+    /// mod is preceded by its dependencies. Mods which are missing dependencies, depend on themselves or conflict
+    /// with an already loaded mod are skipped.
+    pub fn resolve_stack(&self, mod_path: Option<&str>, enabled_packs: &[String]) -> Vec<&ModInfo> {
+        let selected = mod_path.and_then(|mod_path| self.get_info_from_path(mod_path));
+        let packs = enabled_packs
+            .iter()
+            .filter_map(|id| self.mods.iter().find(|mod_info| mod_info.id == *id && mod_info.is_content_pack()));
+
+        let mut stack = Vec::new();
+        for mod_info in selected.into_iter().chain(packs) {
+            let mut layers = stack.clone();
+            match self.push_with_dependencies(mod_info, &mut layers, &mut Vec::new()) {
+                Ok(()) => stack = layers,
+                Err(err) => log::warn!("Not loading mod {}: {}", mod_info.name, err),
+            }
+        }
+
+        stack
+    }
+
+    fn push_with_dependencies<'a>(
+        &'a self,
+        mod_info: &'a ModInfo,
+        stack: &mut Vec<&'a ModInfo>,
+        visiting: &mut Vec<&'a str>,
+    ) -> Result<(), String> {
+        if stack.iter().any(|loaded| loaded.id == mod_info.id) {
+            return Ok(());
+        }
+
+        if visiting.contains(&mod_info.id.as_str()) {
+            return Err(format!("circular dependency on {}", mod_info.id));
+        }
+
+        if !mod_info.valid {
+            return Err(mod_info.description.clone());
+        }
+
+        visiting.push(&mod_info.id);
+        if let Some(manifest) = &mod_info.manifest {
+            for id in manifest.dependencies.iter() {
+                match self.mods.iter().find(|dependency| dependency.id == *id) {
+                    Some(dependency) => self.push_with_dependencies(dependency, stack, visiting)?,
+                    None => return Err(format!("missing dependency {}", id)),
+                }
+            }
+        }
+        visiting.pop();
+
+        let conflict = stack.iter().find(|loaded| loaded.conflicts_with(mod_info) || mod_info.conflicts_with(loaded));
+        if let Some(loaded) = conflict {
+            return Err(format!("conflicts with {}", loaded.name));
+        }
+
+        stack.push(mod_info);
+        Ok(())
+    }
+
+    pub fn get_info_from_path(&self, mod_path: &str) -> Option<&ModInfo> {
+        self.mods.iter().find(|x| x.path == mod_path)
+    }
+
     pub fn get_save_from_path(&self, mod_path: String) -> i32 {
         if let Some(mod_sel) = self.mods.iter().find(|x| x.path == mod_path) {
             mod_sel.save_slot
@@ -197,4 +356,61 @@ impl ModList {
             "NoName"
         }
     }
+
+    pub fn get_id_from_path(&self, mod_path: String) -> &str {
+        if let Some(mod_sel) = self.mods.iter().find(|x| x.path == mod_path) {
+            &mod_sel.id
+        } else {
+            ""
+        }
+    }
+}
+
+#[test]
+fn test_resolve_stack() {
+    let mod_info = |id: &str, dependencies: &[&str], conflicts: &[&str], content_pack: bool| {
+        let manifest: ModManifest = serde_json::from_value(serde_json::json!({
+            "id": id,
+            "name": id,
+            "dependencies": dependencies,
+            "conflicts": conflicts,
+            "content_pack": content_pack,
+        }))
+        .unwrap();
+
+        ModInfo {
+            id: id.to_owned(),
+            requirement: Requirement::Unlocked,
+            priority: 1000,
+            save_slot: 0,
+            path: format!("/mods/{}/", id),
+            name: id.to_owned(),
+            description: String::new(),
+            valid: true,
+            manifest: Some(manifest),
+        }
+    };
+
+    let mod_list = ModList {
+        mods: vec![
+            mod_info("story", &["library"], &[], false),
+            mod_info("library", &[], &[], true),
+            mod_info("skins", &["library"], &[], true),
+            mod_info("other_skins", &[], &["skins"], true),
+            mod_info("broken", &["nonexistent"], &[], true),
+            mod_info("cycle_a", &["cycle_b"], &[], true),
+            mod_info("cycle_b", &["cycle_a"], &[], true),
+        ],
+    };
+
+    let ids = |mod_path: Option<&str>, enabled: &[&str]| {
+        let enabled: Vec<String> = enabled.iter().map(|id| id.to_string()).collect();
+        mod_list.resolve_stack(mod_path, &enabled).iter().map(|mod_info| mod_info.id.as_str()).collect::<Vec<_>>()
+    };
+
+    assert_eq!(ids(Some("/mods/story/"), &[]), vec!["library", "story"]);
+    assert_eq!(ids(Some("/mods/story/"), &["skins", "library"]), vec!["library", "story", "skins"]);
+    assert_eq!(ids(None, &["skins", "other_skins"]), vec!["library", "skins"]);
+    assert_eq!(ids(None, &["broken", "cycle_a", "other_skins"]), vec!["other_skins"]);
+    assert_eq!(ids(Some("/mods/nonexistent/"), &[]), Vec::<&str>::new());
 }
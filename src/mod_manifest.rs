@@ -0,0 +1,142 @@
+use std::io::Read;
+
+use serde::{Deserialize, Serialize};
+
+use crate::engine_constants::EngineConstants;
+use crate::framework::context::Context;
+use crate::framework::error::{GameError, GameResult};
+use crate::framework::filesystem;
+
+/// Release of the game a mod is made for, as their data files aren't interchangeable.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DataEdition {
+    Freeware,
+    #[serde(rename = "csplus")]
+    CSPlus,
+    Switch,
+}
+
+impl DataEdition {
+    /// Returns the edition of the loaded base data files.
+    pub fn current(constants: &EngineConstants) -> DataEdition {
+        if constants.is_switch {
+            DataEdition::Switch
+        } else if constants.is_cs_plus {
+            DataEdition::CSPlus
+        } else {
+            DataEdition::Freeware
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            DataEdition::Freeware => "freeware",
+            DataEdition::CSPlus => "Cave Story+",
+            DataEdition::Switch => "Cave Story+ (Switch)",
+        }
+    }
+}
+
+#[inline(always)]
+fn default_saves() -> bool {
+    true
+}
+
+/// Contents of the `mod.json` file in the root of a mod directory or archive, eg.
+///
+/// ```json
+/// {
+///     "id": "my_mod",
+///     "name": "My Mod",
+///     "author": "Me",
+///     "version": "1.0",
+///     "description": "A short description.",
+///     "dependencies": ["some_other_mod"],
+///     "conflicts": ["incompatible_mod"],
+///     "edition": "freeware",
+///     "entry_point": "main.lua"
+/// }
+/// ```
+///
+/// Only `name` is required. Paths are relative to the mod directory.
+///
+/// Content packs (`"content_pack": true`) aren't started from the title screen, but can be enabled in the settings
+/// to be layered on top of any mod, eg. new skins, soundtracks or translations.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ModManifest {
+    /// Defaults to the name of the mod directory.
+    #[serde(default)]
+    pub id: String,
+    pub name: String,
+    #[serde(default)]
+    pub author: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub description: String,
+    /// IDs of the mods which have to be installed for this one to work, they're loaded below it.
+    #[serde(default)]
+    pub dependencies: Vec<String>,
+    /// IDs of the mods which can't be loaded together with this one.
+    #[serde(default)]
+    pub conflicts: Vec<String>,
+    #[serde(default)]
+    pub content_pack: bool,
+    /// Base data the mod has been made for, `None` if it works with any.
+    #[serde(default)]
+    pub edition: Option<DataEdition>,
+    /// Lua script loaded after the ones in `drs-scripts`.
+    #[serde(default)]
+    pub entry_point: Option<String>,
+    /// Whether the mod has its own save files. Mods without saves are started like the CS+ challenges.
+    #[serde(default = "default_saves")]
+    pub saves: bool,
+}
+
+impl ModManifest {
+    pub const FILE_NAME: &'static str = "mod.json";
+
+    /// Loads the manifest of the mod at `mod_path` (with a trailing slash), if it has one.
+    pub fn load(ctx: &Context, mod_path: &str) -> GameResult<Option<ModManifest>> {
+        let path = [mod_path, ModManifest::FILE_NAME].join("");
+        if !filesystem::exists(ctx, &path) {
+            return Ok(None);
+        }
+
+        let mut data = String::new();
+        filesystem::open(ctx, &path)?.read_to_string(&mut data)?;
+
+        let mut manifest: ModManifest = serde_json::from_str(&data)
+            .map_err(|err| GameError::ResourceLoadError(format!("Failed to parse {}: {}", path, err)))?;
+
+        if manifest.id.is_empty() {
+            manifest.id = mod_path.trim_end_matches('/').rsplit('/').next().unwrap_or_default().to_owned();
+        }
+
+        Ok(Some(manifest))
+    }
+
+    /// Returns the VFS path of the Lua entry point, if the mod has one.
+    pub fn entry_point_path(&self, mod_path: &str) -> Option<String> {
+        let entry_point = self.entry_point.as_ref()?;
+        Some(format!("{}/{}", mod_path.trim_end_matches('/'), entry_point.trim_start_matches('/')))
+    }
+}
+
+#[test]
+fn test_parse_manifest() {
+    let manifest: ModManifest = serde_json::from_str(
+        r#"{ "name": "Test", "version": "1.2", "edition": "csplus", "dependencies": ["base_pack"] }"#,
+    )
+    .unwrap();
+
+    assert_eq!(manifest.name, "Test");
+    assert_eq!(manifest.edition, Some(DataEdition::CSPlus));
+    assert_eq!(manifest.dependencies, vec!["base_pack".to_owned()]);
+    assert!(manifest.saves);
+    assert!(manifest.entry_point.is_none());
+
+    let manifest = ModManifest { entry_point: Some("scripts/main.lua".to_owned()), ..manifest };
+    assert_eq!(manifest.entry_point_path("/mods/test/"), Some("/mods/test/scripts/main.lua".to_owned()));
+}
@@ -0,0 +1,245 @@
+use std::collections::VecDeque;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::Instant;
+
+use crate::framework::error::GameResult;
+use crate::netplay::protocol::{
+    NetMessage, SessionInfo, StartInfo, MAX_FRAMES_PER_MESSAGE, MAX_PACKET_SIZE, PROTOCOL_VERSION, RESEND_INTERVAL,
+    TIMEOUT,
+};
+use crate::netplay::resolve_address;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClientStatus {
+    /// Waiting for the server to answer.
+    Connecting,
+    /// Joined, waiting for the second player.
+    Waiting,
+    /// The session has started, see `NetClient::start_info`.
+    Playing,
+    /// The connection is over, holds a human readable reason.
+    Closed(String),
+}
+
+/// Client side of a netplay session, exchanges local inputs for merged frames with the server.
+pub struct NetClient {
+    socket: UdpSocket,
+    server: SocketAddr,
+    hello: NetMessage,
+    status: ClientStatus,
+    player_id: u8,
+    start_info: Option<StartInfo>,
+    /// Local inputs the server hasn't acknowledged yet, the first one is input number `inputs_acked`.
+    inputs: VecDeque<u16>,
+    inputs_acked: u32,
+    /// Whether there are inputs which were never sent.
+    inputs_pending: bool,
+    /// Received frames which weren't taken yet.
+    frames: VecDeque<[u16; 2]>,
+    frames_received: u32,
+    last_recv: Instant,
+    last_send: Option<Instant>,
+}
+
+impl NetClient {
+    /// Starts connecting to a server, `update` has to be called regularly to make progress.
+    pub fn connect(address: &str, info: SessionInfo, difficulty: u8, skin: u16) -> GameResult<NetClient> {
+        let server = resolve_address(address)?;
+        let local: SocketAddr = if server.is_ipv4() { ([0, 0, 0, 0], 0).into() } else { ([0u16; 8], 0).into() };
+        let socket = UdpSocket::bind(local)?;
+        socket.set_nonblocking(true)?;
+
+        let mut client = NetClient {
+            socket,
+            server,
+            hello: NetMessage::Hello { version: PROTOCOL_VERSION, info, difficulty, skin },
+            status: ClientStatus::Connecting,
+            player_id: 0,
+            start_info: None,
+            inputs: VecDeque::new(),
+            inputs_acked: 0,
+            inputs_pending: false,
+            frames: VecDeque::new(),
+            frames_received: 0,
+            last_recv: Instant::now(),
+            last_send: None,
+        };
+        client.send_pending();
+
+        Ok(client)
+    }
+
+    pub fn server_addr(&self) -> SocketAddr {
+        self.server
+    }
+
+    pub fn status(&self) -> &ClientStatus {
+        &self.status
+    }
+
+    pub fn is_closed(&self) -> bool {
+        matches!(self.status, ClientStatus::Closed(_))
+    }
+
+    /// Slot of the local player, 0 for player 1 and 1 for player 2.
+    pub fn player_id(&self) -> u8 {
+        self.player_id
+    }
+
+    pub fn start_info(&self) -> Option<StartInfo> {
+        self.start_info
+    }
+
+    /// Processes received messages and sends out pending data, never blocks.
+    pub fn update(&mut self) {
+        let mut buf = [0u8; MAX_PACKET_SIZE];
+
+        while !self.is_closed() {
+            match self.socket.recv_from(&mut buf) {
+                Ok((len, addr)) if addr == self.server => match NetMessage::decode(&buf[..len]) {
+                    Ok(message) => self.handle_message(message),
+                    Err(err) => log::warn!("Invalid netplay message from the server: {}", err),
+                },
+                Ok(_) => {}
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                Err(err) if err.kind() == ErrorKind::ConnectionReset => break,
+                Err(err) => self.close_with(format!("Network error: {}", err)),
+            }
+        }
+
+        if self.is_closed() {
+            return;
+        }
+
+        if self.last_recv.elapsed() >= TIMEOUT {
+            let reason = match self.status {
+                ClientStatus::Connecting => "The server didn't respond.",
+                _ => "Connection to the server timed out.",
+            };
+            self.close_with(reason.to_owned());
+            return;
+        }
+
+        let resend = match self.last_send {
+            Some(time) => time.elapsed() >= RESEND_INTERVAL,
+            None => true,
+        };
+        if resend || self.inputs_pending {
+            self.send_pending();
+        }
+    }
+
+    fn handle_message(&mut self, message: NetMessage) {
+        self.last_recv = Instant::now();
+
+        match message {
+            NetMessage::Welcome { player_id } => {
+                if self.status == ClientStatus::Connecting {
+                    log::info!("Joined the session as player {}.", player_id + 1);
+                    self.player_id = player_id;
+                    self.status = ClientStatus::Waiting;
+                }
+            }
+            NetMessage::Reject { reason } => {
+                self.status = ClientStatus::Closed(reason);
+            }
+            NetMessage::Start { info } => {
+                if self.start_info.is_none() {
+                    log::info!("Session started, seed {:x}.", info.rng_seed);
+                    self.start_info = Some(info);
+                    self.status = ClientStatus::Playing;
+                }
+            }
+            NetMessage::Frames { start, frames, ack } => {
+                while self.inputs_acked < ack && !self.inputs.is_empty() {
+                    self.inputs.pop_front();
+                    self.inputs_acked += 1;
+                }
+
+                if start <= self.frames_received {
+                    let skip = (self.frames_received - start) as usize;
+                    for frame in frames.into_iter().skip(skip) {
+                        self.frames.push_back(frame);
+                        self.frames_received += 1;
+                    }
+                }
+            }
+            NetMessage::Desync { frame } => {
+                self.close_with(format!("Lost sync with the other player at frame {}.", frame));
+            }
+            NetMessage::Bye => {
+                self.status = ClientStatus::Closed("The other player left the session.".to_owned());
+            }
+            NetMessage::Hello { .. } | NetMessage::Input { .. } | NetMessage::Checksum { .. } => {}
+        }
+    }
+
+    fn send(&mut self, message: &NetMessage) {
+        let result = message.encode().and_then(|data| {
+            NetMessage::check_size(&data)?;
+            self.socket.send_to(&data, self.server)?;
+            Ok(())
+        });
+
+        match result {
+            Ok(()) => self.last_send = Some(Instant::now()),
+            Err(err) => log::warn!("Failed to send a netplay message: {}", err),
+        }
+    }
+
+    fn send_pending(&mut self) {
+        match self.status {
+            ClientStatus::Connecting | ClientStatus::Waiting => {
+                let hello = self.hello.clone();
+                self.send(&hello);
+            }
+            ClientStatus::Playing => {
+                let keys = self.inputs.iter().take(MAX_FRAMES_PER_MESSAGE).copied().collect();
+                self.send(&NetMessage::Input { start: self.inputs_acked, keys, ack: self.frames_received });
+                self.inputs_pending = false;
+            }
+            ClientStatus::Closed(_) => {}
+        }
+    }
+
+    /// Queues input of the local player for the next frame.
+    pub fn submit_input(&mut self, keys: u16) {
+        self.inputs.push_back(keys);
+        self.inputs_pending = true;
+    }
+
+    /// Number of inputs submitted since the session started.
+    pub fn inputs_submitted(&self) -> u32 {
+        self.inputs_acked + self.inputs.len() as u32
+    }
+
+    /// Inputs of both players for the next frame, if they've arrived already.
+    pub fn take_frame(&mut self) -> Option<[u16; 2]> {
+        self.frames.pop_front()
+    }
+
+    /// Reports the state hash before simulating `frame`, so the server can detect desyncs.
+    pub fn send_checksum(&mut self, frame: u32, hash: u64) {
+        self.send(&NetMessage::Checksum { frame, hash });
+    }
+
+    /// Leaves the session with a custom reason.
+    pub fn close_with(&mut self, reason: String) {
+        if !self.is_closed() {
+            self.send(&NetMessage::Bye);
+            self.status = ClientStatus::Closed(reason);
+        }
+    }
+
+    /// Leaves the session, the server ends it for the other player as well.
+    pub fn close(&mut self) {
+        self.close_with("Disconnected.".to_owned());
+    }
+}
+
+impl Drop for NetClient {
+    fn drop(&mut self) {
+        self.close();
+    }
+}
@@ -0,0 +1,296 @@
+use std::collections::VecDeque;
+use std::net::{SocketAddr, ToSocketAddrs};
+
+use crate::components::replay::{Replay, CHECKSUM_INTERVAL};
+use crate::framework::context::Context;
+use crate::framework::error::{GameError, GameResult};
+use crate::game::shared_game_state::SharedGameState;
+use crate::game::snapshot::RollbackSnapshot;
+use crate::input::input_sync::{feed_controllers, InputSync};
+use crate::input::player_controller::PlayerController;
+use crate::netplay::client::NetClient;
+use crate::netplay::protocol::DEFAULT_PORT;
+use crate::scene::game_scene::GameScene;
+use crate::scene::Scene;
+
+pub mod client;
+pub mod protocol;
+pub mod server;
+
+/// Number of frames local inputs are delayed by, hides a bit of latency without rolling back.
+pub const INPUT_DELAY: u32 = 1;
+
+/// Number of frames the game may run ahead of the other player's inputs before it waits for them.
+pub const MAX_PREDICTION: u32 = 8;
+
+/// Resolves `host:port`, or just `host` using the default port.
+pub fn resolve_address(address: &str) -> GameResult<SocketAddr> {
+    let resolved = match address.to_socket_addrs() {
+        Ok(mut addrs) => addrs.next(),
+        Err(_) => (address, DEFAULT_PORT).to_socket_addrs().ok().and_then(|mut addrs| addrs.next()),
+    };
+
+    resolved.ok_or_else(|| GameError::InvalidValue(format!("Cannot resolve netplay address '{}'.", address)))
+}
+
+/// A running online co-op game, kept in `SharedGameState` so it persists across stage transitions.
+///
+/// Both players' characters are driven by the frames received from the server, the same way replays drive them,
+/// so every client simulates exactly the same inputs. Until the other player's inputs arrive, they're predicted
+/// and the affected frames are rolled back and simulated again if the prediction was wrong.
+pub struct NetplaySession {
+    pub client: NetClient,
+    sync: InputSync,
+    local_controller: Box<dyn PlayerController>,
+    /// State before each predicted frame.
+    snapshots: VecDeque<(u32, RollbackSnapshot)>,
+    /// State hashes before simulating every `CHECKSUM_INTERVAL`th frame, sent once the frame can't be rolled back.
+    checksums: Vec<(u32, u64)>,
+    /// Frame which caused a stage transition while predicted, it's simulated again once its inputs are confirmed.
+    transition_frame: Option<u32>,
+    /// Frames before this one were simulated at least once, sound effects are muted when they're simulated again.
+    presented_frames: u32,
+    /// Song which kept playing since a rollback, until the frames are simulated up to `presented_frames` again.
+    playing_song: Option<usize>,
+    /// Number of ticks the game has been waiting for the other player's inputs.
+    pub stall_ticks: u32,
+}
+
+impl NetplaySession {
+    pub fn new(mut client: NetClient, state: &SharedGameState) -> NetplaySession {
+        let mut sync = InputSync::new(client.player_id() as usize, MAX_PREDICTION);
+        for _ in 0..INPUT_DELAY {
+            sync.add_local_input(0);
+            client.submit_input(0);
+        }
+
+        NetplaySession {
+            client,
+            sync,
+            local_controller: state.settings.create_player1_controller(),
+            snapshots: VecDeque::new(),
+            checksums: Vec::new(),
+            transition_frame: None,
+            presented_frames: 0,
+            playing_song: None,
+            stall_ticks: 0,
+        }
+    }
+
+    /// Exchanges inputs and simulates the frames which can be simulated, with `Scene::tick` of the game scene.
+    /// Has to be called while the session is taken out of `SharedGameState`.
+    ///
+    /// Pausing only stops the local game, the other player waits until it's resumed.
+    pub fn tick(&mut self, state: &mut SharedGameState, ctx: &mut Context, scene: &mut GameScene) -> GameResult {
+        self.client.update();
+        if self.client.is_closed() {
+            return Ok(());
+        }
+
+        self.local_controller.update(state, ctx)?;
+        self.local_controller.update_trigger();
+
+        if !scene.pause_menu.is_paused() && self.local_controller.trigger_menu_pause() {
+            scene.pause_menu.pause(state);
+        }
+
+        if scene.pause_menu.is_paused() {
+            return scene.tick(state, ctx);
+        }
+
+        while let Some(inputs) = self.client.take_frame() {
+            self.sync.confirm(inputs);
+        }
+
+        if self.sync.local_frames() <= self.sync.frame() + INPUT_DELAY {
+            let keys = Replay::pack_inputs(self.local_controller.as_ref());
+            self.sync.add_local_input(keys);
+            self.client.submit_input(keys);
+            self.client.update();
+        }
+
+        if let Some(frame) = self.sync.take_rollback() {
+            self.rollback(state, scene, frame)?;
+        }
+
+        let mut simulated = false;
+        loop {
+            let frame = self.sync.frame();
+            let previous = self.sync.previous_inputs();
+            let allow_prediction = match self.transition_frame {
+                Some(transition) => frame > transition,
+                None => true,
+            };
+            let inputs = match self.sync.next_frame(allow_prediction) {
+                Some(inputs) => inputs,
+                None => break,
+            };
+
+            let predicted = self.sync.is_predicted(frame);
+            if predicted {
+                self.snapshots.push_back((frame, RollbackSnapshot::capture(state, scene)));
+            }
+
+            if frame % CHECKSUM_INTERVAL as u32 == 0 {
+                self.checksums.retain(|&(checksum_frame, _)| checksum_frame != frame);
+                self.checksums.push((frame, scene.state_hash(state)));
+            }
+
+            feed_controllers([&mut scene.player1, &mut scene.player2], inputs, previous);
+
+            let resimulated = frame < self.presented_frames;
+            state.sound_manager.sfx_muted = resimulated;
+            state.sound_manager.music_muted = resimulated;
+            let result = scene.tick(state, ctx);
+            state.sound_manager.sfx_muted = false;
+            state.sound_manager.music_muted = false;
+            result?;
+
+            self.presented_frames = self.presented_frames.max(frame + 1);
+            if frame + 1 == self.presented_frames {
+                self.resume_song(state, ctx)?;
+            }
+            simulated = true;
+
+            if state.next_scene.is_some() {
+                if predicted {
+                    // stage transitions can't be rolled back, wait until the inputs leading to it are confirmed
+                    state.next_scene = None;
+                    self.rollback(state, scene, frame)?;
+                    self.sync.rewind(frame);
+                    self.transition_frame = Some(frame);
+                } else {
+                    self.snapshots.clear();
+                    self.transition_frame = None;
+                }
+                break;
+            }
+        }
+
+        let verified = self.sync.verified_frames();
+        for &(frame, hash) in self.checksums.iter().filter(|&&(frame, _)| frame <= verified) {
+            self.client.send_checksum(frame, hash);
+        }
+        self.checksums.retain(|&(frame, _)| frame > verified);
+        while self.snapshots.front().is_some_and(|&(frame, _)| frame < verified) {
+            self.snapshots.pop_front();
+        }
+
+        self.stall_ticks = if simulated { 0 } else { self.stall_ticks + 1 };
+
+        Ok(())
+    }
+
+    fn rollback(&mut self, state: &mut SharedGameState, scene: &mut GameScene, frame: u32) -> GameResult {
+        match self.snapshots.iter().find(|(snapshot_frame, _)| *snapshot_frame == frame) {
+            Some((_, snapshot)) => {
+                self.playing_song.get_or_insert(state.sound_manager.current_song());
+                snapshot.restore(state, scene)?;
+            }
+            None => {
+                self.client.close_with(format!("Cannot roll back to frame {}.", frame));
+                return Ok(());
+            }
+        }
+
+        self.snapshots.retain(|&(snapshot_frame, _)| snapshot_frame < frame);
+        self.checksums.retain(|&(checksum_frame, _)| checksum_frame <= frame);
+
+        Ok(())
+    }
+
+    /// Switches to the song the simulation ended up with after a rollback, if it's not the one still playing.
+    fn resume_song(&mut self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
+        if let Some(playing_song) = self.playing_song.take() {
+            let song = state.sound_manager.current_song();
+            if song != playing_song {
+                state.sound_manager.set_current_song(playing_song);
+                state.sound_manager.play_song(song, &state.constants, &state.settings, ctx)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_loopback_session() -> GameResult {
+    use std::time::{Duration, Instant};
+
+    use crate::netplay::client::ClientStatus;
+    use crate::netplay::protocol::SessionInfo;
+    use crate::netplay::server::NetServer;
+
+    fn pump(
+        server: &mut NetServer,
+        clients: &mut [NetClient],
+        mut done: impl FnMut(&NetServer, &mut [NetClient]) -> bool,
+    ) -> GameResult {
+        let deadline = Instant::now() + Duration::from_secs(5);
+
+        while !done(server, clients) {
+            if Instant::now() >= deadline {
+                return Err(GameError::InvalidValue("Loopback session timed out.".to_owned()));
+            }
+
+            server.update()?;
+            clients.iter_mut().for_each(NetClient::update);
+            std::thread::sleep(Duration::from_millis(1));
+        }
+
+        Ok(())
+    }
+
+    let mut server = NetServer::bind("127.0.0.1:0")?;
+    let address = server.local_addr()?.to_string();
+    let info = SessionInfo { engine_version: "test".to_owned(), mod_id: String::new(), content_hash: 1 };
+
+    let mut clients = vec![NetClient::connect(&address, info.clone(), 2, 0)?];
+    pump(&mut server, &mut clients, |_, clients| clients[0].status() == &ClientStatus::Waiting)?;
+
+    let mut intruder = vec![NetClient::connect(&address, SessionInfo { content_hash: 2, ..info.clone() }, 0, 0)?];
+    pump(&mut server, &mut intruder, |_, clients| clients[0].is_closed())?;
+    assert_eq!(intruder[0].status(), &ClientStatus::Closed("The other player runs different game data.".to_owned()));
+
+    clients.push(NetClient::connect(&address, info, 0, 3)?);
+    pump(&mut server, &mut clients, |_, clients| clients.iter().all(|client| client.start_info().is_some()))?;
+    assert!(server.is_running());
+    assert_eq!(clients[0].start_info(), clients[1].start_info());
+    assert_eq!(clients[0].start_info().map(|info| (info.difficulty, info.player2_skin)), Some((2, 3)));
+    assert_eq!((clients[0].player_id(), clients[1].player_id()), (0, 1));
+
+    // more than fits in a single message
+    const FRAMES: u16 = 200;
+    for frame in 0..FRAMES {
+        for client in clients.iter_mut() {
+            client.submit_input(frame * 2 + client.player_id() as u16);
+        }
+    }
+
+    let mut received = [Vec::new(), Vec::new()];
+    pump(&mut server, &mut clients, |_, clients| {
+        for (client, received) in clients.iter_mut().zip(received.iter_mut()) {
+            while let Some(keys) = client.take_frame() {
+                received.push(keys);
+            }
+        }
+
+        received.iter().all(|received| received.len() == FRAMES as usize)
+    })?;
+
+    assert_eq!(received[0], received[1]);
+    for (frame, keys) in received[0].iter().enumerate() {
+        assert_eq!(*keys, [frame as u16 * 2, frame as u16 * 2 + 1]);
+    }
+
+    // frames are dropped once both clients acknowledged them
+    pump(&mut server, &mut clients, |server, _| server.buffered_frames() == 0)?;
+    assert_eq!(server.frame_count(), FRAMES as u32);
+
+    clients[0].send_checksum(0, 0x1234);
+    clients[1].send_checksum(0, 0x4321);
+    pump(&mut server, &mut clients, |_, clients| clients.iter().all(NetClient::is_closed))?;
+    assert_eq!(server.desync, Some(0));
+
+    Ok(())
+}
@@ -0,0 +1,106 @@
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::framework::error::{GameError, GameResult};
+
+/// Bumped whenever the message layout changes, peers with a different version are rejected.
+pub const PROTOCOL_VERSION: u16 = 1;
+
+pub const DEFAULT_PORT: u16 = 23456;
+
+/// Largest datagram we're going to receive, messages are kept well below the usual MTU.
+pub const MAX_PACKET_SIZE: usize = 1400;
+
+/// Upper bound of inputs or frames sent in a single message, unacknowledged ones are sent again in the next one.
+pub const MAX_FRAMES_PER_MESSAGE: usize = 64;
+
+/// How often unacknowledged data is sent again when nothing new is sent in the meantime.
+pub const RESEND_INTERVAL: Duration = Duration::from_millis(30);
+
+/// Peers that haven't sent anything for this long are considered disconnected.
+pub const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Describes the game data a client runs, both players have to run the same data to stay in sync.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct SessionInfo {
+    pub engine_version: String,
+    pub mod_id: String,
+    /// See `Replay::content_hash`.
+    pub content_hash: u64,
+}
+
+/// Settings the game is started with, the host picks difficulty from player 1 and skin from player 2.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StartInfo {
+    pub rng_seed: u64,
+    pub difficulty: u8,
+    pub player2_skin: u16,
+}
+
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub enum NetMessage {
+    /// Client -> server, sent until the client is welcomed or rejected.
+    Hello { version: u16, info: SessionInfo, difficulty: u8, skin: u16 },
+    /// Server -> client, assigns the player slot (0 or 1).
+    Welcome { player_id: u8 },
+    /// Server -> client, the connection is refused and won't be answered anymore.
+    Reject { reason: String },
+    /// Server -> client, sent to both clients once the second player joins.
+    Start { info: StartInfo },
+    /// Client -> server, inputs of the local player starting at frame `start`.
+    /// `ack` is the number of combined frames the client has received so far.
+    Input { start: u32, keys: Vec<u16>, ack: u32 },
+    /// Server -> client, inputs of both players starting at frame `start`.
+    /// `ack` is the number of inputs received from this client so far.
+    Frames { start: u32, frames: Vec<[u16; 2]>, ack: u32 },
+    /// Client -> server, hash of the game state before simulating `frame`.
+    Checksum { frame: u32, hash: u64 },
+    /// Server -> client, the clients reported different game state for `frame`.
+    Desync { frame: u32 },
+    /// Either direction, the peer is leaving the session.
+    Bye,
+}
+
+impl NetMessage {
+    pub fn encode(&self) -> GameResult<Vec<u8>> {
+        Ok(serde_cbor::to_vec(self)?)
+    }
+
+    pub fn decode(data: &[u8]) -> GameResult<NetMessage> {
+        Ok(serde_cbor::from_slice(data)?)
+    }
+
+    pub fn check_size(data: &[u8]) -> GameResult {
+        if data.len() > MAX_PACKET_SIZE {
+            return Err(GameError::InvalidValue(format!("Netplay message too large: {} bytes.", data.len())));
+        }
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_message_round_trip() -> GameResult {
+    let messages = [
+        NetMessage::Hello {
+            version: PROTOCOL_VERSION,
+            info: SessionInfo { engine_version: "0.100.0".to_owned(), mod_id: String::new(), content_hash: 0x1234 },
+            difficulty: 2,
+            skin: 1,
+        },
+        NetMessage::Input { start: 10, keys: vec![0xffff; MAX_FRAMES_PER_MESSAGE], ack: 8 },
+        NetMessage::Frames { start: 8, frames: vec![[0xffff, 0xffff]; MAX_FRAMES_PER_MESSAGE], ack: 12 },
+        NetMessage::Bye,
+    ];
+
+    for message in messages {
+        let data = message.encode()?;
+        NetMessage::check_size(&data)?;
+        assert_eq!(NetMessage::decode(&data)?, message);
+    }
+
+    assert!(NetMessage::decode(&[0xff, 0x00]).is_err());
+
+    Ok(())
+}
@@ -0,0 +1,385 @@
+use std::collections::HashMap;
+use std::io::ErrorKind;
+use std::net::{SocketAddr, UdpSocket};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::framework::error::GameResult;
+use crate::netplay::protocol::{
+    NetMessage, SessionInfo, StartInfo, MAX_FRAMES_PER_MESSAGE, MAX_PACKET_SIZE, PROTOCOL_VERSION, RESEND_INTERVAL,
+    TIMEOUT,
+};
+use crate::netplay::resolve_address;
+use crate::util::rng::XorShift;
+
+/// Checksums older than this many frames are dropped if the other client never reported theirs.
+const CHECKSUM_WINDOW: u32 = 3000;
+
+struct Peer {
+    addr: SocketAddr,
+    info: SessionInfo,
+    difficulty: u8,
+    skin: u16,
+    /// Inputs received from this client which aren't merged into frames yet.
+    inputs: Vec<u16>,
+    /// Number of inputs merged into frames and dropped from `inputs`.
+    inputs_merged: u32,
+    /// Number of combined frames the client has acknowledged.
+    frames_acked: u32,
+    /// Number of combined frames sent at least once.
+    frames_sent: u32,
+    last_recv: Instant,
+    last_send: Instant,
+}
+
+impl Peer {
+    /// Number of inputs received from this client so far.
+    fn inputs_received(&self) -> u32 {
+        self.inputs_merged + self.inputs.len() as u32
+    }
+}
+
+/// Hosts a single co-op session at a time for two clients.
+///
+/// The server doesn't simulate the game, it merges the inputs of both players into frames, which every client
+/// simulates in lockstep, and compares the state checksums they report.
+pub struct NetServer {
+    socket: UdpSocket,
+    peers: [Option<Peer>; 2],
+    start: Option<StartInfo>,
+    /// Frames not acknowledged by both clients yet, starting at frame `frames_acked`.
+    frames: Vec<[u16; 2]>,
+    /// Number of frames both clients have acknowledged, they're dropped from `frames`.
+    frames_acked: u32,
+    /// First checksum reported for a frame and the slot of the client which reported it.
+    checksums: HashMap<u32, (usize, u64)>,
+    /// Frame of the last detected desync.
+    pub desync: Option<u32>,
+}
+
+impl NetServer {
+    pub fn bind(address: &str) -> GameResult<NetServer> {
+        let socket = UdpSocket::bind(resolve_address(address)?)?;
+        socket.set_nonblocking(true)?;
+
+        Ok(NetServer {
+            socket,
+            peers: [None, None],
+            start: None,
+            frames: Vec::new(),
+            frames_acked: 0,
+            checksums: HashMap::new(),
+            desync: None,
+        })
+    }
+
+    pub fn local_addr(&self) -> GameResult<SocketAddr> {
+        Ok(self.socket.local_addr()?)
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.start.is_some()
+    }
+
+    /// Number of frames merged from the inputs of both clients so far.
+    pub fn frame_count(&self) -> u32 {
+        self.frames_acked + self.frames.len() as u32
+    }
+
+    /// Number of frames kept in memory until both clients acknowledge them.
+    pub fn buffered_frames(&self) -> usize {
+        self.frames.len()
+    }
+
+    /// Processes received messages and sends out pending data, never blocks.
+    pub fn update(&mut self) -> GameResult {
+        let mut buf = [0u8; MAX_PACKET_SIZE];
+
+        loop {
+            match self.socket.recv_from(&mut buf) {
+                Ok((len, addr)) => match NetMessage::decode(&buf[..len]) {
+                    Ok(message) => self.handle_message(addr, message),
+                    Err(err) => log::warn!("Invalid netplay message from {}: {}", addr, err),
+                },
+                Err(err) if err.kind() == ErrorKind::WouldBlock => break,
+                // ICMP port unreachable replies from clients that are gone, they'll time out.
+                Err(err) if err.kind() == ErrorKind::ConnectionReset => continue,
+                Err(err) => return Err(err.into()),
+            }
+        }
+
+        for slot in 0..self.peers.len() {
+            if let Some(peer) = &self.peers[slot] {
+                if peer.last_recv.elapsed() >= TIMEOUT {
+                    log::warn!("Player {} ({}) timed out.", slot + 1, peer.addr);
+                    self.remove_peer(slot);
+                }
+            }
+        }
+
+        self.merge_frames();
+        self.send_updates();
+
+        Ok(())
+    }
+
+    fn find_peer(&self, addr: SocketAddr) -> Option<usize> {
+        self.peers.iter().position(|peer| peer.as_ref().is_some_and(|peer| peer.addr == addr))
+    }
+
+    fn send(&self, addr: SocketAddr, message: &NetMessage) {
+        let result = message.encode().and_then(|data| {
+            NetMessage::check_size(&data)?;
+            self.socket.send_to(&data, addr)?;
+            Ok(())
+        });
+
+        if let Err(err) = result {
+            log::warn!("Failed to send a netplay message to {}: {}", addr, err);
+        }
+    }
+
+    fn handle_message(&mut self, addr: SocketAddr, message: NetMessage) {
+        let slot = self.find_peer(addr);
+
+        match message {
+            NetMessage::Hello { version, info, difficulty, skin } => {
+                if let Some(slot) = slot {
+                    // the welcome got lost, the next update sends it again
+                    if let Some(peer) = &mut self.peers[slot] {
+                        peer.last_recv = Instant::now();
+                    }
+                    return;
+                }
+
+                if let Err(reason) = self.check_hello(version, &info) {
+                    log::info!("Rejected {}: {}", addr, reason);
+                    self.send(addr, &NetMessage::Reject { reason });
+                    return;
+                }
+
+                let slot = match self.peers.iter().position(|peer| peer.is_none()) {
+                    Some(slot) => slot,
+                    None => return,
+                };
+
+                log::info!("Player {} joined from {}.", slot + 1, addr);
+                let now = Instant::now();
+                self.peers[slot] = Some(Peer {
+                    addr,
+                    info,
+                    difficulty,
+                    skin,
+                    inputs: Vec::new(),
+                    inputs_merged: 0,
+                    frames_acked: 0,
+                    frames_sent: 0,
+                    last_recv: now,
+                    last_send: now,
+                });
+                self.send(addr, &NetMessage::Welcome { player_id: slot as u8 });
+
+                if self.peers.iter().all(|peer| peer.is_some()) {
+                    self.start_session();
+                }
+            }
+            NetMessage::Input { start, keys, ack } => {
+                let frame_count = self.frame_count();
+                let peer = match slot.and_then(|slot| self.peers[slot].as_mut()) {
+                    Some(peer) => peer,
+                    None => return,
+                };
+
+                peer.last_recv = Instant::now();
+                peer.frames_acked = peer.frames_acked.max(ack.min(frame_count));
+
+                let received = peer.inputs_received();
+                if start <= received {
+                    let skip = (received - start) as usize;
+                    peer.inputs.extend(keys.iter().skip(skip));
+                }
+            }
+            NetMessage::Checksum { frame, hash } => {
+                let slot = match slot {
+                    Some(slot) => slot,
+                    None => return,
+                };
+
+                match self.checksums.get(&frame) {
+                    Some(&(other_slot, other_hash)) if other_slot != slot => {
+                        self.checksums.remove(&frame);
+
+                        if other_hash != hash && self.desync.is_none() {
+                            log::error!("Clients desynchronized at frame {}.", frame);
+                            self.desync = Some(frame);
+
+                            for peer in self.peers.iter().flatten() {
+                                self.send(peer.addr, &NetMessage::Desync { frame });
+                            }
+                        }
+                    }
+                    Some(_) => {}
+                    None => {
+                        self.checksums.insert(frame, (slot, hash));
+                    }
+                }
+
+                let current = self.frame_count();
+                self.checksums.retain(|&frame, _| frame + CHECKSUM_WINDOW > current);
+            }
+            NetMessage::Bye => {
+                if let Some(slot) = slot {
+                    log::info!("Player {} left.", slot + 1);
+                    self.remove_peer(slot);
+                }
+            }
+            NetMessage::Welcome { .. }
+            | NetMessage::Reject { .. }
+            | NetMessage::Start { .. }
+            | NetMessage::Frames { .. }
+            | NetMessage::Desync { .. } => {}
+        }
+    }
+
+    fn check_hello(&self, version: u16, info: &SessionInfo) -> Result<(), String> {
+        if version != PROTOCOL_VERSION {
+            return Err(format!(
+                "Protocol version mismatch: server uses {}, client uses {}.",
+                PROTOCOL_VERSION, version
+            ));
+        }
+
+        if self.start.is_some() || self.peers.iter().all(|peer| peer.is_some()) {
+            return Err("The session is full.".to_owned());
+        }
+
+        if let Some(other) = self.peers.iter().flatten().next() {
+            if other.info.mod_id != info.mod_id {
+                return Err(format!("The other player runs mod '{}', you run '{}'.", other.info.mod_id, info.mod_id));
+            }
+
+            if other.info.content_hash != info.content_hash {
+                return Err("The other player runs different game data.".to_owned());
+            }
+
+            if other.info.engine_version != info.engine_version {
+                return Err(format!(
+                    "The other player runs {}, you run {}.",
+                    other.info.engine_version, info.engine_version
+                ));
+            }
+        }
+
+        Ok(())
+    }
+
+    fn start_session(&mut self) {
+        let (difficulty, player2_skin) = match &self.peers {
+            [Some(player1), Some(player2)] => (player1.difficulty, player2.skin),
+            _ => return,
+        };
+
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap_or_default();
+        let seed = (now.as_secs() as u32 ^ now.subsec_nanos()) as i32;
+        let info = StartInfo { rng_seed: XorShift::new(seed | 1).dump_state(), difficulty, player2_skin };
+
+        log::info!("Starting the session, seed {:x}.", info.rng_seed);
+        self.start = Some(info);
+        self.frames.clear();
+        self.frames_acked = 0;
+        self.checksums.clear();
+        self.desync = None;
+
+        for peer in self.peers.iter().flatten() {
+            self.send(peer.addr, &NetMessage::Start { info });
+        }
+    }
+
+    /// Drops a client, a running session can't continue with a single player so it's ended for both of them.
+    fn remove_peer(&mut self, slot: usize) {
+        self.peers[slot] = None;
+
+        if self.start.take().is_some() {
+            for peer in self.peers.iter().flatten() {
+                self.send(peer.addr, &NetMessage::Bye);
+            }
+
+            self.peers = [None, None];
+            log::info!("Session ended after {} frames.", self.frame_count());
+        }
+    }
+
+    /// Merges the inputs both clients have sent into frames, and drops the frames both of them have received.
+    fn merge_frames(&mut self) {
+        if let [Some(player1), Some(player2)] = &mut self.peers {
+            let merged = self.frames_acked + self.frames.len() as u32;
+            let available = player1.inputs_received().min(player2.inputs_received());
+
+            for frame in merged..available {
+                self.frames.push([
+                    player1.inputs[(frame - player1.inputs_merged) as usize],
+                    player2.inputs[(frame - player2.inputs_merged) as usize],
+                ]);
+            }
+
+            let acked = player1.frames_acked.min(player2.frames_acked);
+            for peer in [player1, player2] {
+                peer.inputs.drain(..(available - peer.inputs_merged) as usize);
+                peer.inputs_merged = available;
+            }
+
+            if acked > self.frames_acked {
+                self.frames.drain(..(acked - self.frames_acked) as usize);
+                self.frames_acked = acked;
+            }
+        }
+    }
+
+    fn send_updates(&mut self) {
+        let now = Instant::now();
+        let frame_count = self.frame_count();
+        let mut messages = Vec::new();
+
+        for (slot, peer) in self.peers.iter_mut().enumerate() {
+            let peer = match peer {
+                Some(peer) => peer,
+                None => continue,
+            };
+
+            let has_new_frames = frame_count > peer.frames_sent;
+            if !has_new_frames && now.duration_since(peer.last_send) < RESEND_INTERVAL {
+                continue;
+            }
+
+            let message = match self.start {
+                None => NetMessage::Welcome { player_id: slot as u8 },
+                Some(info) if peer.inputs_received() == 0 => NetMessage::Start { info },
+                Some(_) => {
+                    let start = peer.frames_acked;
+                    let end = frame_count.min(start + MAX_FRAMES_PER_MESSAGE as u32);
+                    peer.frames_sent = peer.frames_sent.max(end);
+
+                    let buffered = (start - self.frames_acked) as usize..(end - self.frames_acked) as usize;
+                    NetMessage::Frames { start, frames: self.frames[buffered].to_vec(), ack: peer.inputs_received() }
+                }
+            };
+
+            peer.last_send = now;
+            messages.push((peer.addr, message));
+        }
+
+        for (addr, message) in messages {
+            self.send(addr, &message);
+        }
+    }
+}
+
+/// Runs a dedicated server until the process is killed, used by `--server-mode`.
+pub fn run(address: &str) -> GameResult {
+    let mut server = NetServer::bind(address)?;
+    log::info!("Netplay server listening on {}", server.local_addr()?);
+
+    loop {
+        server.update()?;
+        std::thread::sleep(Duration::from_millis(1));
+    }
+}
@@ -2,16 +2,22 @@ use std::cell::RefCell;
 use std::rc::Rc;
 
 use downcast::Downcast;
-use imgui::{Condition, MenuItem, TabItem, TabItemFlags, Window};
+use imgui::{Condition, Key, MenuItem, TabItem, TabItemFlags, Window};
+use strum::IntoEnumIterator;
 
+use crate::common::{Color, Rect};
+use crate::editor::stage_table::{create_stage_files, save_stage_table};
 use crate::editor::{CurrentTool, EditorInstance};
 use crate::framework::context::Context;
 use crate::framework::error::GameResult;
+use crate::framework::filesystem;
 use crate::framework::keyboard;
 use crate::framework::keyboard::ScanCode;
 use crate::framework::ui::Components;
+use crate::game::map::NPCData;
 use crate::game::shared_game_state::SharedGameState;
-use crate::game::stage::Stage;
+use crate::game::stage::{Background, BackgroundType, NpcType, Stage, StageData, StageTableFormat, Tileset};
+use crate::live_debugger::script_editor::ScriptEditor;
 use crate::scene::game_scene::GameScene;
 use crate::scene::title_scene::TitleScene;
 
@@ -29,6 +35,22 @@ impl ErrorList {
             self.errors.push(err.to_string());
         }
     }
+
+    fn window(&mut self, ui: &imgui::Ui) {
+        if self.errors.is_empty() {
+            return;
+        }
+
+        Window::new("Errors").size([400.0, 160.0], Condition::FirstUseEver).build(ui, || {
+            for error in self.errors.iter() {
+                ui.text_wrapped(error);
+            }
+
+            if ui.button("Dismiss") {
+                self.errors.clear();
+            }
+        });
+    }
 }
 
 fn catch(error_list: Rc<RefCell<ErrorList>>, func: impl FnOnce() -> GameResult<()>) {
@@ -37,31 +59,90 @@ fn catch(error_list: Rc<RefCell<ErrorList>>, func: impl FnOnce() -> GameResult<(
 
 pub struct EditorScene {
     stage_list: StageListWindow,
+    stage_entry: StageEntryWindow,
+    script_editor: ScriptEditor,
+    /// Whether the stage table has been modified since it was last saved.
+    stage_table_dirty: bool,
+    /// Whether the editor is waiting for confirmation to exit without saving the stage table.
+    confirm_exit: bool,
     error_list: Rc<RefCell<ErrorList>>,
     instances: Vec<EditorInstance>,
     subscene: Option<Box<GameScene>>,
     current_tool: CurrentTool,
     selected_instance: usize,
     switch_tab: bool,
+    npc_preview_rects: Rc<Vec<Option<Rect<u16>>>>,
 }
 
 impl EditorScene {
     pub fn new() -> Self {
         EditorScene {
             stage_list: StageListWindow::new(),
+            stage_entry: StageEntryWindow::new(),
+            script_editor: ScriptEditor::new(),
+            stage_table_dirty: false,
+            confirm_exit: false,
             error_list: Rc::new(RefCell::new(ErrorList::new())),
             instances: Vec::new(),
             subscene: None,
             current_tool: CurrentTool::Move,
             selected_instance: 0,
             switch_tab: false,
+            npc_preview_rects: Rc::new(Vec::new()),
         }
     }
 
     fn exit_editor(&mut self, state: &mut SharedGameState) {
+        if self.stage_table_dirty {
+            self.confirm_exit = true;
+            return;
+        }
+
         state.next_scene = Some(Box::new(TitleScene::new()));
     }
 
+    fn confirm_exit_window(&mut self, state: &mut SharedGameState, ctx: &mut Context, ui: &imgui::Ui) {
+        if !self.confirm_exit {
+            return;
+        }
+
+        let mut save = false;
+        let mut discard = false;
+        let mut cancel = false;
+
+        Window::new("Exit editor")
+            .resizable(false)
+            .collapsible(false)
+            .position([state.screen_size.0 / 2.0, state.screen_size.1 / 2.0], Condition::Appearing)
+            .position_pivot([0.5, 0.5])
+            .build(ui, || {
+                ui.text("The stage table has unsaved changes.");
+
+                save = ui.button("Save and exit");
+                ui.same_line();
+                discard = ui.button("Exit without saving");
+                ui.same_line();
+                cancel = ui.button("Cancel");
+            });
+
+        if save {
+            self.save_stage_table(state, ctx);
+        }
+
+        if discard {
+            self.stage_table_dirty = false;
+        }
+
+        if save || discard || cancel {
+            self.confirm_exit = false;
+        }
+
+        // saving can fail, the error is shown and the editor stays open
+        if (save || discard) && !self.stage_table_dirty {
+            self.exit_editor(state);
+        }
+    }
+
     fn open_stage(&mut self, state: &mut SharedGameState, ctx: &mut Context, stage_id: usize) {
         catch(self.error_list.clone(), || {
             for (idx, instance) in self.instances.iter().enumerate() {
@@ -73,9 +154,18 @@ impl EditorScene {
             }
 
             if let Some(stage) = state.stages.get(stage_id) {
-                let stage = Stage::load(&state.constants.base_paths, stage, ctx)?;
-
-                let new_instance = EditorInstance::new(stage_id, stage);
+                let roots = &state.constants.base_paths;
+                let stage = Stage::load(roots, stage, ctx)?;
+
+                // stages without an entity file are valid, it will be created on save
+                let (pxe_version, npcs) =
+                    match filesystem::open_find(ctx, roots, ["Stage/", &stage.data.map, ".pxe"].join("")) {
+                        Ok(pxe_file) => NPCData::load_versioned_from(pxe_file)?,
+                        Err(_) => (0, Vec::new()),
+                    };
+
+                let new_instance =
+                    EditorInstance::new(stage_id, stage, pxe_version, npcs, self.npc_preview_rects.clone());
                 self.instances.push(new_instance);
                 self.selected_instance = self.instances.len() - 1;
                 self.switch_tab = true;
@@ -85,6 +175,162 @@ impl EditorScene {
         });
     }
 
+    fn save_stage(&mut self, state: &mut SharedGameState, ctx: &mut Context) {
+        catch(self.error_list.clone(), || {
+            if let Some(instance) = self.instances.get(self.selected_instance) {
+                instance.save(state, ctx)?;
+            }
+
+            Ok(())
+        });
+    }
+
+    fn open_script(&mut self, state: &mut SharedGameState, ctx: &mut Context) {
+        catch(self.error_list.clone(), || {
+            if let Some(instance) = self.instances.get(self.selected_instance) {
+                let path = ["Stage/", &instance.stage.data.map, ".tsc"].join("");
+                self.script_editor.open(state, ctx, &path)?;
+            }
+
+            Ok(())
+        });
+    }
+
+    fn save_stage_table(&mut self, state: &mut SharedGameState, ctx: &mut Context) {
+        catch(self.error_list.clone(), || {
+            save_stage_table(state, ctx)?;
+            self.stage_table_dirty = false;
+
+            Ok(())
+        });
+    }
+
+    fn new_stage(&mut self, state: &mut SharedGameState) {
+        let mut map = "New".to_owned();
+        let mut counter = 1;
+        while state.stages.iter().any(|stage| stage.map == map) {
+            counter += 1;
+            map = format!("New{}", counter);
+        }
+
+        let stage = StageData {
+            name: "New stage".to_owned(),
+            name_jp: "New stage".to_owned(),
+            map,
+            boss_no: 0,
+            tileset: Tileset::new("0"),
+            pxpack_data: None,
+            background: Background::new("bk0"),
+            background_type: BackgroundType::TiledStatic,
+            background_type_id: BackgroundType::TiledStatic as u8,
+            background_color: Color::from_rgb(0, 0, 32),
+            npc1: NpcType::new("0"),
+            npc2: NpcType::new("0"),
+        };
+
+        self.stage_entry.open(None, &stage);
+    }
+
+    fn apply_stage_entry(
+        &mut self,
+        state: &mut SharedGameState,
+        ctx: &mut Context,
+        stage_id: Option<usize>,
+        stage: &StageData,
+    ) {
+        let mut new_stage_id = None;
+
+        catch(self.error_list.clone(), || {
+            match stage_id {
+                Some(stage_id) => {
+                    if let Some(entry) = state.stages.get_mut(stage_id) {
+                        *entry = stage.clone();
+                    }
+
+                    for instance in self.instances.iter_mut().filter(|i| i.stage_id == stage_id) {
+                        instance.set_stage_data(state, ctx, stage.clone())?;
+                    }
+                }
+                None => {
+                    create_stage_files(state, ctx, stage)?;
+                    state.stages.push(stage.clone());
+                    new_stage_id = Some(state.stages.len() - 1);
+                }
+            }
+
+            self.stage_table_dirty = true;
+
+            Ok(())
+        });
+
+        if let Some(stage_id) = new_stage_id {
+            self.open_stage(state, ctx, stage_id);
+        }
+    }
+
+    /// Blanks a stage table entry, the map files are kept. The entry isn't removed, so the following stages keep their
+    /// IDs used by scripts and save files.
+    fn delete_stage(&mut self, state: &mut SharedGameState, stage_id: usize) {
+        let stage = match state.stages.get_mut(stage_id) {
+            Some(stage) => stage,
+            None => return,
+        };
+
+        *stage = StageData {
+            name: String::new(),
+            name_jp: String::new(),
+            map: String::new(),
+            boss_no: 0,
+            tileset: Tileset::new("0"),
+            pxpack_data: None,
+            background: Background::new("0"),
+            background_type: BackgroundType::TiledStatic,
+            background_type_id: BackgroundType::TiledStatic as u8,
+            background_color: Color::from_rgb(0, 0, 32),
+            npc1: NpcType::new("0"),
+            npc2: NpcType::new("0"),
+        };
+        self.instances.retain(|instance| instance.stage_id != stage_id);
+
+        self.selected_instance = self.selected_instance.min(self.instances.len().saturating_sub(1));
+        self.stage_table_dirty = true;
+    }
+
+    fn undo(&mut self) {
+        if let Some(instance) = self.instances.get_mut(self.selected_instance) {
+            instance.undo();
+        }
+    }
+
+    fn redo(&mut self) {
+        if let Some(instance) = self.instances.get_mut(self.selected_instance) {
+            instance.redo();
+        }
+    }
+
+    fn process_shortcuts(&mut self, state: &mut SharedGameState, ctx: &mut Context, ui: &imgui::Ui) {
+        let io = ui.io();
+
+        // leave the shortcuts to text fields while typing
+        if !io.key_ctrl || io.want_text_input {
+            return;
+        }
+
+        if ui.is_key_pressed(Key::Z) {
+            if io.key_shift {
+                self.redo();
+            } else {
+                self.undo();
+            }
+        } else if ui.is_key_pressed(Key::Y) {
+            self.redo();
+        } else if ui.is_key_pressed(Key::S) {
+            self.save_stage(state, ctx);
+        } else if ui.is_key_pressed(Key::O) {
+            self.stage_list.show();
+        }
+    }
+
     fn test_stage(&mut self, state: &mut SharedGameState, ctx: &mut Context) {
         catch(self.error_list.clone(), || {
             if let Some(instance) = self.instances.get(self.selected_instance) {
@@ -106,10 +352,20 @@ impl EditorScene {
     }
 
     fn perform_actions(&mut self, state: &mut SharedGameState, ctx: &mut Context) {
-        let actions = std::mem::take(&mut self.stage_list.actions);
+        let mut actions = std::mem::take(&mut self.stage_list.actions);
+        actions.append(&mut self.stage_entry.actions);
+
         for action in actions.iter() {
             match action {
                 StageListAction::OpenStage(idx) => self.open_stage(state, ctx, *idx),
+                StageListAction::EditStage(idx) => {
+                    if let Some(stage) = state.stages.get(*idx) {
+                        self.stage_entry.open(Some(*idx), stage);
+                    }
+                }
+                StageListAction::NewStage => self.new_stage(state),
+                StageListAction::DeleteStage(idx) => self.delete_stage(state, *idx),
+                StageListAction::ApplyEntry(stage_id, stage) => self.apply_stage_entry(state, ctx, *stage_id, stage),
             }
         }
     }
@@ -136,6 +392,7 @@ impl ExtraWidgetsExt for imgui::Ui<'_> {
 impl Scene for EditorScene {
     fn init(&mut self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
         state.sound_manager.play_song(0, &state.constants, &state.settings, ctx)?;
+        self.npc_preview_rects = Rc::new(state.constants.npc.preview_rects());
 
         Ok(())
     }
@@ -223,6 +480,14 @@ impl Scene for EditorScene {
                     self.stage_list.show();
                 }
 
+                if MenuItem::new("Save").shortcut("Ctrl+S").enabled(!self.instances.is_empty()).build(ui) {
+                    self.save_stage(state, ctx);
+                }
+
+                if MenuItem::new("Save stage table").enabled(self.stage_table_dirty).build(ui) {
+                    self.save_stage_table(state, ctx);
+                }
+
                 ui.separator();
 
                 if MenuItem::new("Exit editor").build(ui) {
@@ -231,9 +496,52 @@ impl Scene for EditorScene {
 
                 menu.end();
             }
+
+            if let Some(menu) = ui.begin_menu("Edit") {
+                let (can_undo, can_redo) = self
+                    .instances
+                    .get(self.selected_instance)
+                    .map_or((false, false), |i| (i.history.can_undo(), i.history.can_redo()));
+
+                if MenuItem::new("Undo").shortcut("Ctrl+Z").enabled(can_undo).build(ui) {
+                    self.undo();
+                }
+
+                if MenuItem::new("Redo").shortcut("Ctrl+Y").enabled(can_redo).build(ui) {
+                    self.redo();
+                }
+
+                menu.end();
+            }
+
+            if let Some(menu) = ui.begin_menu("Stage") {
+                if MenuItem::new("New stage").build(ui) {
+                    self.new_stage(state);
+                }
+
+                if MenuItem::new("Edit table entry").enabled(!self.instances.is_empty()).build(ui) {
+                    if let Some(instance) = self.instances.get(self.selected_instance) {
+                        self.stage_entry.open(Some(instance.stage_id), &instance.stage.data);
+                    }
+                }
+
+                if MenuItem::new("Edit script").enabled(!self.instances.is_empty()).build(ui) {
+                    self.open_script(state, ctx);
+                }
+
+                if MenuItem::new("Resize map").enabled(!self.instances.is_empty()).build(ui) {
+                    if let Some(instance) = self.instances.get_mut(self.selected_instance) {
+                        instance.show_resize_window();
+                    }
+                }
+
+                menu.end();
+            }
             menu_bar.end();
         }
 
+        self.process_shortcuts(state, ctx, ui);
+
         Window::new("Toolbar")
             .title_bar(false)
             .resizable(false)
@@ -255,6 +563,10 @@ impl Scene for EditorScene {
                 if ui.tool_button("Rectangle", self.current_tool == CurrentTool::Rectangle) {
                     self.current_tool = CurrentTool::Rectangle;
                 }
+                ui.same_line();
+                if ui.tool_button("Entity", self.current_tool == CurrentTool::Entity) {
+                    self.current_tool = CurrentTool::Entity;
+                }
 
                 ui.same_line();
                 ui.text("|");
@@ -285,6 +597,10 @@ impl Scene for EditorScene {
             });
 
         self.stage_list.action(state, ctx, ui);
+        self.stage_entry.action(state, ui);
+        self.script_editor.action(state, ctx, ui);
+        self.error_list.borrow_mut().window(ui);
+        self.confirm_exit_window(state, ctx, ui);
 
         if let Some(instance) = self.instances.get_mut(self.selected_instance) {
             instance.process(state, ctx, ui, self.current_tool);
@@ -302,6 +618,11 @@ struct StageListWindow {
 
 enum StageListAction {
     OpenStage(usize),
+    EditStage(usize),
+    NewStage,
+    DeleteStage(usize),
+    /// Replaces the given stage table entry, or adds a new stage if there's no index.
+    ApplyEntry(Option<usize>, StageData),
 }
 
 impl StageListWindow {
@@ -338,9 +659,23 @@ impl StageListWindow {
                     }
 
                     ui.same_line();
-                    if ui.button("Edit table entry") {}
+                    if ui.button("Edit table entry") {
+                        self.actions.push(StageListAction::EditStage(self.selected_stage as usize));
+                    }
+
+                    ui.same_line();
+                    if ui.button("Delete") {
+                        self.actions.push(StageListAction::DeleteStage(self.selected_stage as usize));
+                    }
+                    if ui.is_item_hovered() {
+                        ui.tooltip_text("Clears the entry, the IDs of the other stages stay the same.");
+                    }
                 });
 
+                if ui.button("New stage") {
+                    self.actions.push(StageListAction::NewStage);
+                }
+
                 ui.same_line();
                 if ui.button("Cancel") {
                     self.visible = false;
@@ -348,3 +683,117 @@ impl StageListWindow {
             });
     }
 }
+
+/// Editor of a single stage table entry.
+struct StageEntryWindow {
+    visible: bool,
+    /// Index of the edited stage, none if it's a new one.
+    stage_id: Option<usize>,
+    name: String,
+    name_jp: String,
+    map: String,
+    tileset: String,
+    background: String,
+    background_type: usize,
+    /// Background type id of the edited entry, kept if it's unknown and the type isn't changed.
+    background_type_id: u8,
+    npc1: String,
+    npc2: String,
+    boss_no: i32,
+    actions: Vec<StageListAction>,
+}
+
+impl StageEntryWindow {
+    fn new() -> Self {
+        StageEntryWindow {
+            visible: false,
+            stage_id: None,
+            name: String::new(),
+            name_jp: String::new(),
+            map: String::new(),
+            tileset: String::new(),
+            background: String::new(),
+            background_type: 0,
+            background_type_id: 0,
+            npc1: String::new(),
+            npc2: String::new(),
+            boss_no: 0,
+            actions: Vec::new(),
+        }
+    }
+
+    fn open(&mut self, stage_id: Option<usize>, stage: &StageData) {
+        self.visible = true;
+        self.stage_id = stage_id;
+        self.name = stage.name.clone();
+        self.name_jp = stage.name_jp.clone();
+        self.map = stage.map.clone();
+        self.tileset = stage.tileset.name.clone();
+        self.background = stage.background.name().to_owned();
+        self.background_type = stage.background_type as usize;
+        self.background_type_id = stage.background_type_id;
+        self.npc1 = stage.npc1.name().to_owned();
+        self.npc2 = stage.npc2.name().to_owned();
+        self.boss_no = stage.boss_no as i32;
+    }
+
+    fn action(&mut self, state: &mut SharedGameState, ui: &mut imgui::Ui) {
+        if !self.visible {
+            return;
+        }
+
+        let title = match self.stage_id {
+            Some(stage_id) => format!("Stage table entry {}###StageEntry", stage_id),
+            None => "New stage###StageEntry".to_owned(),
+        };
+        let has_name_jp = state.stage_table_format == StageTableFormat::CavestoryPlus;
+        let background_types: Vec<String> = BackgroundType::iter().map(|t| format!("{:?}", t)).collect();
+        let background_types: Vec<&str> = background_types.iter().map(|t| t.as_str()).collect();
+        let mut apply = false;
+        let mut close = false;
+
+        Window::new(title).resizable(false).collapsible(false).build(ui, || {
+            ui.input_text("Name", &mut self.name).build();
+            if has_name_jp {
+                ui.input_text("Japanese name", &mut self.name_jp).build();
+            }
+            ui.input_text("Map", &mut self.map).build();
+            ui.input_text("Tileset", &mut self.tileset).build();
+            ui.input_text("Background", &mut self.background).build();
+            ui.combo_simple_string("Background type", &mut self.background_type, &background_types);
+            ui.input_text("NPC sheet 1", &mut self.npc1).build();
+            ui.input_text("NPC sheet 2", &mut self.npc2).build();
+            ui.input_int("Boss number", &mut self.boss_no).build();
+            self.boss_no = self.boss_no.clamp(0, u8::MAX as i32);
+
+            ui.disabled(self.map.trim().is_empty(), || {
+                apply = ui.button(if self.stage_id.is_some() { "Apply" } else { "Create" });
+            });
+            ui.same_line();
+            close = ui.button("Cancel");
+        });
+
+        if apply {
+            let stage = StageData {
+                name: self.name.clone(),
+                name_jp: if has_name_jp { self.name_jp.clone() } else { self.name.clone() },
+                map: self.map.trim().to_owned(),
+                boss_no: self.boss_no as u8,
+                tileset: Tileset::new(self.tileset.trim()),
+                pxpack_data: None,
+                background: Background::new(self.background.trim()),
+                background_type: BackgroundType::from(self.background_type as u8),
+                background_type_id: self.background_type_id,
+                background_color: Color::from_rgb(0, 0, 32),
+                npc1: NpcType::new(self.npc1.trim()),
+                npc2: NpcType::new(self.npc2.trim()),
+            };
+
+            self.actions.push(StageListAction::ApplyEntry(self.stage_id, stage));
+        }
+
+        if apply || close {
+            self.visible = false;
+        }
+    }
+}
@@ -1,4 +1,5 @@
 use std::cell::RefCell;
+use std::hash::Hasher;
 use std::ops::{Deref, Range};
 use std::rc::Rc;
 
@@ -16,7 +17,7 @@ use crate::components::hud::HUD;
 use crate::components::inventory::InventoryUI;
 use crate::components::map_system::MapSystem;
 use crate::components::nikumaru::NikumaruCounter;
-use crate::components::replay::Replay;
+use crate::components::replay::{Replay, StateChecksum};
 use crate::components::stage_select::StageSelect;
 use crate::components::text_boxes::TextBoxes;
 use crate::components::tilemap::{TileLayer, Tilemap};
@@ -43,6 +44,7 @@ use crate::game::scripting::tsc::credit_script::CreditScriptVM;
 use crate::game::scripting::tsc::text_script::{ScriptMode, TextScriptExecutionState, TextScriptVM};
 use crate::game::settings::ControllerType;
 use crate::game::shared_game_state::{CutsceneSkipMode, PlayerCount, ReplayState, SharedGameState, TileSize};
+use crate::game::snapshot::GameSnapshot;
 use crate::game::stage::{BackgroundType, Stage, StageTexturePaths};
 use crate::game::weapon::bullet::BulletManager;
 use crate::game::weapon::{Weapon, WeaponType};
@@ -50,8 +52,13 @@ use crate::graphics::font::{Font, Symbols};
 use crate::graphics::texture_set::SpriteBatch;
 use crate::input::touch_controls::TouchControlType;
 use crate::menu::pause_menu::PauseMenu;
+#[cfg(feature = "netplay")]
+use crate::netplay::client::ClientStatus;
+#[cfg(feature = "netplay")]
+use crate::scene::netplay_scene::NetplayLobbyScene;
 use crate::scene::title_scene::TitleScene;
 use crate::scene::Scene;
+use crate::util::hash::Fnv1a;
 use crate::util::rng::RNG;
 
 pub struct GameScene {
@@ -88,6 +95,8 @@ pub struct GameScene {
     pub pause_menu: PauseMenu,
     pub stage_textures: Rc<RefCell<StageTexturePaths>>,
     pub replay: Replay,
+    /// Quick-save restored once the scene is initialized.
+    pub pending_snapshot: Option<Box<GameSnapshot>>,
     map_name_counter: u16,
     skip_counter: u16,
     inventory_dim: f32,
@@ -102,6 +111,9 @@ pub enum LightingMode {
 
 const P2_OFFSCREEN_TEXT: &'static str = "P2";
 const CUTSCENE_SKIP_WAIT: u16 = 50;
+/// Ticks spent waiting for the other player before a notice is shown.
+#[cfg(feature = "netplay")]
+const NETPLAY_STALL_NOTICE_TICKS: u32 = 25;
 
 impl GameScene {
     pub fn new(state: &mut SharedGameState, ctx: &mut Context, id: usize) -> GameResult<Self> {
@@ -175,6 +187,7 @@ impl GameScene {
             skip_counter: 0,
             inventory_dim: 0.0,
             replay: Replay::new(),
+            pending_snapshot: None,
         })
     }
 
@@ -195,6 +208,91 @@ impl GameScene {
         self.player2.cond.set_alive(false);
     }
 
+    /// Checksums of the deterministic parts of the game state, used to detect replay desyncs.
+    pub fn state_checksum(&self, state: &SharedGameState) -> StateChecksum {
+        fn fold(hasher: Fnv1a) -> u32 {
+            let hash = hasher.finish();
+            (hash ^ (hash >> 32)) as u32
+        }
+
+        // fixed-width little-endian values only, so checksums match between platforms
+        fn write_le(hasher: &mut Fnv1a, values: &[i32]) {
+            for value in values {
+                hasher.write(&value.to_le_bytes());
+            }
+        }
+
+        let mut players = Fnv1a::new();
+        for player in [&self.player1, &self.player2] {
+            let values = [player.x, player.y, player.vel_x, player.vel_y, player.life as i32, player.cond.0 as i32];
+            write_le(&mut players, &values);
+        }
+
+        let mut npcs = Fnv1a::new();
+        for npc in self.npc_list.iter_alive() {
+            let values = [npc.id as i32, npc.npc_type as i32, npc.x, npc.y, npc.action_num as i32, npc.life as i32];
+            write_le(&mut npcs, &values);
+        }
+
+        for part in self.boss.parts.iter().filter(|n| n.cond.alive()) {
+            write_le(&mut npcs, &[part.x, part.y, part.action_num as i32, part.life as i32]);
+        }
+
+        let mut rng = Fnv1a::new();
+        rng.write(&state.game_rng.dump_state().to_le_bytes());
+
+        let mut flags = Fnv1a::new();
+        for flag in state.game_flags.iter() {
+            flags.write(&[flag as u8]);
+        }
+
+        StateChecksum { players: fold(players), npcs: fold(npcs), rng: fold(rng), flags: fold(flags) }
+    }
+
+    /// Hashes the deterministic part of the game state, used to tell whether two runs of a replay diverged.
+    pub fn state_hash(&self, state: &SharedGameState) -> u64 {
+        let mut hasher = Fnv1a::new();
+
+        let checksum = self.state_checksum(state);
+        hasher.write(&(self.stage_id as u32).to_le_bytes());
+        for value in [checksum.players, checksum.npcs, checksum.rng, checksum.flags] {
+            hasher.write(&value.to_le_bytes());
+        }
+
+        hasher.finish()
+    }
+
+    fn tick_replay(&mut self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
+        if self.replay.needs_checksum(state) {
+            let checksum = self.state_checksum(state);
+            self.replay.process_checksum(state, checksum);
+        }
+
+        self.replay.tick(state, (ctx, &mut self.player1, &mut self.player2))
+    }
+
+    /// Lets the netplay session run as many frames as its inputs allow, each one being a regular tick of this
+    /// scene, with the session taken out of the state.
+    #[cfg(feature = "netplay")]
+    fn tick_netplay(&mut self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
+        let mut session = match state.netplay.take() {
+            Some(session) => session,
+            None => return Ok(()),
+        };
+
+        let result = session.tick(state, ctx, self);
+
+        if let ClientStatus::Closed(reason) = session.client.status() {
+            log::error!("Netplay session ended: {}", reason);
+            let address = session.client.server_addr().to_string();
+            state.next_scene = Some(Box::new(NetplayLobbyScene::session_ended(address, reason.clone())));
+            return Ok(());
+        }
+
+        state.netplay = Some(session);
+        result
+    }
+
     fn draw_npc_layer(&self, state: &mut SharedGameState, ctx: &mut Context, layer: NPCLayer) -> GameResult {
         for npc in self.npc_list.iter_alive() {
             if npc.layer != layer
@@ -1167,7 +1265,7 @@ impl GameScene {
                     } else {
                         if npc.shock < 14 {
                             if let Some(table_entry) = state.npc_table.get_entry(npc.npc_type) {
-                                state.sound_manager.play_sfx(table_entry.hurt_sound);
+                                state.sound_manager.play_sfx_at(table_entry.hurt_sound, npc.x, npc.y);
                             }
 
                             npc.shock = 16;
@@ -1200,7 +1298,7 @@ impl GameScene {
                         CaretType::ProjectileDissipation,
                         Direction::Right,
                     );
-                    state.sound_manager.play_sfx(31);
+                    state.sound_manager.play_sfx_at(31, bullet.x, bullet.y);
                     bullet.life = 0;
                     continue;
                 }
@@ -1266,7 +1364,7 @@ impl GameScene {
                             state.control_flags.set_interactions_disabled(true);
                             state.textscript_vm.start_script(npc.event_num);
                         } else {
-                            state.sound_manager.play_sfx(self.boss.death_sound[idx]);
+                            state.sound_manager.play_sfx_at(self.boss.death_sound[idx], npc.x, npc.y);
 
                             let destroy_count = 4usize * (2usize).pow((npc.size as u32).saturating_sub(1));
 
@@ -1285,7 +1383,7 @@ impl GameScene {
                             for _ in 0..3 {
                                 state.create_caret(bullet.x, bullet.y, CaretType::HurtParticles, Direction::Left);
                             }
-                            state.sound_manager.play_sfx(self.boss.hurt_sound[idx]);
+                            state.sound_manager.play_sfx_at(self.boss.hurt_sound[idx], npc.x, npc.y);
                         }
 
                         npc.shock = 8;
@@ -1302,7 +1400,7 @@ impl GameScene {
                     bullet.life = bullet.life.saturating_sub(1);
                 } else if !bullet.weapon_flags.no_proj_dissipation() {
                     state.create_caret(bullet.x, bullet.y, CaretType::ProjectileDissipation, Direction::Right);
-                    state.sound_manager.play_sfx(31);
+                    state.sound_manager.play_sfx_at(31, bullet.x, bullet.y);
                     bullet.life = 0;
                     continue;
                 }
@@ -1363,6 +1461,7 @@ impl GameScene {
         }
 
         for npc in self.npc_list.iter_alive() {
+            state.sound_manager.set_sfx_origin(Some((npc.x, npc.y)));
             npc.tick(
                 state,
                 (
@@ -1375,6 +1474,7 @@ impl GameScene {
                 ),
             )?;
         }
+        state.sound_manager.set_sfx_origin(Some((self.boss.parts[0].x, self.boss.parts[0].y)));
         self.boss.tick(
             state,
             (
@@ -1385,6 +1485,7 @@ impl GameScene {
                 &mut self.flash,
             ),
         )?;
+        state.sound_manager.set_sfx_origin(None);
         //decides if the player is tangible or not
         if !state.settings.noclip {
             self.player1.tick_map_collisions(state, &self.npc_list, &mut self.stage);
@@ -1506,6 +1607,10 @@ impl GameScene {
         self.tilemap.tick()?;
 
         self.frame.update(state, &self.stage);
+        state.sound_manager.set_listener(
+            self.frame.x + state.canvas_size.0 as i32 * 0x200 / 2,
+            self.frame.y + state.canvas_size.1 as i32 * 0x200 / 2,
+        );
 
         if state.control_flags.control_enabled() {
             self.hud_player1.tick(state, (&self.player1, &mut self.inventory_player1))?;
@@ -1628,7 +1733,7 @@ impl GameScene {
 impl Scene for GameScene {
     fn init(&mut self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
         if state.mod_path.is_some() && state.replay_state == ReplayState::Recording {
-            self.replay.initialize_recording(state);
+            self.replay.initialize_recording(state, self.stage_id);
         }
         if state.player_count == PlayerCount::Two {
             self.add_player2(state);
@@ -1722,6 +1827,10 @@ impl Scene for GameScene {
             _ => LightingMode::None,
         };
 
+        if let Some(snapshot) = self.pending_snapshot.take() {
+            snapshot.restore(state, self, ctx)?;
+        }
+
         self.pause_menu.init(state, ctx)?;
         self.whimsical_star.init(&self.player1);
 
@@ -1729,9 +1838,14 @@ impl Scene for GameScene {
     }
 
     fn tick(&mut self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
+        #[cfg(feature = "netplay")]
+        if state.netplay.is_some() {
+            return self.tick_netplay(state, ctx);
+        }
+
         if !self.pause_menu.is_paused() {
             if let ReplayState::Playback(_) = state.replay_state {
-                self.replay.tick(state, (ctx, &mut self.player1))?;
+                self.tick_replay(state, ctx)?;
             }
         }
 
@@ -1778,16 +1892,22 @@ impl Scene for GameScene {
 
         if self.pause_menu.is_paused() {
             self.pause_menu.tick(state, ctx)?;
+
+            if self.pause_menu.take_quick_save_request() && state.quick_save(self, ctx)? {
+                state.sound_manager.play_sfx(18);
+            }
+
             return Ok(());
         }
 
         if state.replay_state == ReplayState::Recording {
-            self.replay.tick(state, (ctx, &mut self.player1))?;
+            self.tick_replay(state, ctx)?;
         }
 
         match state.textscript_vm.state {
             TextScriptExecutionState::Running(_, _)
             | TextScriptExecutionState::WaitTicks(_, _, _)
+            | TextScriptExecutionState::WaitScript(_, _, _)
             | TextScriptExecutionState::WaitInput(_, _, _)
             | TextScriptExecutionState::WaitStanding(_, _)
             | TextScriptExecutionState::WaitFade(_, _)
@@ -2116,6 +2236,9 @@ impl Scene for GameScene {
             _ => {}
         }
 
+        #[cfg(feature = "scripting-lua")]
+        state.lua.scene_draw();
+
         self.map_system.draw(state, ctx, &self.stage, [&self.player1, &self.player2])?;
         self.fade.draw(state, ctx, &self.frame)?;
 
@@ -2259,6 +2382,16 @@ impl Scene for GameScene {
 
         self.replay.draw(state, ctx, &self.frame)?;
 
+        #[cfg(feature = "netplay")]
+        if state.netplay.as_ref().is_some_and(|session| session.stall_ticks > NETPLAY_STALL_NOTICE_TICKS) {
+            state.font.builder().center(state.canvas_size.0).y(state.canvas_size.1 / 2.0).shadow(true).draw(
+                state.loc.t("game.netplay.stalled"),
+                ctx,
+                &state.constants,
+                &mut state.texture_set,
+            )?;
+        }
+
         self.pause_menu.draw(state, ctx)?;
 
         //draw_number(state.canvas_size.0 - 8.0, 8.0, timer::fps(ctx) as usize, Alignment::Right, state, ctx)?;
@@ -2282,6 +2415,34 @@ impl Scene for GameScene {
             return Ok(());
         }
 
+        if key_code == ScanCode::F5 && ctx.keyboard_context.active_mods().ctrl() {
+            if state.quick_save(self, ctx)? {
+                state.sound_manager.play_sfx(18);
+            }
+            return Ok(());
+        }
+
+        if key_code == ScanCode::F9 && ctx.keyboard_context.active_mods().ctrl() {
+            state.quick_load(ctx)?;
+            return Ok(());
+        }
+
+        if ctx.keyboard_context.active_mods().ctrl() {
+            let quick_save_slot = match key_code {
+                ScanCode::Key1 => Some(0),
+                ScanCode::Key2 => Some(1),
+                ScanCode::Key3 => Some(2),
+                ScanCode::Key4 => Some(3),
+                _ => None,
+            };
+
+            if let Some(slot) = quick_save_slot {
+                state.quick_save_slot = slot;
+                log::info!("Quick-save slot {} selected.", slot + 1);
+                return Ok(());
+            }
+        }
+
         if key_code == ScanCode::S && ctx.keyboard_context.active_mods().ctrl() {
             let _ = state.save_game(self, ctx);
             state.sound_manager.play_sfx(18);
@@ -1,23 +1,41 @@
 use crate::framework::context::Context;
 use crate::framework::error::GameResult;
 use crate::framework::graphics;
+use crate::game::replay_verifier::ReplayVerifier;
 use crate::game::shared_game_state::SharedGameState;
+#[cfg(feature = "netplay")]
+use crate::scene::netplay_scene::NetplayLobbyScene;
 use crate::scene::no_data_scene::NoDataScene;
 use crate::scene::Scene;
 
 pub struct LoadingScene {
     tick: usize,
+    /// Server to join once the game data is loaded.
+    #[cfg(feature = "netplay")]
+    pub netplay_address: Option<String>,
 }
 
 impl LoadingScene {
     pub fn new() -> Self {
-        Self { tick: 0 }
+        Self {
+            tick: 0,
+            #[cfg(feature = "netplay")]
+            netplay_address: None,
+        }
     }
 
     fn load_stuff(&mut self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
         state.reload_resources(ctx)?;
 
-        if ctx.headless {
+        #[cfg(feature = "netplay")]
+        if let Some(address) = self.netplay_address.take() {
+            state.next_scene = Some(Box::new(NetplayLobbyScene::new(address)));
+            return Ok(());
+        }
+
+        if state.replay_verifier.is_some() {
+            ReplayVerifier::start(state, ctx)?;
+        } else if ctx.headless {
             log::info!("Headless mode detected, skipping intro and loading last saved game.");
             state.load_or_start_game(ctx)?;
         } else {
@@ -33,6 +51,12 @@ impl Scene for LoadingScene {
         // deferred to let the loading image draw
         if self.tick == 1 {
             if let Err(err) = self.load_stuff(state, ctx) {
+                if let Some(mut verifier) = state.replay_verifier.take() {
+                    verifier.fail(state, err.to_string());
+                    state.replay_verifier = Some(verifier);
+                    return Ok(());
+                }
+
                 log::error!("Failed to load game data: {}", err);
 
                 state.next_scene = Some(Box::new(NoDataScene::new(err)));
@@ -0,0 +1,143 @@
+use crate::components::replay::ReplayHeader;
+use crate::framework::context::Context;
+use crate::framework::error::GameResult;
+use crate::game::shared_game_state::{GameDifficulty, PlayerCount, SharedGameState};
+use crate::graphics::font::Font;
+use crate::input::combined_menu_controller::CombinedMenuController;
+use crate::netplay::client::{ClientStatus, NetClient};
+use crate::netplay::protocol::SessionInfo;
+use crate::netplay::NetplaySession;
+use crate::scene::title_scene::TitleScene;
+use crate::scene::Scene;
+
+/// Connects to a netplay server and waits for the second player, then starts a new co-op game.
+pub struct NetplayLobbyScene {
+    address: String,
+    client: Option<NetClient>,
+    error: Option<String>,
+    controller: CombinedMenuController,
+}
+
+impl NetplayLobbyScene {
+    pub fn new(address: String) -> NetplayLobbyScene {
+        NetplayLobbyScene { address, client: None, error: None, controller: CombinedMenuController::new() }
+    }
+
+    /// Shows why a running session ended.
+    pub fn session_ended(address: String, reason: String) -> NetplayLobbyScene {
+        NetplayLobbyScene { error: Some(reason), ..NetplayLobbyScene::new(address) }
+    }
+
+    fn connect(&self, state: &SharedGameState) -> GameResult<NetClient> {
+        let header = ReplayHeader::current(state, state.constants.game.new_game_stage as usize);
+        let info = SessionInfo {
+            engine_version: header.engine_version,
+            mod_id: header.mod_id,
+            content_hash: header.content_hash,
+        };
+
+        NetClient::connect(&self.address, info, state.difficulty as u8, state.player2_skin)
+    }
+
+    fn start_game(&mut self, state: &mut SharedGameState, ctx: &mut Context, client: NetClient) -> GameResult {
+        let info = match client.start_info() {
+            Some(info) => info,
+            None => return Ok(()),
+        };
+
+        state.difficulty = GameDifficulty::from_primitive(info.difficulty);
+        state.player_count = PlayerCount::Two;
+        state.player2_skin = info.player2_skin;
+        state.start_new_game(ctx)?;
+        state.game_rng.load_state(info.rng_seed);
+        state.netplay = Some(NetplaySession::new(client, state));
+
+        Ok(())
+    }
+}
+
+impl Scene for NetplayLobbyScene {
+    fn init(&mut self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
+        self.controller.add(state.settings.create_player1_controller());
+        self.controller.add(state.settings.create_player2_controller());
+
+        if self.error.is_none() {
+            match self.connect(state) {
+                Ok(client) => self.client = Some(client),
+                Err(err) => self.error = Some(err.to_string()),
+            }
+        }
+
+        Ok(())
+    }
+
+    fn tick(&mut self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
+        self.controller.update(state, ctx)?;
+        self.controller.update_trigger();
+
+        if self.controller.trigger_back() {
+            self.client = None;
+            state.next_scene = Some(Box::new(TitleScene::new()));
+            return Ok(());
+        }
+
+        let status = match &mut self.client {
+            Some(client) => {
+                client.update();
+                client.status().clone()
+            }
+            None => return Ok(()),
+        };
+
+        match status {
+            ClientStatus::Playing => {
+                if let Some(client) = self.client.take() {
+                    self.start_game(state, ctx, client)?;
+                }
+            }
+            ClientStatus::Closed(reason) => {
+                self.error = Some(reason);
+                self.client = None;
+            }
+            ClientStatus::Connecting | ClientStatus::Waiting => {}
+        }
+
+        Ok(())
+    }
+
+    fn draw(&self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
+        let status = match (&self.client, &self.error) {
+            (_, Some(_)) => state.loc.t("game.netplay.disconnected").to_owned(),
+            (Some(client), None) if client.status() == &ClientStatus::Waiting => {
+                state.tt("game.netplay.joined", &[("player", &(client.player_id() + 1).to_string())])
+            }
+            _ => state.tt("game.netplay.connecting", &[("address", &self.address)]),
+        };
+
+        state.font.builder().center(state.canvas_size.0).y(state.canvas_size.1 / 2.0 - 20.0).draw(
+            &status,
+            ctx,
+            &state.constants,
+            &mut state.texture_set,
+        )?;
+
+        if let Some(error) = &self.error {
+            state
+                .font
+                .builder()
+                .center(state.canvas_size.0)
+                .y(state.canvas_size.1 / 2.0)
+                .color((255, 100, 100, 255))
+                .draw(error, ctx, &state.constants, &mut state.texture_set)?;
+        }
+
+        state.font.builder().center(state.canvas_size.0).y(state.canvas_size.1 - 30.0).draw(
+            state.loc.t("game.netplay.back_to_title"),
+            ctx,
+            &state.constants,
+            &mut state.texture_set,
+        )?;
+
+        Ok(())
+    }
+}
@@ -1,6 +1,7 @@
 use crate::common::{Color, VERSION_BANNER};
 use crate::components::background::Background;
 use crate::components::nikumaru::NikumaruCounter;
+use crate::components::replay::Replay;
 use crate::entity::GameEntity;
 use crate::framework::context::Context;
 use crate::framework::error::GameResult;
@@ -17,6 +18,7 @@ use crate::menu::coop_menu::PlayerCountMenu;
 use crate::menu::save_select_menu::SaveSelectMenu;
 use crate::menu::settings_menu::SettingsMenu;
 use crate::menu::{Menu, MenuEntry, MenuSelectionResult};
+use crate::mod_list::ModInfo;
 use crate::scene::jukebox_scene::JukeboxScene;
 use crate::scene::Scene;
 
@@ -107,6 +109,7 @@ impl TitleScene {
                 pxpack_data: None,
                 background: crate::game::stage::Background::new("bkMoon"),
                 background_type: BackgroundType::Outside,
+                background_type_id: BackgroundType::Outside as u8,
                 background_color: Color { r: 0.0, g: 0.0, b: 0.0, a: 0.0 },
                 npc1: NpcType::new("0"),
                 npc2: NpcType::new("0"),
@@ -182,6 +185,26 @@ impl TitleScene {
         self.current_menu = CurrentMenu::OptionMenu;
         Ok(())
     }
+
+    /// Returns the name of the mod in the challenge list, with the version and author from its manifest.
+    fn mod_title(state: &SharedGameState, mod_info: &ModInfo) -> String {
+        let manifest = match &mod_info.manifest {
+            Some(manifest) => manifest,
+            None => return mod_info.name.clone(),
+        };
+
+        let name = if manifest.version.is_empty() {
+            mod_info.name.clone()
+        } else {
+            format!("{} {}", mod_info.name, manifest.version)
+        };
+
+        if manifest.author.is_empty() {
+            name
+        } else {
+            state.tt("menus.challenge_menu.mod_author", &[("name", &name), ("author", &manifest.author)])
+        }
+    }
 }
 
 static COPYRIGHT_PIXEL: &str = "2004.12  Studio Pixel";
@@ -190,6 +213,12 @@ static COPYRIGHT_NICALIS: &str = "@2022 NICALIS INC."; // Nicalis font uses @ fo
 
 impl Scene for TitleScene {
     fn init(&mut self, state: &mut SharedGameState, ctx: &mut Context) -> GameResult {
+        #[cfg(feature = "netplay")]
+        {
+            // leaving the game ends the session for the other player too
+            state.netplay = None;
+        }
+
         if !state.mod_path.is_none() {
             state.mod_path = None;
             state.reload_resources(ctx)?;
@@ -220,7 +249,7 @@ impl Scene for TitleScene {
             }
         }
 
-        if !state.mod_list.mods.is_empty() {
+        if state.mod_list.mods.iter().any(|mod_info| !mod_info.is_content_pack()) {
             self.main_menu.push_entry(
                 MainMenuEntry::Challenges,
                 MenuEntry::Active(state.loc.t("menus.main_menu.challenges").to_owned()),
@@ -253,15 +282,18 @@ impl Scene for TitleScene {
         let mut mutate_selection = true;
 
         for (idx, mod_info) in state.mod_list.mods.iter().enumerate() {
-            if mod_info.id.clone() != "csmod_03" {
+            // content packs are enabled in the settings instead
+            if mod_info.id.clone() != "csmod_03" && !mod_info.is_content_pack() {
                 if !mod_info.valid {
                     self.challenges_menu
                         .push_entry(ChallengesMenuEntry::Challenge(idx), MenuEntry::Disabled(mod_info.path.clone()));
                     continue;
                 }
                 if mod_info.satisfies_requirement(&state.mod_requirements) {
-                    self.challenges_menu
-                        .push_entry(ChallengesMenuEntry::Challenge(idx), MenuEntry::Active(mod_info.name.clone()));
+                    self.challenges_menu.push_entry(
+                        ChallengesMenuEntry::Challenge(idx),
+                        MenuEntry::Active(TitleScene::mod_title(state, mod_info)),
+                    );
 
                     if mutate_selection {
                         selected = ChallengesMenuEntry::Challenge(idx);
@@ -485,10 +517,22 @@ impl Scene for TitleScene {
                     self.current_menu = CurrentMenu::PlayerCountMenu;
                 }
                 MenuSelectionResult::Selected(ConfirmMenuEntry::Replay(kind), _) => {
-                    state.difficulty = GameDifficulty::Normal;
-                    state.replay_state = ReplayState::Playback(kind);
                     state.reload_resources(ctx)?;
-                    state.start_new_game(ctx)?;
+
+                    match Replay::check_replay_data(state, ctx, kind) {
+                        Ok(header) => {
+                            header.apply(state);
+                            state.replay_state = ReplayState::Playback(kind);
+                            state.start_new_game(ctx)?;
+                        }
+                        Err(e) => {
+                            log::error!("Cannot play back replay: {}", e);
+                            self.confirm_menu.set_entry(
+                                ConfirmMenuEntry::Replay(kind),
+                                MenuEntry::Disabled(state.loc.t("menus.challenge_menu.replay_mismatch").to_owned()),
+                            );
+                        }
+                    }
                 }
                 MenuSelectionResult::Selected(ConfirmMenuEntry::DeleteReplay, _) => {
                     state.delete_replay_data(ctx, ReplayKind::Best)?;
@@ -2,7 +2,9 @@ use std::io;
 use std::io::{BufRead, BufReader, Lines};
 use std::str::FromStr;
 use std::sync::mpsc;
-use std::sync::mpsc::{Receiver, Sender};
+use std::sync::mpsc::{Receiver, Sender, TryRecvError};
+use std::thread::JoinHandle;
+use std::time::Duration;
 
 use cpal::Sample;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
@@ -22,6 +24,9 @@ use crate::sound::ogg_playback::{OggPlaybackEngine, SavedOggPlaybackState};
 use crate::sound::org_playback::{OrgPlaybackEngine, SavedOrganyaPlaybackState};
 use crate::sound::organya::Song;
 use crate::sound::pixtone::{PixToneParameters, PixTonePlayback};
+pub use crate::sound::sink::AudioOutput;
+use crate::sound::tracker::Module;
+use crate::sound::tracker_playback::{SavedTrackerPlaybackState, TrackerPlaybackEngine};
 use crate::sound::wave_bank::SoundBank;
 
 mod fir;
@@ -31,7 +36,11 @@ mod org_playback;
 mod organya;
 pub mod pixtone;
 mod pixtone_sfx;
+pub mod render;
+mod sink;
 mod stuff;
+mod tracker;
+mod tracker_playback;
 mod wav;
 mod wave_bank;
 
@@ -41,8 +50,57 @@ pub struct SoundManager {
     prev_song_id: usize,
     current_song_id: usize,
     no_audio: bool,
+    /// Suppresses one-shot sound effects, used while frames are simulated again after a rollback.
+    pub sfx_muted: bool,
+    /// Song changes only update the song ids without touching the playback, used alongside `sfx_muted`.
+    pub music_muted: bool,
     load_failed: bool,
+    output: AudioOutput,
     stream: Option<cpal::Stream>,
+    sink: Option<JoinHandle<()>>,
+    /// Pans and attenuates sound effects by the position of their source, see [SoundManager::play_sfx_at].
+    positional_sfx: bool,
+    /// Centre of the camera, in world coordinates.
+    listener: (i32, i32),
+    /// Position of the entity that's being ticked, sound effects it plays come from there.
+    sfx_origin: Option<(i32, i32)>,
+}
+
+/// Distance from the camera centre (in pixels) at which a sound effect is panned the furthest.
+const SFX_PAN_DISTANCE: f32 = 240.0;
+/// Sound effects are never panned fully to one side, so they can be heard with both ears.
+const SFX_MAX_PAN: f32 = 0.8;
+/// Sound effects are attenuated once their source is further from the camera centre than that (in pixels).
+const SFX_FULL_VOLUME_DISTANCE: f32 = 160.0;
+/// Distance at which sound effects reach [SFX_MIN_VOLUME], they stay audible beyond it as directional cues.
+const SFX_MIN_VOLUME_DISTANCE: f32 = 480.0;
+const SFX_MIN_VOLUME: f32 = 0.25;
+
+/// Returns the pan and volume of a sound effect played `dx`, `dy` pixels away from the camera centre.
+fn sfx_pan_volume(dx: f32, dy: f32) -> (f32, f32) {
+    let pan = (dx / SFX_PAN_DISTANCE).clamp(-SFX_MAX_PAN, SFX_MAX_PAN);
+    let falloff = (dx.hypot(dy) - SFX_FULL_VOLUME_DISTANCE) / (SFX_MIN_VOLUME_DISTANCE - SFX_FULL_VOLUME_DISTANCE);
+    let volume = 1.0 - falloff.clamp(0.0, 1.0) * (1.0 - SFX_MIN_VOLUME);
+
+    (pan, volume)
+}
+
+/// Position in a song, without the song itself, so it can be stored in save files.
+#[derive(Copy, Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub enum SongPosition {
+    Stopped,
+    Organya { play_pos: i32 },
+    Ogg { playing_intro: bool, position: u64 },
+    Tracker { order: usize, row: usize, speed: u32, tempo: u32, global_volume: i32 },
+}
+
+/// The song that's playing and the one saved for `<RMU`, along with their positions.
+#[derive(Clone, Debug, PartialEq, serde::Serialize, serde::Deserialize)]
+pub struct SongSnapshot {
+    pub current_song: usize,
+    pub current_position: SongPosition,
+    pub saved_song: usize,
+    pub saved_position: SongPosition,
 }
 
 enum SongFormat {
@@ -51,6 +109,7 @@ enum SongFormat {
     OggSinglePart,
     #[cfg(feature = "ogg-playback")]
     OggMultiPart,
+    Tracker,
 }
 
 #[derive(Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
@@ -62,30 +121,86 @@ pub enum InterpolationMode {
     Polyphase,
 }
 
+impl FromStr for InterpolationMode {
+    type Err = GameError;
+
+    fn from_str(s: &str) -> GameResult<InterpolationMode> {
+        match s.to_lowercase().as_str() {
+            "nearest" => Ok(InterpolationMode::Nearest),
+            "linear" => Ok(InterpolationMode::Linear),
+            "cosine" => Ok(InterpolationMode::Cosine),
+            "cubic" => Ok(InterpolationMode::Cubic),
+            "polyphase" => Ok(InterpolationMode::Polyphase),
+            _ => Err(InvalidValue(format!("Unknown interpolation mode: {}", s))),
+        }
+    }
+}
+
+/// Shape of the volume change of the songs during a crossfade.
+#[derive(Copy, Clone, PartialEq, Eq, serde::Serialize, serde::Deserialize)]
+pub enum FadeCurve {
+    Linear,
+    /// Keeps the perceived loudness constant, the default.
+    EqualPower,
+    /// Eases in and out of the fade.
+    SCurve,
+}
+
+impl FadeCurve {
+    /// Returns the volumes of the outgoing and incoming song at `t` (0.0 - 1.0) into the crossfade.
+    pub fn gains(self, t: f32) -> (f32, f32) {
+        let t = t.clamp(0.0, 1.0);
+
+        match self {
+            FadeCurve::Linear => (1.0 - t, t),
+            FadeCurve::EqualPower => {
+                let angle = t * std::f32::consts::FRAC_PI_2;
+                (angle.cos(), angle.sin())
+            }
+            FadeCurve::SCurve => {
+                let t = t * t * (3.0 - 2.0 * t);
+                (1.0 - t, t)
+            }
+        }
+    }
+}
+
 impl SoundManager {
     pub fn new(ctx: &mut Context) -> GameResult<SoundManager> {
         let (tx, rx): (Sender<PlaybackMessage>, Receiver<PlaybackMessage>) = mpsc::channel();
 
-        if ctx.headless {
-            log::info!("Running in headless mode, skipping initialization.");
-
-            return Ok(SoundManager {
-                soundbank: None,
-                tx: tx.clone(),
-                prev_song_id: 0,
-                current_song_id: 0,
-                no_audio: true,
-                load_failed: false,
-                stream: None,
-            });
-        }
+        let output = match &ctx.audio_output {
+            Some(output) => output.clone(),
+            None if ctx.headless => {
+                log::info!("Running in headless mode, skipping initialization.");
+
+                return Ok(SoundManager {
+                    soundbank: None,
+                    tx: tx.clone(),
+                    prev_song_id: 0,
+                    current_song_id: 0,
+                    no_audio: true,
+                    sfx_muted: false,
+                    music_muted: false,
+                    load_failed: false,
+                    output: AudioOutput::Null { speed: 1.0 },
+                    stream: None,
+                    sink: None,
+                    positional_sfx: false,
+                    listener: (0, 0),
+                    sfx_origin: None,
+                });
+            }
+            None => AudioOutput::Device,
+        };
 
         let bnk = wave_bank::SoundBank::load_from(filesystem::open(ctx, "/builtin/organya-wavetable-doukutsu.bin")?)?;
-        Ok(SoundManager::bootstrap(&bnk, tx, rx)?)
+        Ok(SoundManager::bootstrap(&bnk, output, tx, rx)?)
     }
 
     fn bootstrap(
         soundbank: &SoundBank,
+        output: AudioOutput,
         tx: Sender<PlaybackMessage>,
         rx: Receiver<PlaybackMessage>,
     ) -> GameResult<SoundManager> {
@@ -95,10 +210,31 @@ impl SoundManager {
             prev_song_id: 0,
             current_song_id: 0,
             no_audio: false,
+            sfx_muted: false,
+            music_muted: false,
             load_failed: false,
+            output,
             stream: None,
+            sink: None,
+            positional_sfx: false,
+            listener: (0, 0),
+            sfx_origin: None,
         };
 
+        if sound_manager.output != AudioOutput::Device {
+            let mixer = Mixer::new(rx, soundbank.to_owned(), sink::SAMPLE_RATE, 2);
+
+            match sink::spawn(mixer, &sound_manager.output) {
+                Ok(sink) => sound_manager.sink = Some(sink),
+                Err(e) => {
+                    log::error!("Error initializing audio: {}", e);
+                    sound_manager.load_failed = true;
+                }
+            }
+
+            return Ok(sound_manager);
+        }
+
         let host = cpal::default_host();
 
         let device_result =
@@ -121,11 +257,12 @@ impl SoundManager {
         }
 
         let config = config_result.unwrap();
+        let mixer = Mixer::new(rx, soundbank.to_owned(), config.sample_rate().0, config.channels() as usize);
 
         let res = match config.sample_format() {
-            cpal::SampleFormat::F32 => run::<f32>(rx, soundbank.to_owned(), device, config.into()),
-            cpal::SampleFormat::I16 => run::<i16>(rx, soundbank.to_owned(), device, config.into()),
-            cpal::SampleFormat::U16 => run::<u16>(rx, soundbank.to_owned(), device, config.into()),
+            cpal::SampleFormat::F32 => run::<f32>(mixer, device, config.into()),
+            cpal::SampleFormat::I16 => run::<i16>(mixer, device, config.into()),
+            cpal::SampleFormat::U16 => run::<u16>(mixer, device, config.into()),
         };
 
         if let Err(res) = &res {
@@ -145,8 +282,18 @@ impl SoundManager {
         log::info!("Reloading sound manager.");
 
         let (tx, rx): (Sender<PlaybackMessage>, Receiver<PlaybackMessage>) = mpsc::channel();
+
+        // the old mixer thread has to stop before its output is opened again
+        self.tx = tx.clone();
+        if let Some(sink) = self.sink.take() {
+            let _ = sink.join();
+        }
+
         let soundbank = self.soundbank.take().unwrap();
-        *self = SoundManager::bootstrap(&soundbank, tx, rx)?;
+        let output = self.output.clone();
+        let positional_sfx = self.positional_sfx;
+        *self = SoundManager::bootstrap(&soundbank, output, tx, rx)?;
+        self.positional_sfx = positional_sfx;
 
         Ok(())
     }
@@ -179,13 +326,51 @@ impl SoundManager {
     }
 
     pub fn play_sfx(&mut self, id: u8) {
-        if self.no_audio {
+        if let Some((x, y)) = self.sfx_origin {
+            return self.play_sfx_at(id, x, y);
+        }
+
+        if self.no_audio || self.sfx_muted {
             return;
         }
 
         self.send(PlaybackMessage::PlaySample(id)).unwrap();
     }
 
+    /// Plays a sound effect coming from a point in the world, it's panned and attenuated relative to
+    /// the camera if positional sound effects are enabled.
+    pub fn play_sfx_at(&mut self, id: u8, x: i32, y: i32) {
+        if self.no_audio || self.sfx_muted {
+            return;
+        }
+
+        if !self.positional_sfx {
+            self.send(PlaybackMessage::PlaySample(id)).unwrap();
+            return;
+        }
+
+        let dx = (x - self.listener.0) as f32 / 512.0;
+        let dy = (y - self.listener.1) as f32 / 512.0;
+        let (pan, volume) = sfx_pan_volume(dx, dy);
+
+        self.send(PlaybackMessage::PlaySamplePanned(id, pan, volume)).unwrap();
+    }
+
+    pub fn set_positional_sfx(&mut self, enabled: bool) {
+        self.positional_sfx = enabled;
+    }
+
+    /// Sets the point positional sound effects are heard from, usually the centre of the camera.
+    pub fn set_listener(&mut self, x: i32, y: i32) {
+        self.listener = (x, y);
+    }
+
+    /// Makes [SoundManager::play_sfx] play sound effects at `origin` until it's reset to `None`,
+    /// used while ticking NPCs and bullets.
+    pub fn set_sfx_origin(&mut self, origin: Option<(i32, i32)>) {
+        self.sfx_origin = origin;
+    }
+
     pub fn loop_sfx(&self, id: u8) {
         if self.no_audio {
             return;
@@ -240,10 +425,10 @@ impl SoundManager {
         let prev_song = self.prev_song_id;
         let current_song = self.current_song_id;
 
-        self.play_song(0, constants, settings, ctx)?;
-        self.play_song(prev_song, constants, settings, ctx)?;
+        self.switch_song(0, 0.0, constants, settings, ctx)?;
+        self.switch_song(prev_song, 0.0, constants, settings, ctx)?;
         self.save_state()?;
-        self.play_song(current_song, constants, settings, ctx)?;
+        self.switch_song(current_song, 0.0, constants, settings, ctx)?;
 
         Ok(())
     }
@@ -254,11 +439,40 @@ impl SoundManager {
         constants: &EngineConstants,
         settings: &Settings,
         ctx: &mut Context,
+    ) -> GameResult {
+        self.switch_song(song_id, settings.crossfade_time, constants, settings, ctx)
+    }
+
+    /// Crossfades to the song over `seconds`, regardless of the crossfade settings.
+    pub fn crossfade_song(
+        &mut self,
+        song_id: usize,
+        seconds: f32,
+        constants: &EngineConstants,
+        settings: &Settings,
+        ctx: &mut Context,
+    ) -> GameResult {
+        self.switch_song(song_id, seconds, constants, settings, ctx)
+    }
+
+    fn switch_song(
+        &mut self,
+        song_id: usize,
+        crossfade: f32,
+        constants: &EngineConstants,
+        settings: &Settings,
+        ctx: &mut Context,
     ) -> GameResult {
         if self.current_song_id == song_id || self.no_audio {
             return Ok(());
         }
 
+        if self.music_muted {
+            self.prev_song_id = self.current_song_id;
+            self.current_song_id = song_id;
+            return Ok(());
+        }
+
         if song_id == 0 {
             log::info!("Stopping BGM");
 
@@ -267,6 +481,7 @@ impl SoundManager {
 
             self.send(PlaybackMessage::SetOrgInterpolation(settings.organya_interpolation)).unwrap();
             self.send(PlaybackMessage::SaveState).unwrap();
+            self.send_crossfade(crossfade, settings);
             self.send(PlaybackMessage::Stop).unwrap();
         } else if let Some(song_name) = constants.music_table.get(song_id) {
             let mut paths = constants.organya_paths.clone();
@@ -288,6 +503,9 @@ impl SoundManager {
                     ),
                         #[cfg(feature = "ogg-playback")]
                     (SongFormat::OggSinglePart, vec![format!("{}{}.ogg", prefix, song_name)]),
+                    (SongFormat::Tracker, vec![format!("{}{}.xm", prefix, song_name)]),
+                    (SongFormat::Tracker, vec![format!("{}{}.it", prefix, song_name)]),
+                    (SongFormat::Tracker, vec![format!("{}{}.mod", prefix, song_name)]),
                     (SongFormat::Organya, vec![format!("{}{}.org", prefix, song_name)]),
                 ]
             });
@@ -311,6 +529,7 @@ impl SoundManager {
                                         .send(PlaybackMessage::SetOrgInterpolation(settings.organya_interpolation))
                                         .unwrap();
                                     self.send(PlaybackMessage::SaveState).unwrap();
+                                    self.send_crossfade(crossfade, settings);
                                     self.send(PlaybackMessage::PlayOrganyaSong(Box::new(org))).unwrap();
 
                                     return Ok(());
@@ -320,6 +539,27 @@ impl SoundManager {
                                 }
                             }
                         }
+                        SongFormat::Tracker => {
+                            // we're sure that there's one element
+                            let path = unsafe { paths.get_unchecked(0) };
+
+                            match filesystem::open(ctx, path).map(Module::load_from) {
+                                Ok(Ok(module)) => {
+                                    log::info!("Playing tracker module BGM: {} {}", song_id, path);
+
+                                    self.prev_song_id = self.current_song_id;
+                                    self.current_song_id = song_id;
+                                    self.send(PlaybackMessage::SaveState).unwrap();
+                                    self.send_crossfade(crossfade, settings);
+                                    self.send(PlaybackMessage::PlayTrackerSong(Box::new(module))).unwrap();
+
+                                    return Ok(());
+                                }
+                                Ok(Err(err)) | Err(err) => {
+                                    log::warn!("Failed to load tracker module BGM {}: {}", song_id, err);
+                                }
+                            }
+                        }
                         #[cfg(feature = "ogg-playback")]
                         SongFormat::OggSinglePart => {
                             // we're sure that there's one element
@@ -334,6 +574,7 @@ impl SoundManager {
                                     self.prev_song_id = self.current_song_id;
                                     self.current_song_id = song_id;
                                     self.send(PlaybackMessage::SaveState).unwrap();
+                                    self.send_crossfade(crossfade, settings);
                                     self.send(PlaybackMessage::PlayOggSongSinglePart(Box::new(song))).unwrap();
 
                                     return Ok(());
@@ -368,6 +609,7 @@ impl SoundManager {
                                     self.prev_song_id = self.current_song_id;
                                     self.current_song_id = song_id;
                                     self.send(PlaybackMessage::SaveState).unwrap();
+                                    self.send_crossfade(crossfade, settings);
                                     self.send(PlaybackMessage::PlayOggSongMultiPart(
                                         Box::new(song_intro),
                                         Box::new(song_loop),
@@ -389,12 +631,21 @@ impl SoundManager {
         Ok(())
     }
 
+    /// The crossfade is applied by the mixer once the next song starts, or the music stops.
+    fn send_crossfade(&mut self, seconds: f32, settings: &Settings) {
+        if seconds > 0.0 {
+            self.send(PlaybackMessage::Crossfade(seconds, settings.crossfade_curve)).unwrap();
+        }
+    }
+
     pub fn save_state(&mut self) -> GameResult {
         if self.no_audio {
             return Ok(());
         }
 
-        self.send(PlaybackMessage::SaveState).unwrap();
+        if !self.music_muted {
+            self.send(PlaybackMessage::SaveState).unwrap();
+        }
         self.prev_song_id = self.current_song_id;
 
         Ok(())
@@ -405,12 +656,59 @@ impl SoundManager {
             return Ok(());
         }
 
-        self.send(PlaybackMessage::RestoreState).unwrap();
+        if !self.music_muted {
+            self.send(PlaybackMessage::RestoreState).unwrap();
+        }
         self.current_song_id = self.prev_song_id;
 
         Ok(())
     }
 
+    /// Returns the current and saved songs, the positions are reported by the mixer.
+    pub fn song_snapshot(&mut self) -> SongSnapshot {
+        let mut snapshot = SongSnapshot {
+            current_song: self.current_song_id,
+            current_position: SongPosition::Stopped,
+            saved_song: self.prev_song_id,
+            saved_position: SongPosition::Stopped,
+        };
+
+        if self.no_audio || self.load_failed {
+            return snapshot;
+        }
+
+        let (tx, rx) = mpsc::channel();
+        self.send(PlaybackMessage::GetSongPositions(tx)).unwrap();
+
+        match rx.recv_timeout(Duration::from_millis(500)) {
+            Ok((current, saved)) => {
+                snapshot.current_position = current;
+                snapshot.saved_position = saved;
+            }
+            Err(_) => log::warn!("Audio thread didn't report the song positions, songs will play from the start."),
+        }
+
+        snapshot
+    }
+
+    /// Plays the songs of a snapshot from their saved positions.
+    pub fn restore_song_snapshot(
+        &mut self,
+        snapshot: &SongSnapshot,
+        constants: &EngineConstants,
+        settings: &Settings,
+        ctx: &mut Context,
+    ) -> GameResult {
+        self.switch_song(0, 0.0, constants, settings, ctx)?;
+        self.switch_song(snapshot.saved_song, 0.0, constants, settings, ctx)?;
+        self.send(PlaybackMessage::Seek(snapshot.saved_position)).unwrap();
+        self.save_state()?;
+        self.switch_song(snapshot.current_song, 0.0, constants, settings, ctx)?;
+        self.send(PlaybackMessage::Seek(snapshot.current_position)).unwrap();
+
+        Ok(())
+    }
+
     pub fn set_speed(&mut self, speed: f32) -> GameResult {
         if self.no_audio {
             return Ok(());
@@ -429,6 +727,11 @@ impl SoundManager {
         self.current_song_id
     }
 
+    /// Changes the current song id without playing it, for going back to an earlier state of the game.
+    pub fn set_current_song(&mut self, song_id: usize) {
+        self.current_song_id = song_id;
+    }
+
     pub fn set_sample_params_from_file<R: io::Read>(&mut self, id: u8, data: R) -> GameResult {
         if self.no_audio {
             return Ok(());
@@ -535,6 +838,16 @@ impl SoundManager {
     }
 }
 
+impl Drop for SoundManager {
+    fn drop(&mut self) {
+        // the headless sink stops once the sender is gone, wait for it so the captured audio gets finished
+        if let Some(sink) = self.sink.take() {
+            self.tx = mpsc::channel().0;
+            let _ = sink.join();
+        }
+    }
+}
+
 pub(in crate::sound) enum PlaybackMessage {
     Stop,
     PlayOrganyaSong(Box<Song>),
@@ -542,7 +855,9 @@ pub(in crate::sound) enum PlaybackMessage {
     PlayOggSongSinglePart(Box<OggStreamReader<File>>),
     #[cfg(feature = "ogg-playback")]
     PlayOggSongMultiPart(Box<OggStreamReader<File>>, Box<OggStreamReader<File>>),
+    PlayTrackerSong(Box<Module>),
     PlaySample(u8),
+    PlaySamplePanned(u8, f32, f32),
     LoopSample(u8),
     LoopSampleFreq(u8, f32),
     StopSample(u8),
@@ -551,9 +866,12 @@ pub(in crate::sound) enum PlaybackMessage {
     SetSampleVolume(f32),
     SaveState,
     RestoreState,
+    GetSongPositions(Sender<(SongPosition, SongPosition)>),
+    Seek(SongPosition),
     SetSampleParams(u8, PixToneParameters),
     SetOrgInterpolation(InterpolationMode),
     SetSampleData(u8, Vec<i16>),
+    Crossfade(f32, FadeCurve),
 }
 
 #[derive(PartialEq, Eq)]
@@ -562,6 +880,7 @@ enum PlaybackState {
     PlayingOrg,
     #[cfg(feature = "ogg-playback")]
     PlayingOgg,
+    PlayingTracker,
 }
 
 enum PlaybackStateType {
@@ -569,6 +888,19 @@ enum PlaybackStateType {
     Organya(SavedOrganyaPlaybackState),
     #[cfg(feature = "ogg-playback")]
     Ogg(SavedOggPlaybackState),
+    Tracker(SavedTrackerPlaybackState),
+}
+
+impl PlaybackStateType {
+    fn position(&self) -> SongPosition {
+        match self {
+            PlaybackStateType::None => SongPosition::Stopped,
+            PlaybackStateType::Organya(state) => state.position(),
+            #[cfg(feature = "ogg-playback")]
+            PlaybackStateType::Ogg(state) => state.position(),
+            PlaybackStateType::Tracker(state) => state.position(),
+        }
+    }
 }
 
 impl Default for PlaybackStateType {
@@ -577,265 +909,445 @@ impl Default for PlaybackStateType {
     }
 }
 
-fn run<T>(
-    rx: Receiver<PlaybackMessage>,
-    bank: SoundBank,
-    device: cpal::Device,
-    config: cpal::StreamConfig,
-) -> GameResult<cpal::Stream>
-    where
-        T: cpal::Sample,
-{
-    let sample_rate = config.sample_rate.0 as f32;
-    let channels = config.channels as usize;
-    let mut state = PlaybackState::Stopped;
-    let mut saved_state: PlaybackStateType = PlaybackStateType::None;
-    let mut speed = 1.0;
-    let mut org_engine = Box::new(OrgPlaybackEngine::new());
+/// A song being played, the mixer has a second one while crossfading between songs.
+struct BgmChannel {
+    state: PlaybackState,
+    org_engine: Box<OrgPlaybackEngine>,
     #[cfg(feature = "ogg-playback")]
-        let mut ogg_engine = Box::new(OggPlaybackEngine::new());
-    let mut pixtone = Box::new(PixTonePlayback::new());
-    pixtone.create_samples();
+    ogg_engine: Box<OggPlaybackEngine>,
+    tracker_engine: Box<TrackerPlaybackEngine>,
+    buf: Vec<u16>,
+    index: usize,
+    samples: usize,
+}
 
-    log::info!("Audio format: {} {}", sample_rate, channels);
-    org_engine.set_sample_rate(sample_rate as usize);
-    #[cfg(feature = "ogg-playback")]
-        {
-            org_engine.loops = usize::MAX;
-            ogg_engine.set_sample_rate(sample_rate as usize);
+impl BgmChannel {
+    fn new(sample_rate: usize, buf_size: usize) -> BgmChannel {
+        let mut org_engine = Box::new(OrgPlaybackEngine::new());
+        #[cfg(feature = "ogg-playback")]
+            let mut ogg_engine = Box::new(OggPlaybackEngine::new());
+        let mut tracker_engine = Box::new(TrackerPlaybackEngine::new());
+
+        org_engine.set_sample_rate(sample_rate);
+        tracker_engine.set_sample_rate(sample_rate);
+        #[cfg(feature = "ogg-playback")]
+            {
+                org_engine.loops = usize::MAX;
+                ogg_engine.set_sample_rate(sample_rate);
+            }
+
+        BgmChannel {
+            state: PlaybackState::Stopped,
+            org_engine,
+            #[cfg(feature = "ogg-playback")]
+            ogg_engine,
+            tracker_engine,
+            buf: vec![0x8080; buf_size * 2],
+            index: 0,
+            samples: 0,
         }
+    }
 
-    let buf_size = sample_rate as usize * 10 / 1000;
-    let mut bgm_buf = vec![0x8080; buf_size * 2];
-    let mut pxt_buf = vec![0x8000; buf_size];
-    let mut bgm_index = 0;
-    let mut pxt_index = 0;
-    let mut samples = 0;
-    let mut bgm_vol = 1.0_f32;
-    let mut sfx_vol = 1.0_f32;
-    pixtone.mix(&mut pxt_buf, sample_rate);
+    fn set_sample_rate(&mut self, sample_rate: usize) {
+        #[cfg(feature = "ogg-playback")]
+            self.ogg_engine.set_sample_rate(sample_rate);
+        self.org_engine.set_sample_rate(sample_rate);
+        self.tracker_engine.set_sample_rate(sample_rate);
+    }
 
-    let err_fn = |err| eprintln!("an error occurred on stream: {}", err);
+    fn get_state(&self) -> PlaybackStateType {
+        match self.state {
+            PlaybackState::Stopped => PlaybackStateType::None,
+            PlaybackState::PlayingOrg => PlaybackStateType::Organya(self.org_engine.get_state()),
+            #[cfg(feature = "ogg-playback")]
+            PlaybackState::PlayingOgg => PlaybackStateType::Ogg(self.ogg_engine.get_state()),
+            PlaybackState::PlayingTracker => PlaybackStateType::Tracker(self.tracker_engine.get_state()),
+        }
+    }
 
-    let stream_result = device.build_output_stream(
-        &config,
-        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
-            loop {
-                match rx.try_recv() {
-                    Ok(PlaybackMessage::PlayOrganyaSong(song)) => {
-                        if state == PlaybackState::Stopped {
-                            saved_state = PlaybackStateType::None;
-                        }
+    /// Moves to `position` if it belongs to the engine that's playing.
+    fn seek(&mut self, position: SongPosition) {
+        match (&self.state, position) {
+            (PlaybackState::PlayingOrg, SongPosition::Organya { play_pos }) => {
+                self.org_engine.set_position(play_pos);
+            }
+            #[cfg(feature = "ogg-playback")]
+            (PlaybackState::PlayingOgg, SongPosition::Ogg { playing_intro, position }) => {
+                self.ogg_engine.seek(playing_intro, position);
+            }
+            (PlaybackState::PlayingTracker, SongPosition::Tracker { order, row, speed, tempo, global_volume }) => {
+                self.tracker_engine.seek(order, row, speed, tempo, global_volume);
+            }
+            _ => return,
+        }
 
-                        org_engine.start_song(*song, &bank);
+        // drop the audio rendered ahead from the old position
+        self.index = self.samples;
+    }
 
-                        for i in &mut bgm_buf[0..samples] {
-                            *i = 0x8000
-                        }
-                        samples = org_engine.render_to(&mut bgm_buf);
-                        bgm_index = 0;
+    /// Starts playing from the current position of the engine for `state`.
+    fn start(&mut self, state: PlaybackState) {
+        self.state = state;
+        self.render();
+        self.index = 0;
+    }
 
-                        state = PlaybackState::PlayingOrg;
-                    }
-                    #[cfg(feature = "ogg-playback")]
-                    Ok(PlaybackMessage::PlayOggSongSinglePart(data)) => {
-                        if state == PlaybackState::Stopped {
-                            saved_state = PlaybackStateType::None;
-                        }
+    fn render(&mut self) {
+        for i in &mut self.buf[0..self.samples] {
+            *i = 0x8000
+        }
 
-                        ogg_engine.start_single(data);
+        match self.state {
+            PlaybackState::PlayingOrg => {
+                self.samples = self.org_engine.render_to(&mut self.buf);
+            }
+            #[cfg(feature = "ogg-playback")]
+            PlaybackState::PlayingOgg => {
+                self.samples = self.ogg_engine.render_to(&mut self.buf);
+            }
+            PlaybackState::PlayingTracker => {
+                self.samples = self.tracker_engine.render_to(&mut self.buf);
+            }
+            PlaybackState::Stopped => unreachable!(),
+        }
+    }
 
-                        for i in &mut bgm_buf[0..samples] {
-                            *i = 0x8000
-                        }
-                        samples = ogg_engine.render_to(&mut bgm_buf);
-                        bgm_index = 0;
+    /// Returns the next frame as signed samples.
+    fn next_frame(&mut self) -> (f32, f32) {
+        let (sample_l, sample_r) = if self.state == PlaybackState::Stopped {
+            (0x8000, 0x8000)
+        } else if self.index < self.samples {
+            let samples = (self.buf[self.index], self.buf[self.index + 1]);
+            self.index += 2;
+            samples
+        } else {
+            self.render();
+            self.index = 2;
+            (self.buf[0], self.buf[1])
+        };
 
-                        state = PlaybackState::PlayingOgg;
-                    }
-                    #[cfg(feature = "ogg-playback")]
-                    Ok(PlaybackMessage::PlayOggSongMultiPart(data_intro, data_loop)) => {
-                        if state == PlaybackState::Stopped {
-                            saved_state = PlaybackStateType::None;
-                        }
+        (((sample_l ^ 0x8000) as i16) as f32, ((sample_r ^ 0x8000) as i16) as f32)
+    }
+}
 
-                        ogg_engine.start_multi(data_intro, data_loop);
+/// The song that's being faded out while the current one fades in.
+struct Crossfade {
+    bgm: BgmChannel,
+    curve: FadeCurve,
+    position: usize,
+    length: usize,
+}
 
-                        for i in &mut bgm_buf[0..samples] {
-                            *i = 0x8000
-                        }
-                        samples = ogg_engine.render_to(&mut bgm_buf);
-                        bgm_index = 0;
+/// Mixes the song and sound effects, driven by the messages sent by [SoundManager].
+pub(in crate::sound) struct Mixer {
+    rx: Receiver<PlaybackMessage>,
+    bank: SoundBank,
+    sample_rate: f32,
+    channels: usize,
+    bgm: BgmChannel,
+    crossfade: Option<Crossfade>,
+    /// Length in frames and curve of the crossfade applied when the next song starts or the music stops.
+    pending_crossfade: Option<(usize, FadeCurve)>,
+    saved_state: PlaybackStateType,
+    speed: f32,
+    pixtone: Box<PixTonePlayback>,
+    pxt_buf: Vec<u16>,
+    pxt_index: usize,
+    bgm_vol: f32,
+    sfx_vol: f32,
+}
 
-                        state = PlaybackState::PlayingOgg;
-                    }
-                    Ok(PlaybackMessage::PlaySample(id)) => {
-                        pixtone.play_sfx(id);
-                    }
+impl Mixer {
+    pub fn new(rx: Receiver<PlaybackMessage>, bank: SoundBank, sample_rate: u32, channels: usize) -> Mixer {
+        let sample_rate = sample_rate as f32;
+        let mut pixtone = Box::new(PixTonePlayback::new());
+        pixtone.create_samples();
+
+        log::info!("Audio format: {} {}", sample_rate, channels);
+
+        let buf_size = sample_rate as usize * 10 / 1000;
+        let mut pxt_buf = vec![0x8000; buf_size * 2];
+        pixtone.mix(&mut pxt_buf, sample_rate);
+
+        Mixer {
+            rx,
+            bank,
+            sample_rate,
+            channels,
+            bgm: BgmChannel::new(sample_rate as usize, buf_size),
+            crossfade: None,
+            pending_crossfade: None,
+            saved_state: PlaybackStateType::None,
+            speed: 1.0,
+            pixtone,
+            pxt_buf,
+            pxt_index: 0,
+            bgm_vol: 1.0,
+            sfx_vol: 1.0,
+        }
+    }
 
-                    Ok(PlaybackMessage::LoopSample(id)) => {
-                        pixtone.loop_sfx(id);
-                    }
-                    Ok(PlaybackMessage::LoopSampleFreq(id, freq)) => {
-                        pixtone.loop_sfx_freq(id, freq);
-                    }
-                    Ok(PlaybackMessage::StopSample(id)) => {
-                        pixtone.stop_sfx(id);
+    pub fn sample_rate(&self) -> u32 {
+        self.sample_rate as u32
+    }
+
+    pub fn channels(&self) -> usize {
+        self.channels
+    }
+
+    /// Moves the current song to the fading out channel if a crossfade was requested.
+    fn begin_crossfade(&mut self) {
+        let (length, curve) = match self.pending_crossfade.take() {
+            Some(crossfade) => crossfade,
+            None => return,
+        };
+
+        if self.bgm.state == PlaybackState::Stopped {
+            return;
+        }
+
+        let mut bgm = BgmChannel::new((self.sample_rate / self.speed) as usize, self.bgm.buf.len() / 2);
+        bgm.org_engine.interpolation = self.bgm.org_engine.interpolation;
+
+        let outgoing = std::mem::replace(&mut self.bgm, bgm);
+        self.crossfade = Some(Crossfade { bgm: outgoing, curve, position: 0, length });
+    }
+
+    /// Handles pending messages, returns false once the [SoundManager] is gone.
+    fn handle_messages(&mut self) -> bool {
+        loop {
+            match self.rx.try_recv() {
+                Ok(PlaybackMessage::PlayOrganyaSong(song)) => {
+                    if self.bgm.state == PlaybackState::Stopped {
+                        self.saved_state = PlaybackStateType::None;
                     }
-                    Ok(PlaybackMessage::Stop) => {
-                        if state == PlaybackState::Stopped {
-                            saved_state = PlaybackStateType::None;
-                        }
 
-                        state = PlaybackState::Stopped;
+                    self.begin_crossfade();
+                    self.bgm.org_engine.start_song(*song, &self.bank);
+                    self.bgm.start(PlaybackState::PlayingOrg);
+                }
+                #[cfg(feature = "ogg-playback")]
+                Ok(PlaybackMessage::PlayOggSongSinglePart(data)) => {
+                    if self.bgm.state == PlaybackState::Stopped {
+                        self.saved_state = PlaybackStateType::None;
                     }
-                    Ok(PlaybackMessage::SetSpeed(new_speed)) => {
-                        assert!(new_speed > 0.0);
-                        speed = new_speed;
-                        #[cfg(feature = "ogg-playback")]
-                            ogg_engine.set_sample_rate((sample_rate / new_speed) as usize);
-                        org_engine.set_sample_rate((sample_rate / new_speed) as usize);
+
+                    self.begin_crossfade();
+                    self.bgm.ogg_engine.start_single(data);
+                    self.bgm.start(PlaybackState::PlayingOgg);
+                }
+                #[cfg(feature = "ogg-playback")]
+                Ok(PlaybackMessage::PlayOggSongMultiPart(data_intro, data_loop)) => {
+                    if self.bgm.state == PlaybackState::Stopped {
+                        self.saved_state = PlaybackStateType::None;
                     }
-                    Ok(PlaybackMessage::SetSongVolume(new_volume)) => {
-                        assert!(bgm_vol >= 0.0);
-                        bgm_vol = new_volume;
+
+                    self.begin_crossfade();
+                    self.bgm.ogg_engine.start_multi(data_intro, data_loop);
+                    self.bgm.start(PlaybackState::PlayingOgg);
+                }
+                Ok(PlaybackMessage::PlayTrackerSong(module)) => {
+                    if self.bgm.state == PlaybackState::Stopped {
+                        self.saved_state = PlaybackStateType::None;
                     }
-                    Ok(PlaybackMessage::SetSampleVolume(new_volume)) => {
-                        assert!(sfx_vol >= 0.0);
-                        sfx_vol = new_volume;
+
+                    self.begin_crossfade();
+                    self.bgm.tracker_engine.start_song(*module);
+                    self.bgm.start(PlaybackState::PlayingTracker);
+                }
+                Ok(PlaybackMessage::PlaySample(id)) => {
+                    self.pixtone.play_sfx(id);
+                }
+                Ok(PlaybackMessage::PlaySamplePanned(id, pan, volume)) => {
+                    self.pixtone.play_sfx_panned(id, pan, volume);
+                }
+
+                Ok(PlaybackMessage::LoopSample(id)) => {
+                    self.pixtone.loop_sfx(id);
+                }
+                Ok(PlaybackMessage::LoopSampleFreq(id, freq)) => {
+                    self.pixtone.loop_sfx_freq(id, freq);
+                }
+                Ok(PlaybackMessage::StopSample(id)) => {
+                    self.pixtone.stop_sfx(id);
+                }
+                Ok(PlaybackMessage::Stop) => {
+                    if self.bgm.state == PlaybackState::Stopped {
+                        self.saved_state = PlaybackStateType::None;
                     }
-                    Ok(PlaybackMessage::SaveState) => {
-                        saved_state = match state {
-                            PlaybackState::Stopped => PlaybackStateType::None,
-                            PlaybackState::PlayingOrg => PlaybackStateType::Organya(org_engine.get_state()),
-                            #[cfg(feature = "ogg-playback")]
-                            PlaybackState::PlayingOgg => PlaybackStateType::Ogg(ogg_engine.get_state()),
-                        };
+
+                    self.begin_crossfade();
+                    self.bgm.state = PlaybackState::Stopped;
+                }
+                Ok(PlaybackMessage::SetSpeed(new_speed)) => {
+                    assert!(new_speed > 0.0);
+                    self.speed = new_speed;
+                    self.bgm.set_sample_rate((self.sample_rate / new_speed) as usize);
+                    if let Some(crossfade) = &mut self.crossfade {
+                        crossfade.bgm.set_sample_rate((self.sample_rate / new_speed) as usize);
                     }
-                    Ok(PlaybackMessage::RestoreState) => {
-                        let saved_state_loc = std::mem::take(&mut saved_state);
+                }
+                Ok(PlaybackMessage::SetSongVolume(new_volume)) => {
+                    assert!(self.bgm_vol >= 0.0);
+                    self.bgm_vol = new_volume;
+                }
+                Ok(PlaybackMessage::SetSampleVolume(new_volume)) => {
+                    assert!(self.sfx_vol >= 0.0);
+                    self.sfx_vol = new_volume;
+                }
+                Ok(PlaybackMessage::SaveState) => {
+                    self.saved_state = self.bgm.get_state();
+                }
+                Ok(PlaybackMessage::RestoreState) => {
+                    let saved_state_loc = std::mem::take(&mut self.saved_state);
 
-                        match saved_state_loc {
-                            PlaybackStateType::None => {
-                                state = PlaybackState::Stopped;
-                            }
-                            PlaybackStateType::Organya(playback_state) => {
-                                org_engine.set_state(playback_state, &bank);
+                    match saved_state_loc {
+                        PlaybackStateType::None => {
+                            self.bgm.state = PlaybackState::Stopped;
+                        }
+                        PlaybackStateType::Organya(playback_state) => {
+                            self.bgm.org_engine.set_state(playback_state, &self.bank);
 
-                                if state == PlaybackState::Stopped {
-                                    org_engine.rewind();
-                                }
+                            if self.bgm.state == PlaybackState::Stopped {
+                                self.bgm.org_engine.rewind();
+                            }
 
-                                for i in &mut bgm_buf[0..samples] {
-                                    *i = 0x8000
-                                }
-                                samples = org_engine.render_to(&mut bgm_buf);
-                                bgm_index = 0;
+                            self.bgm.start(PlaybackState::PlayingOrg);
+                        }
+                        #[cfg(feature = "ogg-playback")]
+                        PlaybackStateType::Ogg(playback_state) => {
+                            self.bgm.ogg_engine.set_state(playback_state);
 
-                                state = PlaybackState::PlayingOrg;
+                            if self.bgm.state == PlaybackState::Stopped {
+                                self.bgm.ogg_engine.rewind();
                             }
-                            #[cfg(feature = "ogg-playback")]
-                            PlaybackStateType::Ogg(playback_state) => {
-                                ogg_engine.set_state(playback_state);
-
-                                if state == PlaybackState::Stopped {
-                                    ogg_engine.rewind();
-                                }
 
-                                for i in &mut bgm_buf[0..samples] {
-                                    *i = 0x8000
-                                }
-                                samples = ogg_engine.render_to(&mut bgm_buf);
-                                bgm_index = 0;
+                            self.bgm.start(PlaybackState::PlayingOgg);
+                        }
+                        PlaybackStateType::Tracker(playback_state) => {
+                            self.bgm.tracker_engine.set_state(playback_state);
 
-                                state = PlaybackState::PlayingOgg;
+                            if self.bgm.state == PlaybackState::Stopped {
+                                self.bgm.tracker_engine.rewind();
                             }
+
+                            self.bgm.start(PlaybackState::PlayingTracker);
                         }
                     }
-                    Ok(PlaybackMessage::SetSampleParams(id, params)) => {
-                        pixtone.set_sample_parameters(id, params);
-                    }
-                    Ok(PlaybackMessage::SetOrgInterpolation(interpolation)) => {
-                        org_engine.interpolation = interpolation;
-                    }
-                    Ok(PlaybackMessage::SetSampleData(id, data)) => {
-                        pixtone.set_sample_data(id, data);
-                    }
-                    Err(_) => {
-                        break;
+                }
+                Ok(PlaybackMessage::GetSongPositions(reply)) => {
+                    let _ = reply.send((self.bgm.get_state().position(), self.saved_state.position()));
+                }
+                Ok(PlaybackMessage::Seek(position)) => {
+                    self.bgm.seek(position);
+                }
+                Ok(PlaybackMessage::SetSampleParams(id, params)) => {
+                    self.pixtone.set_sample_parameters(id, params);
+                }
+                Ok(PlaybackMessage::SetOrgInterpolation(interpolation)) => {
+                    self.bgm.org_engine.interpolation = interpolation;
+                    if let Some(crossfade) = &mut self.crossfade {
+                        crossfade.bgm.org_engine.interpolation = interpolation;
                     }
                 }
+                Ok(PlaybackMessage::SetSampleData(id, data)) => {
+                    self.pixtone.set_sample_data(id, data);
+                }
+                Ok(PlaybackMessage::Crossfade(seconds, curve)) => {
+                    let length = (seconds * self.sample_rate / self.speed) as usize;
+                    self.pending_crossfade = if length > 0 { Some((length, curve)) } else { None };
+                }
+                Err(TryRecvError::Empty) => {
+                    return true;
+                }
+                Err(TryRecvError::Disconnected) => {
+                    return false;
+                }
+            }
+        }
+    }
+
+    /// Returns the next frame of the music, with the outgoing song mixed in during a crossfade.
+    fn next_bgm_frame(&mut self) -> (f32, f32) {
+        let (mut bgm_l, mut bgm_r) = self.bgm.next_frame();
+
+        if let Some(crossfade) = &mut self.crossfade {
+            let (gain_out, gain_in) = crossfade.curve.gains(crossfade.position as f32 / crossfade.length as f32);
+            let (out_l, out_r) = crossfade.bgm.next_frame();
+
+            bgm_l = bgm_l * gain_in + out_l * gain_out;
+            bgm_r = bgm_r * gain_in + out_r * gain_out;
+
+            crossfade.position += 1;
+            if crossfade.position >= crossfade.length {
+                self.crossfade = None;
             }
+        }
 
-            for frame in data.chunks_mut(channels) {
-                let (bgm_sample_l, bgm_sample_r): (u16, u16) = {
-                    if state == PlaybackState::Stopped {
-                        (0x8000, 0x8000)
-                    } else if bgm_index < samples {
-                        let samples = (bgm_buf[bgm_index], bgm_buf[bgm_index + 1]);
-                        bgm_index += 2;
-                        samples
-                    } else {
-                        for i in &mut bgm_buf[0..samples] {
-                            *i = 0x8000
-                        }
+        (bgm_l, bgm_r)
+    }
 
-                        match state {
-                            PlaybackState::PlayingOrg => {
-                                samples = org_engine.render_to(&mut bgm_buf);
-                            }
-                            #[cfg(feature = "ogg-playback")]
-                            PlaybackState::PlayingOgg => {
-                                samples = ogg_engine.render_to(&mut bgm_buf);
-                            }
-                            _ => unreachable!(),
-                        }
-                        bgm_index = 2;
-                        (bgm_buf[0], bgm_buf[1])
-                    }
-                };
+    /// Fills `data` with interleaved frames, returns false once the [SoundManager] is gone.
+    pub fn mix<T: cpal::Sample>(&mut self, data: &mut [T]) -> bool {
+        let connected = self.handle_messages();
 
-                let pxt_sample: u16 = pxt_buf[pxt_index];
+        for frame in data.chunks_mut(self.channels) {
+            let (bgm_sample_l, bgm_sample_r) = self.next_bgm_frame();
 
-                if pxt_index < (pxt_buf.len() - 1) {
-                    pxt_index += 1;
-                } else {
-                    pxt_index = 0;
-                    pxt_buf.fill(0x8000);
-                    pixtone.mix(&mut pxt_buf, sample_rate / speed);
-                }
+            let pxt_sample_l = ((self.pxt_buf[self.pxt_index] ^ 0x8000) as i16) as f32;
+            let pxt_sample_r = ((self.pxt_buf[self.pxt_index + 1] ^ 0x8000) as i16) as f32;
 
-                if frame.len() >= 2 {
-                    let sample_l = clamp(
-                        (((bgm_sample_l ^ 0x8000) as i16) as f32 * bgm_vol) as isize
-                            + (((pxt_sample ^ 0x8000) as i16) as f32 * sfx_vol) as isize,
-                        -0x7fff,
-                        0x7fff,
-                    ) as u16
-                        ^ 0x8000;
-                    let sample_r = clamp(
-                        (((bgm_sample_r ^ 0x8000) as i16) as f32 * bgm_vol) as isize
-                            + (((pxt_sample ^ 0x8000) as i16) as f32 * sfx_vol) as isize,
-                        -0x7fff,
-                        0x7fff,
-                    ) as u16
-                        ^ 0x8000;
-
-                    frame[0] = Sample::from::<u16>(&sample_l);
-                    frame[1] = Sample::from::<u16>(&sample_r);
-                } else {
-                    let sample = clamp(
-                        ((((bgm_sample_l ^ 0x8000) as i16) + ((bgm_sample_r ^ 0x8000) as i16)) as f32 * bgm_vol / 2.0)
-                            as isize
-                            + (((pxt_sample ^ 0x8000) as i16) as f32 * sfx_vol) as isize,
-                        -0x7fff,
-                        0x7fff,
-                    ) as u16
-                        ^ 0x8000;
-
-                    frame[0] = Sample::from::<u16>(&sample);
-                }
+            if self.pxt_index < (self.pxt_buf.len() - 2) {
+                self.pxt_index += 2;
+            } else {
+                self.pxt_index = 0;
+                self.pxt_buf.fill(0x8000);
+                self.pixtone.mix(&mut self.pxt_buf, self.sample_rate / self.speed);
             }
+
+            if frame.len() >= 2 {
+                let sample_l = clamp(
+                    (bgm_sample_l * self.bgm_vol) as isize + (pxt_sample_l * self.sfx_vol) as isize,
+                    -0x7fff,
+                    0x7fff,
+                ) as u16
+                    ^ 0x8000;
+                let sample_r = clamp(
+                    (bgm_sample_r * self.bgm_vol) as isize + (pxt_sample_r * self.sfx_vol) as isize,
+                    -0x7fff,
+                    0x7fff,
+                ) as u16
+                    ^ 0x8000;
+
+                frame[0] = Sample::from::<u16>(&sample_l);
+                frame[1] = Sample::from::<u16>(&sample_r);
+            } else {
+                let sample = clamp(
+                    ((bgm_sample_l + bgm_sample_r) * self.bgm_vol / 2.0) as isize
+                        + ((pxt_sample_l + pxt_sample_r) * self.sfx_vol / 2.0) as isize,
+                    -0x7fff,
+                    0x7fff,
+                ) as u16
+                    ^ 0x8000;
+
+                frame[0] = Sample::from::<u16>(&sample);
+            }
+        }
+
+        connected
+    }
+}
+
+fn run<T>(mut mixer: Mixer, device: cpal::Device, config: cpal::StreamConfig) -> GameResult<cpal::Stream>
+    where
+        T: cpal::Sample,
+{
+    let err_fn = |err| eprintln!("an error occurred on stream: {}", err);
+
+    let stream_result = device.build_output_stream(
+        &config,
+        move |data: &mut [T], _: &cpal::OutputCallbackInfo| {
+            mixer.mix(data);
         },
         err_fn,
     );
@@ -849,3 +1361,109 @@ fn run<T>(
 
     Ok(stream)
 }
+
+#[test]
+fn test_fade_curves() {
+    for curve in [FadeCurve::Linear, FadeCurve::EqualPower, FadeCurve::SCurve] {
+        assert_eq!(curve.gains(0.0), (1.0, 0.0));
+
+        let (gain_out, gain_in) = curve.gains(1.0);
+        assert!(gain_out.abs() < 1e-6 && (gain_in - 1.0).abs() < 1e-6);
+    }
+
+    let (gain_out, gain_in) = FadeCurve::EqualPower.gains(0.5);
+    assert!((gain_out * gain_out + gain_in * gain_in - 1.0).abs() < 1e-6);
+}
+
+#[test]
+fn test_sfx_pan_volume() {
+    assert_eq!(sfx_pan_volume(0.0, 0.0), (0.0, 1.0));
+    assert_eq!(sfx_pan_volume(-120.0, 0.0), (-0.5, 1.0));
+
+    let (pan, volume) = sfx_pan_volume(1000.0, 0.0);
+    assert_eq!(pan, SFX_MAX_PAN);
+    assert_eq!(volume, SFX_MIN_VOLUME);
+
+    let (pan, volume) = sfx_pan_volume(0.0, 320.0);
+    assert_eq!(pan, 0.0);
+    assert!(volume > SFX_MIN_VOLUME && volume < 1.0);
+}
+
+#[test]
+fn test_song_snapshot_round_trip() {
+    let bank = SoundBank::load_from(&include_bytes!("../data/builtin/organya-wavetable-doukutsu.bin")[..]).unwrap();
+    let mut song = Song::empty();
+    song.time.loop_range.end = 64;
+
+    let positions = |tx: &Sender<PlaybackMessage>, mixer: &mut Mixer| {
+        let (reply_tx, reply_rx) = mpsc::channel();
+        tx.send(PlaybackMessage::GetSongPositions(reply_tx)).unwrap();
+        mixer.mix(&mut [0i16; 2]);
+        reply_rx.recv().unwrap()
+    };
+
+    let (tx, rx) = mpsc::channel();
+    let mut mixer = Mixer::new(rx, bank.clone(), 44100, 2);
+    let mut buf = vec![0i16; 4410 * 2];
+
+    tx.send(PlaybackMessage::PlayOrganyaSong(Box::new(song.clone()))).unwrap();
+    mixer.mix(&mut buf);
+    tx.send(PlaybackMessage::SaveState).unwrap();
+    tx.send(PlaybackMessage::PlayOrganyaSong(Box::new(song.clone()))).unwrap();
+    for _ in 0..3 {
+        mixer.mix(&mut buf);
+    }
+
+    let (current, saved) = positions(&tx, &mut mixer);
+    assert!(matches!(saved, SongPosition::Organya { play_pos } if play_pos > 0));
+    assert!(matches!(current, SongPosition::Organya { .. }) && current != saved);
+
+    let snapshot = SongSnapshot { current_song: 2, current_position: current, saved_song: 1, saved_position: saved };
+    let json = serde_json::to_string(&snapshot).unwrap();
+    assert_eq!(serde_json::from_str::<SongSnapshot>(&json).unwrap(), snapshot);
+
+    // the messages sent by SoundManager::restore_song_snapshot
+    let (tx, rx) = mpsc::channel();
+    let mut mixer = Mixer::new(rx, bank, 44100, 2);
+    tx.send(PlaybackMessage::PlayOrganyaSong(Box::new(song.clone()))).unwrap();
+    tx.send(PlaybackMessage::Seek(saved)).unwrap();
+    tx.send(PlaybackMessage::SaveState).unwrap();
+    tx.send(PlaybackMessage::PlayOrganyaSong(Box::new(song))).unwrap();
+    tx.send(PlaybackMessage::Seek(current)).unwrap();
+
+    assert_eq!(positions(&tx, &mut mixer), (current, saved));
+}
+
+#[test]
+fn test_mixer_crossfade() {
+    use crate::sound::organya::Note;
+
+    let bank = SoundBank::load_from(&include_bytes!("../data/builtin/organya-wavetable-doukutsu.bin")[..]).unwrap();
+    let silent = Song::empty();
+    let mut playing = Song::empty();
+    playing.time.loop_range.end = 64;
+    playing.tracks[0].inst.notes = 1;
+    playing.tracks[0].notes.push(Note { pos: 0, key: 48, len: 255, vol: 200, pan: 6 });
+
+    // 0.1s crossfade, mixed in 0.05s blocks on the headless sink's sample rate
+    let mix = |from: &Song, to: &Song| {
+        let (tx, rx) = mpsc::channel();
+        let mut mixer = Mixer::new(rx, bank.clone(), sink::SAMPLE_RATE, 2);
+        let mut blocks = vec![vec![0i16; sink::SAMPLE_RATE as usize / 20 * 2]; 3];
+
+        tx.send(PlaybackMessage::PlayOrganyaSong(Box::new(from.clone()))).unwrap();
+        mixer.mix(&mut [0i16; 2]);
+        tx.send(PlaybackMessage::Crossfade(0.1, FadeCurve::Linear)).unwrap();
+        tx.send(PlaybackMessage::PlayOrganyaSong(Box::new(to.clone()))).unwrap();
+        for block in blocks.iter_mut() {
+            mixer.mix(block);
+        }
+
+        assert!(mixer.crossfade.is_none());
+        blocks.iter().map(|block| block.iter().any(|&sample| sample != 0)).collect::<Vec<_>>()
+    };
+
+    // the outgoing song is heard during the crossfade only, the incoming one from its start
+    assert_eq!(mix(&playing, &silent), [true, true, false]);
+    assert_eq!(mix(&silent, &playing), [true, true, true]);
+}
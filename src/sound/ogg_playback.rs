@@ -5,6 +5,7 @@ use num_traits::clamp;
 
 use crate::framework::filesystem::File;
 use crate::sound::stuff::cubic_interp;
+use crate::sound::SongPosition;
 use crate::sound::wav::WavFormat;
 
 pub(crate) struct OggPlaybackEngine {
@@ -23,6 +24,12 @@ pub struct SavedOggPlaybackState {
     position: u64,
 }
 
+impl SavedOggPlaybackState {
+    pub fn position(&self) -> SongPosition {
+        SongPosition::Ogg { playing_intro: self.playing_intro, position: self.position }
+    }
+}
+
 impl OggPlaybackEngine {
     pub fn new() -> OggPlaybackEngine {
         OggPlaybackEngine {
@@ -69,6 +76,20 @@ impl OggPlaybackEngine {
         self.position = 0;
     }
 
+    /// Continues playing from `position`, a granule position of the intro or the loop part.
+    pub fn seek(&mut self, playing_intro: bool, position: u64) {
+        let playing_intro = playing_intro && self.intro_music.is_some();
+        let music = if playing_intro { &self.intro_music } else { &self.loop_music };
+
+        if let Some(music) = music {
+            let _ = music.write().unwrap().seek_absgp_pg(position);
+        }
+
+        self.playing_intro = playing_intro;
+        self.position = position;
+        self.buffer.clear();
+    }
+
     pub fn rewind(&mut self) {
         if let Some(music) = &self.intro_music {
             let _ = music.write().unwrap().seek_absgp_pg(0);
@@ -4,7 +4,7 @@ use std::mem::MaybeUninit;
 
 use crate::sound::fir::FIR;
 use crate::sound::fir::FIR_STEP;
-use crate::sound::InterpolationMode;
+use crate::sound::{InterpolationMode, SongPosition};
 use crate::sound::organya::{Song as Organya, Version};
 use crate::sound::stuff::*;
 use crate::sound::wav::*;
@@ -55,6 +55,12 @@ pub struct SavedOrganyaPlaybackState {
     play_pos: i32,
 }
 
+impl SavedOrganyaPlaybackState {
+    pub fn position(&self) -> SongPosition {
+        SongPosition::Organya { play_pos: self.play_pos }
+    }
+}
+
 impl Clone for SavedOrganyaPlaybackState {
     fn clone(&self) -> SavedOrganyaPlaybackState {
         SavedOrganyaPlaybackState { song: self.song.clone(), play_pos: self.play_pos }
@@ -146,13 +152,13 @@ impl OrgPlaybackEngine {
         self.set_position(0);
     }
 
-    #[allow(unused)]
-    pub fn get_total_samples(&self) -> u32 {
-        let ticks_intro = self.song.time.loop_range.start;
-        let ticks_loop = self.song.time.loop_range.end - self.song.time.loop_range.start;
-        let ticks_total = ticks_intro + ticks_loop + (ticks_loop * self.loops as i32);
+    pub fn get_total_samples(&self) -> usize {
+        let ticks_intro = self.song.time.loop_range.start.max(0) as usize;
+        let ticks_loop = (self.song.time.loop_range.end - self.song.time.loop_range.start).max(0) as usize;
+        // saturates for songs looped forever
+        let ticks_total = ticks_intro.saturating_add(ticks_loop.saturating_mul(self.loops.saturating_add(1)));
 
-        self.frames_per_tick as u32 * ticks_total as u32
+        self.frames_per_tick.saturating_mul(ticks_total)
     }
 
     fn update_play_state(&mut self) {
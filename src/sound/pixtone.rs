@@ -183,6 +183,9 @@ pub struct PlaybackState {
     pos: f32,
     tag: u32,
     freq: f32,
+    /// -1.0 plays only on the left channel, 1.0 only on the right one.
+    pan: f32,
+    volume: f32,
 }
 
 pub struct PixTonePlayback {
@@ -219,15 +222,21 @@ impl PixTonePlayback {
     }
 
     pub fn play_sfx(&mut self, id: u8) {
+        self.play_sfx_panned(id, 0.0, 1.0);
+    }
+
+    pub fn play_sfx_panned(&mut self, id: u8, pan: f32, volume: f32) {
         for state in &mut self.playback_state {
             if state.id == id && state.tag == 0 {
                 state.pos = 0.0;
                 state.looping = false;
+                state.pan = pan;
+                state.volume = volume;
                 return;
             }
         }
 
-        self.playback_state.push(PlaybackState { id, pos: 0.0, tag: 0, looping: false, freq: 1.0 });
+        self.playback_state.push(PlaybackState { id, pos: 0.0, tag: 0, looping: false, freq: 1.0, pan, volume });
     }
 
     pub fn loop_sfx(&mut self, id: u8) {
@@ -238,7 +247,15 @@ impl PixTonePlayback {
             }
         }
 
-        self.playback_state.push(PlaybackState { id, pos: 0.0, tag: 0, looping: true, freq: 1.0 });
+        self.playback_state.push(PlaybackState {
+            id,
+            pos: 0.0,
+            tag: 0,
+            looping: true,
+            freq: 1.0,
+            pan: 0.0,
+            volume: 1.0,
+        });
     }
 
     pub fn loop_sfx_freq(&mut self, id: u8, freq: f32) {
@@ -249,7 +266,7 @@ impl PixTonePlayback {
             }
         }
 
-        self.playback_state.push(PlaybackState { id, pos: 0.0, tag: 0, looping: true, freq });
+        self.playback_state.push(PlaybackState { id, pos: 0.0, tag: 0, looping: true, freq, pan: 0.0, volume: 1.0 });
     }
 
     pub fn stop_sfx(&mut self, id: u8) {
@@ -259,9 +276,10 @@ impl PixTonePlayback {
     }
 
     pub fn play_concurrent(&mut self, id: u8, tag: u32) {
-        self.playback_state.push(PlaybackState { id, pos: 0.0, tag, looping: false, freq: 1.0 });
+        self.playback_state.push(PlaybackState { id, pos: 0.0, tag, looping: false, freq: 1.0, pan: 0.0, volume: 1.0 });
     }
 
+    /// Mixes the playing sound effects into interleaved stereo frames.
     pub fn mix(&mut self, dst: &mut [u16], sample_rate: f32) {
         let mut scan = VecMutScan::new(&mut self.playback_state);
         let delta = 22050.0 / sample_rate;
@@ -276,7 +294,10 @@ impl PixTonePlayback {
                     continue;
                 };
 
-                for result in dst.iter_mut() {
+                let gain_l = state.volume * (1.0 - state.pan).min(1.0);
+                let gain_r = state.volume * (1.0 + state.pan).min(1.0);
+
+                for frame in dst.chunks_exact_mut(2) {
                     if state.pos >= sample.len() as f32 {
                         if state.looping {
                             state.pos = 0.0;
@@ -294,8 +315,10 @@ impl PixTonePlayback {
 
                     let s = cubic_interp(s1, s2, s4, s3, state.pos.fract()) * 32768.0;
                     // let s = sample[pos] as f32;
-                    let sam = (*result ^ 0x8000) as i16;
-                    *result = sam.saturating_add(s as i16) as u16 ^ 0x8000;
+                    for (result, gain) in frame.iter_mut().zip([gain_l, gain_r]) {
+                        let sam = (*result ^ 0x8000) as i16;
+                        *result = sam.saturating_add((s * gain) as i16) as u16 ^ 0x8000;
+                    }
 
                     state.pos += delta * state.freq;
                 }
@@ -0,0 +1,180 @@
+//! Offline rendering of Organya songs and PixTone sound effects to WAV files, works without an audio device.
+
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+
+use crate::data::builtin_fs::BuiltinFS;
+use crate::framework::error::GameError::InvalidValue;
+use crate::framework::error::GameResult;
+use crate::framework::vfs::VFS;
+use crate::sound::org_playback::OrgPlaybackEngine;
+use crate::sound::organya::Song;
+use crate::sound::pixtone_sfx::DEFAULT_PIXTONE_TABLE;
+use crate::sound::wav::{WavFormat, WavSample};
+use crate::sound::wave_bank::SoundBank;
+use crate::sound::InterpolationMode;
+
+/// Sample rate of the PixTone synthesizer.
+pub const PIXTONE_SAMPLE_RATE: u32 = 22050;
+/// Songs are cut after that many seconds, in case their loop end is never reached.
+const MAX_SONG_LENGTH: usize = 60 * 60;
+
+pub enum RenderSource {
+    /// Path to an `.org` file.
+    Organya(PathBuf),
+    /// ID of a sound effect from the built-in PixTone table.
+    PixTone(u8),
+}
+
+pub struct RenderOptions {
+    pub source: RenderSource,
+    /// Path of the WAV file to write.
+    pub output: PathBuf,
+    /// Only used for songs, sound effects are always rendered at [PIXTONE_SAMPLE_RATE].
+    pub sample_rate: u32,
+    pub interpolation: InterpolationMode,
+    /// How many times the looped part of a song is repeated after it has been played once.
+    pub loops: usize,
+    /// Length of the fade-out after the last loop, in seconds.
+    pub fade_out: f32,
+}
+
+impl RenderOptions {
+    pub fn new(source: RenderSource, output: PathBuf) -> RenderOptions {
+        RenderOptions {
+            source,
+            output,
+            sample_rate: 44100,
+            interpolation: InterpolationMode::Linear,
+            loops: 1,
+            fade_out: 0.0,
+        }
+    }
+}
+
+/// Renders the song to interleaved 16-bit stereo samples.
+pub fn render_organya(song: Song, bank: &SoundBank, options: &RenderOptions) -> Vec<i16> {
+    let mut engine = Box::new(OrgPlaybackEngine::new());
+    engine.set_sample_rate(options.sample_rate as usize);
+    engine.interpolation = options.interpolation;
+    engine.start_song(song, bank);
+    engine.loops = options.loops;
+
+    let song_frames = engine.get_total_samples().min(MAX_SONG_LENGTH * options.sample_rate as usize);
+    let fade_frames = (options.fade_out.max(0.0) * options.sample_rate as f32) as usize;
+    if fade_frames > 0 {
+        // keep looping while fading out
+        engine.loops = usize::MAX;
+    }
+
+    let total_frames = song_frames + fade_frames;
+    let mut samples = Vec::with_capacity(total_frames * 2);
+    let mut buf = vec![0x8000u16; 4096 * 2];
+
+    while samples.len() < total_frames * 2 {
+        buf.fill(0x8000);
+        let len = engine.render_to(&mut buf);
+        samples.extend(buf[..len].iter().map(|&s| (s ^ 0x8000) as i16));
+
+        if len < buf.len() {
+            break;
+        }
+    }
+
+    samples.truncate(total_frames * 2);
+
+    for (i, frame) in samples.chunks_mut(2).skip(song_frames).enumerate() {
+        let volume = 1.0 - i as f32 / fade_frames as f32;
+        for sample in frame {
+            *sample = (*sample as f32 * volume) as i16;
+        }
+    }
+
+    samples
+}
+
+/// Synthesizes a sound effect from the built-in PixTone table to 16-bit mono samples.
+pub fn render_pixtone(id: u8) -> GameResult<Vec<i16>> {
+    let samples = DEFAULT_PIXTONE_TABLE.get(id as usize).map(|params| params.synth()).unwrap_or_default();
+    if samples.is_empty() {
+        return Err(InvalidValue(format!("There's no PixTone sound effect with ID {}.", id)));
+    }
+
+    Ok(samples)
+}
+
+/// Renders the song or sound effect described by `options` to a WAV file.
+pub fn render_to_file(options: &RenderOptions) -> GameResult {
+    let (format, samples) = match &options.source {
+        RenderSource::Organya(path) => {
+            let song = Song::load_from(File::open(path)?)?;
+            let bank =
+                SoundBank::load_from(BuiltinFS::new().open(Path::new("/builtin/organya-wavetable-doukutsu.bin"))?)?;
+
+            log::info!("Rendering {:?} ({} loops, {}s fade-out)...", path, options.loops, options.fade_out);
+            let format = WavFormat { channels: 2, sample_rate: options.sample_rate, bit_depth: 16 };
+
+            (format, render_organya(song, &bank, options))
+        }
+        RenderSource::PixTone(id) => {
+            log::info!("Rendering PixTone sound effect {}...", id);
+            let format = WavFormat { channels: 1, sample_rate: PIXTONE_SAMPLE_RATE, bit_depth: 16 };
+
+            (format, render_pixtone(*id)?)
+        }
+    };
+
+    let data = samples.iter().flat_map(|sample| sample.to_le_bytes()).collect();
+    let wav = WavSample { format, data };
+    wav.write_to(BufWriter::new(File::create(&options.output)?))?;
+
+    log::info!("Saved {} to {:?}.", wav, options.output);
+
+    Ok(())
+}
+
+#[cfg(test)]
+fn test_samples_hash(samples: &[i16]) -> u64 {
+    use std::hash::Hasher;
+
+    let mut hasher = crate::util::hash::Fnv1a::new();
+    for sample in samples {
+        hasher.write(&sample.to_le_bytes());
+    }
+
+    hasher.finish()
+}
+
+#[test]
+fn test_render_organya() {
+    use crate::sound::organya::Note;
+
+    let bank = SoundBank::load_from(&include_bytes!("../data/builtin/organya-wavetable-doukutsu.bin")[..]).unwrap();
+    let mut song = Song::empty();
+    song.time.loop_range = crate::sound::organya::LoopRange { start: 8, end: 24 };
+    song.tracks[0].inst.notes = 2;
+    song.tracks[0].notes.push(Note { pos: 0, key: 48, len: 6, vol: 200, pan: 6 });
+    song.tracks[0].notes.push(Note { pos: 8, key: 55, len: 12, vol: 160, pan: 3 });
+
+    let mut options = RenderOptions::new(RenderSource::PixTone(0), PathBuf::new());
+    options.sample_rate = 22050;
+    options.loops = 2;
+    options.fade_out = 0.1;
+    let samples = render_organya(song, &bank, &options);
+
+    // 8 intro ticks and 3 times 16 looped ticks of 8ms, then the fade-out
+    let song_frames = 56 * (22050 / 1000) * 8;
+    assert_eq!(samples.len(), (song_frames + 2205) * 2);
+    assert!(samples.iter().any(|&sample| sample != 0));
+    assert_eq!(test_samples_hash(&samples), 0xf9ef7e7551faaaea);
+}
+
+#[test]
+fn test_render_pixtone() {
+    let samples = render_pixtone(1).unwrap();
+
+    assert!(samples.iter().any(|&sample| sample != 0));
+    assert_eq!(test_samples_hash(&samples), 0xef582db69e23f5a5);
+    assert!(render_pixtone(0).is_err());
+}
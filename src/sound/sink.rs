@@ -0,0 +1,154 @@
+//! Audio outputs that don't need an audio device, they run the mixer on their own thread.
+
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::thread::JoinHandle;
+use std::time::{Duration, Instant};
+
+use byteorder::{LE, WriteBytesExt};
+
+use crate::framework::error::GameError::AudioError;
+use crate::framework::error::GameResult;
+use crate::sound::wav::WavFormat;
+use crate::sound::Mixer;
+
+/// Sample rate of the headless outputs.
+pub const SAMPLE_RATE: u32 = 44100;
+/// Amount of frames mixed at once by the headless outputs.
+const BLOCK_SIZE: usize = 512;
+
+/// Where the output of the mixer goes.
+#[derive(Clone, Debug, PartialEq)]
+pub enum AudioOutput {
+    /// Default output device of the system.
+    Device,
+    /// Mixes and discards the audio, at `speed` times real time, or as fast as possible if it's 0.
+    Null { speed: f32 },
+    /// Mixes the audio and writes it to a WAV file, at `speed` times real time, or as fast as possible if it's 0.
+    File { path: PathBuf, speed: f32 },
+}
+
+/// Writes 16-bit samples to a WAV file, the sizes in the header are filled in once the capture is finished.
+struct WavWriter {
+    file: BufWriter<File>,
+    format: WavFormat,
+    data_len: u32,
+}
+
+impl WavWriter {
+    fn create(path: &Path, format: WavFormat) -> io::Result<WavWriter> {
+        let mut file = BufWriter::new(File::create(path)?);
+        format.write_header(0, &mut file)?;
+
+        Ok(WavWriter { file, format, data_len: 0 })
+    }
+
+    fn write(&mut self, samples: &[i16]) -> io::Result<()> {
+        for &sample in samples {
+            self.file.write_i16::<LE>(sample)?;
+        }
+        self.data_len = self.data_len.saturating_add(samples.len() as u32 * 2);
+
+        Ok(())
+    }
+
+    /// Writes the final sizes to the header.
+    fn finish(&mut self) -> io::Result<()> {
+        self.file.seek(SeekFrom::Start(0))?;
+        self.format.write_header(self.data_len, &mut self.file)?;
+        self.file.seek(SeekFrom::End(0))?;
+        self.file.flush()
+    }
+}
+
+impl Drop for WavWriter {
+    fn drop(&mut self) {
+        if let Err(e) = self.finish() {
+            log::error!("Error finishing captured audio: {}", e);
+        }
+    }
+}
+
+/// Starts mixing to a headless output, the thread stops once the [SoundManager](crate::sound::SoundManager)
+/// that owns the mixer is gone.
+pub(in crate::sound) fn spawn(mixer: Mixer, output: &AudioOutput) -> GameResult<JoinHandle<()>> {
+    let (writer, speed) = match output {
+        AudioOutput::Device => return Err(AudioError("Audio devices aren't handled by sinks.".to_owned())),
+        AudioOutput::Null { speed } => (None, *speed),
+        AudioOutput::File { path, speed } => {
+            let format =
+                WavFormat { channels: mixer.channels() as u16, sample_rate: mixer.sample_rate(), bit_depth: 16 };
+            log::info!("Capturing audio to {:?}.", path);
+
+            (Some(WavWriter::create(path, format)?), *speed)
+        }
+    };
+
+    thread::Builder::new()
+        .name("audio sink".to_owned())
+        .spawn(move || run(mixer, writer, speed))
+        .map_err(|e| AudioError(format!("Error starting audio thread: {}", e)))
+}
+
+fn run(mut mixer: Mixer, mut writer: Option<WavWriter>, speed: f32) {
+    let sample_rate = mixer.sample_rate() as f64;
+    let mut buf = vec![0i16; BLOCK_SIZE * mixer.channels()];
+    let start = Instant::now();
+    let mut frames = 0u64;
+
+    loop {
+        // the last block is still mixed from the messages sent before the SoundManager was gone
+        let connected = mixer.mix(&mut buf);
+
+        if let Some(w) = &mut writer {
+            if let Err(e) = w.write(&buf) {
+                log::error!("Error writing captured audio: {}", e);
+                writer = None;
+            }
+        }
+
+        if !connected {
+            break;
+        }
+
+        frames += BLOCK_SIZE as u64;
+
+        if speed > 0.0 {
+            let target = Duration::from_secs_f64(frames as f64 / sample_rate / speed as f64);
+            if let Some(delay) = target.checked_sub(start.elapsed()) {
+                thread::sleep(delay);
+            }
+        }
+    }
+}
+
+#[test]
+fn test_file_sink_captures_sfx() {
+    use std::sync::mpsc;
+
+    use crate::sound::wav::WavSample;
+    use crate::sound::wave_bank::SoundBank;
+    use crate::sound::PlaybackMessage;
+
+    let bank = SoundBank::load_from(&include_bytes!("../data/builtin/organya-wavetable-doukutsu.bin")[..]).unwrap();
+    let dir = std::env::temp_dir().join(format!("doukutsu-rs-sink-test-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let path = dir.join("capture.wav");
+
+    // the sink stops after mixing the queued messages once the sender is gone
+    let (tx, rx) = mpsc::channel();
+    tx.send(PlaybackMessage::PlaySample(1)).unwrap();
+    drop(tx);
+    let output = AudioOutput::File { path: path.clone(), speed: 0.0 };
+    spawn(Mixer::new(rx, bank, SAMPLE_RATE, 2), &output).unwrap().join().unwrap();
+
+    let wav = WavSample::read_from(File::open(&path).unwrap()).unwrap();
+    let _ = std::fs::remove_dir_all(&dir);
+
+    assert_eq!(wav.format, WavFormat { channels: 2, sample_rate: SAMPLE_RATE, bit_depth: 16 });
+    assert_eq!(wav.data.len(), BLOCK_SIZE * 2 * 2);
+    assert!(wav.data.chunks_exact(2).any(|s| s != [0, 0]));
+}
@@ -0,0 +1,1642 @@
+//! Tracker modules (MOD, XM and IT), loaded into a common representation.
+//!
+//! Notes, volumes, panning and effects of all formats are converted to the same units,
+//! so the playback engine only has to care about the few behaviours that differ between trackers.
+
+use std::io;
+use std::io::{Cursor, Read, Seek, SeekFrom};
+
+use byteorder::{BE, LE, ReadBytesExt};
+
+use crate::framework::error::{GameError, GameResult};
+
+/// C-0, the lowest note.
+pub const NOTE_MIN: u8 = 1;
+/// B-9, the highest note.
+pub const NOTE_MAX: u8 = 120;
+/// C-5, samples play at their `c5_speed` on this note.
+pub const NOTE_MIDDLE_C: u8 = 61;
+/// Fades out the playing note.
+pub const NOTE_FADE: u8 = 253;
+/// Stops the playing note.
+pub const NOTE_CUT: u8 = 254;
+/// Releases the playing note, envelopes continue past their sustain points.
+pub const NOTE_OFF: u8 = 255;
+
+/// Order skipped during playback.
+pub const ORDER_SKIP: u16 = 0xfffe;
+/// Order marking the end of the song.
+pub const ORDER_END: u16 = 0xffff;
+
+/// Frequency of middle C of samples without finetune.
+const DEFAULT_C5_SPEED: f32 = 8363.0;
+/// Longest sample accepted from IT files, in frames.
+const MAX_SAMPLE_LENGTH: usize = 1 << 26;
+/// Limits of XM and IT patterns, larger values only appear in corrupted files.
+const MAX_PATTERNS: usize = 256;
+const MAX_ROWS: usize = 1024;
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum ModuleFormat {
+    Mod,
+    Xm,
+    It,
+}
+
+/// Effects of the effect column. Parameters are kept as they were written when their meaning
+/// depends on the format or on effect memory, otherwise they're converted to common units.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Effect {
+    None,
+    Arpeggio(u8),
+    PortaUp(u8),
+    PortaDown(u8),
+    FinePortaUp(u8),
+    FinePortaDown(u8),
+    ExtraFinePortaUp(u8),
+    ExtraFinePortaDown(u8),
+    TonePorta(u8),
+    Vibrato(u8),
+    FineVibrato(u8),
+    TonePortaVolumeSlide(u8),
+    VibratoVolumeSlide(u8),
+    Tremolo(u8),
+    Tremor(u8),
+    /// 0..=255
+    SetPanning(u8),
+    PanningSlide(u8),
+    SampleOffset(u8),
+    SampleOffsetHigh(u8),
+    VolumeSlide(u8),
+    FineVolumeSlideUp(u8),
+    FineVolumeSlideDown(u8),
+    /// 0..=64
+    SetVolume(u8),
+    /// 0..=64
+    SetChannelVolume(u8),
+    ChannelVolumeSlide(u8),
+    PositionJump(u8),
+    PatternBreak(u8),
+    PatternLoop(u8),
+    PatternDelay(u8),
+    /// High nibble is the volume change, low nibble the interval in ticks.
+    Retrigger(u8),
+    NoteCut(u8),
+    NoteDelay(u8),
+    KeyOff(u8),
+    SetSpeed(u8),
+    SetTempo(u8),
+    /// 0..=128
+    SetGlobalVolume(u8),
+    GlobalVolumeSlide(u8),
+    VibratoWaveform(u8),
+    TremoloWaveform(u8),
+    /// IT S7x, past note and new note action controls.
+    NoteAction(u8),
+}
+
+/// Commands of the volume column.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum VolumeCommand {
+    None,
+    /// 0..=64
+    Volume(u8),
+    /// 0..=255
+    Panning(u8),
+    SlideUp(u8),
+    SlideDown(u8),
+    FineSlideUp(u8),
+    FineSlideDown(u8),
+    VibratoSpeed(u8),
+    VibratoDepth(u8),
+    PanningSlideLeft(u8),
+    PanningSlideRight(u8),
+    /// Same units as the parameter of [Effect::TonePorta].
+    TonePorta(u8),
+    /// Same units as the parameter of [Effect::PortaUp].
+    PortaUp(u8),
+    /// Same units as the parameter of [Effect::PortaDown].
+    PortaDown(u8),
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct Cell {
+    /// 0 if empty, [NOTE_MIN]..=[NOTE_MAX] or one of [NOTE_FADE], [NOTE_CUT] and [NOTE_OFF].
+    pub note: u8,
+    /// 1-based instrument number, 0 if empty.
+    pub instrument: u8,
+    pub volume: VolumeCommand,
+    pub effect: Effect,
+}
+
+impl Default for Cell {
+    fn default() -> Self {
+        Cell { note: 0, instrument: 0, volume: VolumeCommand::None, effect: Effect::None }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Pattern {
+    pub rows: usize,
+    pub channels: usize,
+    pub cells: Vec<Cell>,
+}
+
+impl Pattern {
+    pub fn new(rows: usize, channels: usize) -> Pattern {
+        Pattern { rows, channels, cells: vec![Cell::default(); rows * channels] }
+    }
+
+    pub fn cell(&self, row: usize, channel: usize) -> Cell {
+        self.cells.get(row * self.channels + channel).copied().unwrap_or_default()
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum Waveform {
+    Sine,
+    RampDown,
+    RampUp,
+    Square,
+    Random,
+}
+
+impl Waveform {
+    /// Waveform selected by the vibrato and tremolo waveform effects.
+    pub fn from_effect(value: u8) -> Waveform {
+        match value & 3 {
+            0 => Waveform::Sine,
+            1 => Waveform::RampDown,
+            2 => Waveform::Square,
+            _ => Waveform::Random,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct AutoVibrato {
+    pub waveform: Waveform,
+    /// Added to the position (out of 256) every tick.
+    pub speed: u8,
+    /// Maximum pitch change in 1/64 semitones.
+    pub depth: f32,
+    /// Ticks taken to reach the full depth.
+    pub sweep: u16,
+}
+
+impl Default for AutoVibrato {
+    fn default() -> Self {
+        AutoVibrato { waveform: Waveform::Sine, speed: 0, depth: 0.0, sweep: 0 }
+    }
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct SampleLoop {
+    pub start: usize,
+    /// exclusive
+    pub end: usize,
+    pub ping_pong: bool,
+}
+
+impl SampleLoop {
+    fn new(start: usize, end: usize, ping_pong: bool, length: usize) -> Option<SampleLoop> {
+        let end = end.min(length);
+
+        if start < end {
+            Some(SampleLoop { start, end, ping_pong })
+        } else {
+            None
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Sample {
+    pub name: String,
+    pub data: Vec<i16>,
+    pub c5_speed: f32,
+    /// 0..=64
+    pub volume: u8,
+    /// 0..=64
+    pub global_volume: u8,
+    /// 0..=255, overrides the panning of the channel if set.
+    pub panning: Option<u8>,
+    pub sample_loop: Option<SampleLoop>,
+    /// Used instead of `sample_loop` until the note is released.
+    pub sustain_loop: Option<SampleLoop>,
+    pub vibrato: AutoVibrato,
+}
+
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct EnvelopePoint {
+    pub tick: u16,
+    /// 0..=64, 32 is the center for panning envelopes.
+    pub value: u8,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Envelope {
+    pub enabled: bool,
+    pub points: Vec<EnvelopePoint>,
+    /// Points looped until the note is released, start and end are the same for a sustain point.
+    pub sustain: Option<(usize, usize)>,
+    pub loop_range: Option<(usize, usize)>,
+}
+
+impl Envelope {
+    fn new(
+        enabled: bool,
+        points: Vec<EnvelopePoint>,
+        sustain: Option<(u8, u8)>,
+        loop_range: Option<(u8, u8)>,
+    ) -> Envelope {
+        let count = points.len();
+        let range = |range: Option<(u8, u8)>| {
+            range.map(|(start, end)| (start as usize, end as usize)).filter(|&(start, end)| start <= end && end < count)
+        };
+
+        Envelope { enabled: enabled && count > 0, sustain: range(sustain), loop_range: range(loop_range), points }
+    }
+}
+
+/// What happens to the playing note when a new one starts on the same channel.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub enum NewNoteAction {
+    Cut,
+    Continue,
+    Off,
+    Fade,
+}
+
+#[derive(Debug, Clone)]
+pub struct Instrument {
+    pub name: String,
+    /// Note played and 0-based sample index for each note, starting from [NOTE_MIN].
+    pub keymap: Vec<(u8, Option<usize>)>,
+    pub volume_envelope: Envelope,
+    pub panning_envelope: Envelope,
+    /// Subtracted from the fade out volume (65536) every tick while the note fades out.
+    pub fadeout: u32,
+    /// 0..=128
+    pub global_volume: u8,
+    /// 0..=255, overrides the panning of the channel if set.
+    pub panning: Option<u8>,
+    pub new_note_action: NewNoteAction,
+}
+
+impl Instrument {
+    /// Instrument playing a single sample, for formats and modules without instruments.
+    fn from_sample(name: String, sample: usize) -> Instrument {
+        Instrument {
+            name,
+            keymap: (NOTE_MIN..=NOTE_MAX).map(|note| (note, Some(sample))).collect(),
+            volume_envelope: Envelope::default(),
+            panning_envelope: Envelope::default(),
+            fadeout: 0,
+            global_volume: 128,
+            panning: None,
+            new_note_action: NewNoteAction::Cut,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Module {
+    pub name: String,
+    pub format: ModuleFormat,
+    pub channels: usize,
+    /// Pattern indices, [ORDER_SKIP] or [ORDER_END].
+    pub orders: Vec<u16>,
+    /// Order the song loops back to after the last one.
+    pub restart_position: usize,
+    pub patterns: Vec<Pattern>,
+    pub instruments: Vec<Instrument>,
+    pub samples: Vec<Sample>,
+    pub initial_speed: u8,
+    pub initial_tempo: u8,
+    /// 0..=128
+    pub initial_global_volume: u8,
+    /// Pitch slides are in 1/64 semitones instead of Amiga periods.
+    pub linear_slides: bool,
+    /// 0..=255
+    pub channel_panning: Vec<u8>,
+    /// 0..=64
+    pub channel_volume: Vec<u8>,
+}
+
+impl Module {
+    pub fn load_from<R: io::Read>(mut f: R) -> GameResult<Module> {
+        let mut data = Vec::new();
+        f.read_to_end(&mut data)?;
+
+        if data.starts_with(b"Extended Module: ") {
+            load_xm(&data)
+        } else if data.starts_with(b"IMPM") {
+            load_it(&data)
+        } else if let Some(channels) = data.get(1080..1084).and_then(mod_channels) {
+            load_mod(&data, channels)
+        } else {
+            Err(GameError::ResourceLoadError("Unknown tracker module format.".to_string()))
+        }
+    }
+}
+
+fn read_string<R: io::Read>(f: &mut R, length: usize) -> GameResult<String> {
+    let mut buf = vec![0u8; length];
+    f.read_exact(&mut buf)?;
+
+    let end = buf.iter().position(|&c| c == 0).unwrap_or(length);
+
+    Ok(String::from_utf8_lossy(&buf[..end]).trim_end().to_string())
+}
+
+fn mod_channels(magic: &[u8]) -> Option<usize> {
+    let channels = match magic {
+        b"M.K." | b"M!K!" | b"M&K!" | b"N.T." | b"FLT4" | b"4CHN" => 4,
+        b"FLT8" | b"CD81" | b"OKTA" | b"OCTA" => 8,
+        [d, b'C', b'H', b'N'] if d.is_ascii_digit() => (d - b'0') as usize,
+        [a, b, b'C', b'H'] | [a, b, b'C', b'N'] if a.is_ascii_digit() && b.is_ascii_digit() => {
+            ((a - b'0') * 10 + (b - b'0')) as usize
+        }
+        _ => return None,
+    };
+
+    Some(channels).filter(|&c| c > 0 && c <= 64)
+}
+
+/// Converts an Amiga period of a MOD pattern to a note, 428 (C-2 in ProTracker) is middle C.
+fn mod_period_to_note(period: u16) -> u8 {
+    let note = NOTE_MIDDLE_C as f32 + 12.0 * (428.0 / period as f32).log2();
+
+    note.round().clamp(NOTE_MIN as f32, NOTE_MAX as f32) as u8
+}
+
+/// Converts effects of MOD and XM modules, MOD uses a subset of the XM effects.
+fn xm_effect(effect: u8, param: u8, format: ModuleFormat) -> Effect {
+    let (x, y) = (param >> 4, param & 0x0f);
+    // MOD effects have no memory, a parameter of 0 does nothing.
+    let memory = format != ModuleFormat::Mod || param != 0;
+
+    match effect {
+        0x0 if param != 0 => Effect::Arpeggio(param),
+        0x1 if memory => Effect::PortaUp(param),
+        0x2 if memory => Effect::PortaDown(param),
+        0x3 => Effect::TonePorta(param),
+        0x4 => Effect::Vibrato(param),
+        0x5 if memory => Effect::TonePortaVolumeSlide(param),
+        0x5 => Effect::TonePorta(0),
+        0x6 if memory => Effect::VibratoVolumeSlide(param),
+        0x6 => Effect::Vibrato(0),
+        0x7 => Effect::Tremolo(param),
+        0x8 => Effect::SetPanning(param),
+        0x9 => Effect::SampleOffset(param),
+        0xa if memory => Effect::VolumeSlide(param),
+        0xb => Effect::PositionJump(param),
+        0xc => Effect::SetVolume(param.min(64)),
+        0xd => Effect::PatternBreak(x * 10 + y),
+        0xe => match x {
+            0x1 => Effect::FinePortaUp(y),
+            0x2 => Effect::FinePortaDown(y),
+            0x4 => Effect::VibratoWaveform(y),
+            0x6 => Effect::PatternLoop(y),
+            0x7 => Effect::TremoloWaveform(y),
+            0x8 => Effect::SetPanning(y << 4),
+            0x9 if y != 0 => Effect::Retrigger(y),
+            0xa => Effect::FineVolumeSlideUp(y),
+            0xb => Effect::FineVolumeSlideDown(y),
+            0xc => Effect::NoteCut(y),
+            0xd => Effect::NoteDelay(y),
+            0xe => Effect::PatternDelay(y),
+            _ => Effect::None,
+        },
+        0xf if param == 0 => Effect::None,
+        0xf if param < 0x20 => Effect::SetSpeed(param),
+        0xf => Effect::SetTempo(param),
+        // G
+        0x10 => Effect::SetGlobalVolume(param.min(64) * 2),
+        // H
+        0x11 => Effect::GlobalVolumeSlide(param),
+        // K
+        0x14 => Effect::KeyOff(param),
+        // P
+        0x19 => Effect::PanningSlide(param),
+        // R
+        0x1b => Effect::Retrigger(param),
+        // T
+        0x1d => Effect::Tremor(param),
+        // X
+        0x21 => match x {
+            0x1 => Effect::ExtraFinePortaUp(y),
+            0x2 => Effect::ExtraFinePortaDown(y),
+            _ => Effect::None,
+        },
+        _ => Effect::None,
+    }
+}
+
+fn xm_volume(volume: u8) -> VolumeCommand {
+    let y = volume & 0x0f;
+
+    match volume {
+        0x10..=0x50 => VolumeCommand::Volume(volume - 0x10),
+        0x60..=0x6f => VolumeCommand::SlideDown(y),
+        0x70..=0x7f => VolumeCommand::SlideUp(y),
+        0x80..=0x8f => VolumeCommand::FineSlideDown(y),
+        0x90..=0x9f => VolumeCommand::FineSlideUp(y),
+        0xa0..=0xaf => VolumeCommand::VibratoSpeed(y),
+        0xb0..=0xbf => VolumeCommand::VibratoDepth(y),
+        0xc0..=0xcf => VolumeCommand::Panning(y << 4),
+        0xd0..=0xdf => VolumeCommand::PanningSlideLeft(y),
+        0xe0..=0xef => VolumeCommand::PanningSlideRight(y),
+        0xf0..=0xff => VolumeCommand::TonePorta(y << 4),
+        _ => VolumeCommand::None,
+    }
+}
+
+fn xm_note(note: u8) -> u8 {
+    match note {
+        // XM plays samples at their base frequency on C-4
+        1..=96 => note + 12,
+        97 => NOTE_OFF,
+        _ => 0,
+    }
+}
+
+fn load_mod(data: &[u8], channels: usize) -> GameResult<Module> {
+    let mut f = Cursor::new(data);
+    let name = read_string(&mut f, 20)?;
+
+    struct SampleHeader {
+        name: String,
+        length: usize,
+        finetune: i8,
+        volume: u8,
+        loop_start: usize,
+        loop_length: usize,
+    }
+
+    let mut headers = Vec::with_capacity(31);
+    for _ in 0..31 {
+        headers.push(SampleHeader {
+            name: read_string(&mut f, 22)?,
+            length: f.read_u16::<BE>()? as usize * 2,
+            // signed nibble
+            finetune: ((f.read_u8()? & 0x0f) << 4) as i8 >> 4,
+            volume: f.read_u8()?.min(64),
+            loop_start: f.read_u16::<BE>()? as usize * 2,
+            loop_length: f.read_u16::<BE>()? as usize * 2,
+        });
+    }
+
+    let song_length = (f.read_u8()? as usize).clamp(1, 128);
+    let restart_position = f.read_u8()? as usize;
+    let mut order_table = [0u8; 128];
+    f.read_exact(&mut order_table)?;
+    f.seek(SeekFrom::Current(4))?;
+
+    let pattern_count = order_table.iter().copied().max().unwrap_or(0) as usize + 1;
+    let mut patterns = Vec::with_capacity(pattern_count);
+    for _ in 0..pattern_count {
+        let mut pattern = Pattern::new(64, channels);
+
+        for cell in &mut pattern.cells {
+            let mut b = [0u8; 4];
+            f.read_exact(&mut b)?;
+
+            let period = ((b[0] as u16 & 0x0f) << 8) | b[1] as u16;
+            *cell = Cell {
+                note: if period == 0 { 0 } else { mod_period_to_note(period) },
+                instrument: (b[0] & 0xf0) | (b[2] >> 4),
+                volume: VolumeCommand::None,
+                effect: xm_effect(b[2] & 0x0f, b[3], ModuleFormat::Mod),
+            };
+        }
+
+        patterns.push(pattern);
+    }
+
+    let mut samples = Vec::with_capacity(headers.len());
+    let mut instruments = Vec::with_capacity(headers.len());
+    let mut offset = f.position() as usize;
+    for (i, header) in headers.into_iter().enumerate() {
+        // some modules are truncated, keep the part that's there
+        let start = offset.min(data.len());
+        let end = (offset + header.length).min(data.len());
+        offset += header.length;
+
+        let data: Vec<i16> = data[start..end].iter().map(|&s| (s as i8 as i16) << 8).collect();
+        let sample_loop = if header.loop_length > 2 {
+            SampleLoop::new(header.loop_start, header.loop_start + header.loop_length, false, data.len())
+        } else {
+            None
+        };
+
+        instruments.push(Instrument::from_sample(header.name.clone(), i));
+        samples.push(Sample {
+            name: header.name,
+            data,
+            c5_speed: DEFAULT_C5_SPEED * 2f32.powf(header.finetune as f32 / (12.0 * 8.0)),
+            volume: header.volume,
+            global_volume: 64,
+            panning: None,
+            sample_loop,
+            sustain_loop: None,
+            vibrato: AutoVibrato::default(),
+        });
+    }
+
+    Ok(Module {
+        name,
+        format: ModuleFormat::Mod,
+        channels,
+        orders: order_table[..song_length].iter().map(|&o| o as u16).collect(),
+        restart_position: if restart_position < song_length { restart_position } else { 0 },
+        patterns,
+        instruments,
+        samples,
+        initial_speed: 6,
+        initial_tempo: 125,
+        initial_global_volume: 128,
+        linear_slides: false,
+        // Amiga channels are panned left, right, right, left
+        channel_panning: (0..channels).map(|c| if c % 4 == 0 || c % 4 == 3 { 0x40 } else { 0xc0 }).collect(),
+        channel_volume: vec![64; channels],
+    })
+}
+
+fn load_xm(data: &[u8]) -> GameResult<Module> {
+    let mut f = Cursor::new(data);
+    f.seek(SeekFrom::Start(17))?;
+    let name = read_string(&mut f, 20)?;
+
+    f.seek(SeekFrom::Start(58))?;
+    let version = f.read_u16::<LE>()?;
+    if version < 0x0104 {
+        return Err(GameError::ResourceLoadError(format!("Unsupported XM version: {:#06x}", version)));
+    }
+
+    let header_size = f.read_u32::<LE>()? as u64;
+    let song_length = f.read_u16::<LE>()? as usize;
+    let restart_position = f.read_u16::<LE>()? as usize;
+    let channels = f.read_u16::<LE>()? as usize;
+    let pattern_count = f.read_u16::<LE>()? as usize;
+    let instrument_count = f.read_u16::<LE>()? as usize;
+    let flags = f.read_u16::<LE>()?;
+    let speed = f.read_u16::<LE>()?;
+    let tempo = f.read_u16::<LE>()?;
+
+    if channels == 0 || channels > 64 {
+        return Err(GameError::ResourceLoadError(format!("Invalid XM channel count: {}", channels)));
+    }
+    if pattern_count > MAX_PATTERNS {
+        return Err(GameError::ResourceLoadError(format!("Invalid XM pattern count: {}", pattern_count)));
+    }
+
+    let mut order_table = [0u8; 256];
+    f.read_exact(&mut order_table)?;
+    let song_length = song_length.min(256);
+
+    f.seek(SeekFrom::Start(60 + header_size))?;
+
+    let mut patterns = Vec::with_capacity(pattern_count);
+    for _ in 0..pattern_count {
+        let start = f.position();
+        let header_length = f.read_u32::<LE>()? as u64;
+        let _packing = f.read_u8()?;
+        let rows = match f.read_u16::<LE>()? as usize {
+            0 => 64,
+            rows if rows > MAX_ROWS => {
+                return Err(GameError::ResourceLoadError(format!("Invalid XM pattern row count: {}", rows)));
+            }
+            rows => rows,
+        };
+        let packed_size = f.read_u16::<LE>()? as u64;
+
+        f.seek(SeekFrom::Start(start + header_length))?;
+        let end = f.position() + packed_size;
+
+        let mut pattern = Pattern::new(rows, channels);
+        if packed_size > 0 {
+            for cell in &mut pattern.cells {
+                let flags = f.read_u8()?;
+                let (mut note, mut instrument, mut volume, mut effect, mut param) = (0, 0, 0, 0, 0);
+
+                if flags & 0x80 != 0 {
+                    if flags & 0x01 != 0 {
+                        note = f.read_u8()?;
+                    }
+                    if flags & 0x02 != 0 {
+                        instrument = f.read_u8()?;
+                    }
+                    if flags & 0x04 != 0 {
+                        volume = f.read_u8()?;
+                    }
+                    if flags & 0x08 != 0 {
+                        effect = f.read_u8()?;
+                    }
+                    if flags & 0x10 != 0 {
+                        param = f.read_u8()?;
+                    }
+                } else {
+                    note = flags;
+                    instrument = f.read_u8()?;
+                    volume = f.read_u8()?;
+                    effect = f.read_u8()?;
+                    param = f.read_u8()?;
+                }
+
+                *cell = Cell {
+                    note: xm_note(note),
+                    instrument,
+                    volume: xm_volume(volume),
+                    effect: xm_effect(effect, param, ModuleFormat::Xm),
+                };
+            }
+        }
+
+        f.seek(SeekFrom::Start(end))?;
+        patterns.push(pattern);
+    }
+
+    let mut instruments = Vec::with_capacity(instrument_count);
+    let mut samples = Vec::new();
+    for _ in 0..instrument_count {
+        let start = f.position();
+        let header_size = f.read_u32::<LE>()? as u64;
+        let name = read_string(&mut f, 22)?;
+        let _kind = f.read_u8()?;
+        let sample_count = f.read_u16::<LE>()? as usize;
+
+        let mut instrument = Instrument {
+            name,
+            keymap: (NOTE_MIN..=NOTE_MAX).map(|note| (note, None)).collect(),
+            volume_envelope: Envelope::default(),
+            panning_envelope: Envelope::default(),
+            fadeout: 0,
+            global_volume: 128,
+            panning: None,
+            new_note_action: NewNoteAction::Cut,
+        };
+
+        if sample_count == 0 {
+            f.seek(SeekFrom::Start(start + header_size))?;
+            instruments.push(instrument);
+            continue;
+        }
+
+        let _sample_header_size = f.read_u32::<LE>()?;
+        let mut keymap = [0u8; 96];
+        f.read_exact(&mut keymap)?;
+
+        let read_points = |f: &mut Cursor<&[u8]>| -> GameResult<Vec<EnvelopePoint>> {
+            let mut points = Vec::with_capacity(12);
+            for _ in 0..12 {
+                let tick = f.read_u16::<LE>()?;
+                let value = f.read_u16::<LE>()?.min(64) as u8;
+                points.push(EnvelopePoint { tick, value });
+            }
+            Ok(points)
+        };
+        let mut volume_points = read_points(&mut f)?;
+        let mut panning_points = read_points(&mut f)?;
+
+        let volume_count = f.read_u8()? as usize;
+        let panning_count = f.read_u8()? as usize;
+        let volume_sustain = f.read_u8()?;
+        let volume_loop = (f.read_u8()?, f.read_u8()?);
+        let panning_sustain = f.read_u8()?;
+        let panning_loop = (f.read_u8()?, f.read_u8()?);
+        let volume_flags = f.read_u8()?;
+        let panning_flags = f.read_u8()?;
+        let vibrato_type = f.read_u8()?;
+        let vibrato_sweep = f.read_u8()?;
+        let vibrato_depth = f.read_u8()?;
+        let vibrato_rate = f.read_u8()?;
+        let fadeout = f.read_u16::<LE>()?;
+
+        volume_points.truncate(volume_count);
+        panning_points.truncate(panning_count);
+
+        instrument.volume_envelope = Envelope::new(
+            volume_flags & 1 != 0,
+            volume_points,
+            Some((volume_sustain, volume_sustain)).filter(|_| volume_flags & 2 != 0),
+            Some(volume_loop).filter(|_| volume_flags & 4 != 0),
+        );
+        instrument.panning_envelope = Envelope::new(
+            panning_flags & 1 != 0,
+            panning_points,
+            Some((panning_sustain, panning_sustain)).filter(|_| panning_flags & 2 != 0),
+            Some(panning_loop).filter(|_| panning_flags & 4 != 0),
+        );
+        // FT2 fades out from 32768
+        instrument.fadeout = fadeout as u32 * 2;
+
+        let vibrato = AutoVibrato {
+            waveform: match vibrato_type {
+                1 => Waveform::Square,
+                2 => Waveform::RampDown,
+                3 => Waveform::RampUp,
+                _ => Waveform::Sine,
+            },
+            speed: vibrato_rate,
+            depth: vibrato_depth as f32,
+            sweep: vibrato_sweep as u16,
+        };
+
+        f.seek(SeekFrom::Start(start + header_size))?;
+
+        struct SampleHeader {
+            length: usize,
+            loop_start: usize,
+            loop_length: usize,
+            volume: u8,
+            finetune: i8,
+            kind: u8,
+            panning: u8,
+            relative_note: i8,
+            name: String,
+        }
+
+        let mut headers = Vec::with_capacity(sample_count);
+        for _ in 0..sample_count {
+            let length = f.read_u32::<LE>()? as usize;
+            let loop_start = f.read_u32::<LE>()? as usize;
+            let loop_length = f.read_u32::<LE>()? as usize;
+            let volume = f.read_u8()?.min(64);
+            let finetune = f.read_i8()?;
+            let kind = f.read_u8()?;
+            let panning = f.read_u8()?;
+            let relative_note = f.read_i8()?;
+            let _reserved = f.read_u8()?;
+            let name = read_string(&mut f, 22)?;
+
+            headers.push(SampleHeader {
+                length,
+                loop_start,
+                loop_length,
+                volume,
+                finetune,
+                kind,
+                panning,
+                relative_note,
+                name,
+            });
+        }
+
+        let first_sample = samples.len();
+        for header in headers {
+            let is_16bit = header.kind & 0x10 != 0;
+            let start = (f.position() as usize).min(data.len());
+            let end = start.saturating_add(header.length).min(data.len());
+            f.seek(SeekFrom::Start(end as u64))?;
+
+            // samples are delta encoded
+            let sample_data: Vec<i16> = if is_16bit {
+                data[start..end]
+                    .chunks_exact(2)
+                    .scan(0i16, |acc, s| {
+                        *acc = acc.wrapping_add(i16::from_le_bytes([s[0], s[1]]));
+                        Some(*acc)
+                    })
+                    .collect()
+            } else {
+                data[start..end]
+                    .iter()
+                    .scan(0i8, |acc, &s| {
+                        *acc = acc.wrapping_add(s as i8);
+                        Some((*acc as i16) << 8)
+                    })
+                    .collect()
+            };
+
+            let scale = if is_16bit { 2 } else { 1 };
+            let (loop_start, loop_length) = (header.loop_start / scale, header.loop_length / scale);
+            let sample_loop = match header.kind & 3 {
+                1 | 2 if loop_length > 0 => SampleLoop::new(
+                    loop_start,
+                    loop_start.saturating_add(loop_length),
+                    header.kind & 3 == 2,
+                    sample_data.len(),
+                ),
+                _ => None,
+            };
+
+            samples.push(Sample {
+                name: header.name,
+                data: sample_data,
+                c5_speed: DEFAULT_C5_SPEED
+                    * 2f32.powf((header.relative_note as f32 * 128.0 + header.finetune as f32) / (12.0 * 128.0)),
+                volume: header.volume,
+                global_volume: 64,
+                panning: Some(header.panning),
+                sample_loop,
+                sustain_loop: None,
+                vibrato,
+            });
+        }
+
+        for (i, (_, sample)) in instrument.keymap.iter_mut().enumerate() {
+            // the keymap starts at C-0 of XM, which is C-1 here
+            let index = keymap[i.saturating_sub(12).min(95)] as usize;
+            if index < sample_count {
+                *sample = Some(first_sample + index);
+            }
+        }
+
+        instruments.push(instrument);
+    }
+
+    Ok(Module {
+        name,
+        format: ModuleFormat::Xm,
+        channels,
+        orders: order_table[..song_length].iter().map(|&o| o as u16).collect(),
+        restart_position: if restart_position < song_length { restart_position } else { 0 },
+        patterns,
+        instruments,
+        samples,
+        initial_speed: speed.clamp(1, 31) as u8,
+        initial_tempo: tempo.clamp(32, 255) as u8,
+        initial_global_volume: 128,
+        linear_slides: flags & 1 != 0,
+        channel_panning: vec![128; channels],
+        channel_volume: vec![64; channels],
+    })
+}
+
+fn it_effect(command: u8, param: u8) -> Effect {
+    let (x, y) = (param >> 4, param & 0x0f);
+
+    match command {
+        // A
+        1 if param != 0 => Effect::SetSpeed(param),
+        2 => Effect::PositionJump(param),
+        3 => Effect::PatternBreak(param),
+        4 => Effect::VolumeSlide(param),
+        5 => Effect::PortaDown(param),
+        6 => Effect::PortaUp(param),
+        7 => Effect::TonePorta(param),
+        8 => Effect::Vibrato(param),
+        9 => Effect::Tremor(param),
+        10 => Effect::Arpeggio(param),
+        11 => Effect::VibratoVolumeSlide(param),
+        12 => Effect::TonePortaVolumeSlide(param),
+        13 => Effect::SetChannelVolume(param.min(64)),
+        14 => Effect::ChannelVolumeSlide(param),
+        15 => Effect::SampleOffset(param),
+        16 => Effect::PanningSlide(param),
+        17 => Effect::Retrigger(param),
+        18 => Effect::Tremolo(param),
+        // S
+        19 => match x {
+            0x3 => Effect::VibratoWaveform(y),
+            0x4 => Effect::TremoloWaveform(y),
+            0x7 => Effect::NoteAction(y),
+            0x8 => Effect::SetPanning(y << 4),
+            0xa => Effect::SampleOffsetHigh(y),
+            0xb => Effect::PatternLoop(y),
+            0xc => Effect::NoteCut(y),
+            0xd => Effect::NoteDelay(y),
+            0xe => Effect::PatternDelay(y),
+            _ => Effect::None,
+        },
+        20 => Effect::SetTempo(param),
+        21 => Effect::FineVibrato(param),
+        22 => Effect::SetGlobalVolume(param.min(128)),
+        23 => Effect::GlobalVolumeSlide(param),
+        24 => Effect::SetPanning(param),
+        _ => Effect::None,
+    }
+}
+
+fn it_volume(volume: u8) -> VolumeCommand {
+    const TONE_PORTA: [u8; 10] = [0, 1, 4, 8, 16, 32, 64, 96, 128, 255];
+
+    match volume {
+        0..=64 => VolumeCommand::Volume(volume),
+        65..=74 => VolumeCommand::FineSlideUp(volume - 65),
+        75..=84 => VolumeCommand::FineSlideDown(volume - 75),
+        85..=94 => VolumeCommand::SlideUp(volume - 85),
+        95..=104 => VolumeCommand::SlideDown(volume - 95),
+        105..=114 => VolumeCommand::PortaDown((volume - 105) * 4),
+        115..=124 => VolumeCommand::PortaUp((volume - 115) * 4),
+        128..=192 => VolumeCommand::Panning(((volume - 128) as u16 * 4).min(255) as u8),
+        193..=202 => VolumeCommand::TonePorta(TONE_PORTA[(volume - 193) as usize]),
+        203..=212 => VolumeCommand::VibratoDepth(volume - 203),
+        _ => VolumeCommand::None,
+    }
+}
+
+fn it_panning(panning: u8) -> u8 {
+    (panning.min(64) as u16 * 4).min(255) as u8
+}
+
+fn load_it(data: &[u8]) -> GameResult<Module> {
+    let mut f = Cursor::new(data);
+    f.seek(SeekFrom::Start(4))?;
+    let name = read_string(&mut f, 26)?;
+
+    f.seek(SeekFrom::Start(0x20))?;
+    let order_count = f.read_u16::<LE>()? as usize;
+    let instrument_count = f.read_u16::<LE>()? as usize;
+    let sample_count = f.read_u16::<LE>()? as usize;
+    let pattern_count = f.read_u16::<LE>()? as usize;
+    let _created_with = f.read_u16::<LE>()?;
+    let compatible_with = f.read_u16::<LE>()?;
+    let flags = f.read_u16::<LE>()?;
+    let _special = f.read_u16::<LE>()?;
+    let global_volume = f.read_u8()?;
+    let _mix_volume = f.read_u8()?;
+    let speed = f.read_u8()?;
+    let tempo = f.read_u8()?;
+
+    if pattern_count > MAX_PATTERNS {
+        return Err(GameError::ResourceLoadError(format!("Invalid IT pattern count: {}", pattern_count)));
+    }
+
+    f.seek(SeekFrom::Start(0x40))?;
+    let mut channel_panning = [0u8; 64];
+    f.read_exact(&mut channel_panning)?;
+    let mut channel_volume = [0u8; 64];
+    f.read_exact(&mut channel_volume)?;
+
+    let mut orders = Vec::with_capacity(order_count);
+    for _ in 0..order_count {
+        orders.push(match f.read_u8()? {
+            255 => ORDER_END,
+            254 => ORDER_SKIP,
+            order => order as u16,
+        });
+    }
+
+    let mut read_offsets =
+        |count: usize| -> GameResult<Vec<u64>> { (0..count).map(|_| Ok(f.read_u32::<LE>()? as u64)).collect() };
+    let instrument_offsets = read_offsets(instrument_count)?;
+    let sample_offsets = read_offsets(sample_count)?;
+    let pattern_offsets = read_offsets(pattern_count)?;
+
+    let samples = sample_offsets.iter().map(|&offset| load_it_sample(data, offset)).collect::<GameResult<Vec<_>>>()?;
+
+    let instruments = if flags & 4 != 0 {
+        if compatible_with < 0x200 {
+            return Err(GameError::ResourceLoadError("IT instruments older than 2.00 aren't supported.".to_string()));
+        }
+
+        instrument_offsets
+            .iter()
+            .map(|&offset| load_it_instrument(data, offset, samples.len()))
+            .collect::<GameResult<Vec<_>>>()?
+    } else {
+        samples.iter().enumerate().map(|(i, sample)| Instrument::from_sample(sample.name.clone(), i)).collect()
+    };
+
+    let mut patterns = Vec::with_capacity(pattern_count);
+    let mut channels = 1;
+    for &offset in &pattern_offsets {
+        let (pattern, used_channels) = load_it_pattern(data, offset)?;
+        channels = channels.max(used_channels);
+        patterns.push(pattern);
+    }
+
+    // patterns are loaded with all 64 channels, drop the unused ones
+    for pattern in &mut patterns {
+        let mut cells = Vec::with_capacity(pattern.rows * channels);
+        for row in pattern.cells.chunks_exact(pattern.channels) {
+            cells.extend_from_slice(&row[..channels]);
+        }
+
+        pattern.cells = cells;
+        pattern.channels = channels;
+    }
+
+    Ok(Module {
+        name,
+        format: ModuleFormat::It,
+        channels,
+        orders,
+        restart_position: 0,
+        patterns,
+        instruments,
+        samples,
+        initial_speed: speed.max(1),
+        initial_tempo: tempo.max(32),
+        initial_global_volume: global_volume.min(128),
+        linear_slides: flags & 8 != 0,
+        channel_panning: channel_panning[..channels]
+            .iter()
+            .map(|&p| match p & 0x7f {
+                // surround
+                100 => 128,
+                p => it_panning(p),
+            })
+            .collect(),
+        channel_volume: channel_volume[..channels]
+            .iter()
+            .zip(channel_panning.iter())
+            // channels with the highest bit of the panning set are disabled
+            .map(|(&v, &p)| if p & 0x80 != 0 { 0 } else { v.min(64) })
+            .collect(),
+    })
+}
+
+fn load_it_sample(data: &[u8], offset: u64) -> GameResult<Sample> {
+    let mut f = Cursor::new(data);
+    f.seek(SeekFrom::Start(offset))?;
+
+    let mut magic = [0u8; 4];
+    f.read_exact(&mut magic)?;
+    if &magic != b"IMPS" {
+        return Err(GameError::ResourceLoadError("Invalid IT sample header.".to_string()));
+    }
+
+    f.seek(SeekFrom::Start(offset + 0x11))?;
+    let global_volume = f.read_u8()?.min(64);
+    let flags = f.read_u8()?;
+    let volume = f.read_u8()?.min(64);
+    let name = read_string(&mut f, 26)?;
+    let convert = f.read_u8()?;
+    let panning = f.read_u8()?;
+    let length = f.read_u32::<LE>()? as usize;
+    let loop_start = f.read_u32::<LE>()? as usize;
+    let loop_end = f.read_u32::<LE>()? as usize;
+    let c5_speed = f.read_u32::<LE>()?;
+    let sustain_start = f.read_u32::<LE>()? as usize;
+    let sustain_end = f.read_u32::<LE>()? as usize;
+    let pointer = f.read_u32::<LE>()? as usize;
+    let vibrato_speed = f.read_u8()?;
+    let vibrato_depth = f.read_u8()?;
+    let vibrato_rate = f.read_u8()?;
+    let vibrato_type = f.read_u8()?;
+
+    if length > MAX_SAMPLE_LENGTH {
+        return Err(GameError::ResourceLoadError(format!("IT sample is too long: {} frames.", length)));
+    }
+
+    let is_16bit = flags & 2 != 0;
+    let sample_data = if flags & 1 == 0 || length == 0 {
+        Vec::new()
+    } else {
+        // truncated files keep the part of the sample that's there
+        let raw = data.get(pointer..).unwrap_or_default();
+
+        if flags & 8 != 0 {
+            decompress_it_sample(raw, length, is_16bit, convert & 4 != 0)
+        } else {
+            // stereo samples store the left channel first, only that one is used
+            let signed = convert & 1 != 0;
+            if is_16bit {
+                raw.chunks_exact(2)
+                    .take(length)
+                    .map(|s| {
+                        let s = u16::from_le_bytes([s[0], s[1]]);
+                        (if signed { s } else { s ^ 0x8000 }) as i16
+                    })
+                    .collect()
+            } else {
+                raw.iter().take(length).map(|&s| ((if signed { s } else { s ^ 0x80 }) as i8 as i16) << 8).collect()
+            }
+        }
+    };
+
+    let sample_loop = if flags & 0x10 != 0 {
+        SampleLoop::new(loop_start, loop_end, flags & 0x40 != 0, sample_data.len())
+    } else {
+        None
+    };
+    let sustain_loop = if flags & 0x20 != 0 {
+        SampleLoop::new(sustain_start, sustain_end, flags & 0x80 != 0, sample_data.len())
+    } else {
+        None
+    };
+
+    Ok(Sample {
+        name,
+        data: sample_data,
+        c5_speed: if c5_speed == 0 { DEFAULT_C5_SPEED } else { c5_speed as f32 },
+        volume,
+        global_volume,
+        panning: if panning & 0x80 != 0 { Some(it_panning(panning & 0x7f)) } else { None },
+        sample_loop,
+        sustain_loop,
+        vibrato: AutoVibrato {
+            waveform: match vibrato_type {
+                1 => Waveform::RampDown,
+                2 => Waveform::Square,
+                3 => Waveform::Random,
+                _ => Waveform::Sine,
+            },
+            speed: vibrato_speed,
+            depth: vibrato_depth as f32 / 4.0,
+            // the rate is how much the depth grows every tick, in 1/256ths
+            sweep: if vibrato_rate == 0 {
+                0
+            } else {
+                (vibrato_depth as u32 * 256 / vibrato_rate as u32).min(u16::MAX as u32) as u16
+            },
+        },
+    })
+}
+
+fn load_it_instrument(data: &[u8], offset: u64, sample_count: usize) -> GameResult<Instrument> {
+    let mut f = Cursor::new(data);
+    f.seek(SeekFrom::Start(offset))?;
+
+    let mut magic = [0u8; 4];
+    f.read_exact(&mut magic)?;
+    if &magic != b"IMPI" {
+        return Err(GameError::ResourceLoadError("Invalid IT instrument header.".to_string()));
+    }
+
+    f.seek(SeekFrom::Start(offset + 0x11))?;
+    let new_note_action = match f.read_u8()? {
+        1 => NewNoteAction::Continue,
+        2 => NewNoteAction::Off,
+        3 => NewNoteAction::Fade,
+        _ => NewNoteAction::Cut,
+    };
+    let _duplicate_check_type = f.read_u8()?;
+    let _duplicate_check_action = f.read_u8()?;
+    let fadeout = f.read_u16::<LE>()? as u32;
+    let _pitch_pan_separation = f.read_i8()?;
+    let _pitch_pan_center = f.read_u8()?;
+    let global_volume = f.read_u8()?.min(128);
+    let panning = f.read_u8()?;
+
+    f.seek(SeekFrom::Start(offset + 0x20))?;
+    let name = read_string(&mut f, 26)?;
+
+    f.seek(SeekFrom::Start(offset + 0x40))?;
+    let mut keymap = Vec::with_capacity(NOTE_MAX as usize);
+    for _ in 0..NOTE_MAX {
+        let note = f.read_u8()?;
+        let sample = f.read_u8()? as usize;
+
+        keymap.push((
+            note.min(NOTE_MAX - 1) + NOTE_MIN,
+            if sample > 0 && sample <= sample_count { Some(sample - 1) } else { None },
+        ));
+    }
+
+    let volume_envelope = read_it_envelope(&mut f, 0)?;
+    let panning_envelope = read_it_envelope(&mut f, 32)?;
+
+    Ok(Instrument {
+        name,
+        keymap,
+        volume_envelope,
+        panning_envelope,
+        fadeout: fadeout * 32,
+        global_volume,
+        panning: if panning & 0x80 == 0 { Some(it_panning(panning)) } else { None },
+        new_note_action,
+    })
+}
+
+/// Reads an envelope, `center` is added to the values so they fit in 0..=64.
+fn read_it_envelope(f: &mut Cursor<&[u8]>, center: i32) -> GameResult<Envelope> {
+    let flags = f.read_u8()?;
+    let count = f.read_u8()?.min(25) as usize;
+    let loop_start = f.read_u8()?;
+    let loop_end = f.read_u8()?;
+    let sustain_start = f.read_u8()?;
+    let sustain_end = f.read_u8()?;
+
+    let mut points = Vec::with_capacity(25);
+    for _ in 0..25 {
+        let value = (f.read_i8()? as i32 + center).clamp(0, 64) as u8;
+        let tick = f.read_u16::<LE>()?;
+        points.push(EnvelopePoint { tick, value });
+    }
+    points.truncate(count);
+    let _reserved = f.read_u8()?;
+
+    Ok(Envelope::new(
+        flags & 1 != 0,
+        points,
+        Some((sustain_start, sustain_end)).filter(|_| flags & 4 != 0),
+        Some((loop_start, loop_end)).filter(|_| flags & 2 != 0),
+    ))
+}
+
+/// Loads a pattern with all 64 channels, also returns how many of them are used.
+fn load_it_pattern(data: &[u8], offset: u64) -> GameResult<(Pattern, usize)> {
+    if offset == 0 {
+        return Ok((Pattern::new(64, 64), 0));
+    }
+
+    let mut f = Cursor::new(data);
+    f.seek(SeekFrom::Start(offset))?;
+    let _length = f.read_u16::<LE>()?;
+    let rows = (f.read_u16::<LE>()? as usize).max(1);
+    if rows > MAX_ROWS {
+        return Err(GameError::ResourceLoadError(format!("Invalid IT pattern row count: {}", rows)));
+    }
+    f.seek(SeekFrom::Current(4))?;
+
+    let mut pattern = Pattern::new(rows, 64);
+    let mut used_channels = 0;
+    let mut last_mask = [0u8; 64];
+    let mut last_note = [0u8; 64];
+    let mut last_instrument = [0u8; 64];
+    let mut last_volume = [0u8; 64];
+    let mut last_effect = [(0u8, 0u8); 64];
+
+    let mut row = 0;
+    while row < rows {
+        let channel_variable = f.read_u8()?;
+        if channel_variable == 0 {
+            row += 1;
+            continue;
+        }
+
+        let channel = (channel_variable as usize - 1) & 63;
+        let mask = if channel_variable & 0x80 != 0 { f.read_u8()? } else { last_mask[channel] };
+        last_mask[channel] = mask;
+
+        if mask & 0x01 != 0 {
+            last_note[channel] = f.read_u8()?;
+        }
+        if mask & 0x02 != 0 {
+            last_instrument[channel] = f.read_u8()?;
+        }
+        if mask & 0x04 != 0 {
+            last_volume[channel] = f.read_u8()?;
+        }
+        if mask & 0x08 != 0 {
+            last_effect[channel] = (f.read_u8()?, f.read_u8()?);
+        }
+
+        let cell = &mut pattern.cells[row * 64 + channel];
+        if mask & 0x11 != 0 {
+            cell.note = match last_note[channel] {
+                note @ 0..=119 => note + NOTE_MIN,
+                255 => NOTE_OFF,
+                254 => NOTE_CUT,
+                _ => NOTE_FADE,
+            };
+        }
+        if mask & 0x22 != 0 {
+            cell.instrument = last_instrument[channel];
+        }
+        if mask & 0x44 != 0 {
+            cell.volume = it_volume(last_volume[channel]);
+        }
+        if mask & 0x88 != 0 {
+            let (command, param) = last_effect[channel];
+            cell.effect = it_effect(command, param);
+        }
+
+        used_channels = used_channels.max(channel + 1);
+    }
+
+    Ok((pattern, used_channels))
+}
+
+/// Reads the bits of IT compressed samples, least significant bit first.
+struct BitReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+    bit: u32,
+}
+
+impl BitReader<'_> {
+    fn read(&mut self, count: u32) -> u32 {
+        let mut value = 0;
+
+        for i in 0..count {
+            let byte = self.data.get(self.pos).copied().unwrap_or(0);
+            value |= ((byte as u32 >> self.bit) & 1) << i;
+
+            self.bit += 1;
+            if self.bit == 8 {
+                self.bit = 0;
+                self.pos += 1;
+            }
+        }
+
+        value
+    }
+}
+
+fn sign_extend(value: u32, bits: u32) -> i32 {
+    ((value << (32 - bits)) as i32) >> (32 - bits)
+}
+
+/// Decompresses IT 2.14 and 2.15 compressed samples, stops early if `data` is truncated or corrupted.
+fn decompress_it_sample(data: &[u8], length: usize, is_16bit: bool, it215: bool) -> Vec<i16> {
+    let (block_size, sample_bits, width_bits) = if is_16bit { (0x4000, 16, 4) } else { (0x8000, 8, 3) };
+    let max_width = sample_bits + 1;
+    // every sample takes at least a bit
+    let length = length.min(data.len().saturating_mul(8));
+
+    let mut output = Vec::with_capacity(length);
+    let mut pos = 0;
+
+    while output.len() < length {
+        let block_length = match data.get(pos..pos + 2) {
+            Some(b) => u16::from_le_bytes([b[0], b[1]]) as usize,
+            None => break,
+        };
+        let block = &data[(pos + 2).min(data.len())..(pos + 2 + block_length).min(data.len())];
+        pos += 2 + block_length;
+
+        let mut bits = BitReader { data: block, pos: 0, bit: 0 };
+        let count = block_size.min(length - output.len());
+        let mut width = max_width;
+        let (mut d1, mut d2) = (0i32, 0i32);
+        let mut decoded = 0;
+
+        while decoded < count && bits.pos < block.len() {
+            if width == 0 || width > max_width {
+                break;
+            }
+
+            let value = bits.read(width);
+
+            if width < 7 {
+                if value == 1 << (width - 1) {
+                    let new_width = bits.read(width_bits) + 1;
+                    width = if new_width < width { new_width } else { new_width + 1 };
+                    continue;
+                }
+            } else if width < max_width {
+                let border = ((1u32 << sample_bits) - 1) >> (max_width - width);
+                let border = border - if is_16bit { 8 } else { 4 };
+
+                if value > border && value <= border + if is_16bit { 16 } else { 8 } {
+                    let new_width = value - border;
+                    width = if new_width < width { new_width } else { new_width + 1 };
+                    continue;
+                }
+            } else if value & (1 << sample_bits) != 0 {
+                width = (value + 1) & 0xff;
+                continue;
+            }
+
+            let delta = sign_extend(value, width.min(sample_bits));
+            d1 = sign_extend(d1.wrapping_add(delta) as u32, sample_bits);
+            d2 = sign_extend(d2.wrapping_add(d1) as u32, sample_bits);
+
+            let sample = if it215 { d2 } else { d1 };
+            output.push(if is_16bit { sample as i16 } else { (sample as i16) << 8 });
+            decoded += 1;
+        }
+
+        if decoded < count {
+            break;
+        }
+    }
+
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use byteorder::{LE, WriteBytesExt};
+
+    use super::*;
+
+    fn pad_to(buf: &mut Vec<u8>, len: usize) {
+        assert!(buf.len() <= len);
+        buf.resize(len, 0);
+    }
+
+    /// Two channels, one two row pattern and an instrument with a looped 8-bit sample and a volume envelope.
+    fn xm_module() -> Vec<u8> {
+        let mut b = Vec::new();
+        b.extend_from_slice(b"Extended Module: test");
+        pad_to(&mut b, 37);
+        b.push(0x1a);
+        pad_to(&mut b, 58);
+        b.write_u16::<LE>(0x0104).unwrap();
+        b.write_u32::<LE>(276).unwrap();
+        for value in [1, 0, 2, 1, 1, 1, 5, 150] {
+            b.write_u16::<LE>(value).unwrap();
+        }
+        pad_to(&mut b, 60 + 276);
+
+        // C-4, instrument 1, F03 on the first row, key off on the second one
+        let pattern = [0x9b, 49, 1, 0x0f, 3, 0x80, 0x81, 97, 0x80];
+        b.write_u32::<LE>(9).unwrap();
+        b.push(0);
+        b.write_u16::<LE>(2).unwrap();
+        b.write_u16::<LE>(pattern.len() as u16).unwrap();
+        b.extend_from_slice(&pattern);
+
+        let start = b.len();
+        b.write_u32::<LE>(263).unwrap();
+        b.extend_from_slice(&[0; 22]);
+        b.push(0);
+        b.write_u16::<LE>(1).unwrap();
+        b.write_u32::<LE>(40).unwrap();
+        b.extend_from_slice(&[0; 96]);
+        for (tick, value) in [(0, 64), (10, 0)] {
+            b.write_u16::<LE>(tick).unwrap();
+            b.write_u16::<LE>(value).unwrap();
+        }
+        pad_to(&mut b, start + 225);
+        // two volume envelope points with a sustain point on the first one
+        b.extend_from_slice(&[2, 0, 0, 0, 0, 0, 0, 0, 3, 0, 0, 0, 0, 0]);
+        b.write_u16::<LE>(512).unwrap();
+        pad_to(&mut b, start + 263);
+
+        b.write_u32::<LE>(16).unwrap();
+        b.write_u32::<LE>(0).unwrap();
+        b.write_u32::<LE>(16).unwrap();
+        b.extend_from_slice(&[64, 0, 1, 128, 0, 0]);
+        b.extend_from_slice(&[0; 22]);
+        // delta encoded square wave
+        b.extend_from_slice(&[32, 0, 0, 0, 0, 0, 0, 0, 192, 0, 0, 0, 0, 0, 0, 0]);
+
+        b
+    }
+
+    struct BitWriter {
+        data: Vec<u8>,
+        bit: u32,
+    }
+
+    impl BitWriter {
+        fn write(&mut self, value: u32, count: u32) {
+            for i in 0..count {
+                if self.bit == 0 {
+                    self.data.push(0);
+                }
+
+                *self.data.last_mut().unwrap() |= (((value >> i) & 1) as u8) << self.bit;
+                self.bit = (self.bit + 1) % 8;
+            }
+        }
+    }
+
+    /// One instrument playing the second of two samples, the first one is 8-bit PCM and the second one
+    /// IT 2.14 compressed.
+    fn it_module() -> Vec<u8> {
+        let mut b = Vec::new();
+        b.extend_from_slice(b"IMPMtest");
+        pad_to(&mut b, 0x20);
+        for value in [2, 1, 2, 1, 0x0214, 0x0214, 4 | 8, 0] {
+            b.write_u16::<LE>(value).unwrap();
+        }
+        b.extend_from_slice(&[128, 48, 6, 125]);
+        pad_to(&mut b, 0x40);
+        b.extend_from_slice(&[32; 64]);
+        b.extend_from_slice(&[64; 64]);
+        b.extend_from_slice(&[0, 255]);
+
+        let offsets = b.len();
+        b.extend_from_slice(&[0; 16]);
+        let set_offset = |b: &mut Vec<u8>, index: usize| {
+            let offset = b.len() as u32;
+            b[offsets + index * 4..offsets + index * 4 + 4].copy_from_slice(&offset.to_le_bytes());
+        };
+
+        set_offset(&mut b, 0);
+        let start = b.len();
+        b.extend_from_slice(b"IMPI");
+        pad_to(&mut b, start + 0x11);
+        b.push(3);
+        b.extend_from_slice(&[0, 0]);
+        b.write_u16::<LE>(256).unwrap();
+        b.extend_from_slice(&[0, 0, 128, 32 | 0x80]);
+        pad_to(&mut b, start + 0x40);
+        for note in 0..120 {
+            b.extend_from_slice(&[note, 2]);
+        }
+        pad_to(&mut b, start + 554);
+
+        for (index, compressed) in [(1, false), (2, true)] {
+            set_offset(&mut b, index);
+            let start = b.len();
+            b.extend_from_slice(b"IMPS");
+            pad_to(&mut b, start + 0x11);
+            b.extend_from_slice(&[64, if compressed { 1 | 8 } else { 1 | 0x10 }, 64]);
+            pad_to(&mut b, start + 0x2e);
+            b.extend_from_slice(&[1, 0]);
+            for value in [if compressed { 6 } else { 4 }, 0, 4, 8363, 0, 0, start as u32 + 0x50] {
+                b.write_u32::<LE>(value).unwrap();
+            }
+            b.extend_from_slice(&[0; 4]);
+
+            if compressed {
+                // three 9-bit deltas, a switch to 4-bit deltas and three of them
+                let mut bits = BitWriter { data: Vec::new(), bit: 0 };
+                for delta in [16, 16, 0xe0, 0x103] {
+                    bits.write(delta, 9);
+                }
+                for delta in [3, 0x0f, 2] {
+                    bits.write(delta, 4);
+                }
+
+                b.write_u16::<LE>(bits.data.len() as u16).unwrap();
+                b.extend_from_slice(&bits.data);
+            } else {
+                b.extend_from_slice(&[0x40, 0x40, 0xc0, 0xc0]);
+            }
+        }
+
+        // C-5, instrument 1, A04 on the first row of the first channel, note off on the second row of the second one
+        set_offset(&mut b, 3);
+        let pattern = [0x81, 0x0b, 60, 1, 1, 4, 0, 0x82, 0x01, 255, 0];
+        b.write_u16::<LE>(pattern.len() as u16).unwrap();
+        b.write_u16::<LE>(2).unwrap();
+        b.extend_from_slice(&[0; 4]);
+        b.extend_from_slice(&pattern);
+
+        b
+    }
+
+    #[test]
+    fn test_load_xm() {
+        let module = Module::load_from(&xm_module()[..]).unwrap();
+
+        assert_eq!(module.format, ModuleFormat::Xm);
+        assert_eq!(module.channels, 2);
+        assert_eq!(module.orders, [0]);
+        assert_eq!((module.initial_speed, module.initial_tempo), (5, 150));
+        assert!(module.linear_slides);
+
+        let pattern = &module.patterns[0];
+        assert_eq!(pattern.rows, 2);
+        assert_eq!(pattern.cell(0, 0).note, NOTE_MIDDLE_C);
+        assert_eq!(pattern.cell(0, 0).instrument, 1);
+        assert_eq!(pattern.cell(0, 0).effect, Effect::SetSpeed(3));
+        assert_eq!(pattern.cell(1, 0).note, NOTE_OFF);
+        assert_eq!(pattern.cell(1, 1), Cell::default());
+
+        let instrument = &module.instruments[0];
+        assert_eq!(instrument.keymap[NOTE_MIDDLE_C as usize - 1], (NOTE_MIDDLE_C, Some(0)));
+        assert_eq!(instrument.fadeout, 1024);
+        assert!(instrument.volume_envelope.enabled);
+        assert_eq!(instrument.volume_envelope.points.len(), 2);
+        assert_eq!(instrument.volume_envelope.sustain, Some((0, 0)));
+
+        let sample = &module.samples[0];
+        assert_eq!(sample.data.len(), 16);
+        assert_eq!((sample.data[0], sample.data[7], sample.data[8]), (32 << 8, 32 << 8, -32 << 8));
+        assert_eq!(sample.sample_loop, Some(SampleLoop { start: 0, end: 16, ping_pong: false }));
+        assert_eq!(sample.panning, Some(128));
+    }
+
+    #[test]
+    fn test_load_it() {
+        let module = Module::load_from(&it_module()[..]).unwrap();
+
+        assert_eq!(module.format, ModuleFormat::It);
+        assert_eq!(module.channels, 2);
+        assert_eq!(module.orders, [0, ORDER_END]);
+        assert_eq!((module.initial_speed, module.initial_tempo, module.initial_global_volume), (6, 125, 128));
+
+        let pattern = &module.patterns[0];
+        assert_eq!(pattern.channels, 2);
+        assert_eq!(pattern.cell(0, 0).note, NOTE_MIDDLE_C);
+        assert_eq!(pattern.cell(0, 0).effect, Effect::SetSpeed(4));
+        assert_eq!(pattern.cell(1, 1).note, NOTE_OFF);
+
+        let instrument = &module.instruments[0];
+        assert_eq!(instrument.new_note_action, NewNoteAction::Fade);
+        assert_eq!(instrument.fadeout, 256 * 32);
+        assert_eq!(instrument.panning, None);
+        assert_eq!(instrument.keymap[NOTE_MIDDLE_C as usize - 1], (NOTE_MIDDLE_C, Some(1)));
+
+        assert_eq!(module.samples[0].data, [0x4000, 0x4000, -0x4000, -0x4000]);
+        assert_eq!(module.samples[0].sample_loop, Some(SampleLoop { start: 0, end: 4, ping_pong: false }));
+        assert_eq!(module.samples[1].data, [16 << 8, 32 << 8, 0, 3 << 8, 2 << 8, 4 << 8]);
+    }
+
+    #[test]
+    fn test_it_sample_length() {
+        let mut data = it_module();
+        let sample = u32::from_le_bytes(data[0xc2 + 8..0xc2 + 12].try_into().unwrap()) as usize;
+
+        // longer than the data that's there
+        data[sample + 0x30..sample + 0x34].copy_from_slice(&1000u32.to_le_bytes());
+        assert_eq!(Module::load_from(&data[..]).unwrap().samples[1].data.len(), 6);
+
+        data[sample + 0x30..sample + 0x34].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert!(Module::load_from(&data[..]).is_err());
+    }
+
+    #[test]
+    fn test_load_broken_modules() {
+        for data in [xm_module(), it_module()] {
+            // cut anywhere, headers are required but samples may be incomplete
+            for length in 0..data.len() {
+                let _ = Module::load_from(&data[..length]);
+            }
+            assert!(Module::load_from(&data[..64]).is_err());
+
+            // corrupted counts and offsets
+            for i in 0..data.len().min(0x100) {
+                let mut data = data.clone();
+                data[i] = 0xff;
+                let _ = Module::load_from(&data[..]);
+            }
+        }
+
+        let mut data = xm_module();
+        data[70..72].copy_from_slice(&0xffffu16.to_le_bytes());
+        assert!(Module::load_from(&data[..]).is_err());
+    }
+}
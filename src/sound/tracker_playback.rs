@@ -0,0 +1,1255 @@
+use std::f32::consts::TAU;
+use std::sync::Arc;
+
+use crate::sound::tracker::*;
+use crate::sound::wav::WavFormat;
+use crate::sound::SongPosition;
+
+/// Amiga period of middle C, in the quarter periods used by XM.
+const AMIGA_C5_PERIOD: f32 = 1712.0;
+/// Linear periods are in 1/64 semitones, counting down from above the highest note.
+const LINEAR_PERIOD_TOP: f32 = (NOTE_MAX as f32 + 1.0) * 64.0;
+const MAX_BACKGROUND_VOICES: usize = 64;
+/// Length of the volume ramps used to avoid clicks, in frames.
+const RAMP_FRAMES: usize = 64;
+const FADE_VOLUME_MAX: i32 = 65536;
+
+fn note_period(linear: bool, note: u8, c5_speed: f32) -> f32 {
+    if linear {
+        LINEAR_PERIOD_TOP - note as f32 * 64.0
+    } else {
+        AMIGA_C5_PERIOD * 2f32.powf((NOTE_MIDDLE_C as f32 - note as f32) / 12.0) * 8363.0 / c5_speed
+    }
+}
+
+fn period_frequency(linear: bool, period: f32, c5_speed: f32) -> f32 {
+    if linear {
+        c5_speed * 2f32.powf((LINEAR_PERIOD_TOP - NOTE_MIDDLE_C as f32 * 64.0 - period) / 768.0)
+    } else {
+        8363.0 * AMIGA_C5_PERIOD / period
+    }
+}
+
+fn clamp_period(linear: bool, period: f32) -> f32 {
+    if linear {
+        period.clamp(0.0, LINEAR_PERIOD_TOP * 2.0)
+    } else {
+        period.clamp(4.0, 131072.0)
+    }
+}
+
+/// Shifts the period by `semitones`, used by arpeggios.
+fn transpose_period(linear: bool, period: f32, semitones: u8) -> f32 {
+    if linear {
+        period - semitones as f32 * 64.0
+    } else {
+        period * 2f32.powf(-(semitones as f32) / 12.0)
+    }
+}
+
+/// Returns the value of the waveform in -1.0..=1.0, `phase` is in 0.0..1.0.
+fn waveform_value(waveform: Waveform, phase: f32, random: &mut u32) -> f32 {
+    match waveform {
+        Waveform::Sine => (phase * TAU).sin(),
+        Waveform::RampDown => 1.0 - phase * 2.0,
+        Waveform::RampUp => phase * 2.0 - 1.0,
+        Waveform::Square => {
+            if phase < 0.5 {
+                1.0
+            } else {
+                -1.0
+            }
+        }
+        Waveform::Random => {
+            *random = random.wrapping_mul(1103515245).wrapping_add(12345);
+            ((*random >> 16) & 0x7fff) as f32 / 16384.0 - 1.0
+        }
+    }
+}
+
+/// Splits a slide parameter into the change applied on the first tick and on every other tick.
+fn slide_amounts(format: ModuleFormat, param: u8) -> (i32, i32) {
+    let (x, y) = ((param >> 4) as i32, (param & 0x0f) as i32);
+
+    match format {
+        // IT uses xF and Fx for fine slides, 0F and F0 are regular slides
+        ModuleFormat::It => match (x, y) {
+            (0x0f, 0) => (0, 15),
+            (0, 0x0f) => (0, -15),
+            (0x0f, y) => (-y, 0),
+            (x, 0x0f) => (x, 0),
+            (x, 0) => (0, x),
+            (0, y) => (0, -y),
+            _ => (0, 0),
+        },
+        _ => {
+            if x > 0 {
+                (0, x)
+            } else {
+                (0, -y)
+            }
+        }
+    }
+}
+
+fn retrigger_volume(volume: i32, change: u8) -> i32 {
+    let volume = match change {
+        1..=5 => volume - (1 << (change - 1)),
+        6 => volume * 2 / 3,
+        7 => volume / 2,
+        9..=13 => volume + (1 << (change - 9)),
+        14 => volume * 3 / 2,
+        15 => volume * 2,
+        _ => volume,
+    };
+
+    volume.clamp(0, 64)
+}
+
+#[derive(Clone, Copy, Default)]
+struct EnvelopePosition {
+    tick: u16,
+    finished: bool,
+}
+
+impl EnvelopePosition {
+    /// Returns the value at the current position and moves to the next tick.
+    fn advance(&mut self, envelope: &Envelope, key_on: bool) -> f32 {
+        let points = &envelope.points;
+        let value = match points.iter().position(|p| p.tick > self.tick) {
+            Some(0) => points[0].value as f32,
+            Some(i) => {
+                let (a, b) = (points[i - 1], points[i]);
+                let t = (self.tick - a.tick) as f32 / (b.tick - a.tick) as f32;
+                a.value as f32 + (b.value as f32 - a.value as f32) * t
+            }
+            None => points.last().map_or(64.0, |p| p.value as f32),
+        };
+
+        if let Some((start, end)) = envelope.sustain.filter(|_| key_on) {
+            if self.tick == points[end].tick {
+                self.tick = points[start].tick;
+                return value;
+            }
+        }
+
+        self.tick = self.tick.saturating_add(1);
+
+        if let Some((start, end)) = envelope.loop_range {
+            if self.tick > points[end].tick {
+                self.tick = points[start].tick;
+            }
+        }
+
+        if let Some(last) = points.last() {
+            if self.tick > last.tick {
+                self.tick = last.tick;
+                self.finished = true;
+            }
+        }
+
+        value
+    }
+}
+
+/// A playing sample, either owned by a channel or left playing in the background by new note actions.
+#[derive(Clone, Default)]
+struct Voice {
+    active: bool,
+    /// Ramps the volume down and stops.
+    stopping: bool,
+    channel: usize,
+    instrument: Option<usize>,
+    sample: Option<usize>,
+    position: f64,
+    backwards: bool,
+    step: f64,
+    key_on: bool,
+    fading: bool,
+    fade_volume: i32,
+    volume_envelope: EnvelopePosition,
+    panning_envelope: EnvelopePosition,
+    vibrato_position: u8,
+    vibrato_ticks: u32,
+    /// 0.0..=1.0, set by the channel.
+    volume: f32,
+    /// 0.0..=255.0, set by the channel.
+    panning: f32,
+    period: f32,
+    c5_speed: f32,
+    gain: (f32, f32),
+    target_gain: (f32, f32),
+    ramp_step: (f32, f32),
+    ramp_frames: usize,
+}
+
+impl Voice {
+    fn new(channel: usize, instrument: usize, sample: usize, c5_speed: f32) -> Voice {
+        Voice {
+            active: true,
+            channel,
+            instrument: Some(instrument),
+            sample: Some(sample),
+            key_on: true,
+            fade_volume: FADE_VOLUME_MAX,
+            c5_speed,
+            ..Voice::default()
+        }
+    }
+
+    fn restart(&mut self) {
+        self.position = 0.0;
+        self.backwards = false;
+        self.key_on = true;
+        self.fading = false;
+        self.fade_volume = FADE_VOLUME_MAX;
+        self.volume_envelope = EnvelopePosition::default();
+        self.panning_envelope = EnvelopePosition::default();
+    }
+
+    fn key_off(&mut self, module: &Module) {
+        self.key_on = false;
+
+        let instrument = self.instrument.and_then(|i| module.instruments.get(i));
+        let envelope = instrument.map(|i| &i.volume_envelope).filter(|e| e.enabled);
+
+        match module.format {
+            ModuleFormat::It => {
+                if envelope.is_none_or(|e| e.loop_range.is_some()) {
+                    self.fading = true;
+                }
+            }
+            _ => {
+                if envelope.is_some() {
+                    self.fading = true;
+                } else {
+                    self.stopping = true;
+                }
+            }
+        }
+    }
+
+    /// Advances the envelopes and auto vibrato by a tick and updates the frequency and volume.
+    fn update(&mut self, module: &Module, global_volume: f32, sample_rate: f32, ramp: usize, random: &mut u32) {
+        if !self.active {
+            return;
+        }
+
+        let sample = match self.sample.and_then(|s| module.samples.get(s)) {
+            Some(sample) => sample,
+            None => {
+                self.active = false;
+                return;
+            }
+        };
+
+        let mut volume = self.volume * global_volume * sample.global_volume as f32 / 64.0;
+        let mut panning = self.panning;
+
+        if let Some(instrument) = self.instrument.and_then(|i| module.instruments.get(i)) {
+            volume *= instrument.global_volume as f32 / 128.0;
+
+            if instrument.volume_envelope.enabled {
+                let value = self.volume_envelope.advance(&instrument.volume_envelope, self.key_on);
+                volume *= value / 64.0;
+
+                if self.volume_envelope.finished {
+                    if value <= 0.0 {
+                        self.stopping = true;
+                    } else if module.format == ModuleFormat::It && !self.key_on {
+                        self.fading = true;
+                    }
+                }
+            }
+
+            if instrument.panning_envelope.enabled {
+                let value = self.panning_envelope.advance(&instrument.panning_envelope, self.key_on) - 32.0;
+                panning += value * (128.0 - (panning - 128.0).abs()) / 32.0;
+            }
+
+            if self.fading {
+                volume *= self.fade_volume as f32 / FADE_VOLUME_MAX as f32;
+                self.fade_volume -= instrument.fadeout as i32;
+
+                if self.fade_volume <= 0 {
+                    self.fade_volume = 0;
+                    self.stopping = true;
+                }
+            }
+        }
+
+        let mut period = self.period;
+        let vibrato = &sample.vibrato;
+        if vibrato.depth > 0.0 {
+            let mut depth = vibrato.depth;
+            if vibrato.sweep > 0 {
+                depth *= (self.vibrato_ticks as f32 / vibrato.sweep as f32).min(1.0);
+            }
+
+            period += waveform_value(vibrato.waveform, self.vibrato_position as f32 / 256.0, random) * depth;
+            self.vibrato_position = self.vibrato_position.wrapping_add(vibrato.speed);
+            self.vibrato_ticks += 1;
+        }
+
+        let period = clamp_period(module.linear_slides, period);
+        self.step = period_frequency(module.linear_slides, period, self.c5_speed) as f64 / sample_rate as f64;
+
+        let target = if self.stopping {
+            (0.0, 0.0)
+        } else {
+            let panning = panning.clamp(0.0, 255.0);
+            (volume * ((255.0 - panning) / 128.0).min(1.0), volume * (panning / 128.0).min(1.0))
+        };
+
+        let ramp = ramp.max(1);
+        self.target_gain = target;
+        self.ramp_step = ((target.0 - self.gain.0) / ramp as f32, (target.1 - self.gain.1) / ramp as f32);
+        self.ramp_frames = ramp;
+    }
+
+    /// Adds the voice to interleaved stereo `buf`.
+    fn mix(&mut self, module: &Module, buf: &mut [f32]) {
+        if !self.active {
+            return;
+        }
+
+        let sample = match self.sample.and_then(|s| module.samples.get(s)) {
+            Some(sample) if !sample.data.is_empty() => sample,
+            _ => {
+                self.active = false;
+                return;
+            }
+        };
+        let data = &sample.data;
+        let length = data.len();
+
+        for frame in buf.chunks_exact_mut(2) {
+            if self.ramp_frames > 0 {
+                self.ramp_frames -= 1;
+                if self.ramp_frames == 0 {
+                    self.gain = self.target_gain;
+                } else {
+                    self.gain.0 += self.ramp_step.0;
+                    self.gain.1 += self.ramp_step.1;
+                }
+            } else if self.stopping {
+                self.active = false;
+                return;
+            }
+
+            let sample_loop = if self.key_on { sample.sustain_loop.or(sample.sample_loop) } else { sample.sample_loop };
+
+            let index = (self.position as usize).min(length - 1);
+            let next = match sample_loop {
+                Some(l) if !l.ping_pong && index + 1 >= l.end => data[l.start],
+                _ => data[(index + 1).min(length - 1)],
+            };
+            let s1 = data[index] as f32;
+            let s = s1 + (next as f32 - s1) * (self.position - index as f64) as f32;
+
+            frame[0] += s * self.gain.0;
+            frame[1] += s * self.gain.1;
+
+            if self.backwards {
+                self.position -= self.step;
+            } else {
+                self.position += self.step;
+            }
+
+            match sample_loop {
+                Some(l) => {
+                    let (start, end) = (l.start as f64, l.end as f64);
+
+                    if l.ping_pong {
+                        if !self.backwards && self.position >= end {
+                            self.position = end - (self.position - end);
+                            self.backwards = true;
+                        }
+                        if self.backwards && self.position < start {
+                            self.position = start + (start - self.position);
+                            self.backwards = false;
+                        }
+                        self.position = self.position.clamp(start, end - 0.001);
+                    } else if self.position >= end {
+                        self.position = start + (self.position - start) % (end - start);
+                    }
+                }
+                None => {
+                    if self.position >= length as f64 {
+                        self.active = false;
+                        return;
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Plays the voice of a note replaced by a new one in the background, according to the new note action.
+fn push_background_voice(voices: &mut Vec<Voice>, mut voice: Voice, action: NewNoteAction, module: &Module) {
+    match action {
+        NewNoteAction::Cut => voice.stopping = true,
+        NewNoteAction::Continue => {}
+        NewNoteAction::Off => voice.key_off(module),
+        NewNoteAction::Fade => voice.fading = true,
+    }
+
+    if voices.len() >= MAX_BACKGROUND_VOICES {
+        voices.remove(0);
+    }
+
+    voices.push(voice);
+}
+
+#[derive(Clone, Default)]
+struct Channel {
+    voice: Voice,
+    effect: Option<Effect>,
+    volume_command: Option<VolumeCommand>,
+    /// Cell waiting for a note delay and the tick it's played on.
+    delayed_cell: Option<(u32, Cell)>,
+    note: u8,
+    instrument: Option<usize>,
+    /// Overrides the new note action of the instrument for the playing note.
+    new_note_action: Option<NewNoteAction>,
+    period: f32,
+    target_period: f32,
+    /// 0..=64
+    volume: i32,
+    /// 0..=255
+    panning: i32,
+    /// 0..=64
+    channel_volume: i32,
+    // changes applied for the current tick only
+    period_delta: f32,
+    volume_delta: i32,
+    arpeggio: u8,
+    tremor_off: bool,
+    // effect memory
+    arpeggio_memory: u8,
+    porta_up_memory: u8,
+    porta_down_memory: u8,
+    fine_porta_up_memory: u8,
+    fine_porta_down_memory: u8,
+    extra_fine_porta_up_memory: u8,
+    extra_fine_porta_down_memory: u8,
+    tone_porta_memory: u8,
+    volume_slide_memory: u8,
+    fine_volume_up_memory: u8,
+    fine_volume_down_memory: u8,
+    channel_volume_slide_memory: u8,
+    panning_slide_memory: u8,
+    global_volume_slide_memory: u8,
+    sample_offset_memory: u8,
+    sample_offset_high: u8,
+    retrigger_memory: u8,
+    retrigger_count: u8,
+    tremor_memory: u8,
+    tremor_count: u8,
+    tempo_memory: u8,
+    vibrato_speed: u8,
+    vibrato_depth: u8,
+    vibrato_waveform: u8,
+    vibrato_position: u8,
+    tremolo_speed: u8,
+    tremolo_depth: u8,
+    tremolo_waveform: u8,
+    tremolo_position: u8,
+    pattern_loop_row: usize,
+    pattern_loop_count: u8,
+}
+
+impl Channel {
+    fn slide_period(&mut self, linear: bool, amount: f32) {
+        self.period = clamp_period(linear, self.period + amount);
+    }
+
+    fn tone_porta(&mut self) {
+        let speed = self.tone_porta_memory as f32 * 4.0;
+
+        if self.period < self.target_period {
+            self.period = (self.period + speed).min(self.target_period);
+        } else if self.period > self.target_period {
+            self.period = (self.period - speed).max(self.target_period);
+        }
+    }
+
+    fn vibrato(&mut self, format: ModuleFormat, fine: bool, random: &mut u32) {
+        let phase = self.vibrato_position as f32 / 64.0;
+        let wave = waveform_value(Waveform::from_effect(self.vibrato_waveform), phase, random);
+        let depth = self.vibrato_depth as f32;
+
+        self.period_delta = match format {
+            ModuleFormat::It if fine => wave * depth,
+            ModuleFormat::It => wave * depth * 4.0,
+            _ => wave * depth * 255.0 / 32.0,
+        };
+        self.vibrato_position = (self.vibrato_position + self.vibrato_speed) & 63;
+    }
+
+    fn tremolo(&mut self, random: &mut u32) {
+        let phase = self.tremolo_position as f32 / 64.0;
+        let wave = waveform_value(Waveform::from_effect(self.tremolo_waveform), phase, random);
+
+        self.volume_delta = (wave * self.tremolo_depth as f32 * 255.0 / 64.0) as i32;
+        self.tremolo_position = (self.tremolo_position + self.tremolo_speed) & 63;
+    }
+
+    fn slide_volume(&mut self, amount: i32) {
+        self.volume = (self.volume + amount).clamp(0, 64);
+    }
+
+    fn slide_panning(&mut self, amount: i32) {
+        self.panning = (self.panning + amount).clamp(0, 255);
+    }
+
+    fn set_memory(memory: &mut u8, param: u8) -> u8 {
+        if param != 0 {
+            *memory = param;
+        }
+
+        *memory
+    }
+}
+
+pub(crate) struct TrackerPlaybackEngine {
+    module: Option<Arc<Module>>,
+    output_format: WavFormat,
+    order: usize,
+    row: usize,
+    tick: u32,
+    speed: u32,
+    tempo: u32,
+    /// 0..=128
+    global_volume: i32,
+    pattern_delay: u32,
+    next_order: Option<usize>,
+    next_row: Option<usize>,
+    loop_row: Option<usize>,
+    channels: Vec<Channel>,
+    voices: Vec<Voice>,
+    frames_left: usize,
+    frame_fraction: f32,
+    mix_buf: Vec<f32>,
+    random: u32,
+}
+
+pub struct SavedTrackerPlaybackState {
+    module: Option<Arc<Module>>,
+    order: usize,
+    row: usize,
+    speed: u32,
+    tempo: u32,
+    global_volume: i32,
+}
+
+impl SavedTrackerPlaybackState {
+    pub fn position(&self) -> SongPosition {
+        SongPosition::Tracker {
+            order: self.order,
+            row: self.row,
+            speed: self.speed,
+            tempo: self.tempo,
+            global_volume: self.global_volume,
+        }
+    }
+}
+
+impl TrackerPlaybackEngine {
+    pub fn new() -> TrackerPlaybackEngine {
+        TrackerPlaybackEngine {
+            module: None,
+            output_format: WavFormat { channels: 2, sample_rate: 44100, bit_depth: 16 },
+            order: 0,
+            row: 0,
+            tick: 0,
+            speed: 6,
+            tempo: 125,
+            global_volume: 128,
+            pattern_delay: 0,
+            next_order: None,
+            next_row: None,
+            loop_row: None,
+            channels: Vec::new(),
+            voices: Vec::new(),
+            frames_left: 0,
+            frame_fraction: 0.0,
+            mix_buf: Vec::with_capacity(4096),
+            random: 1,
+        }
+    }
+
+    pub fn set_sample_rate(&mut self, sample_rate: usize) {
+        self.output_format.sample_rate = sample_rate as u32;
+    }
+
+    pub fn get_state(&self) -> SavedTrackerPlaybackState {
+        SavedTrackerPlaybackState {
+            module: self.module.clone(),
+            order: self.order,
+            row: self.row,
+            speed: self.speed,
+            tempo: self.tempo,
+            global_volume: self.global_volume,
+        }
+    }
+
+    pub fn set_state(&mut self, state: SavedTrackerPlaybackState) {
+        self.module = state.module;
+        self.seek(state.order, state.row, state.speed, state.tempo, state.global_volume);
+    }
+
+    /// Continues playing from the start of `row`, notes that were playing before it are not restored.
+    pub fn seek(&mut self, order: usize, row: usize, speed: u32, tempo: u32, global_volume: i32) {
+        self.rewind();
+
+        self.order = order;
+        self.row = row;
+        self.speed = speed.max(1);
+        self.tempo = tempo.max(32);
+        self.global_volume = global_volume.clamp(0, 128);
+    }
+
+    pub fn start_song(&mut self, module: Module) {
+        self.module = Some(Arc::new(module));
+        self.rewind();
+    }
+
+    pub fn rewind(&mut self) {
+        let module = match &self.module {
+            Some(module) => module.clone(),
+            None => return,
+        };
+
+        self.row = 0;
+        self.tick = 0;
+        self.speed = module.initial_speed.max(1) as u32;
+        self.tempo = module.initial_tempo.max(32) as u32;
+        self.global_volume = module.initial_global_volume as i32;
+        self.pattern_delay = 0;
+        self.next_order = None;
+        self.next_row = None;
+        self.loop_row = None;
+        self.channels = (0..module.channels)
+            .map(|c| Channel {
+                panning: module.channel_panning.get(c).copied().unwrap_or(128) as i32,
+                channel_volume: module.channel_volume.get(c).copied().unwrap_or(64) as i32,
+                ..Channel::default()
+            })
+            .collect();
+        self.voices.clear();
+        self.frames_left = 0;
+        self.frame_fraction = 0.0;
+        self.set_order(&module, 0);
+    }
+
+    /// Moves to the first playable order starting from `order`, wrapping to the restart position at the end.
+    fn set_order(&mut self, module: &Module, mut order: usize) {
+        for _ in 0..=module.orders.len() * 2 {
+            match module.orders.get(order) {
+                Some(&ORDER_SKIP) => order += 1,
+                Some(&ORDER_END) | None => {
+                    order = module.restart_position;
+
+                    for channel in &mut self.channels {
+                        channel.pattern_loop_count = 0;
+                    }
+                }
+                Some(_) => break,
+            }
+        }
+
+        self.order = order;
+    }
+
+    fn pattern<'a>(&self, module: &'a Module) -> Option<&'a Pattern> {
+        module.orders.get(self.order).and_then(|&p| module.patterns.get(p as usize))
+    }
+
+    fn next_row(&mut self, module: &Module) {
+        if let Some(row) = self.loop_row.take() {
+            self.row = row;
+            self.next_order = None;
+            self.next_row = None;
+            return;
+        }
+
+        if self.next_order.is_some() || self.next_row.is_some() {
+            let order = self.next_order.take().unwrap_or(self.order + 1);
+            let row = self.next_row.take().unwrap_or(0);
+
+            self.set_order(module, order);
+            self.row = if row < self.pattern(module).map_or(64, |p| p.rows) { row } else { 0 };
+            return;
+        }
+
+        self.row += 1;
+        if self.row >= self.pattern(module).map_or(64, |p| p.rows) {
+            self.row = 0;
+            self.set_order(module, self.order + 1);
+        }
+    }
+
+    fn process_tick(&mut self, module: &Module, frames: usize) {
+        for channel in &mut self.channels {
+            channel.period_delta = 0.0;
+            channel.volume_delta = 0;
+            channel.arpeggio = 0;
+            channel.tremor_off = false;
+        }
+
+        if self.tick == 0 {
+            let pattern = self.pattern(module);
+            let cells: Vec<Cell> =
+                (0..self.channels.len()).map(|c| pattern.map(|p| p.cell(self.row, c)).unwrap_or_default()).collect();
+
+            for (c, cell) in cells.into_iter().enumerate() {
+                let channel = &mut self.channels[c];
+                channel.delayed_cell = None;
+
+                match cell.effect {
+                    Effect::NoteDelay(delay) if delay > 0 => {
+                        channel.effect = None;
+                        channel.volume_command = None;
+                        channel.delayed_cell = Some((delay as u32, cell));
+                    }
+                    _ => self.process_cell(module, c, cell),
+                }
+            }
+        } else {
+            let row_tick = self.tick % self.speed;
+
+            for c in 0..self.channels.len() {
+                match self.channels[c].delayed_cell {
+                    Some((delay, cell)) if delay == row_tick => {
+                        self.channels[c].delayed_cell = None;
+                        self.process_cell(module, c, cell);
+                    }
+                    _ => self.process_effects(module, c, row_tick),
+                }
+            }
+        }
+
+        self.update_voices(module, frames);
+
+        self.tick += 1;
+        if self.tick >= self.speed * (self.pattern_delay + 1) {
+            self.tick = 0;
+            self.pattern_delay = 0;
+            self.next_row(module);
+        }
+    }
+
+    fn update_voices(&mut self, module: &Module, frames: usize) {
+        let linear = module.linear_slides;
+        let sample_rate = self.output_format.sample_rate as f32;
+        let global_volume = self.global_volume as f32 / 128.0 / (module.channels.max(4) as f32).sqrt();
+        let ramp = RAMP_FRAMES.min(frames);
+
+        for channel in &mut self.channels {
+            let volume = if channel.tremor_off { 0 } else { (channel.volume + channel.volume_delta).clamp(0, 64) };
+            let mut period = channel.period + channel.period_delta;
+            if channel.arpeggio != 0 {
+                period = transpose_period(linear, period, channel.arpeggio);
+            }
+
+            let voice = &mut channel.voice;
+            voice.volume = volume as f32 / 64.0 * channel.channel_volume as f32 / 64.0;
+            voice.panning = channel.panning as f32;
+            voice.period = period;
+            voice.update(module, global_volume, sample_rate, ramp, &mut self.random);
+        }
+
+        for voice in &mut self.voices {
+            voice.update(module, global_volume, sample_rate, ramp, &mut self.random);
+        }
+
+        self.voices.retain(|v| v.active);
+    }
+
+    /// Plays a note, or sets the target of a tone portamento.
+    fn note_on(&mut self, module: &Module, c: usize, note: u8, tone_porta: bool) -> bool {
+        let linear = module.linear_slides;
+        let channel = &mut self.channels[c];
+        let instrument_index = match channel.instrument {
+            Some(i) => i,
+            None => return false,
+        };
+        let instrument = &module.instruments[instrument_index];
+
+        let (mapped_note, sample_index) = instrument.keymap[(note - NOTE_MIN) as usize];
+        let sample = match sample_index.and_then(|s| module.samples.get(s)) {
+            Some(sample) => sample,
+            None => return false,
+        };
+
+        channel.note = note;
+        let period = note_period(linear, mapped_note, sample.c5_speed);
+
+        if tone_porta && channel.voice.active {
+            channel.target_period = period;
+            return false;
+        }
+
+        if channel.voice.active {
+            let action = match module.format {
+                ModuleFormat::It => channel.new_note_action.unwrap_or_else(|| {
+                    channel
+                        .voice
+                        .instrument
+                        .and_then(|i| module.instruments.get(i))
+                        .map_or(NewNoteAction::Cut, |i| i.new_note_action)
+                }),
+                _ => NewNoteAction::Cut,
+            };
+
+            let voice = std::mem::take(&mut channel.voice);
+            push_background_voice(&mut self.voices, voice, action, module);
+        }
+
+        channel.new_note_action = None;
+        channel.period = period;
+        channel.target_period = period;
+        channel.voice = Voice::new(c, instrument_index, sample_index.unwrap_or_default(), sample.c5_speed);
+        channel.retrigger_count = 0;
+        channel.tremor_count = 0;
+
+        if channel.vibrato_waveform & 4 == 0 {
+            channel.vibrato_position = 0;
+        }
+        if channel.tremolo_waveform & 4 == 0 {
+            channel.tremolo_position = 0;
+        }
+
+        true
+    }
+
+    /// Processes the first tick of a cell.
+    fn process_cell(&mut self, module: &Module, c: usize, cell: Cell) {
+        let linear = module.linear_slides;
+        let format = module.format;
+        let tone_porta = matches!(cell.effect, Effect::TonePorta(_) | Effect::TonePortaVolumeSlide(_))
+            || matches!(cell.volume, VolumeCommand::TonePorta(_));
+
+        {
+            let channel = &mut self.channels[c];
+            channel.effect = Some(cell.effect);
+            channel.volume_command = Some(cell.volume);
+
+            if cell.instrument != 0 && (cell.instrument as usize) <= module.instruments.len() {
+                channel.instrument = Some(cell.instrument as usize - 1);
+            }
+        }
+
+        let mut triggered = false;
+        match cell.note {
+            NOTE_MIN..=NOTE_MAX => triggered = self.note_on(module, c, cell.note, tone_porta),
+            NOTE_OFF => self.channels[c].voice.key_off(module),
+            NOTE_CUT => self.channels[c].voice.stopping = true,
+            NOTE_FADE => self.channels[c].voice.fading = true,
+            _ => {}
+        }
+
+        let channel = &mut self.channels[c];
+
+        if cell.instrument != 0 {
+            let instrument = channel.instrument.and_then(|i| module.instruments.get(i));
+            let sample = instrument
+                .zip(channel.note.checked_sub(NOTE_MIN))
+                .and_then(|(i, n)| i.keymap.get(n as usize))
+                .and_then(|&(_, s)| s)
+                .and_then(|s| module.samples.get(s));
+
+            if let Some(sample) = sample {
+                channel.volume = sample.volume as i32;
+
+                if let Some(panning) = sample.panning.or_else(|| instrument.and_then(|i| i.panning)) {
+                    channel.panning = panning as i32;
+                }
+            }
+
+            // instruments without a note retrigger the envelopes in FT2
+            if format == ModuleFormat::Xm && cell.note == 0 && channel.voice.active {
+                channel.voice.key_on = true;
+                channel.voice.fading = false;
+                channel.voice.fade_volume = FADE_VOLUME_MAX;
+                channel.voice.volume_envelope = EnvelopePosition::default();
+                channel.voice.panning_envelope = EnvelopePosition::default();
+            }
+        }
+
+        match cell.volume {
+            VolumeCommand::Volume(volume) => channel.volume = volume as i32,
+            VolumeCommand::Panning(panning) => channel.panning = panning as i32,
+            VolumeCommand::FineSlideUp(amount) => channel.slide_volume(amount as i32),
+            VolumeCommand::FineSlideDown(amount) => channel.slide_volume(-(amount as i32)),
+            VolumeCommand::VibratoSpeed(speed) => {
+                Channel::set_memory(&mut channel.vibrato_speed, speed);
+            }
+            VolumeCommand::VibratoDepth(depth) => {
+                Channel::set_memory(&mut channel.vibrato_depth, depth);
+            }
+            VolumeCommand::TonePorta(speed) => {
+                Channel::set_memory(&mut channel.tone_porta_memory, speed);
+            }
+            _ => {}
+        }
+
+        match cell.effect {
+            Effect::Arpeggio(param) => {
+                Channel::set_memory(&mut channel.arpeggio_memory, param);
+            }
+            Effect::PortaUp(param) | Effect::PortaDown(param) if format == ModuleFormat::It => {
+                // E and F share their memory in IT, EFx and FFx are fine slides, EEx and FEx extra fine
+                channel.porta_up_memory = if param != 0 { param } else { channel.porta_up_memory };
+                channel.porta_down_memory = channel.porta_up_memory;
+
+                let param = channel.porta_up_memory;
+                let amount = match param {
+                    0xf0..=0xff => (param & 0x0f) as f32 * 4.0,
+                    0xe0..=0xef => (param & 0x0f) as f32,
+                    _ => 0.0,
+                };
+                let up = matches!(cell.effect, Effect::PortaUp(_));
+                channel.slide_period(linear, if up { -amount } else { amount });
+            }
+            Effect::PortaUp(param) => {
+                Channel::set_memory(&mut channel.porta_up_memory, param);
+            }
+            Effect::PortaDown(param) => {
+                Channel::set_memory(&mut channel.porta_down_memory, param);
+            }
+            Effect::FinePortaUp(param) => {
+                let amount = Channel::set_memory(&mut channel.fine_porta_up_memory, param) as f32 * 4.0;
+                channel.slide_period(linear, -amount);
+            }
+            Effect::FinePortaDown(param) => {
+                let amount = Channel::set_memory(&mut channel.fine_porta_down_memory, param) as f32 * 4.0;
+                channel.slide_period(linear, amount);
+            }
+            Effect::ExtraFinePortaUp(param) => {
+                let amount = Channel::set_memory(&mut channel.extra_fine_porta_up_memory, param) as f32;
+                channel.slide_period(linear, -amount);
+            }
+            Effect::ExtraFinePortaDown(param) => {
+                let amount = Channel::set_memory(&mut channel.extra_fine_porta_down_memory, param) as f32;
+                channel.slide_period(linear, amount);
+            }
+            Effect::TonePorta(param) => {
+                Channel::set_memory(&mut channel.tone_porta_memory, param);
+            }
+            Effect::Vibrato(param) | Effect::FineVibrato(param) => {
+                Channel::set_memory(&mut channel.vibrato_speed, param >> 4);
+                Channel::set_memory(&mut channel.vibrato_depth, param & 0x0f);
+            }
+            Effect::Tremolo(param) => {
+                Channel::set_memory(&mut channel.tremolo_speed, param >> 4);
+                Channel::set_memory(&mut channel.tremolo_depth, param & 0x0f);
+            }
+            Effect::Tremor(param) => {
+                Channel::set_memory(&mut channel.tremor_memory, param);
+            }
+            Effect::TonePortaVolumeSlide(param) | Effect::VibratoVolumeSlide(param) | Effect::VolumeSlide(param) => {
+                let param = Channel::set_memory(&mut channel.volume_slide_memory, param);
+                channel.slide_volume(slide_amounts(format, param).0);
+            }
+            Effect::FineVolumeSlideUp(param) => {
+                let amount = Channel::set_memory(&mut channel.fine_volume_up_memory, param);
+                channel.slide_volume(amount as i32);
+            }
+            Effect::FineVolumeSlideDown(param) => {
+                let amount = Channel::set_memory(&mut channel.fine_volume_down_memory, param);
+                channel.slide_volume(-(amount as i32));
+            }
+            Effect::ChannelVolumeSlide(param) => {
+                let param = Channel::set_memory(&mut channel.channel_volume_slide_memory, param);
+                channel.channel_volume = (channel.channel_volume + slide_amounts(format, param).0).clamp(0, 64);
+            }
+            Effect::PanningSlide(param) => {
+                let param = Channel::set_memory(&mut channel.panning_slide_memory, param);
+                // IT slides to the left with the high nibble, and uses 0..=64 for panning
+                if format == ModuleFormat::It {
+                    channel.slide_panning(-slide_amounts(format, param).0 * 4);
+                }
+            }
+            Effect::GlobalVolumeSlide(param) => {
+                let param = Channel::set_memory(&mut channel.global_volume_slide_memory, param);
+                if format == ModuleFormat::It {
+                    self.global_volume = (self.global_volume + slide_amounts(format, param).0).clamp(0, 128);
+                }
+            }
+            Effect::SetPanning(panning) => channel.panning = panning as i32,
+            Effect::SampleOffsetHigh(offset) => channel.sample_offset_high = offset,
+            Effect::SampleOffset(param) => {
+                let offset = Channel::set_memory(&mut channel.sample_offset_memory, param) as usize * 256
+                    + channel.sample_offset_high as usize * 65536;
+
+                if triggered {
+                    channel.voice.position = offset as f64;
+                }
+            }
+            Effect::SetVolume(volume) => channel.volume = volume as i32,
+            Effect::SetChannelVolume(volume) => channel.channel_volume = volume as i32,
+            Effect::PositionJump(order) => self.next_order = Some(order as usize),
+            Effect::PatternBreak(row) => self.next_row = Some(row as usize),
+            Effect::PatternLoop(0) => channel.pattern_loop_row = self.row,
+            Effect::PatternLoop(count) => {
+                if channel.pattern_loop_count == 0 {
+                    channel.pattern_loop_count = count;
+                    self.loop_row = Some(channel.pattern_loop_row);
+                } else {
+                    channel.pattern_loop_count -= 1;
+                    if channel.pattern_loop_count > 0 {
+                        self.loop_row = Some(channel.pattern_loop_row);
+                    }
+                }
+            }
+            Effect::PatternDelay(rows) if self.pattern_delay == 0 => self.pattern_delay = rows as u32,
+            Effect::Retrigger(param) => {
+                if param & 0xf0 != 0 {
+                    channel.retrigger_memory = (channel.retrigger_memory & 0x0f) | (param & 0xf0);
+                }
+                if param & 0x0f != 0 {
+                    channel.retrigger_memory = (channel.retrigger_memory & 0xf0) | (param & 0x0f);
+                }
+            }
+            Effect::NoteCut(0) => match format {
+                ModuleFormat::It => channel.voice.stopping = true,
+                _ => channel.volume = 0,
+            },
+            Effect::KeyOff(0) => channel.voice.key_off(module),
+            Effect::SetSpeed(speed) => self.speed = speed.max(1) as u32,
+            Effect::SetTempo(param) => {
+                let param = if format == ModuleFormat::It {
+                    Channel::set_memory(&mut channel.tempo_memory, param)
+                } else {
+                    param
+                };
+
+                // T0x and T1x slide the tempo in IT
+                if param >= 0x20 {
+                    self.tempo = param as u32;
+                }
+            }
+            Effect::SetGlobalVolume(volume) => self.global_volume = volume as i32,
+            Effect::VibratoWaveform(waveform) => channel.vibrato_waveform = waveform,
+            Effect::TremoloWaveform(waveform) => channel.tremolo_waveform = waveform,
+            Effect::NoteAction(action) => {
+                let past_action = match action {
+                    0 => Some(NewNoteAction::Cut),
+                    1 => Some(NewNoteAction::Off),
+                    2 => Some(NewNoteAction::Fade),
+                    _ => None,
+                };
+
+                match action {
+                    3 => channel.new_note_action = Some(NewNoteAction::Cut),
+                    4 => channel.new_note_action = Some(NewNoteAction::Continue),
+                    5 => channel.new_note_action = Some(NewNoteAction::Off),
+                    6 => channel.new_note_action = Some(NewNoteAction::Fade),
+                    _ => {}
+                }
+
+                if let Some(past_action) = past_action {
+                    for voice in self.voices.iter_mut().filter(|v| v.channel == c) {
+                        match past_action {
+                            NewNoteAction::Off => voice.key_off(module),
+                            NewNoteAction::Fade => voice.fading = true,
+                            _ => voice.stopping = true,
+                        }
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Processes the ticks of the row after the first one.
+    fn process_effects(&mut self, module: &Module, c: usize, row_tick: u32) {
+        let linear = module.linear_slides;
+        let format = module.format;
+        let channel = &mut self.channels[c];
+
+        match channel.volume_command {
+            Some(VolumeCommand::SlideUp(amount)) => channel.slide_volume(amount as i32),
+            Some(VolumeCommand::SlideDown(amount)) => channel.slide_volume(-(amount as i32)),
+            Some(VolumeCommand::PanningSlideLeft(amount)) => channel.slide_panning(-(amount as i32)),
+            Some(VolumeCommand::PanningSlideRight(amount)) => channel.slide_panning(amount as i32),
+            Some(VolumeCommand::TonePorta(_)) => channel.tone_porta(),
+            Some(VolumeCommand::VibratoDepth(_)) => channel.vibrato(format, false, &mut self.random),
+            Some(VolumeCommand::PortaUp(amount)) => channel.slide_period(linear, -(amount as f32) * 4.0),
+            Some(VolumeCommand::PortaDown(amount)) => channel.slide_period(linear, amount as f32 * 4.0),
+            _ => {}
+        }
+
+        let effect = match channel.effect {
+            Some(effect) => effect,
+            None => return,
+        };
+
+        match effect {
+            Effect::Arpeggio(_) => {
+                let param = channel.arpeggio_memory;
+                channel.arpeggio = match row_tick % 3 {
+                    1 => param >> 4,
+                    2 => param & 0x0f,
+                    _ => 0,
+                };
+            }
+            Effect::PortaUp(_) => {
+                let param = channel.porta_up_memory;
+                if format != ModuleFormat::It || param < 0xe0 {
+                    channel.slide_period(linear, -(param as f32) * 4.0);
+                }
+            }
+            Effect::PortaDown(_) => {
+                let param = channel.porta_down_memory;
+                if format != ModuleFormat::It || param < 0xe0 {
+                    channel.slide_period(linear, param as f32 * 4.0);
+                }
+            }
+            Effect::TonePorta(_) => channel.tone_porta(),
+            Effect::Vibrato(_) => channel.vibrato(format, false, &mut self.random),
+            Effect::FineVibrato(_) => channel.vibrato(format, true, &mut self.random),
+            Effect::TonePortaVolumeSlide(_) | Effect::VibratoVolumeSlide(_) | Effect::VolumeSlide(_) => {
+                match effect {
+                    Effect::TonePortaVolumeSlide(_) => channel.tone_porta(),
+                    Effect::VibratoVolumeSlide(_) => channel.vibrato(format, false, &mut self.random),
+                    _ => {}
+                }
+
+                channel.slide_volume(slide_amounts(format, channel.volume_slide_memory).1);
+            }
+            Effect::Tremolo(_) => channel.tremolo(&mut self.random),
+            Effect::Tremor(_) => {
+                let on = (channel.tremor_memory >> 4) + 1;
+                let off = (channel.tremor_memory & 0x0f) + 1;
+
+                channel.tremor_off = channel.tremor_count >= on;
+                channel.tremor_count = (channel.tremor_count + 1) % (on + off);
+            }
+            Effect::ChannelVolumeSlide(_) => {
+                let amount = slide_amounts(format, channel.channel_volume_slide_memory).1;
+                channel.channel_volume = (channel.channel_volume + amount).clamp(0, 64);
+            }
+            Effect::PanningSlide(_) => {
+                let amount = slide_amounts(format, channel.panning_slide_memory).1;
+                channel.slide_panning(if format == ModuleFormat::It { -amount * 4 } else { amount });
+            }
+            Effect::GlobalVolumeSlide(_) => {
+                let amount = slide_amounts(format, channel.global_volume_slide_memory).1;
+                let amount = if format == ModuleFormat::It { amount } else { amount * 2 };
+                self.global_volume = (self.global_volume + amount).clamp(0, 128);
+            }
+            Effect::Retrigger(_) => {
+                let interval = channel.retrigger_memory & 0x0f;
+
+                channel.retrigger_count += 1;
+                if interval > 0 && channel.retrigger_count >= interval {
+                    channel.retrigger_count = 0;
+                    channel.volume = retrigger_volume(channel.volume, channel.retrigger_memory >> 4);
+                    channel.voice.restart();
+                }
+            }
+            Effect::NoteCut(tick) if tick as u32 == row_tick => match format {
+                ModuleFormat::It => channel.voice.stopping = true,
+                _ => channel.volume = 0,
+            },
+            Effect::KeyOff(tick) if tick as u32 == row_tick => channel.voice.key_off(module),
+            Effect::SetTempo(_) if format == ModuleFormat::It => {
+                let param = channel.tempo_memory;
+                match param >> 4 {
+                    0 => self.tempo = self.tempo.saturating_sub((param & 0x0f) as u32).max(32),
+                    1 => self.tempo = (self.tempo + (param & 0x0f) as u32).min(255),
+                    _ => {}
+                }
+            }
+            _ => {}
+        }
+    }
+
+    pub fn render_to(&mut self, buf: &mut [u16]) -> usize {
+        let module = match &self.module {
+            Some(module) => module.clone(),
+            None => {
+                buf.fill(0x8000);
+                return buf.len();
+            }
+        };
+
+        let frames = buf.len() / 2;
+        self.mix_buf.clear();
+        self.mix_buf.resize(frames * 2, 0.0);
+
+        let mut done = 0;
+        while done < frames {
+            if self.frames_left == 0 {
+                let length = self.output_format.sample_rate as f32 * 2.5 / self.tempo as f32 + self.frame_fraction;
+                self.frames_left = (length as usize).max(1);
+                self.frame_fraction = length.fract();
+                self.process_tick(&module, self.frames_left);
+            }
+
+            let count = self.frames_left.min(frames - done);
+            let out = &mut self.mix_buf[done * 2..(done + count) * 2];
+
+            for channel in &mut self.channels {
+                channel.voice.mix(&module, out);
+            }
+            for voice in &mut self.voices {
+                voice.mix(&module, out);
+            }
+
+            done += count;
+            self.frames_left -= count;
+        }
+
+        for (sample, target) in self.mix_buf.iter().zip(buf.iter_mut()) {
+            *target = (sample.clamp(-32768.0, 32767.0) as i16) as u16 ^ 0x8000;
+        }
+
+        buf.len()
+    }
+}
+
+#[test]
+fn test_mod_playback_loops() {
+    let mut data = vec![0u8; 1084 + 2 * 1024];
+
+    // sample 1: 16 words long, full volume, looped
+    data[42..44].copy_from_slice(&16u16.to_be_bytes());
+    data[45] = 64;
+    data[48..50].copy_from_slice(&16u16.to_be_bytes());
+    // two orders, restarting at the first one
+    data[950] = 2;
+    data[952] = 0;
+    data[953] = 1;
+    data[1080..1084].copy_from_slice(b"M.K.");
+
+    // pattern 0: C-5 with speed 1 on the first channel, break to the next order on the second one
+    data[1084..1092].copy_from_slice(&[0x01, 0xac, 0x1f, 0x01, 0x00, 0x00, 0x0d, 0x00]);
+    // pattern 1: break, wrapping around to the first order
+    data[2108..2116].copy_from_slice(&[0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x0d, 0x00]);
+    data.extend((0..32).map(|i| if i < 16 { 0x60u8 } else { 0xa0u8 }));
+
+    let module = Module::load_from(std::io::Cursor::new(data)).unwrap();
+    let mut engine = TrackerPlaybackEngine::new();
+    engine.start_song(module);
+
+    // speed 1 at tempo 125 plays one row every 882 frames
+    let mut buf = vec![0u16; 882 * 2];
+    let mut orders = Vec::new();
+    for _ in 0..4 {
+        engine.render_to(&mut buf);
+        orders.push(engine.order);
+    }
+
+    assert_eq!(orders, [1, 0, 1, 0]);
+    assert!(buf.iter().any(|&s| s != 0x8000));
+
+    let state = engine.get_state();
+    engine.rewind();
+    engine.render_to(&mut buf);
+    engine.set_state(state);
+    assert_eq!(engine.order, 0);
+}
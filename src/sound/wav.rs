@@ -2,7 +2,7 @@ use std::fmt;
 use std::io;
 use std::io::ErrorKind;
 
-use byteorder::{LE, ReadBytesExt};
+use byteorder::{LE, ReadBytesExt, WriteBytesExt};
 
 #[derive(Copy, Clone, Eq, PartialEq, Debug)]
 pub struct RiffChunk {
@@ -118,4 +118,53 @@ impl WavSample {
 
         Ok(WavSample { format: WavFormat { channels, sample_rate: samples, bit_depth: bits }, data: buf })
     }
+
+    /// Writes the sample as a PCM WAV file.
+    pub fn write_to<W: io::Write>(&self, mut f: W) -> io::Result<()> {
+        self.format.write_header(self.data.len() as u32, &mut f)?;
+        f.write_all(&self.data)?;
+
+        Ok(())
+    }
+}
+
+impl WavFormat {
+    /// Writes the RIFF header of a PCM WAV file, followed by `data_len` bytes of samples.
+    pub fn write_header<W: io::Write>(&self, data_len: u32, mut f: W) -> io::Result<()> {
+        let block_align = self.channels * (self.bit_depth / 8);
+
+        f.write_all(b"RIFF")?;
+        f.write_u32::<LE>(36 + data_len)?;
+        f.write_all(b"WAVE")?;
+
+        f.write_all(b"fmt ")?;
+        f.write_u32::<LE>(16)?;
+        f.write_u16::<LE>(1)?;
+        f.write_u16::<LE>(self.channels)?;
+        f.write_u32::<LE>(self.sample_rate)?;
+        f.write_u32::<LE>(self.sample_rate * block_align as u32)?;
+        f.write_u16::<LE>(block_align)?;
+        f.write_u16::<LE>(self.bit_depth)?;
+
+        f.write_all(b"data")?;
+        f.write_u32::<LE>(data_len)?;
+
+        Ok(())
+    }
+}
+
+#[test]
+fn test_wav_round_trip() {
+    let sample = WavSample {
+        format: WavFormat { channels: 2, sample_rate: 44100, bit_depth: 16 },
+        data: vec![0x00, 0x80, 0xff, 0x7f, 0x34, 0x12, 0x00, 0x00],
+    };
+
+    let mut buf = Vec::new();
+    sample.write_to(&mut buf).unwrap();
+    assert_eq!(buf.len(), 44 + sample.data.len());
+
+    let read = WavSample::read_from(io::Cursor::new(buf)).unwrap();
+    assert_eq!(read.format, sample.format);
+    assert_eq!(read.data, sample.data);
 }
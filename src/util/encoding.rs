@@ -1,6 +1,8 @@
+use std::collections::HashMap;
 use std::io::Cursor;
 
 use byteorder::ReadBytesExt;
+use lazy_static::lazy_static;
 
 /// Decodes UTF-8 character in a less strict way.
 /// http://simonsapin.github.io/wtf-8/#decoding-wtf-8
@@ -7369,3 +7371,69 @@ pub fn read_cur_shift_jis<T: AsRef<[u8]>>(cursor: &mut Cursor<T>, max_bytes: u32
 
     (consumed, std::char::from_u32(result).unwrap_or('\u{fffd}'))
 }
+
+lazy_static! {
+    /// Reverse of the [read_cur_shift_jis] mapping, built by decoding every valid single and double byte sequence.
+    static ref SHIFT_JIS_ENCODE_TABLE: HashMap<char, u16> = {
+        let mut table = HashMap::new();
+
+        for byte in 0x80..=0xffu16 {
+            let (_, chr) = read_cur_shift_jis(&mut Cursor::new([byte as u8]), 1);
+            if chr != '\u{fffd}' {
+                table.entry(chr).or_insert(byte);
+            }
+        }
+
+        for lead in (0x81..=0x9fu16).chain(0xe0..=0xef).chain(0xfa..=0xfc) {
+            for trail in 0x40..=0xfcu16 {
+                let (consumed, chr) = read_cur_shift_jis(&mut Cursor::new([lead as u8, trail as u8]), 2);
+                if consumed == 2 && chr != '\u{fffd}' && chr != '\0' {
+                    table.entry(chr).or_insert((lead << 8) | trail);
+                }
+            }
+        }
+
+        table
+    };
+}
+
+/// Unicode -> Shift-JIS converter, returns None if the string contains characters that can't be represented.
+pub fn encode_shift_jis(s: &str) -> Option<Vec<u8>> {
+    let mut result = Vec::with_capacity(s.len());
+
+    for chr in s.chars() {
+        if chr.is_ascii() {
+            result.push(chr as u8);
+            continue;
+        }
+
+        match SHIFT_JIS_ENCODE_TABLE.get(&chr) {
+            Some(&code) if code > 0xff => {
+                result.push((code >> 8) as u8);
+                result.push(code as u8);
+            }
+            Some(&code) => result.push(code as u8),
+            None => return None,
+        }
+    }
+
+    Some(result)
+}
+
+#[test]
+fn test_shift_jis_round_trip() {
+    let text = "Mimiga Village ミミガーの村";
+    let encoded = encode_shift_jis(text).unwrap();
+
+    let mut cursor = Cursor::new(&encoded);
+    let mut decoded = String::new();
+    let mut bytes = encoded.len() as u32;
+    while bytes > 0 {
+        let (consumed, chr) = read_cur_shift_jis(&mut cursor, bytes);
+        decoded.push(chr);
+        bytes -= consumed;
+    }
+
+    assert_eq!(decoded, text);
+    assert_eq!(encode_shift_jis("\u{1f600}"), None);
+}
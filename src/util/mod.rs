@@ -1,3 +1,4 @@
 pub mod bitvec;
 pub mod encoding;
+pub mod hash;
 pub mod rng;
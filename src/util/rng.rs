@@ -1,6 +1,8 @@
 use std::cell::Cell;
 use std::ops::Range;
 
+use serde::{Deserialize, Serialize};
+
 pub trait RNG {
     fn next(&self) -> i32;
 
@@ -50,7 +52,7 @@ impl RNG for XorShift {
     }
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Xoroshiro32PlusPlus(Cell<(u16, u16)>);
 
 impl Xoroshiro32PlusPlus {